@@ -0,0 +1,127 @@
+//! Errors from AI requests
+//!
+//! Failures the user can do something about get their own variant, and
+//! errors reach the frontend as `{ kind, message, retryAfter? }` so it can
+//! tell them apart without matching on messages.
+
+use reqwest::StatusCode;
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+use std::time::Duration;
+use thiserror::Error;
+
+/// Phrases APIs use when a prompt does not fit the model's context
+const CONTEXT_ERRORS: &[&str] = &[
+    "context length",
+    "context window",
+    "context_length_exceeded",
+    "prompt is too long",
+    "too many tokens",
+    "maximum number of tokens",
+];
+
+#[derive(Error, Debug)]
+pub enum AiError {
+    #[error("HTTP request failed: {0}")]
+    RequestFailed(#[from] reqwest::Error),
+    #[error("API error: {0}")]
+    ApiError(String),
+    #[error("Invalid response: {0}")]
+    InvalidResponse(String),
+    #[error("Unknown AI provider: {0}")]
+    UnknownProvider(String),
+    #[error("Invalid AI configuration: {0}")]
+    Config(String),
+    /// Too many requests; `retry_after` is in seconds, when the API says
+    #[error("Rate limited: {message}")]
+    RateLimited {
+        message: String,
+        retry_after: Option<u64>,
+    },
+    /// The API key is missing, wrong, or lacks access
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
+    /// The prompt is larger than the model accepts
+    #[error("Context too long: {0}")]
+    ContextTooLong(String),
+    /// The API is temporarily unable to serve requests
+    #[error("Overloaded: {0}")]
+    Overloaded(String),
+    #[error("Request cancelled")]
+    Cancelled,
+    /// Another running request already uses the id
+    #[error("Request {0} is already running")]
+    DuplicateRequest(String),
+    /// A chat with tools used all its steps without answering
+    #[error("No answer after {0} tool steps")]
+    ToolLimit(usize),
+}
+
+impl AiError {
+    /// Error for an unsuccessful response with `status`
+    pub fn from_status(status: StatusCode, retry_after: Option<Duration>, message: String) -> Self {
+        match status.as_u16() {
+            401 | 403 => AiError::Unauthorized(message),
+            429 => AiError::RateLimited {
+                message,
+                retry_after: retry_after.map(|delay| delay.as_secs()),
+            },
+            // 529 is Anthropic's "overloaded"
+            503 | 529 => AiError::Overloaded(message),
+            400 | 413 if is_context_error(&message) => AiError::ContextTooLong(message),
+            _ => AiError::ApiError(message),
+        }
+    }
+
+    /// Error reported inside a reply, by its type where the API gives one
+    pub fn from_kind(kind: Option<&str>, message: String) -> Self {
+        match kind {
+            Some("overloaded_error" | "UNAVAILABLE") => AiError::Overloaded(message),
+            Some("rate_limit_error" | "RESOURCE_EXHAUSTED") => AiError::RateLimited {
+                message,
+                retry_after: None,
+            },
+            _ if is_context_error(&message) => AiError::ContextTooLong(message),
+            _ => AiError::ApiError(message),
+        }
+    }
+
+    /// Stable name of the variant, for the frontend
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AiError::RequestFailed(_) => "requestFailed",
+            AiError::ApiError(_) => "apiError",
+            AiError::InvalidResponse(_) => "invalidResponse",
+            AiError::UnknownProvider(_) => "unknownProvider",
+            AiError::Config(_) => "config",
+            AiError::RateLimited { .. } => "rateLimited",
+            AiError::Unauthorized(_) => "unauthorized",
+            AiError::ContextTooLong(_) => "contextTooLong",
+            AiError::Overloaded(_) => "overloaded",
+            AiError::Cancelled => "cancelled",
+            AiError::DuplicateRequest(_) => "duplicateRequest",
+            AiError::ToolLimit(_) => "toolLimit",
+        }
+    }
+}
+
+fn is_context_error(message: &str) -> bool {
+    let message = message.to_lowercase();
+    CONTEXT_ERRORS.iter().any(|phrase| message.contains(phrase))
+}
+
+impl Serialize for AiError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut error = serializer.serialize_struct("AiError", 3)?;
+        error.serialize_field("kind", self.kind())?;
+        error.serialize_field("message", &self.to_string())?;
+        if let AiError::RateLimited {
+            retry_after: Some(secs),
+            ..
+        } = self
+        {
+            error.serialize_field("retryAfter", secs)?;
+        }
+        error.end()
+    }
+}
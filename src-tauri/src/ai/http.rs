@@ -0,0 +1,280 @@
+//! Sending requests to AI APIs
+//!
+//! Requests share one HTTP client, rebuilt only when the configured
+//! timeouts change. Rate limits and overloaded servers are retried with
+//! exponential backoff, waiting as long as `retry-after` asks when it is
+//! given. A request run under an id with [`cancellable`] can be aborted
+//! from elsewhere with [`cancel`].
+
+use super::providers::Provider;
+use super::AiError;
+use futures::future::{AbortHandle, Abortable};
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
+use std::collections::hash_map::{Entry, HashMap};
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard, OnceLock, PoisonError};
+use std::time::Duration;
+
+/// Retries after the first attempt
+pub const MAX_RETRIES: u32 = 3;
+/// Wait before the first retry, doubled for each one after
+const BASE_DELAY: Duration = Duration::from_secs(1);
+/// Longest wait before a retry, whatever the API asks
+const MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// How long to wait on the network
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Timeouts {
+    /// For the connection to be established
+    pub connect: Duration,
+    /// Between two reads of the response
+    pub read: Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Timeouts {
+            connect: Duration::from_secs(10),
+            read: Duration::from_secs(120),
+        }
+    }
+}
+
+static CLIENT: Mutex<Option<(Timeouts, Client)>> = Mutex::new(None);
+
+/// The shared client, with `timeouts`
+pub fn client(timeouts: Timeouts) -> Client {
+    let mut shared = CLIENT.lock().unwrap_or_else(PoisonError::into_inner);
+    match shared.as_ref() {
+        Some((current, client)) if *current == timeouts => client.clone(),
+        _ => {
+            let built = Client::builder()
+                .connect_timeout(timeouts.connect)
+                .read_timeout(timeouts.read)
+                .build();
+            match built {
+                Ok(client) => {
+                    *shared = Some((timeouts, client.clone()));
+                    client
+                }
+                // Not kept, so the next request tries again
+                Err(e) => {
+                    log::error!("Failed to build the AI client, timeouts not applied: {}", e);
+                    Client::default()
+                }
+            }
+        }
+    }
+}
+
+/// Send `request`, retrying while the API is rate limited or overloaded.
+/// Returns the first successful response; other statuses become errors.
+pub(crate) async fn send(
+    provider: &dyn Provider,
+    request: RequestBuilder,
+) -> Result<Response, AiError> {
+    let mut retries = 0;
+    loop {
+        let attempt = request
+            .try_clone()
+            .ok_or_else(|| AiError::InvalidResponse("request cannot be resent".to_string()))?;
+        let response = attempt.send().await?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+
+        let retry_after = retry_after(&response);
+        if retries < MAX_RETRIES && is_transient(status) {
+            let delay = retry_after
+                .unwrap_or(BASE_DELAY * 2u32.pow(retries))
+                .min(MAX_DELAY);
+            log::warn!("AI request got {}, retrying in {:?}", status, delay);
+            tokio::time::sleep(delay).await;
+            retries += 1;
+            continue;
+        }
+
+        let body = response.text().await?;
+        let message = provider
+            .error_message(&body)
+            .unwrap_or_else(|| format!("{}: {}", status, body.trim()));
+        return Err(AiError::from_status(status, retry_after, message));
+    }
+}
+
+/// Whether a request failing with `status` may succeed if sent again
+fn is_transient(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// Delay asked for by a `retry-after` header in seconds
+fn retry_after(response: &Response) -> Option<Duration> {
+    response
+        .headers()
+        .get(RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+/// Requests that can be cancelled, by id, with the token of their
+/// registration
+fn in_flight() -> MutexGuard<'static, HashMap<String, (u64, AbortHandle)>> {
+    static IN_FLIGHT: OnceLock<Mutex<HashMap<String, (u64, AbortHandle)>>> = OnceLock::new();
+    IN_FLIGHT
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Forgets a request once it is over, however it ends. A cancelled request
+/// is only dropped when its task next runs, by which time a new request
+/// may have taken its id: the token tells them apart.
+struct Registration<'a> {
+    request_id: &'a str,
+    token: u64,
+}
+
+impl Drop for Registration<'_> {
+    fn drop(&mut self) {
+        if let Entry::Occupied(entry) = in_flight().entry(self.request_id.to_string()) {
+            if entry.get().0 == self.token {
+                entry.remove();
+            }
+        }
+    }
+}
+
+/// Run `future` so that `cancel(request_id)` aborts it, resolving to
+/// [`AiError::Cancelled`]. Ids must be unique among running requests.
+pub async fn cancellable<T>(
+    request_id: &str,
+    future: impl Future<Output = Result<T, AiError>>,
+) -> Result<T, AiError> {
+    static NEXT_TOKEN: AtomicU64 = AtomicU64::new(0);
+    let token = NEXT_TOKEN.fetch_add(1, Ordering::Relaxed);
+    let (handle, registration) = AbortHandle::new_pair();
+    match in_flight().entry(request_id.to_string()) {
+        Entry::Occupied(_) => return Err(AiError::DuplicateRequest(request_id.to_string())),
+        Entry::Vacant(entry) => entry.insert((token, handle)),
+    };
+    let _registered = Registration { request_id, token };
+    Abortable::new(future, registration)
+        .await
+        .unwrap_or(Err(AiError::Cancelled))
+}
+
+/// Abort the request running under `request_id`; false if there is none
+pub fn cancel(request_id: &str) -> bool {
+    match in_flight().remove(request_id) {
+        Some((_, handle)) => {
+            handle.abort();
+            true
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::test_server::{request, serve_each};
+    use crate::ai::{send_chat, Claude};
+
+    #[tokio::test]
+    async fn test_retries_transient_errors_and_classifies_the_rest() {
+        let (url, requests) = serve_each(vec![
+            (
+                "529 Overloaded\r\nretry-after: 0",
+                r#"{"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#,
+            ),
+            (
+                "429 Too Many Requests\r\nretry-after: 0",
+                r#"{"type":"error","error":{"type":"rate_limit_error","message":"Slow down"}}"#,
+            ),
+            (
+                "200 OK\r\ncontent-type: application/json",
+                r#"{"content":[{"type":"text","text":"Hello"}],"model":"claude","usage":{"input_tokens":3,"output_tokens":1}}"#,
+            ),
+        ])
+        .await;
+        let client = client(Timeouts::default());
+        let response = send_chat(&Claude::at(&url), &client, request("Hi"))
+            .await
+            .unwrap();
+        assert_eq!(response.content, "Hello");
+        assert_eq!(requests.await.unwrap().len(), 3);
+
+        let (url, _) = serve_each(vec![
+            (
+                "400 Bad Request",
+                r#"{"type":"error","error":{"type":"invalid_request_error","message":"prompt is too long: 210000 tokens > 200000 maximum"}}"#,
+            ),
+            (
+                "401 Unauthorized",
+                r#"{"type":"error","error":{"type":"authentication_error","message":"invalid x-api-key"}}"#,
+            ),
+        ])
+        .await;
+        let error = send_chat(&Claude::at(&url), &client, request("Hi"))
+            .await
+            .unwrap_err();
+        assert!(matches!(error, AiError::ContextTooLong(_)));
+        let error = send_chat(&Claude::at(&url), &client, request("Hi"))
+            .await
+            .unwrap_err();
+        assert!(matches!(error, AiError::Unauthorized(_)));
+        assert_eq!(
+            serde_json::to_value(&error).unwrap(),
+            serde_json::json!({
+                "kind": "unauthorized",
+                "message": "Unauthorized: invalid x-api-key"
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cancel_aborts_a_running_request() {
+        let running = tokio::spawn(cancellable("chat-1", async {
+            std::future::pending::<()>().await;
+            Ok(())
+        }));
+        // Let it start and register
+        while !in_flight().contains_key("chat-1") {
+            tokio::task::yield_now().await;
+        }
+
+        // A second request under the same id is refused, and does not
+        // unregister the first
+        let duplicate = cancellable("chat-1", async { Ok(()) }).await;
+        assert!(matches!(duplicate, Err(AiError::DuplicateRequest(_))));
+        assert!(cancel("chat-1"));
+        assert!(matches!(running.await.unwrap(), Err(AiError::Cancelled)));
+        assert!(!cancel("chat-1"));
+    }
+
+    #[tokio::test]
+    async fn test_a_cancelled_request_does_not_unregister_its_successor() {
+        let pending = || async {
+            std::future::pending::<()>().await;
+            Ok(())
+        };
+        let mut first = Box::pin(cancellable("chat-2", pending()));
+        assert!(futures::poll!(&mut first).is_pending());
+        assert!(cancel("chat-2"));
+
+        // The id is reused before the cancelled request is dropped
+        let mut second = Box::pin(cancellable("chat-2", pending()));
+        assert!(futures::poll!(&mut second).is_pending());
+        drop(first);
+
+        assert!(cancel("chat-2"));
+        assert!(matches!(second.await, Err(AiError::Cancelled)));
+    }
+}
@@ -1,11 +1,24 @@
 //! AI module for API integrations
 //!
-//! Provides unified interface for Claude, GPT, and Gemini APIs.
+//! Provides unified interface for Claude, GPT, and Gemini APIs, local
+//! Ollama models, and OpenAI-compatible servers set up as provider profiles.
 
+mod error;
+mod http;
+mod profiles;
 mod providers;
 pub mod speech;
+mod stream;
+#[cfg(test)]
+mod test_server;
+mod tools;
 
+pub use error::*;
+pub use http::*;
+pub use profiles::*;
 pub use providers::*;
+pub use stream::*;
+pub use tools::*;
 
 use serde::{Deserialize, Serialize};
 
@@ -16,13 +29,45 @@ pub enum AiProvider {
     Claude,
     Openai,
     Gemini,
+    /// Local models served by Ollama
+    Ollama,
+    /// Id of a user-defined [`ProviderProfile`]
+    #[serde(untagged)]
+    Profile(String),
 }
 
 /// Chat message format
-#[derive(Debug, Clone, Serialize, Deserialize)]
+///
+/// Besides `user` and `assistant` messages, a conversation using tools has
+/// assistant messages with `tool_calls`, each answered by a `tool` message
+/// whose `tool_call_id` names the call.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct ChatMessage {
     pub role: String,
     pub content: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+}
+
+/// A tool the model may call
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolDefinition {
+    pub name: String,
+    pub description: String,
+    /// JSON Schema of the arguments, an object
+    pub parameters: serde_json::Value,
+}
+
+/// A call the model made to one of the request's tools
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolCall {
+    /// Generated when the API does not give one
+    pub id: String,
+    pub name: String,
+    pub arguments: serde_json::Value,
 }
 
 /// AI chat request
@@ -36,14 +81,21 @@ pub struct AiChatRequest {
     pub max_tokens: Option<u32>,
     pub temperature: Option<f32>,
     pub system_prompt: Option<String>,
+    /// Tools the model may call instead of answering
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<ToolDefinition>,
 }
 
 /// AI chat response
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct AiChatResponse {
     pub content: String,
     pub model: String,
     pub usage: Option<TokenUsage>,
+    /// Calls to answer before the model goes on
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>,
 }
 
 /// Token usage information
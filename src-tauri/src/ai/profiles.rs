@@ -0,0 +1,61 @@
+//! User-defined provider profiles
+//!
+//! A profile points the OpenAI chat completions client at another server
+//! speaking the same API: a local LLM runtime, a hosted gateway, an Azure
+//! deployment. Profiles are stored in the `ai_provider_profiles` setting;
+//! their keys are not, and come with each request like the built-in ones.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+
+/// Ids taken by the built-in providers
+const RESERVED_IDS: &[&str] = &["claude", "openai", "gemini", "ollama"];
+
+/// An OpenAI-compatible server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProviderProfile {
+    /// What requests name as their provider
+    pub id: String,
+    pub name: String,
+    /// API root, such as `http://localhost:8080/v1`
+    pub base_url: String,
+    #[serde(default)]
+    pub auth: AuthStyle,
+    /// Model used when a request names none
+    pub default_model: Option<String>,
+    /// Sent with every request
+    #[serde(default)]
+    pub extra_headers: BTreeMap<String, String>,
+}
+
+/// How the API key is sent
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum AuthStyle {
+    /// `Authorization: Bearer <key>`
+    #[default]
+    Bearer,
+    /// The bare key in a header of this name, such as Azure's `api-key`
+    Header { name: String },
+    /// No key, for local servers
+    None,
+}
+
+/// Check a list of profiles: ids unique and not a built-in provider's,
+/// and URLs that can be requested
+pub fn validate_profiles(profiles: &[ProviderProfile]) -> Result<(), String> {
+    let mut ids = HashSet::new();
+    for profile in profiles {
+        if profile.id.is_empty() || RESERVED_IDS.contains(&profile.id.as_str()) {
+            return Err(format!("invalid profile id: {:?}", profile.id));
+        }
+        if !ids.insert(profile.id.as_str()) {
+            return Err(format!("duplicate profile id: {}", profile.id));
+        }
+        if !(profile.base_url.starts_with("http://") || profile.base_url.starts_with("https://")) {
+            return Err(format!("{}: base URL must be http(s)", profile.id));
+        }
+    }
+    Ok(())
+}
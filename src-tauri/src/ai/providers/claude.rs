@@ -0,0 +1,270 @@
+//! Claude (Anthropic)
+
+use super::{parse, AiError, ErrorDetail, Provider};
+use crate::ai::stream::StreamedReply;
+use crate::ai::{AiChatRequest, AiChatResponse, ChatMessage, TokenUsage, ToolCall};
+use reqwest::{Client, RequestBuilder};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+pub struct Claude {
+    url: String,
+}
+
+impl Default for Claude {
+    fn default() -> Self {
+        Claude::at("https://api.anthropic.com/v1/messages")
+    }
+}
+
+impl Claude {
+    /// The Messages API served at `url`
+    pub fn at(url: &str) -> Self {
+        Claude {
+            url: url.to_string(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ClaudeRequest {
+    model: String,
+    max_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<String>,
+    messages: Vec<ClaudeMessage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<ClaudeTool>,
+}
+
+#[derive(Serialize)]
+struct ClaudeMessage {
+    role: String,
+    content: ClaudeMessageContent,
+}
+
+/// Plain text, or content blocks for tool calls and their results
+#[derive(Serialize)]
+#[serde(untagged)]
+enum ClaudeMessageContent {
+    Text(String),
+    Blocks(Vec<ClaudeBlock>),
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClaudeBlock {
+    Text {
+        text: String,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+        input: Value,
+    },
+    ToolResult {
+        tool_use_id: String,
+        content: String,
+    },
+    /// Thinking and other blocks this app does not use
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Serialize)]
+struct ClaudeTool {
+    name: String,
+    description: String,
+    input_schema: Value,
+}
+
+#[derive(Deserialize)]
+struct ClaudeResponse {
+    content: Vec<ClaudeBlock>,
+    model: String,
+    usage: ClaudeUsage,
+}
+
+#[derive(Deserialize)]
+struct ClaudeUsage {
+    input_tokens: u32,
+    output_tokens: u32,
+}
+
+/// Streamed event
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClaudeEvent {
+    MessageStart {
+        message: ClaudeStartMessage,
+    },
+    ContentBlockDelta {
+        delta: ClaudeDelta,
+    },
+    MessageDelta {
+        usage: ClaudeOutputUsage,
+    },
+    Error {
+        error: ErrorDetail,
+    },
+    /// Pings, block starts and stops, and the final `message_stop`
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+struct ClaudeStartMessage {
+    model: String,
+    usage: ClaudeStartUsage,
+}
+
+#[derive(Deserialize)]
+struct ClaudeStartUsage {
+    input_tokens: u32,
+    #[serde(default)]
+    output_tokens: u32,
+}
+
+#[derive(Deserialize)]
+struct ClaudeDelta {
+    /// Absent from deltas that are not text
+    text: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ClaudeOutputUsage {
+    output_tokens: u32,
+}
+
+/// Messages in Claude's format, where tool results are sent by the user
+fn claude_messages(messages: Vec<ChatMessage>) -> Vec<ClaudeMessage> {
+    let mut claude_messages: Vec<ClaudeMessage> = Vec::new();
+    for m in messages {
+        if m.role == "tool" {
+            let result = ClaudeBlock::ToolResult {
+                tool_use_id: m.tool_call_id.unwrap_or_default(),
+                content: m.content,
+            };
+            // The results of one turn's calls go in a single message
+            match claude_messages.last_mut() {
+                Some(ClaudeMessage {
+                    role,
+                    content: ClaudeMessageContent::Blocks(blocks),
+                }) if role == "user" => blocks.push(result),
+                _ => claude_messages.push(ClaudeMessage {
+                    role: "user".to_string(),
+                    content: ClaudeMessageContent::Blocks(vec![result]),
+                }),
+            }
+        } else if !m.tool_calls.is_empty() {
+            let text = (!m.content.is_empty()).then_some(ClaudeBlock::Text { text: m.content });
+            let calls = m.tool_calls.into_iter().map(|call| ClaudeBlock::ToolUse {
+                id: call.id,
+                name: call.name,
+                input: call.arguments,
+            });
+            claude_messages.push(ClaudeMessage {
+                role: m.role,
+                content: ClaudeMessageContent::Blocks(text.into_iter().chain(calls).collect()),
+            });
+        } else {
+            claude_messages.push(ClaudeMessage {
+                role: m.role,
+                content: ClaudeMessageContent::Text(m.content),
+            });
+        }
+    }
+    claude_messages
+}
+
+impl Provider for Claude {
+    fn default_model(&self) -> &str {
+        "claude-sonnet-4-20250514"
+    }
+
+    fn chat_request(
+        &self,
+        client: &Client,
+        request: AiChatRequest,
+        stream: bool,
+    ) -> RequestBuilder {
+        let claude_request = ClaudeRequest {
+            model: request.model.unwrap_or_default(),
+            max_tokens: request.max_tokens.unwrap_or(4096),
+            system: request.system_prompt,
+            messages: claude_messages(request.messages),
+            temperature: request.temperature,
+            stream,
+            tools: request
+                .tools
+                .into_iter()
+                .map(|tool| ClaudeTool {
+                    name: tool.name,
+                    description: tool.description,
+                    input_schema: tool.parameters,
+                })
+                .collect(),
+        };
+
+        client
+            .post(&self.url)
+            .header("x-api-key", &request.api_key)
+            .header("anthropic-version", "2023-06-01")
+            .header("content-type", "application/json")
+            .json(&claude_request)
+    }
+
+    fn read_reply(&self, _model: &str, body: &str) -> Result<AiChatResponse, AiError> {
+        let claude_response: ClaudeResponse = parse(body)?;
+
+        let mut content = String::new();
+        let mut tool_calls = Vec::new();
+        for block in claude_response.content {
+            match block {
+                ClaudeBlock::Text { text } => content.push_str(&text),
+                ClaudeBlock::ToolUse { id, name, input } => tool_calls.push(ToolCall {
+                    id,
+                    name,
+                    arguments: input,
+                }),
+                _ => {}
+            }
+        }
+
+        Ok(AiChatResponse {
+            content,
+            model: claude_response.model,
+            usage: Some(TokenUsage {
+                input_tokens: claude_response.usage.input_tokens,
+                output_tokens: claude_response.usage.output_tokens,
+            }),
+            tool_calls,
+        })
+    }
+
+    fn read_chunk(&self, reply: &mut StreamedReply, data: &str) -> Result<Option<String>, AiError> {
+        match parse::<ClaudeEvent>(data)? {
+            ClaudeEvent::MessageStart { message } => {
+                reply.model = message.model;
+                reply.usage = Some(TokenUsage {
+                    input_tokens: message.usage.input_tokens,
+                    output_tokens: message.usage.output_tokens,
+                });
+                Ok(None)
+            }
+            ClaudeEvent::ContentBlockDelta { delta } => Ok(delta.text),
+            ClaudeEvent::MessageDelta { usage } => {
+                if let Some(total) = reply.usage.as_mut() {
+                    total.output_tokens = usage.output_tokens;
+                }
+                Ok(None)
+            }
+            ClaudeEvent::Error { error } => Err(error.into()),
+            ClaudeEvent::Other => Ok(None),
+        }
+    }
+}
@@ -0,0 +1,321 @@
+//! Google Gemini
+
+use super::{new_call_id, parse, AiError, ErrorDetail, Provider};
+use crate::ai::stream::StreamedReply;
+use crate::ai::{AiChatRequest, AiChatResponse, ChatMessage, TokenUsage, ToolCall};
+use reqwest::{Client, RequestBuilder};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+pub struct Gemini {
+    /// Followed by `/{model}:{method}`
+    models_url: String,
+}
+
+impl Default for Gemini {
+    fn default() -> Self {
+        Gemini::at("https://generativelanguage.googleapis.com/v1beta/models")
+    }
+}
+
+impl Gemini {
+    /// The API whose models are served under `models_url`
+    pub fn at(models_url: &str) -> Self {
+        Gemini {
+            models_url: models_url.to_string(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct GeminiRequest {
+    contents: Vec<GeminiContent>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system_instruction: Option<GeminiSystemInstruction>,
+    #[serde(skip_serializing_if = "Option::is_none", rename = "generationConfig")]
+    generation_config: Option<GeminiGenerationConfig>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<GeminiTools>,
+}
+
+#[derive(Serialize)]
+struct GeminiContent {
+    role: String,
+    parts: Vec<GeminiPart>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+enum GeminiPart {
+    Text(String),
+    FunctionCall(GeminiFunctionCall),
+    FunctionResponse {
+        name: String,
+        response: GeminiToolOutput,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+struct GeminiFunctionCall {
+    name: String,
+    #[serde(default)]
+    args: Value,
+}
+
+#[derive(Serialize)]
+struct GeminiToolOutput {
+    content: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiTools {
+    function_declarations: Vec<GeminiFunctionDeclaration>,
+}
+
+#[derive(Serialize)]
+struct GeminiFunctionDeclaration {
+    name: String,
+    description: String,
+    /// Left out for tools without arguments, as Gemini rejects objects
+    /// without properties
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parameters: Option<Value>,
+}
+
+#[derive(Serialize)]
+struct GeminiSystemInstruction {
+    parts: Vec<GeminiPart>,
+}
+
+#[derive(Serialize)]
+struct GeminiGenerationConfig {
+    #[serde(skip_serializing_if = "Option::is_none", rename = "maxOutputTokens")]
+    max_output_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+}
+
+/// A whole reply, or one streamed chunk of it
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiResponse {
+    #[serde(default)]
+    candidates: Vec<GeminiCandidate>,
+    usage_metadata: Option<GeminiUsage>,
+    model_version: Option<String>,
+    error: Option<ErrorDetail>,
+}
+
+#[derive(Deserialize)]
+struct GeminiCandidate {
+    content: Option<GeminiContentResponse>,
+}
+
+#[derive(Deserialize)]
+struct GeminiContentResponse {
+    #[serde(default)]
+    parts: Vec<GeminiPartResponse>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiPartResponse {
+    text: Option<String>,
+    function_call: Option<GeminiFunctionCall>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiUsage {
+    #[serde(default)]
+    prompt_token_count: u32,
+    #[serde(default)]
+    candidates_token_count: u32,
+}
+
+impl GeminiResponse {
+    /// Text of the first candidate, and the calls it makes
+    fn into_parts(self) -> (String, Vec<ToolCall>) {
+        let parts = self
+            .candidates
+            .into_iter()
+            .next()
+            .and_then(|c| c.content)
+            .map(|c| c.parts)
+            .unwrap_or_default();
+
+        let mut text = String::new();
+        let mut tool_calls = Vec::new();
+        for part in parts {
+            if let Some(part_text) = part.text {
+                text.push_str(&part_text);
+            }
+            if let Some(call) = part.function_call {
+                tool_calls.push(ToolCall {
+                    id: new_call_id(),
+                    name: call.name,
+                    arguments: call.args,
+                });
+            }
+        }
+        (text, tool_calls)
+    }
+}
+
+/// Contents in Gemini's format. Function responses are matched to calls by
+/// name, found from the id of the call they answer.
+fn gemini_contents(messages: Vec<ChatMessage>) -> Vec<GeminiContent> {
+    let mut call_names: HashMap<String, String> = HashMap::new();
+    let mut contents: Vec<GeminiContent> = Vec::new();
+    for m in messages {
+        if m.role == "tool" {
+            let name = m
+                .tool_call_id
+                .and_then(|id| call_names.get(&id).cloned())
+                .unwrap_or_default();
+            let response = GeminiPart::FunctionResponse {
+                name,
+                response: GeminiToolOutput { content: m.content },
+            };
+            // All of a turn's responses go in one content
+            match contents.last_mut() {
+                Some(last)
+                    if last.role == "user"
+                        && matches!(
+                            last.parts.last(),
+                            Some(GeminiPart::FunctionResponse { .. })
+                        ) =>
+                {
+                    last.parts.push(response)
+                }
+                _ => contents.push(GeminiContent {
+                    role: "user".to_string(),
+                    parts: vec![response],
+                }),
+            }
+            continue;
+        }
+
+        let mut parts = Vec::new();
+        if !m.content.is_empty() || m.tool_calls.is_empty() {
+            parts.push(GeminiPart::Text(m.content));
+        }
+        for call in m.tool_calls {
+            call_names.insert(call.id, call.name.clone());
+            parts.push(GeminiPart::FunctionCall(GeminiFunctionCall {
+                name: call.name,
+                args: call.arguments,
+            }));
+        }
+        contents.push(GeminiContent {
+            role: if m.role == "assistant" {
+                "model".to_string()
+            } else {
+                m.role
+            },
+            parts,
+        });
+    }
+    contents
+}
+
+fn has_properties(schema: &Value) -> bool {
+    schema["properties"]
+        .as_object()
+        .is_some_and(|properties| !properties.is_empty())
+}
+
+impl Provider for Gemini {
+    fn default_model(&self) -> &str {
+        "gemini-2.0-flash"
+    }
+
+    fn chat_request(
+        &self,
+        client: &Client,
+        request: AiChatRequest,
+        stream: bool,
+    ) -> RequestBuilder {
+        let gemini_request = GeminiRequest {
+            contents: gemini_contents(request.messages),
+            system_instruction: request.system_prompt.map(|s| GeminiSystemInstruction {
+                parts: vec![GeminiPart::Text(s)],
+            }),
+            generation_config: Some(GeminiGenerationConfig {
+                max_output_tokens: request.max_tokens,
+                temperature: request.temperature,
+            }),
+            tools: if request.tools.is_empty() {
+                Vec::new()
+            } else {
+                vec![GeminiTools {
+                    function_declarations: request
+                        .tools
+                        .into_iter()
+                        .map(|tool| GeminiFunctionDeclaration {
+                            parameters: has_properties(&tool.parameters).then_some(tool.parameters),
+                            name: tool.name,
+                            description: tool.description,
+                        })
+                        .collect(),
+                }]
+            },
+        };
+
+        let method = if stream {
+            "streamGenerateContent?alt=sse&"
+        } else {
+            "generateContent?"
+        };
+        let url = format!(
+            "{}/{}:{}key={}",
+            self.models_url,
+            request.model.unwrap_or_default(),
+            method,
+            request.api_key
+        );
+
+        client
+            .post(&url)
+            .header("content-type", "application/json")
+            .json(&gemini_request)
+    }
+
+    fn read_reply(&self, model: &str, body: &str) -> Result<AiChatResponse, AiError> {
+        let gemini_response: GeminiResponse = parse(body)?;
+        let usage = gemini_response.usage_metadata.as_ref().map(|u| TokenUsage {
+            input_tokens: u.prompt_token_count,
+            output_tokens: u.candidates_token_count,
+        });
+
+        let (content, tool_calls) = gemini_response.into_parts();
+
+        Ok(AiChatResponse {
+            content,
+            model: model.to_string(),
+            usage,
+            tool_calls,
+        })
+    }
+
+    fn read_chunk(&self, reply: &mut StreamedReply, data: &str) -> Result<Option<String>, AiError> {
+        let mut chunk: GeminiResponse = parse(data)?;
+        if let Some(error) = chunk.error.take() {
+            return Err(error.into());
+        }
+        if let Some(model) = chunk.model_version.take() {
+            reply.model = model;
+        }
+        // Counts are running totals, so the last chunk's win
+        if let Some(usage) = &chunk.usage_metadata {
+            reply.usage = Some(TokenUsage {
+                input_tokens: usage.prompt_token_count,
+                output_tokens: usage.candidates_token_count,
+            });
+        }
+        let (text, _) = chunk.into_parts();
+        Ok((!text.is_empty()).then_some(text))
+    }
+}
@@ -0,0 +1,133 @@
+//! AI Provider implementations
+//!
+//! Each chat API is a [`Provider`]: it turns an [`AiChatRequest`] into an
+//! HTTP request and reads the replies, whole or streamed. Sending, and
+//! everything else that does not depend on the API, is shared.
+
+mod claude;
+mod gemini;
+mod ollama;
+mod openai;
+
+pub use claude::Claude;
+pub use gemini::Gemini;
+pub use ollama::{Ollama, OllamaModel, PullProgress};
+pub use openai::OpenAi;
+
+use super::http;
+use super::profiles::ProviderProfile;
+use super::stream::{Framing, StreamedReply};
+use super::{AiChatRequest, AiChatResponse, AiError, AiProvider};
+use reqwest::{Client, RequestBuilder};
+use serde::Deserialize;
+use serde_json::Value;
+
+/// A chat API
+pub trait Provider: Send + Sync {
+    /// Model used when a request names none
+    fn default_model(&self) -> &str;
+
+    /// HTTP request sending `request`, whose model is set. With `stream`,
+    /// the reply is asked for in pieces, as they are generated.
+    fn chat_request(&self, client: &Client, request: AiChatRequest, stream: bool)
+        -> RequestBuilder;
+
+    /// Read a whole reply to a request for `model`
+    fn read_reply(&self, model: &str, body: &str) -> Result<AiChatResponse, AiError>;
+
+    /// How streamed replies are cut into chunks
+    fn framing(&self) -> Framing {
+        Framing::Sse
+    }
+
+    /// Take in one streamed chunk; returns the text it adds
+    fn read_chunk(&self, reply: &mut StreamedReply, data: &str) -> Result<Option<String>, AiError>;
+
+    /// Message in the body of an unsuccessful response
+    fn error_message(&self, body: &str) -> Option<String> {
+        serde_json::from_str::<ErrorBody>(body)
+            .ok()
+            .map(|error| error.error.message)
+    }
+}
+
+/// Error body shared by the Claude, OpenAI and Gemini APIs
+#[derive(Deserialize)]
+pub(super) struct ErrorBody {
+    pub error: ErrorDetail,
+}
+
+#[derive(Deserialize)]
+pub(super) struct ErrorDetail {
+    pub message: String,
+    /// Claude's and OpenAI's `type`, Gemini's `status`
+    #[serde(default, rename = "type", alias = "status")]
+    pub kind: Option<String>,
+}
+
+impl From<ErrorDetail> for AiError {
+    fn from(error: ErrorDetail) -> Self {
+        AiError::from_kind(error.kind.as_deref(), error.message)
+    }
+}
+
+/// The provider a request names: a built-in API, or one of the user's
+/// OpenAI-compatible `profiles`
+pub fn provider_for(
+    provider: &AiProvider,
+    profiles: &[ProviderProfile],
+) -> Result<Box<dyn Provider>, AiError> {
+    Ok(match provider {
+        AiProvider::Claude => Box::new(Claude::default()),
+        AiProvider::Openai => Box::new(OpenAi::default()),
+        AiProvider::Gemini => Box::new(Gemini::default()),
+        AiProvider::Ollama => Box::new(Ollama::default()),
+        AiProvider::Profile(id) => {
+            let profile = profiles
+                .iter()
+                .find(|profile| profile.id == *id)
+                .ok_or_else(|| AiError::UnknownProvider(id.clone()))?;
+            Box::new(OpenAi::from_profile(profile))
+        }
+    })
+}
+
+/// Fill in the provider's default model if the request names none, and
+/// return the model used
+pub(super) fn resolve_model(provider: &dyn Provider, request: &mut AiChatRequest) -> String {
+    request
+        .model
+        .get_or_insert_with(|| provider.default_model().to_string())
+        .clone()
+}
+
+/// Send a chat request and wait for the whole reply
+pub async fn send_chat(
+    provider: &dyn Provider,
+    client: &Client,
+    mut request: AiChatRequest,
+) -> Result<AiChatResponse, AiError> {
+    let model = resolve_model(provider, &mut request);
+    let response = http::send(provider, provider.chat_request(client, request, false)).await?;
+    let body = response.text().await?;
+    provider.read_reply(&model, &body)
+}
+
+/// Arguments of a tool call sent as JSON text. No arguments at all are an
+/// empty object; text that is not JSON is passed on for the tool to reject.
+pub(super) fn parse_arguments(arguments: &str) -> Value {
+    if arguments.trim().is_empty() {
+        return Value::Object(Default::default());
+    }
+    serde_json::from_str(arguments).unwrap_or_else(|_| Value::String(arguments.to_string()))
+}
+
+/// Id for a tool call, for APIs that do not give one
+pub(super) fn new_call_id() -> String {
+    format!("call_{}", uuid::Uuid::new_v4().simple())
+}
+
+/// Parse a JSON body or chunk
+pub(super) fn parse<T: serde::de::DeserializeOwned>(data: &str) -> Result<T, AiError> {
+    serde_json::from_str(data).map_err(|e| AiError::InvalidResponse(e.to_string()))
+}
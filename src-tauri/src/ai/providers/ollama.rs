@@ -0,0 +1,402 @@
+//! Ollama, serving models on this machine
+//!
+//! Besides chat, Ollama manages the models it serves: they can be listed
+//! and pulled from its library. Its streams are JSON lines rather than
+//! server-sent events, and it takes no API key.
+
+use super::{new_call_id, parse, AiError, Provider};
+use crate::ai::http;
+use crate::ai::stream::{ChunkParser, Framing, StreamedReply};
+use crate::ai::{AiChatRequest, AiChatResponse, TokenUsage, ToolCall};
+use futures::StreamExt;
+use reqwest::{Client, RequestBuilder};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+pub struct Ollama {
+    base_url: String,
+}
+
+impl Default for Ollama {
+    fn default() -> Self {
+        Ollama::at("http://localhost:11434")
+    }
+}
+
+impl Ollama {
+    /// The Ollama server at `base_url`
+    pub fn at(base_url: &str) -> Self {
+        Ollama {
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// Models available locally
+    pub async fn list_models(&self, client: &Client) -> Result<Vec<OllamaModel>, AiError> {
+        let request = client.get(format!("{}/api/tags", self.base_url));
+        let body = http::send(self, request).await?.text().await?;
+
+        let tags: TagsResponse = parse(&body)?;
+        Ok(tags
+            .models
+            .into_iter()
+            .map(|model| OllamaModel {
+                name: model.name,
+                size: model.size,
+                modified_at: model.modified_at,
+                family: model.details.family,
+                parameter_size: model.details.parameter_size,
+                quantization_level: model.details.quantization_level,
+            })
+            .collect())
+    }
+
+    /// Download `model` from the Ollama library, calling `on_progress` as
+    /// the pull goes through its stages
+    pub async fn pull_model(
+        &self,
+        client: &Client,
+        model: &str,
+        mut on_progress: impl FnMut(&PullProgress),
+    ) -> Result<(), AiError> {
+        let request = client
+            .post(format!("{}/api/pull", self.base_url))
+            .json(&PullRequest {
+                model,
+                stream: true,
+            });
+        let response = http::send(self, request).await?;
+
+        let mut parser = ChunkParser::new(Framing::Lines);
+        let mut body = response.bytes_stream();
+        // Whether the line completes the pull
+        let mut read = |line: &str| -> Result<bool, AiError> {
+            let chunk: PullChunk = parse(line)?;
+            if let Some(error) = chunk.error {
+                return Err(AiError::from_kind(None, error));
+            }
+            on_progress(&chunk.progress);
+            Ok(chunk.progress.status == "success")
+        };
+        while let Some(bytes) = body.next().await {
+            for line in parser.push(&bytes?) {
+                if read(&line)? {
+                    return Ok(());
+                }
+            }
+        }
+        if let Some(line) = parser.finish() {
+            if read(&line)? {
+                return Ok(());
+            }
+        }
+        Err(AiError::InvalidResponse(format!(
+            "pull of {} ended before completing",
+            model
+        )))
+    }
+}
+
+/// A model installed in Ollama
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OllamaModel {
+    /// Name and tag, such as `llama3.2:latest`
+    pub name: String,
+    /// Size on disk, in bytes
+    pub size: u64,
+    pub modified_at: String,
+    pub family: Option<String>,
+    /// Such as `3.2B`
+    pub parameter_size: Option<String>,
+    /// Such as `Q4_K_M`
+    pub quantization_level: Option<String>,
+}
+
+/// One step of a pull
+#[derive(Debug, Clone, Deserialize)]
+pub struct PullProgress {
+    /// Stage, such as `pulling manifest`, `pulling <digest>` or `success`
+    #[serde(default)]
+    pub status: String,
+    /// Layer being downloaded
+    pub digest: Option<String>,
+    /// Bytes of the layer, while downloading
+    #[serde(default)]
+    pub total: u64,
+    #[serde(default)]
+    pub completed: u64,
+}
+
+#[derive(Serialize)]
+struct PullRequest<'a> {
+    model: &'a str,
+    stream: bool,
+}
+
+#[derive(Deserialize)]
+struct PullChunk {
+    error: Option<String>,
+    #[serde(flatten)]
+    progress: PullProgress,
+}
+
+#[derive(Deserialize)]
+struct TagsResponse {
+    models: Vec<TagModel>,
+}
+
+#[derive(Deserialize)]
+struct TagModel {
+    name: String,
+    size: u64,
+    modified_at: String,
+    #[serde(default)]
+    details: TagDetails,
+}
+
+#[derive(Deserialize, Default)]
+struct TagDetails {
+    family: Option<String>,
+    parameter_size: Option<String>,
+    quantization_level: Option<String>,
+}
+
+#[derive(Serialize)]
+struct OllamaRequest {
+    model: String,
+    messages: Vec<OllamaMessage>,
+    stream: bool,
+    options: OllamaOptions,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<OllamaTool>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct OllamaMessage {
+    role: String,
+    #[serde(default)]
+    content: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tool_calls: Vec<OllamaToolCall>,
+}
+
+/// Same shape as OpenAI's
+#[derive(Serialize)]
+struct OllamaTool {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    function: OllamaFunction,
+}
+
+#[derive(Serialize)]
+struct OllamaFunction {
+    name: String,
+    description: String,
+    parameters: Value,
+}
+
+#[derive(Serialize, Deserialize)]
+struct OllamaToolCall {
+    function: OllamaFunctionCall,
+}
+
+/// Unlike OpenAI, without an id and with the arguments as an object
+#[derive(Serialize, Deserialize)]
+struct OllamaFunctionCall {
+    name: String,
+    #[serde(default)]
+    arguments: Value,
+}
+
+#[derive(Serialize)]
+struct OllamaOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    /// Maximum tokens to generate
+    #[serde(skip_serializing_if = "Option::is_none")]
+    num_predict: Option<u32>,
+}
+
+/// A whole reply, or one streamed chunk of it
+#[derive(Deserialize)]
+struct OllamaResponse {
+    model: String,
+    message: Option<OllamaMessage>,
+    /// Token counts, on the last chunk
+    prompt_eval_count: Option<u32>,
+    eval_count: Option<u32>,
+    error: Option<String>,
+}
+
+impl OllamaResponse {
+    fn usage(&self) -> Option<TokenUsage> {
+        Some(TokenUsage {
+            input_tokens: self.prompt_eval_count?,
+            output_tokens: self.eval_count?,
+        })
+    }
+}
+
+/// Ollama's errors are a bare message
+#[derive(Deserialize)]
+struct OllamaError {
+    error: String,
+}
+
+impl Provider for Ollama {
+    fn default_model(&self) -> &str {
+        "llama3.2"
+    }
+
+    fn chat_request(
+        &self,
+        client: &Client,
+        request: AiChatRequest,
+        stream: bool,
+    ) -> RequestBuilder {
+        let mut messages: Vec<OllamaMessage> = vec![];
+
+        if let Some(system) = request.system_prompt {
+            messages.push(OllamaMessage {
+                role: "system".to_string(),
+                content: system,
+                tool_calls: Vec::new(),
+            });
+        }
+
+        // Tool results are matched to calls by their order
+        messages.extend(request.messages.into_iter().map(|m| {
+            OllamaMessage {
+                role: m.role,
+                content: m.content,
+                tool_calls: m
+                    .tool_calls
+                    .into_iter()
+                    .map(|call| OllamaToolCall {
+                        function: OllamaFunctionCall {
+                            name: call.name,
+                            arguments: call.arguments,
+                        },
+                    })
+                    .collect(),
+            }
+        }));
+
+        let ollama_request = OllamaRequest {
+            model: request.model.unwrap_or_default(),
+            messages,
+            stream,
+            options: OllamaOptions {
+                temperature: request.temperature,
+                num_predict: request.max_tokens,
+            },
+            tools: request
+                .tools
+                .into_iter()
+                .map(|tool| OllamaTool {
+                    kind: "function",
+                    function: OllamaFunction {
+                        name: tool.name,
+                        description: tool.description,
+                        parameters: tool.parameters,
+                    },
+                })
+                .collect(),
+        };
+
+        client
+            .post(format!("{}/api/chat", self.base_url))
+            .json(&ollama_request)
+    }
+
+    fn read_reply(&self, _model: &str, body: &str) -> Result<AiChatResponse, AiError> {
+        let ollama_response: OllamaResponse = parse(body)?;
+        let usage = ollama_response.usage();
+        let message = ollama_response.message.unwrap_or(OllamaMessage {
+            role: "assistant".to_string(),
+            content: String::new(),
+            tool_calls: Vec::new(),
+        });
+
+        Ok(AiChatResponse {
+            content: message.content,
+            model: ollama_response.model,
+            usage,
+            tool_calls: message
+                .tool_calls
+                .into_iter()
+                .map(|call| ToolCall {
+                    id: new_call_id(),
+                    name: call.function.name,
+                    arguments: call.function.arguments,
+                })
+                .collect(),
+        })
+    }
+
+    fn framing(&self) -> Framing {
+        Framing::Lines
+    }
+
+    fn read_chunk(&self, reply: &mut StreamedReply, data: &str) -> Result<Option<String>, AiError> {
+        let chunk: OllamaResponse = parse(data)?;
+        if let Some(error) = chunk.error {
+            return Err(AiError::from_kind(None, error));
+        }
+        if let Some(usage) = chunk.usage() {
+            reply.usage = Some(usage);
+        }
+        reply.model = chunk.model;
+        Ok(chunk
+            .message
+            .map(|m| m.content)
+            .filter(|content| !content.is_empty()))
+    }
+
+    fn error_message(&self, body: &str) -> Option<String> {
+        serde_json::from_str::<OllamaError>(body)
+            .ok()
+            .map(|error| error.error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::test_server::serve;
+
+    #[tokio::test]
+    async fn test_lists_and_pulls_models() {
+        let (url, _) = serve(
+            "application/json",
+            r#"{"models":[{"name":"llama3.2:latest","model":"llama3.2:latest","modified_at":"2026-09-01T10:00:00Z","size":2019393189,"digest":"a80c","details":{"family":"llama","parameter_size":"3.2B","quantization_level":"Q4_K_M"}}]}"#,
+        )
+        .await;
+        let models = Ollama::at(&url).list_models(&Client::new()).await.unwrap();
+        assert_eq!(models.len(), 1);
+        assert_eq!(models[0].name, "llama3.2:latest");
+        assert_eq!(models[0].parameter_size.as_deref(), Some("3.2B"));
+
+        let (url, received) = serve(
+            "application/x-ndjson",
+            concat!(
+                "{\"status\":\"pulling manifest\"}\n",
+                "{\"status\":\"pulling a80c\",\"digest\":\"sha256:a80c\",\"total\":100,\"completed\":40}\n",
+                "{\"status\":\"pulling a80c\",\"digest\":\"sha256:a80c\",\"total\":100,\"completed\":100}\n",
+                "{\"status\":\"success\"}\n",
+            ),
+        )
+        .await;
+        let mut steps = Vec::new();
+        Ollama::at(&url)
+            .pull_model(&Client::new(), "llama3.2", |progress| {
+                steps.push((progress.status.clone(), progress.completed))
+            })
+            .await
+            .unwrap();
+        assert_eq!(steps.len(), 4);
+        assert_eq!(steps[2], ("pulling a80c".to_string(), 100));
+        assert!(received.await.unwrap().starts_with("POST /api/pull "));
+    }
+}
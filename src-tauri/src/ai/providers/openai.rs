@@ -0,0 +1,342 @@
+//! OpenAI (GPT), and servers implementing its chat completions API
+
+use super::{parse, parse_arguments, AiError, ErrorDetail, Provider};
+use crate::ai::profiles::{AuthStyle, ProviderProfile};
+use crate::ai::stream::StreamedReply;
+use crate::ai::{AiChatRequest, AiChatResponse, TokenUsage, ToolCall};
+use reqwest::{Client, RequestBuilder};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+pub struct OpenAi {
+    url: String,
+    default_model: String,
+    auth: AuthStyle,
+    extra_headers: Vec<(String, String)>,
+}
+
+impl Default for OpenAi {
+    fn default() -> Self {
+        OpenAi {
+            url: "https://api.openai.com/v1/chat/completions".to_string(),
+            default_model: "gpt-4o".to_string(),
+            auth: AuthStyle::Bearer,
+            extra_headers: Vec::new(),
+        }
+    }
+}
+
+impl OpenAi {
+    /// The server a user-defined profile points at
+    pub fn from_profile(profile: &ProviderProfile) -> Self {
+        OpenAi {
+            url: format!(
+                "{}/chat/completions",
+                profile.base_url.trim_end_matches('/')
+            ),
+            default_model: profile.default_model.clone().unwrap_or_default(),
+            auth: profile.auth.clone(),
+            extra_headers: profile
+                .extra_headers
+                .iter()
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct OpenAiRequest {
+    model: String,
+    messages: Vec<OpenAiMessage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<OpenAiStreamOptions>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<OpenAiTool>,
+}
+
+#[derive(Serialize)]
+struct OpenAiStreamOptions {
+    /// Adds a last chunk with the token usage
+    include_usage: bool,
+}
+
+#[derive(Serialize)]
+struct OpenAiMessage {
+    role: String,
+    /// Null on assistant messages with only tool calls
+    content: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tool_calls: Vec<OpenAiToolCall>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_call_id: Option<String>,
+}
+
+#[derive(Serialize)]
+struct OpenAiTool {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    function: OpenAiFunction,
+}
+
+#[derive(Serialize)]
+struct OpenAiFunction {
+    name: String,
+    description: String,
+    parameters: Value,
+}
+
+#[derive(Serialize, Deserialize)]
+struct OpenAiToolCall {
+    id: String,
+    #[serde(rename = "type", default)]
+    kind: String,
+    function: OpenAiFunctionCall,
+}
+
+#[derive(Serialize, Deserialize)]
+struct OpenAiFunctionCall {
+    name: String,
+    /// The arguments object, as JSON text
+    arguments: String,
+}
+
+#[derive(Deserialize)]
+struct OpenAiResponse {
+    choices: Vec<OpenAiChoice>,
+    model: String,
+    /// Some compatible servers leave it out
+    usage: Option<OpenAiUsage>,
+}
+
+#[derive(Deserialize)]
+struct OpenAiChoice {
+    message: OpenAiMessageResponse,
+}
+
+#[derive(Deserialize)]
+struct OpenAiMessageResponse {
+    content: Option<String>,
+    #[serde(default)]
+    tool_calls: Vec<OpenAiToolCall>,
+}
+
+#[derive(Deserialize)]
+struct OpenAiUsage {
+    prompt_tokens: u32,
+    completion_tokens: u32,
+}
+
+/// Streamed `chat.completion.chunk`
+#[derive(Deserialize)]
+struct OpenAiChunk {
+    model: Option<String>,
+    #[serde(default)]
+    choices: Vec<OpenAiChunkChoice>,
+    /// Only on the last chunk
+    usage: Option<OpenAiUsage>,
+    error: Option<ErrorDetail>,
+}
+
+#[derive(Deserialize)]
+struct OpenAiChunkChoice {
+    delta: OpenAiDelta,
+}
+
+#[derive(Deserialize)]
+struct OpenAiDelta {
+    content: Option<String>,
+}
+
+impl Provider for OpenAi {
+    fn default_model(&self) -> &str {
+        &self.default_model
+    }
+
+    fn chat_request(
+        &self,
+        client: &Client,
+        request: AiChatRequest,
+        stream: bool,
+    ) -> RequestBuilder {
+        let mut messages: Vec<OpenAiMessage> = vec![];
+
+        // Add system prompt if present
+        if let Some(system) = request.system_prompt {
+            messages.push(OpenAiMessage {
+                role: "system".to_string(),
+                content: Some(system),
+                tool_calls: Vec::new(),
+                tool_call_id: None,
+            });
+        }
+
+        // Add user messages
+        messages.extend(request.messages.into_iter().map(|m| {
+            OpenAiMessage {
+                role: m.role,
+                content: (!m.content.is_empty() || m.tool_calls.is_empty()).then_some(m.content),
+                tool_calls: m
+                    .tool_calls
+                    .into_iter()
+                    .map(|call| OpenAiToolCall {
+                        id: call.id,
+                        kind: "function".to_string(),
+                        function: OpenAiFunctionCall {
+                            name: call.name,
+                            arguments: call.arguments.to_string(),
+                        },
+                    })
+                    .collect(),
+                tool_call_id: m.tool_call_id,
+            }
+        }));
+
+        let openai_request = OpenAiRequest {
+            model: request.model.unwrap_or_default(),
+            messages,
+            max_tokens: request.max_tokens,
+            temperature: request.temperature,
+            stream,
+            stream_options: stream.then_some(OpenAiStreamOptions {
+                include_usage: true,
+            }),
+            tools: request
+                .tools
+                .into_iter()
+                .map(|tool| OpenAiTool {
+                    kind: "function",
+                    function: OpenAiFunction {
+                        name: tool.name,
+                        description: tool.description,
+                        parameters: tool.parameters,
+                    },
+                })
+                .collect(),
+        };
+
+        let mut http = client
+            .post(&self.url)
+            .header("content-type", "application/json");
+        // Local servers usually take no key at all
+        if !request.api_key.is_empty() {
+            http = match &self.auth {
+                AuthStyle::Bearer => http.bearer_auth(&request.api_key),
+                AuthStyle::Header { name } => http.header(name.as_str(), &request.api_key),
+                AuthStyle::None => http,
+            };
+        }
+        for (name, value) in &self.extra_headers {
+            http = http.header(name.as_str(), value.as_str());
+        }
+        http.json(&openai_request)
+    }
+
+    fn read_reply(&self, _model: &str, body: &str) -> Result<AiChatResponse, AiError> {
+        let openai_response: OpenAiResponse = parse(body)?;
+        let message = openai_response
+            .choices
+            .into_iter()
+            .next()
+            .map(|c| c.message);
+        let (content, tool_calls) = match message {
+            Some(message) => (message.content, message.tool_calls),
+            None => (None, Vec::new()),
+        };
+
+        Ok(AiChatResponse {
+            content: content.unwrap_or_default(),
+            model: openai_response.model,
+            usage: openai_response.usage.map(|u| TokenUsage {
+                input_tokens: u.prompt_tokens,
+                output_tokens: u.completion_tokens,
+            }),
+            tool_calls: tool_calls
+                .into_iter()
+                .map(|call| ToolCall {
+                    id: call.id,
+                    name: call.function.name,
+                    arguments: parse_arguments(&call.function.arguments),
+                })
+                .collect(),
+        })
+    }
+
+    fn read_chunk(&self, reply: &mut StreamedReply, data: &str) -> Result<Option<String>, AiError> {
+        if data == "[DONE]" {
+            return Ok(None);
+        }
+        let chunk: OpenAiChunk = parse(data)?;
+        if let Some(error) = chunk.error {
+            return Err(error.into());
+        }
+        if let Some(model) = chunk.model {
+            reply.model = model;
+        }
+        if let Some(usage) = chunk.usage {
+            reply.usage = Some(TokenUsage {
+                input_tokens: usage.prompt_tokens,
+                output_tokens: usage.completion_tokens,
+            });
+        }
+        Ok(chunk
+            .choices
+            .into_iter()
+            .next()
+            .and_then(|choice| choice.delta.content))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::test_server::{request, serve};
+    use crate::ai::{send_chat, AiProvider};
+    use std::collections::BTreeMap;
+
+    #[tokio::test]
+    async fn test_profile_sets_url_auth_headers_and_model() {
+        let (url, received) = serve(
+            "application/json",
+            r#"{"model":"llama","choices":[{"message":{"role":"assistant","content":"Hello"}}]}"#,
+        )
+        .await;
+        let profile = ProviderProfile {
+            id: "azure".to_string(),
+            name: "Azure".to_string(),
+            base_url: format!("{}/v1/", url),
+            auth: AuthStyle::Header {
+                name: "api-key".to_string(),
+            },
+            default_model: Some("llama".to_string()),
+            extra_headers: BTreeMap::from([("x-team".to_string(), "plum".to_string())]),
+        };
+        let request = AiChatRequest {
+            provider: AiProvider::Profile("azure".to_string()),
+            api_key: "secret".to_string(),
+            ..request("Hi")
+        };
+
+        let provider = crate::ai::provider_for(&request.provider, &[profile]).unwrap();
+        let response = send_chat(provider.as_ref(), &Client::new(), request)
+            .await
+            .unwrap();
+        assert_eq!(response.content, "Hello");
+        // Usage is optional for compatible servers
+        assert!(response.usage.is_none());
+
+        let received = received.await.unwrap();
+        assert!(received.starts_with("POST /v1/chat/completions "));
+        let head = received.to_lowercase();
+        assert!(head.contains("\r\napi-key: secret\r\n"));
+        assert!(head.contains("\r\nx-team: plum\r\n"));
+        assert!(!head.contains("authorization"));
+        assert!(received.contains(r#""model":"llama""#));
+    }
+}
@@ -183,18 +183,9 @@ pub async fn speech_get_config(app: AppHandle) -> Result<SpeechConfig, String> {
     let db_state: State<'_, crate::database::DbState> = app.state();
     let conn = db_state.0.lock().map_err(|e| e.to_string())?;
 
-    let config_json: Option<String> = conn
-        .query_row(
-            "SELECT value FROM app_settings WHERE key = 'speech_config'",
-            [],
-            |row| row.get(0),
-        )
-        .ok();
-
-    match config_json {
-        Some(json) => serde_json::from_str(&json).map_err(|e| e.to_string()),
-        None => Ok(SpeechConfig::default()),
-    }
+    crate::settings::get::<Option<SpeechConfig>>(&conn, "speech_config")
+        .map(Option::unwrap_or_default)
+        .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
@@ -205,13 +196,12 @@ pub async fn speech_set_config(
     let db_state: State<'_, crate::database::DbState> = app.state();
     let conn = db_state.0.lock().map_err(|e| e.to_string())?;
 
-    let json = serde_json::to_string(&config).map_err(|e| e.to_string())?;
-
-    conn.execute(
-        "INSERT OR REPLACE INTO app_settings (key, value) VALUES ('speech_config', ?1)",
-        [&json],
-    )
-    .map_err(|e| e.to_string())?;
+    let mut changes = serde_json::Map::new();
+    changes.insert(
+        "speech_config".to_string(),
+        serde_json::to_value(&config).map_err(|e| e.to_string())?,
+    );
+    crate::settings::update(&conn, &changes).map_err(|e| e.to_string())?;
 
     Ok(())
 }
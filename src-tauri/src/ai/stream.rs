@@ -0,0 +1,223 @@
+//! Streaming chat replies
+//!
+//! Providers stream over server-sent events, or Ollama's JSON lines. Each
+//! event carries a chunk in the provider's own format, read by its
+//! [`Provider`]; the text in it is handed to the caller as it arrives, and
+//! the usage reported along the way is kept for the final response.
+
+use super::http;
+use super::providers::{resolve_model, Provider};
+use super::{AiChatRequest, AiChatResponse, AiError, TokenUsage};
+use futures::StreamExt;
+use reqwest::Client;
+
+/// Send a chat request, calling `on_delta` with each piece of the reply
+/// as it arrives. Resolves to the whole reply once the stream ends.
+pub async fn stream_chat(
+    provider: &dyn Provider,
+    client: &Client,
+    mut request: AiChatRequest,
+    mut on_delta: impl FnMut(&str),
+) -> Result<AiChatResponse, AiError> {
+    // Replaced by the model the provider reports, if it does
+    let mut reply = StreamedReply {
+        model: resolve_model(provider, &mut request),
+        ..Default::default()
+    };
+
+    let response = http::send(provider, provider.chat_request(client, request, true)).await?;
+
+    let mut parser = ChunkParser::new(provider.framing());
+    let mut body = response.bytes_stream();
+    let mut read = |data: &str| -> Result<(), AiError> {
+        if let Some(delta) = provider.read_chunk(&mut reply, data)? {
+            on_delta(&delta);
+            reply.content.push_str(&delta);
+        }
+        Ok(())
+    };
+    while let Some(bytes) = body.next().await {
+        for data in parser.push(&bytes?) {
+            read(&data)?;
+        }
+    }
+    if let Some(data) = parser.finish() {
+        read(&data)?;
+    }
+
+    Ok(AiChatResponse {
+        content: reply.content,
+        model: reply.model,
+        usage: reply.usage,
+        // Tool calls are only read from whole replies
+        tool_calls: Vec::new(),
+    })
+}
+
+/// How a stream is cut into chunks
+#[derive(Clone, Copy, PartialEq)]
+pub enum Framing {
+    /// Server-sent events, whose `data` is the chunk
+    Sse,
+    /// Newline-delimited JSON, a chunk per line
+    Lines,
+}
+
+/// Splits a byte stream into its chunks
+pub(crate) struct ChunkParser {
+    framing: Framing,
+    buffer: Vec<u8>,
+}
+
+impl ChunkParser {
+    pub(crate) fn new(framing: Framing) -> Self {
+        ChunkParser {
+            framing,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Feed the next bytes; returns every chunk they complete
+    pub(crate) fn push(&mut self, bytes: &[u8]) -> Vec<String> {
+        // Lines may end in CRLF; dropping CRs leaves plain LFs
+        self.buffer.extend(bytes.iter().filter(|b| **b != b'\r'));
+
+        let separator: &[u8] = match self.framing {
+            Framing::Sse => b"\n\n",
+            Framing::Lines => b"\n",
+        };
+        let mut chunks = Vec::new();
+        while let Some(end) = self
+            .buffer
+            .windows(separator.len())
+            .position(|w| w == separator)
+        {
+            let block: Vec<u8> = self.buffer.drain(..end + separator.len()).collect();
+            chunks.extend(self.read_block(&block));
+        }
+        chunks
+    }
+
+    /// The chunk left in the buffer once the stream ends, for servers that
+    /// do not close the last one with a separator
+    pub(crate) fn finish(self) -> Option<String> {
+        self.read_block(&self.buffer)
+    }
+
+    fn read_block(&self, block: &[u8]) -> Option<String> {
+        let block = String::from_utf8_lossy(block);
+        if self.framing == Framing::Lines {
+            let line = block.trim();
+            return (!line.is_empty()).then(|| line.to_string());
+        }
+        let data: Vec<&str> = block
+            .lines()
+            .filter_map(|line| line.strip_prefix("data:"))
+            .map(|data| data.strip_prefix(' ').unwrap_or(data))
+            .collect();
+        // Comments and events without data carry nothing to read
+        (!data.is_empty()).then(|| data.join("\n"))
+    }
+}
+
+/// What has been read of a streamed reply so far
+#[derive(Default)]
+pub struct StreamedReply {
+    pub content: String,
+    pub model: String,
+    pub usage: Option<TokenUsage>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::test_server::{request, serve};
+    use crate::ai::{Claude, Gemini, Ollama, OpenAi, ProviderProfile};
+
+    /// OpenAI itself, served from `url`
+    fn profile(url: &str) -> ProviderProfile {
+        ProviderProfile {
+            id: "local".to_string(),
+            name: "Local".to_string(),
+            base_url: url.to_string(),
+            auth: Default::default(),
+            default_model: None,
+            extra_headers: Default::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_streams_each_provider_format() {
+        // Each provider, pointed at the test server's URL
+        type AtUrl = fn(&str) -> Box<dyn Provider>;
+        let cases: [(AtUrl, _, _); 4] = [
+            (
+                |url| Box::new(Claude::at(url)),
+                concat!(
+                    "event: message_start\n",
+                    "data: {\"type\":\"message_start\",\"message\":{\"model\":\"claude\",\"usage\":{\"input_tokens\":7,\"output_tokens\":1}}}\n\n",
+                    ": keep-alive\n\n",
+                    "event: content_block_delta\n",
+                    "data: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"Once \"}}\n\n",
+                    "event: content_block_delta\n",
+                    "data: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"upon\"}}\n\n",
+                    "event: message_delta\n",
+                    "data: {\"type\":\"message_delta\",\"delta\":{},\"usage\":{\"output_tokens\":3}}\n\n",
+                    "event: message_stop\n",
+                    "data: {\"type\":\"message_stop\"}\n\n",
+                ),
+                "claude",
+            ),
+            (
+                |url| Box::new(OpenAi::from_profile(&profile(url))),
+                concat!(
+                    "data: {\"object\":\"chat.completion.chunk\",\"model\":\"gpt\",\"choices\":[{\"delta\":{\"role\":\"assistant\",\"content\":\"Once \"}}]}\r\n\r\n",
+                    "data: {\"object\":\"chat.completion.chunk\",\"model\":\"gpt\",\"choices\":[{\"delta\":{\"content\":\"upon\"}}]}\r\n\r\n",
+                    "data: {\"object\":\"chat.completion.chunk\",\"model\":\"gpt\",\"choices\":[],\"usage\":{\"prompt_tokens\":7,\"completion_tokens\":3}}\r\n\r\n",
+                    "data: [DONE]\r\n\r\n",
+                ),
+                "gpt",
+            ),
+            (
+                |url| Box::new(Gemini::at(url)),
+                concat!(
+                    "data: {\"candidates\":[{\"content\":{\"parts\":[{\"text\":\"Once \"}]}}],\"usageMetadata\":{\"promptTokenCount\":7,\"candidatesTokenCount\":1}}\n\n",
+                    // The last chunk may end without a separator
+                    "data: {\"candidates\":[{\"content\":{\"parts\":[{\"text\":\"upon\"}]}}],\"usageMetadata\":{\"promptTokenCount\":7,\"candidatesTokenCount\":3}}",
+                ),
+                "test-model",
+            ),
+            (
+                |url| Box::new(Ollama::at(url)),
+                concat!(
+                    "{\"model\":\"llama\",\"message\":{\"role\":\"assistant\",\"content\":\"Once \"},\"done\":false}\n",
+                    "{\"model\":\"llama\",\"message\":{\"role\":\"assistant\",\"content\":\"upon\"},\"done\":false}\n",
+                    "{\"model\":\"llama\",\"message\":{\"role\":\"assistant\",\"content\":\"\"},\"done\":true,\"prompt_eval_count\":7,\"eval_count\":3}",
+                ),
+                "llama",
+            ),
+        ];
+
+        for (provider, body, model) in cases {
+            let (url, _) = serve("text/event-stream", body).await;
+            let mut deltas = Vec::new();
+            let response = stream_chat(
+                provider(&url).as_ref(),
+                &Client::new(),
+                AiChatRequest {
+                    model: Some("test-model".to_string()),
+                    ..request("Hi")
+                },
+                |delta| deltas.push(delta.to_string()),
+            )
+            .await
+            .unwrap();
+
+            assert_eq!(deltas, ["Once ", "upon"]);
+            assert_eq!(response.content, "Once upon");
+            assert_eq!(response.model, model);
+            let usage = response.usage.unwrap();
+            assert_eq!((usage.input_tokens, usage.output_tokens), (7, 3));
+        }
+    }
+}
@@ -0,0 +1,87 @@
+//! One-shot HTTP server standing in for an AI API in tests, and a request
+//! to send it
+
+use crate::ai::{AiChatRequest, AiProvider, ChatMessage};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+
+/// A Claude request with one user message and no model, so the
+/// provider's default is used
+pub fn request(content: &str) -> AiChatRequest {
+    AiChatRequest {
+        provider: AiProvider::Claude,
+        api_key: "key".to_string(),
+        model: None,
+        messages: vec![ChatMessage {
+            role: "user".to_string(),
+            content: content.to_string(),
+            ..Default::default()
+        }],
+        max_tokens: None,
+        temperature: None,
+        system_prompt: None,
+        tools: Vec::new(),
+    }
+}
+
+/// Serve one request, answering with `body` of `content_type` sent in two
+/// pieces. Returns the server's URL and a handle resolving to the request
+/// as received, headers and body.
+pub async fn serve(content_type: &'static str, body: &'static str) -> (String, JoinHandle<String>) {
+    let head = match content_type {
+        "application/json" => "200 OK\r\ncontent-type: application/json",
+        "application/x-ndjson" => "200 OK\r\ncontent-type: application/x-ndjson",
+        _ => "200 OK\r\ncontent-type: text/event-stream",
+    };
+    let (url, requests) = serve_each(vec![(head, body)]).await;
+    let request = tokio::spawn(async move { requests.await.unwrap().remove(0) });
+    (url, request)
+}
+
+/// Serve a request, each on its own connection, per `(head, body)`: the
+/// status line without `HTTP/1.1`, then any headers. Returns the server's
+/// URL and a handle resolving to the requests received.
+pub async fn serve_each(
+    responses: Vec<(&'static str, &'static str)>,
+) -> (String, JoinHandle<Vec<String>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let handle = tokio::spawn(async move {
+        let mut requests = Vec::new();
+        for (head, body) in responses {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 4096];
+            while !is_complete(&request) {
+                let n = socket.read(&mut buf).await.unwrap();
+                assert!(n > 0, "request cut short");
+                request.extend_from_slice(&buf[..n]);
+            }
+            let head = format!("HTTP/1.1 {}\r\nconnection: close\r\n\r\n", head);
+            socket.write_all(head.as_bytes()).await.unwrap();
+            // Split mid-line, so readers must buffer partial chunks
+            let (first, rest) = body.split_at(body.len() / 2);
+            socket.write_all(first.as_bytes()).await.unwrap();
+            socket.flush().await.unwrap();
+            socket.write_all(rest.as_bytes()).await.unwrap();
+            requests.push(String::from_utf8_lossy(&request).into_owned());
+        }
+        requests
+    });
+    (url, handle)
+}
+
+/// Whether the headers and as much body as they announce have arrived
+fn is_complete(request: &[u8]) -> bool {
+    let Some(end) = request.windows(4).position(|w| w == b"\r\n\r\n") else {
+        return false;
+    };
+    let head = String::from_utf8_lossy(&request[..end]).to_lowercase();
+    let length = head
+        .lines()
+        .find_map(|line| line.strip_prefix("content-length:"))
+        .and_then(|value| value.trim().parse::<usize>().ok())
+        .unwrap_or(0);
+    request.len() >= end + 4 + length
+}
@@ -0,0 +1,522 @@
+//! Tools the model can use on the open project
+//!
+//! [`chat_with_tools`] runs the whole exchange: it offers the built-in
+//! tools, runs the calls the model makes against the project database and
+//! sends back their results, until the model answers or the step limit is
+//! reached. Tools that change the project are only run once approved.
+
+use super::providers::{send_chat, Provider};
+use super::{
+    AiChatRequest, AiChatResponse, AiError, ChatMessage, TokenUsage, ToolCall, ToolDefinition,
+};
+use crate::database::{self, AuditSource, DbPool, EntityKind, PoolError, TimelineEvent};
+use futures::channel::oneshot;
+use reqwest::Client;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, PoisonError};
+use thiserror::Error;
+
+/// Replies a chat with tools may take, when the caller sets no limit
+pub const DEFAULT_MAX_STEPS: usize = 8;
+/// Characters of a chapter's text sent to the model
+const MAX_CHAPTER_CHARS: usize = 60_000;
+/// Characters of each lore entry found by a search
+const MAX_LORE_CHARS: usize = 4_000;
+/// Characters and lore entries returned by a lookup
+const MAX_MATCHES: usize = 5;
+
+/// Why a tool call failed; sent to the model as the call's result
+#[derive(Error, Debug)]
+enum ToolError {
+    #[error("Missing argument: {0}")]
+    MissingArgument(&'static str),
+
+    #[error("Not found: {0}")]
+    NotFound(String),
+
+    #[error("Database error: {0}")]
+    Database(#[from] rusqlite::Error),
+
+    #[error(transparent)]
+    Pool(#[from] PoolError),
+}
+
+/// A built-in tool
+struct Tool {
+    name: &'static str,
+    description: &'static str,
+    /// JSON Schema of the arguments
+    parameters: fn() -> Value,
+    /// Changes the project, so runs only once approved
+    writes: bool,
+    run: fn(&DbPool, &str, &Value) -> Result<Value, ToolError>,
+}
+
+const TOOLS: &[Tool] = &[
+    Tool {
+        name: "lookup_character",
+        description: "Find characters of the project by name, with their description, personality and history.",
+        parameters: || {
+            json!({
+                "type": "object",
+                "properties": {
+                    "name": { "type": "string", "description": "Name, or part of it" }
+                },
+                "required": ["name"]
+            })
+        },
+        writes: false,
+        run: lookup_character,
+    },
+    Tool {
+        name: "list_chapters",
+        description: "List the chapters of the project in order, with their ids, status and summaries.",
+        parameters: || json!({ "type": "object", "properties": {} }),
+        writes: false,
+        run: list_chapters,
+    },
+    Tool {
+        name: "read_chapter",
+        description: "Read the full text of a chapter.",
+        parameters: || {
+            json!({
+                "type": "object",
+                "properties": {
+                    "chapter_id": { "type": "string", "description": "Id from list_chapters" }
+                },
+                "required": ["chapter_id"]
+            })
+        },
+        writes: false,
+        run: read_chapter,
+    },
+    Tool {
+        name: "search_lore",
+        description: "Search the project's lore entries for a word or phrase.",
+        parameters: || {
+            json!({
+                "type": "object",
+                "properties": {
+                    "query": { "type": "string" }
+                },
+                "required": ["query"]
+            })
+        },
+        writes: false,
+        run: search_lore,
+    },
+    Tool {
+        name: "propose_timeline_event",
+        description: "Add an event to the project's timeline. The author is asked to approve it first.",
+        parameters: || {
+            json!({
+                "type": "object",
+                "properties": {
+                    "title": { "type": "string" },
+                    "description": { "type": "string" },
+                    "date_mode": { "type": "string", "enum": ["absolute", "relative", "era"] },
+                    "date": { "type": "string" },
+                    "era": { "type": "string" },
+                    "importance": { "type": "string", "enum": ["low", "medium", "high"] },
+                    "participants": {
+                        "type": "array",
+                        "items": { "type": "string" },
+                        "description": "Ids of characters taking part, from lookup_character"
+                    }
+                },
+                "required": ["title"]
+            })
+        },
+        writes: true,
+        run: propose_timeline_event,
+    },
+];
+
+/// Definitions of the built-in tools, to offer the model
+pub fn tool_definitions() -> Vec<ToolDefinition> {
+    TOOLS
+        .iter()
+        .map(|tool| ToolDefinition {
+            name: tool.name.to_string(),
+            description: tool.description.to_string(),
+            parameters: (tool.parameters)(),
+        })
+        .collect()
+}
+
+/// Whether the built-in tool `name` changes the project
+pub fn tool_writes(name: &str) -> bool {
+    TOOLS.iter().any(|tool| tool.name == name && tool.writes)
+}
+
+fn string_arg<'a>(args: &'a Value, name: &'static str) -> Result<&'a str, ToolError> {
+    args[name]
+        .as_str()
+        .filter(|value| !value.trim().is_empty())
+        .ok_or(ToolError::MissingArgument(name))
+}
+
+fn optional_arg(args: &Value, name: &str) -> Option<String> {
+    args[name]
+        .as_str()
+        .filter(|value| !value.trim().is_empty())
+        .map(str::to_string)
+}
+
+/// At most `max` characters of `text`, marked when cut
+fn truncate(text: String, max: usize) -> String {
+    match text.char_indices().nth(max) {
+        Some((end, _)) => format!("{}\n[truncated]", &text[..end]),
+        None => text,
+    }
+}
+
+fn lookup_character(pool: &DbPool, project_id: &str, args: &Value) -> Result<Value, ToolError> {
+    let name = string_arg(args, "name")?.to_lowercase();
+    let conn = pool.read()?;
+    let matches: Vec<Value> = database::get_characters_by_project(&conn, project_id)?
+        .into_iter()
+        .filter(|character| character.name.to_lowercase().contains(&name))
+        .take(MAX_MATCHES)
+        .map(|character| {
+            json!({
+                "id": character.id,
+                "name": character.name,
+                "role": character.role,
+                "physicalDescription": character.physical_description,
+                "personality": character.personality,
+                "history": character.history,
+                "notes": character.notes,
+            })
+        })
+        .collect();
+    if matches.is_empty() {
+        return Err(ToolError::NotFound(format!(
+            "no character named {:?}",
+            name
+        )));
+    }
+    Ok(Value::Array(matches))
+}
+
+fn list_chapters(pool: &DbPool, project_id: &str, _args: &Value) -> Result<Value, ToolError> {
+    let conn = pool.read()?;
+    let chapters = database::get_chapters_by_project(&conn, project_id)?;
+    Ok(chapters
+        .into_iter()
+        .map(|chapter| {
+            json!({
+                "id": chapter.id,
+                "number": chapter.number,
+                "title": chapter.title,
+                "status": chapter.status,
+                "wordCount": chapter.word_count,
+                "summary": chapter.summary,
+            })
+        })
+        .collect())
+}
+
+fn read_chapter(pool: &DbPool, project_id: &str, args: &Value) -> Result<Value, ToolError> {
+    let id = string_arg(args, "chapter_id")?;
+    let conn = pool.read()?;
+    let chapter = database::get_chapter(&conn, id)?
+        .filter(|chapter| chapter.project_id == project_id)
+        .ok_or_else(|| ToolError::NotFound(format!("no chapter with id {:?}", id)))?;
+    Ok(json!({
+        "title": chapter.title,
+        "text": truncate(database::strip_html(&chapter.content), MAX_CHAPTER_CHARS),
+    }))
+}
+
+fn search_lore(pool: &DbPool, project_id: &str, args: &Value) -> Result<Value, ToolError> {
+    let query = string_arg(args, "query")?;
+    let conn = pool.read()?;
+    let hits = database::search(
+        &conn,
+        query,
+        Some(project_id),
+        &[EntityKind::LoreItem],
+        Some(MAX_MATCHES as u32),
+    )?;
+    let mut items: HashMap<String, database::LoreItem> =
+        database::get_lore_items_by_project(&conn, project_id)?
+            .into_iter()
+            .map(|item| (item.id.clone(), item))
+            .collect();
+    // In the order of the hits, most relevant first
+    Ok(hits
+        .iter()
+        .filter_map(|hit| items.remove(&hit.entity_id))
+        .map(|item| {
+            json!({
+                "id": item.id,
+                "title": item.title,
+                "category": item.category,
+                "summary": item.summary,
+                "content": truncate(database::strip_html(&item.content), MAX_LORE_CHARS),
+            })
+        })
+        .collect())
+}
+
+fn propose_timeline_event(
+    pool: &DbPool,
+    project_id: &str,
+    args: &Value,
+) -> Result<Value, ToolError> {
+    let conn = pool.lock().map_err(|_| PoolError::Poisoned)?;
+    let characters: Vec<String> = database::get_characters_by_project(&conn, project_id)?
+        .into_iter()
+        .map(|character| character.id)
+        .collect();
+    let choice = |name: &str, options: &[&str], default: &str| {
+        optional_arg(args, name)
+            .filter(|value| options.contains(&value.as_str()))
+            .unwrap_or_else(|| default.to_string())
+    };
+
+    let event = TimelineEvent {
+        id: uuid::Uuid::new_v4().to_string(),
+        project_id: project_id.to_string(),
+        title: string_arg(args, "title")?.to_string(),
+        description: optional_arg(args, "description"),
+        date_mode: choice("date_mode", &["absolute", "relative", "era"], "absolute"),
+        date: optional_arg(args, "date"),
+        era: optional_arg(args, "era"),
+        // Ids the model made up are left out
+        participants: args["participants"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+            .filter(|id| characters.iter().any(|character| character == id))
+            .map(str::to_string)
+            .collect(),
+        location_id: None,
+        importance: choice("importance", &["low", "medium", "high"], "medium"),
+        tags: Vec::new(),
+        scene_id: None,
+        chapter_id: None,
+    };
+    database::undoable(&conn, "Add timeline event", || {
+        database::with_audit_source(AuditSource::Ai, || {
+            database::create_timeline_event(&conn, &event)
+        })
+    })?;
+    Ok(json!({ "id": event.id, "created": true }))
+}
+
+/// Run a tool call, returning what to tell the model
+fn run_tool(pool: &DbPool, project_id: &str, call: &ToolCall) -> String {
+    let Some(tool) = TOOLS.iter().find(|tool| tool.name == call.name) else {
+        return format!("Error: unknown tool {:?}", call.name);
+    };
+    match (tool.run)(pool, project_id, &call.arguments) {
+        Ok(result) => result.to_string(),
+        Err(e) => format!("Error: {}", e),
+    }
+}
+
+/// The answer to a chat with tools
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolChatResponse {
+    /// The final reply, with the usage of every step added up
+    #[serde(flatten)]
+    pub response: AiChatResponse,
+    /// Tool calls and their results, exchanged before the reply; they
+    /// belong in the conversation's history
+    pub messages: Vec<ChatMessage>,
+}
+
+/// Chat with the built-in tools on the project `project_id`, running the
+/// calls the model makes until it answers. Calls to tools that write go
+/// through `approve` first; a declined call is reported to the model. Fails
+/// with [`AiError::ToolLimit`] if there is no answer after `max_steps`
+/// replies.
+pub async fn chat_with_tools<F>(
+    provider: &dyn Provider,
+    client: &Client,
+    pool: Arc<DbPool>,
+    project_id: &str,
+    mut request: AiChatRequest,
+    max_steps: usize,
+    mut approve: impl FnMut(&ToolCall) -> F,
+) -> Result<ToolChatResponse, AiError>
+where
+    F: Future<Output = bool>,
+{
+    request.tools = tool_definitions();
+    let mut messages = Vec::new();
+    let mut usage: Option<TokenUsage> = None;
+
+    for _ in 0..max_steps {
+        let mut response = send_chat(provider, client, request.clone()).await?;
+        if let Some(step) = &response.usage {
+            let total = usage.get_or_insert(TokenUsage {
+                input_tokens: 0,
+                output_tokens: 0,
+            });
+            total.input_tokens += step.input_tokens;
+            total.output_tokens += step.output_tokens;
+        }
+        if response.tool_calls.is_empty() {
+            response.usage = usage;
+            return Ok(ToolChatResponse { response, messages });
+        }
+
+        let calls = std::mem::take(&mut response.tool_calls);
+        let mut step = vec![ChatMessage {
+            role: "assistant".to_string(),
+            content: response.content,
+            tool_calls: calls.clone(),
+            tool_call_id: None,
+        }];
+        for call in calls {
+            let result = if tool_writes(&call.name) && !approve(&call).await {
+                "The author declined this change.".to_string()
+            } else {
+                // Tools wait on database locks, which would stall the runtime
+                let (pool, project_id, call) =
+                    (Arc::clone(&pool), project_id.to_string(), call.clone());
+                tokio::task::spawn_blocking(move || run_tool(&pool, &project_id, &call))
+                    .await
+                    .unwrap_or_else(|e| format!("Error: {}", e))
+            };
+            step.push(ChatMessage {
+                role: "tool".to_string(),
+                content: result,
+                tool_calls: Vec::new(),
+                tool_call_id: Some(call.id),
+            });
+        }
+        request.messages.extend(step.iter().cloned());
+        messages.extend(step);
+    }
+    Err(AiError::ToolLimit(max_steps))
+}
+
+/// Approvals being waited for, by request and call id
+fn pending() -> MutexGuard<'static, HashMap<String, oneshot::Sender<bool>>> {
+    static PENDING: OnceLock<Mutex<HashMap<String, oneshot::Sender<bool>>>> = OnceLock::new();
+    PENDING
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+}
+
+fn approval_key(request_id: &str, call_id: &str) -> String {
+    format!("{}/{}", request_id, call_id)
+}
+
+/// Stops waiting for an approval once it is no longer wanted
+struct Waiting(String);
+
+impl Drop for Waiting {
+    fn drop(&mut self) {
+        pending().remove(&self.0);
+    }
+}
+
+/// Wait for [`resolve_approval`] on call `call_id` of request `request_id`.
+/// The wait starts right away, so the question can be asked afterwards.
+pub fn await_approval(request_id: &str, call_id: &str) -> impl Future<Output = bool> {
+    let key = approval_key(request_id, call_id);
+    let (sender, receiver) = oneshot::channel();
+    pending().insert(key.clone(), sender);
+    let waiting = Waiting(key);
+    async move {
+        let approved = receiver.await.unwrap_or(false);
+        drop(waiting);
+        approved
+    }
+}
+
+/// Approve or decline a call; false if it is not waiting for approval
+pub fn resolve_approval(request_id: &str, call_id: &str, approved: bool) -> bool {
+    match pending().remove(&approval_key(request_id, call_id)) {
+        Some(sender) => sender.send(approved).is_ok(),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::test_server::{request, serve_each};
+    use crate::ai::Claude;
+    use crate::database::test_support::project_db;
+
+    fn pool() -> DbPool {
+        let conn = project_db();
+        let character = serde_json::from_value(json!({
+            "id": "ch1",
+            "projectId": "p1",
+            "name": "Mara Vell",
+            "personality": "Stubborn",
+        }))
+        .unwrap();
+        database::create_character(&conn, &character).unwrap();
+        DbPool::single(conn)
+    }
+
+    #[tokio::test]
+    async fn test_runs_tool_calls_until_the_model_answers() {
+        let (url, requests) = serve_each(vec![
+            (
+                "200 OK\r\ncontent-type: application/json",
+                r#"{"content":[{"type":"tool_use","id":"t1","name":"lookup_character","input":{"name":"mara"}}],"model":"claude","usage":{"input_tokens":10,"output_tokens":5}}"#,
+            ),
+            (
+                "200 OK\r\ncontent-type: application/json",
+                r#"{"content":[{"type":"text","text":"Adding it."},{"type":"tool_use","id":"t2","name":"propose_timeline_event","input":{"title":"Mara leaves home","participants":["ch1","ghost"]}}],"model":"claude","usage":{"input_tokens":20,"output_tokens":5}}"#,
+            ),
+            (
+                "200 OK\r\ncontent-type: application/json",
+                r#"{"content":[{"type":"text","text":"Done."}],"model":"claude","usage":{"input_tokens":30,"output_tokens":2}}"#,
+            ),
+        ])
+        .await;
+        let pool = Arc::new(pool());
+        let request = request("When did Mara leave home?");
+
+        let mut asked = Vec::new();
+        let chat = chat_with_tools(
+            &Claude::at(&url),
+            &Client::new(),
+            Arc::clone(&pool),
+            "p1",
+            request,
+            DEFAULT_MAX_STEPS,
+            |call| {
+                asked.push(call.name.clone());
+                async { true }
+            },
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(chat.response.content, "Done.");
+        let usage = chat.response.usage.unwrap();
+        assert_eq!((usage.input_tokens, usage.output_tokens), (60, 12));
+        // Only the write asked for approval
+        assert_eq!(asked, ["propose_timeline_event"]);
+        assert_eq!(chat.messages.len(), 4);
+        assert!(chat.messages[1].content.contains("Stubborn"));
+
+        // Results went back as tool results, with the tools offered each time
+        let requests = requests.await.unwrap();
+        assert!(requests[0].contains(r#""name":"read_chapter""#));
+        assert!(requests[1].contains(r#""type":"tool_result","tool_use_id":"t1""#));
+
+        let conn = pool.read().unwrap();
+        let events = database::get_timeline_events_by_project(&conn, "p1").unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].title, "Mara leaves home");
+        assert_eq!(events[0].participants, ["ch1"]);
+    }
+}
@@ -0,0 +1,262 @@
+use crate::ai::{
+    self, AiChatRequest, AiChatResponse, AiError, AiProvider, Ollama, OllamaModel, Provider,
+    ProviderProfile, Timeouts, TokenUsage, ToolCall, ToolChatResponse,
+};
+use crate::database::DbConn;
+use crate::settings;
+use reqwest::Client;
+use rusqlite::Connection;
+use serde::Serialize;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tauri::{command, AppHandle, Emitter};
+
+/// Event carrying the next piece of a streamed reply
+const AI_STREAM_DELTA: &str = "ai-stream-delta";
+/// Event sent once a streamed reply has ended, successfully or not
+const AI_STREAM_DONE: &str = "ai-stream-done";
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AiStreamDelta<'a> {
+    request_id: &'a str,
+    delta: &'a str,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AiStreamDone<'a> {
+    request_id: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    model: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    usage: Option<&'a TokenUsage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'a AiError>,
+}
+
+/// The provider a request names, looking up profiles in the settings, and
+/// a client with the configured timeouts
+fn connect(db: &DbConn<'_>, provider: &AiProvider) -> Result<(Box<dyn Provider>, Client), AiError> {
+    let conn = db.0.read().map_err(|e| AiError::Config(e.to_string()))?;
+    let profiles = match provider {
+        AiProvider::Profile(_) => {
+            settings::get::<Vec<ProviderProfile>>(&conn, "ai_provider_profiles")
+                .map_err(|e| AiError::Config(e.to_string()))?
+        }
+        _ => Vec::new(),
+    };
+    let client = ai::client(timeouts(&conn)?);
+    Ok((ai::provider_for(provider, &profiles)?, client))
+}
+
+fn timeouts(conn: &Connection) -> Result<Timeouts, AiError> {
+    let seconds = |key| {
+        settings::get::<u64>(conn, key)
+            .map(Duration::from_secs)
+            .map_err(|e| AiError::Config(e.to_string()))
+    };
+    Ok(Timeouts {
+        connect: seconds("ai_connect_timeout")?,
+        read: seconds("ai_read_timeout")?,
+    })
+}
+
+/// Run `future`, cancellable with `ai_cancel` when given a `request_id`
+async fn run<T>(
+    request_id: Option<&str>,
+    future: impl Future<Output = Result<T, AiError>>,
+) -> Result<T, AiError> {
+    match request_id {
+        Some(request_id) => ai::cancellable(request_id, future).await,
+        None => future.await,
+    }
+}
+
+/// Send a chat request and wait for the whole reply. With a `request_id`,
+/// the request can be cancelled.
+#[command]
+pub async fn ai_chat(
+    db: DbConn<'_>,
+    request: AiChatRequest,
+    request_id: Option<String>,
+) -> Result<AiChatResponse, AiError> {
+    let (provider, client) = connect(&db, &request.provider)?;
+    run(
+        request_id.as_deref(),
+        ai::send_chat(provider.as_ref(), &client, request),
+    )
+    .await
+}
+
+/// Like `ai_chat`, but sends the reply as it is generated: `ai-stream-delta`
+/// events carry its pieces, then `ai-stream-done` the model and usage.
+/// Both are tagged with `request_id`, chosen by the caller, which also
+/// cancels the request.
+#[command]
+pub async fn ai_chat_stream(
+    app: AppHandle,
+    db: DbConn<'_>,
+    request_id: String,
+    request: AiChatRequest,
+) -> Result<AiChatResponse, AiError> {
+    let result = match connect(&db, &request.provider) {
+        Ok((provider, client)) => {
+            let stream = ai::stream_chat(provider.as_ref(), &client, request, |delta| {
+                app.emit(
+                    AI_STREAM_DELTA,
+                    AiStreamDelta {
+                        request_id: &request_id,
+                        delta,
+                    },
+                )
+                .unwrap_or_default();
+            });
+            ai::cancellable(&request_id, stream).await
+        }
+        // Reported like any other failure, so listeners still hear the end
+        Err(e) => Err(e),
+    };
+
+    let done = match &result {
+        Ok(response) => AiStreamDone {
+            request_id: &request_id,
+            model: Some(&response.model),
+            usage: response.usage.as_ref(),
+            error: None,
+        },
+        Err(e) => AiStreamDone {
+            request_id: &request_id,
+            model: None,
+            usage: None,
+            error: Some(e),
+        },
+    };
+    app.emit(AI_STREAM_DONE, done).unwrap_or_default();
+    result
+}
+
+/// Event asking the user to approve a tool call that changes the project
+const AI_TOOL_APPROVAL: &str = "ai-tool-approval";
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AiToolApproval<'a> {
+    request_id: &'a str,
+    call: &'a ToolCall,
+}
+
+/// Chat with the built-in project tools, the backend running the calls the
+/// model makes until it answers, for at most `max_steps` replies. Unless
+/// `approve_writes` is false, each call that would change the project is
+/// first sent in an `ai-tool-approval` event and waits for
+/// `ai_resolve_tool_call`. Cancelled with `request_id`.
+#[command]
+pub async fn ai_chat_with_tools(
+    app: AppHandle,
+    db: DbConn<'_>,
+    request_id: String,
+    project_id: String,
+    request: AiChatRequest,
+    max_steps: Option<usize>,
+    approve_writes: Option<bool>,
+) -> Result<ToolChatResponse, AiError> {
+    let (provider, client) = connect(&db, &request.provider)?;
+    let approve_writes = approve_writes.unwrap_or(true);
+    let chat = ai::chat_with_tools(
+        provider.as_ref(),
+        &client,
+        Arc::clone(&db.0),
+        &project_id,
+        request,
+        max_steps.unwrap_or(ai::DEFAULT_MAX_STEPS),
+        |call| {
+            let approval = approve_writes.then(|| {
+                let approval = ai::await_approval(&request_id, &call.id);
+                app.emit(
+                    AI_TOOL_APPROVAL,
+                    AiToolApproval {
+                        request_id: &request_id,
+                        call,
+                    },
+                )
+                .unwrap_or_default();
+                approval
+            });
+            async move {
+                match approval {
+                    Some(approval) => approval.await,
+                    None => true,
+                }
+            }
+        },
+    );
+    ai::cancellable(&request_id, chat).await
+}
+
+/// Answer an `ai-tool-approval` event; false if the call is not waiting
+#[command]
+pub fn ai_resolve_tool_call(request_id: String, call_id: String, approved: bool) -> bool {
+    ai::resolve_approval(&request_id, &call_id, approved)
+}
+
+/// Abort the AI request started with `request_id`; false if none is running
+#[command]
+pub fn ai_cancel(request_id: String) -> bool {
+    ai::cancel(&request_id)
+}
+
+/// Event reporting each step of an Ollama model pull
+const OLLAMA_PULL_PROGRESS: &str = "ollama-pull-progress";
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct OllamaPullProgress<'a> {
+    model: &'a str,
+    status: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    digest: Option<&'a str>,
+    completed_bytes: u64,
+    total_bytes: u64,
+    percent: f64,
+}
+
+#[command]
+pub async fn ollama_list_models(db: DbConn<'_>) -> Result<Vec<OllamaModel>, AiError> {
+    let (_, client) = connect(&db, &AiProvider::Ollama)?;
+    Ollama::default().list_models(&client).await
+}
+
+/// Pull a model into Ollama, sending `ollama-pull-progress` events as it
+/// downloads. With a `request_id`, the pull can be cancelled.
+#[command]
+pub async fn ollama_pull_model(
+    app: AppHandle,
+    db: DbConn<'_>,
+    model: String,
+    request_id: Option<String>,
+) -> Result<(), AiError> {
+    let (_, client) = connect(&db, &AiProvider::Ollama)?;
+    let ollama = Ollama::default();
+    let pull = ollama.pull_model(&client, &model, |progress| {
+        let percent = if progress.total > 0 {
+            (progress.completed as f64 / progress.total as f64) * 100.0
+        } else {
+            0.0
+        };
+        app.emit(
+            OLLAMA_PULL_PROGRESS,
+            OllamaPullProgress {
+                model: &model,
+                status: &progress.status,
+                digest: progress.digest.as_deref(),
+                completed_bytes: progress.completed,
+                total_bytes: progress.total,
+                percent,
+            },
+        )
+        .unwrap_or_default();
+    });
+    run(request_id.as_deref(), pull).await
+}
@@ -4,18 +4,21 @@
 
 use serde::{Deserialize, Serialize};
 
-use crate::ai::{self, AiChatRequest, AiChatResponse};
 use crate::crypto::{self, EncryptedData};
 use crate::database::{self, DbConn};
 use crate::filesystem::{self, ProjectData};
 use crate::publishing::{self, DocxOptions, ExportDocument, PdfOptions};
 use crate::workspace::{self, WorkspaceState};
 use std::path::PathBuf;
-use tauri::State;
+use tauri::{AppHandle, Emitter, State};
 
+pub mod ai;
 pub mod packages;
+pub mod settings;
 
+pub use ai::*;
 pub use packages::*;
+pub use settings::*;
 
 // ============================================================================
 // App Info Commands
@@ -59,26 +62,43 @@ pub fn db_create_project(db: DbConn<'_>, project: database::Project) -> Result<(
 
 #[tauri::command]
 pub fn db_get_project(db: DbConn<'_>, id: String) -> Result<Option<database::Project>, String> {
-    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    let conn = db.0.read().map_err(|e| e.to_string())?;
     database::get_project(&conn, &id).map_err(|e| e.to_string())
 }
 
 #[tauri::command]
 pub fn db_get_all_projects(db: DbConn<'_>) -> Result<Vec<database::Project>, String> {
-    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    let conn = db.0.read().map_err(|e| e.to_string())?;
     database::get_all_projects(&conn).map_err(|e| e.to_string())
 }
 
 #[tauri::command]
 pub fn db_update_project(db: DbConn<'_>, project: database::Project) -> Result<(), String> {
     let conn = db.0.lock().map_err(|e| e.to_string())?;
-    database::update_project(&conn, &project).map_err(|e| e.to_string())
+    database::undoable(&conn, "Edit project", || {
+        database::update_project(&conn, &project)
+    })
+    .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
 pub fn db_delete_project(db: DbConn<'_>, id: String) -> Result<(), String> {
     let conn = db.0.lock().map_err(|e| e.to_string())?;
-    database::delete_project(&conn, &id).map_err(|e| e.to_string())
+    database::undoable(&conn, "Delete project", || {
+        database::delete_project(&conn, &id)
+    })
+    .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn db_duplicate_project(
+    db: DbConn<'_>,
+    project_id: String,
+    options: Option<database::DuplicateOptions>,
+) -> Result<database::Project, String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    database::duplicate_project(&conn, &project_id, &options.unwrap_or_default())
+        .map_err(|e| e.to_string())
 }
 
 // ============================================================================
@@ -88,7 +108,10 @@ pub fn db_delete_project(db: DbConn<'_>, id: String) -> Result<(), String> {
 #[tauri::command]
 pub fn db_create_chapter(db: DbConn<'_>, chapter: database::Chapter) -> Result<(), String> {
     let conn = db.0.lock().map_err(|e| e.to_string())?;
-    database::create_chapter(&conn, &chapter).map_err(|e| e.to_string())
+    database::undoable(&conn, "Add chapter", || {
+        database::create_chapter(&conn, &chapter)
+    })
+    .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
@@ -96,20 +119,26 @@ pub fn db_get_chapters_by_project(
     db: DbConn<'_>,
     project_id: String,
 ) -> Result<Vec<database::Chapter>, String> {
-    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    let conn = db.0.read().map_err(|e| e.to_string())?;
     database::get_chapters_by_project(&conn, &project_id).map_err(|e| e.to_string())
 }
 
 #[tauri::command]
 pub fn db_update_chapter(db: DbConn<'_>, chapter: database::Chapter) -> Result<(), String> {
     let conn = db.0.lock().map_err(|e| e.to_string())?;
-    database::update_chapter(&conn, &chapter).map_err(|e| e.to_string())
+    database::undoable(&conn, "Edit chapter", || {
+        database::update_chapter(&conn, &chapter)
+    })
+    .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
 pub fn db_delete_chapter(db: DbConn<'_>, id: String) -> Result<(), String> {
     let conn = db.0.lock().map_err(|e| e.to_string())?;
-    database::delete_chapter(&conn, &id).map_err(|e| e.to_string())
+    database::undoable(&conn, "Delete chapter", || {
+        database::delete_chapter(&conn, &id)
+    })
+    .map_err(|e| e.to_string())
 }
 
 // ============================================================================
@@ -119,7 +148,8 @@ pub fn db_delete_chapter(db: DbConn<'_>, id: String) -> Result<(), String> {
 #[tauri::command]
 pub fn db_create_scene(db: DbConn<'_>, scene: database::Scene) -> Result<(), String> {
     let conn = db.0.lock().map_err(|e| e.to_string())?;
-    database::create_scene(&conn, &scene).map_err(|e| e.to_string())
+    database::undoable(&conn, "Add scene", || database::create_scene(&conn, &scene))
+        .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
@@ -127,20 +157,103 @@ pub fn db_get_scenes_by_chapter(
     db: DbConn<'_>,
     chapter_id: String,
 ) -> Result<Vec<database::Scene>, String> {
-    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    let conn = db.0.read().map_err(|e| e.to_string())?;
     database::get_scenes_by_chapter(&conn, &chapter_id).map_err(|e| e.to_string())
 }
 
 #[tauri::command]
 pub fn db_update_scene(db: DbConn<'_>, scene: database::Scene) -> Result<(), String> {
     let conn = db.0.lock().map_err(|e| e.to_string())?;
-    database::update_scene(&conn, &scene).map_err(|e| e.to_string())
+    database::undoable(&conn, "Edit scene", || {
+        database::update_scene(&conn, &scene)
+    })
+    .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
 pub fn db_delete_scene(db: DbConn<'_>, id: String) -> Result<(), String> {
     let conn = db.0.lock().map_err(|e| e.to_string())?;
-    database::delete_scene(&conn, &id).map_err(|e| e.to_string())
+    database::undoable(&conn, "Delete scene", || database::delete_scene(&conn, &id))
+        .map_err(|e| e.to_string())
+}
+
+// ============================================================================
+// Database Commands - Ordering
+// ============================================================================
+
+#[tauri::command]
+pub fn db_reorder_chapters(
+    db: DbConn<'_>,
+    project_id: String,
+    ordered_ids: Vec<String>,
+) -> Result<Vec<database::Chapter>, String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    database::undoable(&conn, "Reorder chapters", || {
+        database::reorder_chapters(&conn, &project_id, &ordered_ids)
+    })
+    .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn db_reorder_scenes(
+    db: DbConn<'_>,
+    chapter_id: String,
+    ordered_ids: Vec<String>,
+) -> Result<Vec<database::Scene>, String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    database::undoable(&conn, "Reorder scenes", || {
+        database::reorder_scenes(&conn, &chapter_id, &ordered_ids)
+    })
+    .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn db_move_scene(
+    db: DbConn<'_>,
+    scene_id: String,
+    target_chapter_id: String,
+    position: Option<usize>,
+) -> Result<database::SceneMove, String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    database::undoable(&conn, "Move scene", || {
+        database::move_scene(&conn, &scene_id, &target_chapter_id, position)
+    })
+    .map_err(|e| e.to_string())
+}
+
+// ============================================================================
+// Database Commands - Chapter Assembly
+// ============================================================================
+
+#[tauri::command]
+pub fn db_set_chapter_content_mode(
+    db: DbConn<'_>,
+    chapter_id: String,
+    mode: database::ContentMode,
+) -> Result<database::Chapter, String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    database::undoable(&conn, "Change chapter content mode", || {
+        database::set_chapter_content_mode(&conn, &chapter_id, mode)
+    })
+    .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn db_compile_chapter(db: DbConn<'_>, chapter_id: String) -> Result<String, String> {
+    let conn = db.0.read().map_err(|e| e.to_string())?;
+    database::compile_chapter(&conn, &chapter_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn db_split_chapter_into_scenes(
+    db: DbConn<'_>,
+    chapter_id: String,
+) -> Result<database::ChapterSplit, String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    database::undoable(&conn, "Split chapter into scenes", || {
+        database::split_chapter_into_scenes(&conn, &chapter_id)
+    })
+    .map_err(|e| e.to_string())
 }
 
 // ============================================================================
@@ -150,7 +263,10 @@ pub fn db_delete_scene(db: DbConn<'_>, id: String) -> Result<(), String> {
 #[tauri::command]
 pub fn db_create_character(db: DbConn<'_>, character: database::Character) -> Result<(), String> {
     let conn = db.0.lock().map_err(|e| e.to_string())?;
-    database::create_character(&conn, &character).map_err(|e| e.to_string())
+    database::undoable(&conn, "Add character", || {
+        database::create_character(&conn, &character)
+    })
+    .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
@@ -158,20 +274,26 @@ pub fn db_get_characters_by_project(
     db: DbConn<'_>,
     project_id: String,
 ) -> Result<Vec<database::Character>, String> {
-    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    let conn = db.0.read().map_err(|e| e.to_string())?;
     database::get_characters_by_project(&conn, &project_id).map_err(|e| e.to_string())
 }
 
 #[tauri::command]
 pub fn db_update_character(db: DbConn<'_>, character: database::Character) -> Result<(), String> {
     let conn = db.0.lock().map_err(|e| e.to_string())?;
-    database::update_character(&conn, &character).map_err(|e| e.to_string())
+    database::undoable(&conn, "Edit character", || {
+        database::update_character(&conn, &character)
+    })
+    .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
 pub fn db_delete_character(db: DbConn<'_>, id: String) -> Result<(), String> {
     let conn = db.0.lock().map_err(|e| e.to_string())?;
-    database::delete_character(&conn, &id).map_err(|e| e.to_string())
+    database::undoable(&conn, "Delete character", || {
+        database::delete_character(&conn, &id)
+    })
+    .map_err(|e| e.to_string())
 }
 
 // ============================================================================
@@ -185,7 +307,10 @@ pub fn db_create_relationship(
     relationship: database::Relationship,
 ) -> Result<(), String> {
     let conn = db.0.lock().map_err(|e| e.to_string())?;
-    database::create_relationship(&conn, &character_id, &relationship).map_err(|e| e.to_string())
+    database::undoable(&conn, "Add relationship", || {
+        database::create_relationship(&conn, &character_id, &relationship)
+    })
+    .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
@@ -193,7 +318,7 @@ pub fn db_get_relationships_by_character(
     db: DbConn<'_>,
     character_id: String,
 ) -> Result<Vec<database::Relationship>, String> {
-    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    let conn = db.0.read().map_err(|e| e.to_string())?;
     database::get_relationships_by_character(&conn, &character_id).map_err(|e| e.to_string())
 }
 
@@ -204,13 +329,19 @@ pub fn db_update_relationship(
     relationship: database::Relationship,
 ) -> Result<(), String> {
     let conn = db.0.lock().map_err(|e| e.to_string())?;
-    database::update_relationship(&conn, &character_id, &relationship).map_err(|e| e.to_string())
+    database::undoable(&conn, "Edit relationship", || {
+        database::update_relationship(&conn, &character_id, &relationship)
+    })
+    .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
 pub fn db_delete_relationship(db: DbConn<'_>, id: String) -> Result<(), String> {
     let conn = db.0.lock().map_err(|e| e.to_string())?;
-    database::delete_relationship(&conn, &id).map_err(|e| e.to_string())
+    database::undoable(&conn, "Delete relationship", || {
+        database::delete_relationship(&conn, &id)
+    })
+    .map_err(|e| e.to_string())
 }
 
 // ============================================================================
@@ -220,7 +351,10 @@ pub fn db_delete_relationship(db: DbConn<'_>, id: String) -> Result<(), String>
 #[tauri::command]
 pub fn db_create_location(db: DbConn<'_>, location: database::Location) -> Result<(), String> {
     let conn = db.0.lock().map_err(|e| e.to_string())?;
-    database::create_location(&conn, &location).map_err(|e| e.to_string())
+    database::undoable(&conn, "Add location", || {
+        database::create_location(&conn, &location)
+    })
+    .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
@@ -228,20 +362,26 @@ pub fn db_get_locations_by_project(
     db: DbConn<'_>,
     project_id: String,
 ) -> Result<Vec<database::Location>, String> {
-    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    let conn = db.0.read().map_err(|e| e.to_string())?;
     database::get_locations_by_project(&conn, &project_id).map_err(|e| e.to_string())
 }
 
 #[tauri::command]
 pub fn db_update_location(db: DbConn<'_>, location: database::Location) -> Result<(), String> {
     let conn = db.0.lock().map_err(|e| e.to_string())?;
-    database::update_location(&conn, &location).map_err(|e| e.to_string())
+    database::undoable(&conn, "Edit location", || {
+        database::update_location(&conn, &location)
+    })
+    .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
 pub fn db_delete_location(db: DbConn<'_>, id: String) -> Result<(), String> {
     let conn = db.0.lock().map_err(|e| e.to_string())?;
-    database::delete_location(&conn, &id).map_err(|e| e.to_string())
+    database::undoable(&conn, "Delete location", || {
+        database::delete_location(&conn, &id)
+    })
+    .map_err(|e| e.to_string())
 }
 
 // ============================================================================
@@ -251,7 +391,10 @@ pub fn db_delete_location(db: DbConn<'_>, id: String) -> Result<(), String> {
 #[tauri::command]
 pub fn db_create_lore_item(db: DbConn<'_>, item: database::LoreItem) -> Result<(), String> {
     let conn = db.0.lock().map_err(|e| e.to_string())?;
-    database::create_lore_item(&conn, &item).map_err(|e| e.to_string())
+    database::undoable(&conn, "Add lore item", || {
+        database::create_lore_item(&conn, &item)
+    })
+    .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
@@ -259,20 +402,26 @@ pub fn db_get_lore_items_by_project(
     db: DbConn<'_>,
     project_id: String,
 ) -> Result<Vec<database::LoreItem>, String> {
-    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    let conn = db.0.read().map_err(|e| e.to_string())?;
     database::get_lore_items_by_project(&conn, &project_id).map_err(|e| e.to_string())
 }
 
 #[tauri::command]
 pub fn db_update_lore_item(db: DbConn<'_>, item: database::LoreItem) -> Result<(), String> {
     let conn = db.0.lock().map_err(|e| e.to_string())?;
-    database::update_lore_item(&conn, &item).map_err(|e| e.to_string())
+    database::undoable(&conn, "Edit lore item", || {
+        database::update_lore_item(&conn, &item)
+    })
+    .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
 pub fn db_delete_lore_item(db: DbConn<'_>, id: String) -> Result<(), String> {
     let conn = db.0.lock().map_err(|e| e.to_string())?;
-    database::delete_lore_item(&conn, &id).map_err(|e| e.to_string())
+    database::undoable(&conn, "Delete lore item", || {
+        database::delete_lore_item(&conn, &id)
+    })
+    .map_err(|e| e.to_string())
 }
 
 // ============================================================================
@@ -285,7 +434,10 @@ pub fn db_create_timeline_event(
     event: database::TimelineEvent,
 ) -> Result<(), String> {
     let conn = db.0.lock().map_err(|e| e.to_string())?;
-    database::create_timeline_event(&conn, &event).map_err(|e| e.to_string())
+    database::undoable(&conn, "Add timeline event", || {
+        database::create_timeline_event(&conn, &event)
+    })
+    .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
@@ -293,7 +445,7 @@ pub fn db_get_timeline_events_by_project(
     db: DbConn<'_>,
     project_id: String,
 ) -> Result<Vec<database::TimelineEvent>, String> {
-    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    let conn = db.0.read().map_err(|e| e.to_string())?;
     database::get_timeline_events_by_project(&conn, &project_id).map_err(|e| e.to_string())
 }
 
@@ -303,40 +455,734 @@ pub fn db_update_timeline_event(
     event: database::TimelineEvent,
 ) -> Result<(), String> {
     let conn = db.0.lock().map_err(|e| e.to_string())?;
-    database::update_timeline_event(&conn, &event).map_err(|e| e.to_string())
+    database::undoable(&conn, "Edit timeline event", || {
+        database::update_timeline_event(&conn, &event)
+    })
+    .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
 pub fn db_delete_timeline_event(db: DbConn<'_>, id: String) -> Result<(), String> {
     let conn = db.0.lock().map_err(|e| e.to_string())?;
-    database::delete_timeline_event(&conn, &id).map_err(|e| e.to_string())
+    database::undoable(&conn, "Delete timeline event", || {
+        database::delete_timeline_event(&conn, &id)
+    })
+    .map_err(|e| e.to_string())
 }
 
+// ============================================================================
+// Database Commands - Creatures
+// ============================================================================
+
 #[tauri::command]
-pub fn db_clear_all_data(db: DbConn<'_>) -> Result<(), String> {
+pub fn db_create_creature(db: DbConn<'_>, creature: database::Creature) -> Result<(), String> {
     let conn = db.0.lock().map_err(|e| e.to_string())?;
-    database::clear_database(&conn).map_err(|e| e.to_string())
+    database::undoable(&conn, "Add creature", || {
+        database::create_creature(&conn, &creature)
+    })
+    .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub fn db_get_setting(db: DbConn<'_>, key: String) -> Result<Option<String>, String> {
+pub fn db_get_creatures_by_project(
+    db: DbConn<'_>,
+    project_id: String,
+) -> Result<Vec<database::Creature>, String> {
+    let conn = db.0.read().map_err(|e| e.to_string())?;
+    database::get_creatures_by_project(&conn, &project_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn db_update_creature(db: DbConn<'_>, creature: database::Creature) -> Result<(), String> {
     let conn = db.0.lock().map_err(|e| e.to_string())?;
-    database::get_setting(&conn, &key).map_err(|e| e.to_string())
+    database::undoable(&conn, "Edit creature", || {
+        database::update_creature(&conn, &creature)
+    })
+    .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub fn db_set_setting(db: DbConn<'_>, key: String, value: String) -> Result<(), String> {
+pub fn db_delete_creature(db: DbConn<'_>, id: String) -> Result<(), String> {
     let conn = db.0.lock().map_err(|e| e.to_string())?;
-    database::set_setting(&conn, &key, &value).map_err(|e| e.to_string())
+    database::undoable(&conn, "Delete creature", || {
+        database::delete_creature(&conn, &id)
+    })
+    .map_err(|e| e.to_string())
 }
 
 // ============================================================================
-// AI Commands
+// Database Commands - NPCs
 // ============================================================================
 
 #[tauri::command]
-pub async fn ai_chat(request: AiChatRequest) -> Result<AiChatResponse, String> {
-    ai::send_chat(request).await.map_err(|e| e.to_string())
+pub fn db_create_npc(db: DbConn<'_>, npc: database::Npc) -> Result<(), String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    database::undoable(&conn, "Add NPC", || database::create_npc(&conn, &npc))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn db_get_npcs_by_project(
+    db: DbConn<'_>,
+    project_id: String,
+) -> Result<Vec<database::Npc>, String> {
+    let conn = db.0.read().map_err(|e| e.to_string())?;
+    database::get_npcs_by_project(&conn, &project_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn db_update_npc(db: DbConn<'_>, npc: database::Npc) -> Result<(), String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    database::undoable(&conn, "Edit NPC", || database::update_npc(&conn, &npc))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn db_delete_npc(db: DbConn<'_>, id: String) -> Result<(), String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    database::undoable(&conn, "Delete NPC", || database::delete_npc(&conn, &id))
+        .map_err(|e| e.to_string())
+}
+
+// ============================================================================
+// Database Commands - World Rules
+// ============================================================================
+
+#[tauri::command]
+pub fn db_create_world_rule(db: DbConn<'_>, rule: database::WorldRule) -> Result<(), String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    database::undoable(&conn, "Add world rule", || {
+        database::create_world_rule(&conn, &rule)
+    })
+    .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn db_get_world_rules_by_project(
+    db: DbConn<'_>,
+    project_id: String,
+) -> Result<Vec<database::WorldRule>, String> {
+    let conn = db.0.read().map_err(|e| e.to_string())?;
+    database::get_world_rules_by_project(&conn, &project_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn db_update_world_rule(db: DbConn<'_>, rule: database::WorldRule) -> Result<(), String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    database::undoable(&conn, "Edit world rule", || {
+        database::update_world_rule(&conn, &rule)
+    })
+    .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn db_delete_world_rule(db: DbConn<'_>, id: String) -> Result<(), String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    database::undoable(&conn, "Delete world rule", || {
+        database::delete_world_rule(&conn, &id)
+    })
+    .map_err(|e| e.to_string())
+}
+
+// ============================================================================
+// Database Commands - Batch
+// ============================================================================
+
+/// Apply several create/update/delete operations atomically. A failed
+/// operation rolls the whole batch back; the result says which one failed.
+#[tauri::command]
+pub fn db_apply_batch(
+    db: DbConn<'_>,
+    operations: Vec<database::BatchOperation>,
+) -> Result<database::BatchResult, String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    database::undoable(&conn, "Apply changes", || {
+        database::apply_batch(&conn, &operations)
+    })
+    .map_err(|e| e.to_string())
+}
+
+// ============================================================================
+// Database Commands - Chapter Revisions
+// ============================================================================
+
+#[tauri::command]
+pub fn db_list_chapter_revisions(
+    db: DbConn<'_>,
+    chapter_id: String,
+) -> Result<Vec<database::RevisionInfo>, String> {
+    let conn = db.0.read().map_err(|e| e.to_string())?;
+    database::list_revisions(&conn, &chapter_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn db_get_chapter_revision(
+    db: DbConn<'_>,
+    id: String,
+) -> Result<Option<database::ChapterRevision>, String> {
+    let conn = db.0.read().map_err(|e| e.to_string())?;
+    database::get_revision(&conn, &id).map_err(|e| e.to_string())
+}
+
+/// Snapshot a chapter on demand. Returns None if the content is unchanged
+/// since the latest revision.
+#[tauri::command]
+pub fn db_create_chapter_revision(
+    db: DbConn<'_>,
+    chapter_id: String,
+    label: Option<String>,
+) -> Result<Option<database::RevisionInfo>, String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    database::capture_revision(
+        &conn,
+        &chapter_id,
+        database::RevisionSource::Manual,
+        label.as_deref(),
+    )
+    .map_err(|e| e.to_string())
+}
+
+/// Word diff between two revisions, or between a revision and the current
+/// chapter content when `to_id` is omitted
+#[tauri::command]
+pub fn db_diff_chapter_revisions(
+    db: DbConn<'_>,
+    from_id: String,
+    to_id: Option<String>,
+) -> Result<database::RevisionDiff, String> {
+    let conn = db.0.read().map_err(|e| e.to_string())?;
+    database::diff_revisions(&conn, &from_id, to_id.as_deref())
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "Revision not found".to_string())
+}
+
+#[tauri::command]
+pub fn db_restore_chapter_revision(
+    db: DbConn<'_>,
+    id: String,
+) -> Result<database::Chapter, String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    database::undoable(&conn, "Restore chapter revision", || {
+        database::restore_revision(&conn, &id)
+    })
+    .map_err(|e| e.to_string())?
+    .ok_or_else(|| format!("Revision not found: {}", id))
+}
+
+// ============================================================================
+// Database Commands - Search
+// ============================================================================
+
+#[tauri::command]
+pub fn db_search(
+    db: DbConn<'_>,
+    query: String,
+    project_id: Option<String>,
+    entity_types: Option<Vec<database::EntityKind>>,
+    limit: Option<u32>,
+) -> Result<Vec<database::SearchHit>, String> {
+    let conn = db.0.read().map_err(|e| e.to_string())?;
+    database::search(
+        &conn,
+        &query,
+        project_id.as_deref(),
+        &entity_types.unwrap_or_default(),
+        limit,
+    )
+    .map_err(|e| e.to_string())
+}
+
+// ============================================================================
+// Database Commands - Mentions
+// ============================================================================
+
+#[tauri::command]
+pub fn db_get_entity_mentions(
+    db: DbConn<'_>,
+    entity_type: database::EntityKind,
+    id: String,
+) -> Result<Vec<database::Mention>, String> {
+    let conn = db.0.read().map_err(|e| e.to_string())?;
+    database::get_entity_mentions(&conn, entity_type, &id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn db_get_chapter_cast(
+    db: DbConn<'_>,
+    chapter_id: String,
+) -> Result<Vec<database::CastMember>, String> {
+    let conn = db.0.read().map_err(|e| e.to_string())?;
+    database::get_chapter_cast(&conn, &chapter_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn db_get_backlinks(
+    db: DbConn<'_>,
+    entity_type: database::EntityKind,
+    id: String,
+) -> Result<Vec<database::Backlink>, String> {
+    let conn = db.0.read().map_err(|e| e.to_string())?;
+    database::get_backlinks(&conn, entity_type, &id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn db_get_entity_aliases(
+    db: DbConn<'_>,
+    entity_type: database::EntityKind,
+    id: String,
+) -> Result<Vec<String>, String> {
+    let conn = db.0.read().map_err(|e| e.to_string())?;
+    database::get_entity_aliases(&conn, entity_type, &id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn db_set_entity_aliases(
+    db: DbConn<'_>,
+    entity_type: database::EntityKind,
+    id: String,
+    aliases: Vec<String>,
+) -> Result<(), String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    database::undoable(&conn, "Edit aliases", || {
+        database::set_entity_aliases(&conn, entity_type, &id, &aliases)
+    })
+    .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn db_reindex_mentions(db: DbConn<'_>, project_id: String) -> Result<usize, String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    database::reindex_project(&conn, &project_id).map_err(|e| e.to_string())
+}
+
+// ============================================================================
+// Database Commands - Links
+// ============================================================================
+
+#[tauri::command]
+pub fn db_resolve_links(
+    db: DbConn<'_>,
+    project_id: String,
+    content: String,
+) -> Result<Vec<database::ResolvedLink>, String> {
+    let conn = db.0.read().map_err(|e| e.to_string())?;
+    database::resolve_links(&conn, &project_id, &content).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn db_get_link_report(
+    db: DbConn<'_>,
+    project_id: String,
+) -> Result<Vec<database::LinkIssue>, String> {
+    let conn = db.0.read().map_err(|e| e.to_string())?;
+    database::get_link_report(&conn, &project_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn db_rename_entity(
+    db: DbConn<'_>,
+    entity_type: database::EntityKind,
+    id: String,
+    new_name: String,
+) -> Result<database::RenameResult, String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    database::undoable(&conn, "Rename", || {
+        database::rename_entity(&conn, entity_type, &id, &new_name)
+    })
+    .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn db_get_link_glossary(
+    db: DbConn<'_>,
+    project_id: String,
+) -> Result<Vec<database::GlossaryTerm>, String> {
+    let conn = db.0.read().map_err(|e| e.to_string())?;
+    database::get_link_glossary(&conn, &project_id).map_err(|e| e.to_string())
+}
+
+// ============================================================================
+// Database Commands - Custom Fields
+// ============================================================================
+
+#[tauri::command]
+pub fn db_create_custom_field(db: DbConn<'_>, field: database::CustomField) -> Result<(), String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    database::undoable(&conn, "Add custom field", || {
+        database::create_custom_field(&conn, &field)
+    })
+    .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn db_get_custom_fields(
+    db: DbConn<'_>,
+    project_id: String,
+    entity_type: Option<database::EntityKind>,
+) -> Result<Vec<database::CustomField>, String> {
+    let conn = db.0.read().map_err(|e| e.to_string())?;
+    database::get_custom_fields(&conn, &project_id, entity_type).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn db_update_custom_field(
+    db: DbConn<'_>,
+    field: database::CustomField,
+) -> Result<usize, String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    database::undoable(&conn, "Edit custom field", || {
+        database::update_custom_field(&conn, &field)
+    })
+    .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn db_delete_custom_field(db: DbConn<'_>, id: String) -> Result<(), String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    database::undoable(&conn, "Delete custom field", || {
+        database::delete_custom_field(&conn, &id)
+    })
+    .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn db_filter_by_custom_fields(
+    db: DbConn<'_>,
+    project_id: String,
+    entity_type: database::EntityKind,
+    filters: Vec<database::CustomFieldFilter>,
+) -> Result<Vec<String>, String> {
+    let conn = db.0.read().map_err(|e| e.to_string())?;
+    database::filter_by_custom_fields(&conn, &project_id, entity_type, &filters)
+        .map_err(|e| e.to_string())
+}
+
+// ============================================================================
+// Database Commands - Tags
+// ============================================================================
+
+#[tauri::command]
+pub fn db_create_tag(db: DbConn<'_>, tag: database::Tag) -> Result<(), String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    database::undoable(&conn, "Add tag", || database::create_tag(&conn, &tag))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn db_get_project_tags(
+    db: DbConn<'_>,
+    project_id: String,
+) -> Result<Vec<database::Tag>, String> {
+    let conn = db.0.read().map_err(|e| e.to_string())?;
+    database::get_project_tags(&conn, &project_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn db_update_tag(db: DbConn<'_>, tag: database::Tag) -> Result<(), String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    database::undoable(&conn, "Edit tag", || database::update_tag(&conn, &tag))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn db_delete_tag(db: DbConn<'_>, id: String) -> Result<(), String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    database::undoable(&conn, "Delete tag", || database::delete_tag(&conn, &id))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn db_merge_tags(
+    db: DbConn<'_>,
+    source_ids: Vec<String>,
+    target_id: String,
+) -> Result<usize, String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    database::undoable(&conn, "Merge tags", || {
+        database::merge_tags(&conn, &source_ids, &target_id)
+    })
+    .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn db_tag_entity(
+    db: DbConn<'_>,
+    entity_type: database::EntityKind,
+    id: String,
+    tag_id: String,
+) -> Result<bool, String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    database::undoable(&conn, "Apply tag", || {
+        database::tag_entity(&conn, entity_type, &id, &tag_id)
+    })
+    .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn db_untag_entity(
+    db: DbConn<'_>,
+    entity_type: database::EntityKind,
+    id: String,
+    tag_id: String,
+) -> Result<bool, String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    database::undoable(&conn, "Remove tag", || {
+        database::untag_entity(&conn, entity_type, &id, &tag_id)
+    })
+    .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn db_get_entity_tags(
+    db: DbConn<'_>,
+    entity_type: database::EntityKind,
+    id: String,
+) -> Result<Vec<database::Tag>, String> {
+    let conn = db.0.read().map_err(|e| e.to_string())?;
+    database::get_entity_tags(&conn, entity_type, &id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn db_get_entities_by_tags(
+    db: DbConn<'_>,
+    project_id: String,
+    tag_ids: Vec<String>,
+    mode: database::TagMatch,
+    entity_types: Option<Vec<database::EntityKind>>,
+) -> Result<Vec<database::TaggedEntity>, String> {
+    let conn = db.0.read().map_err(|e| e.to_string())?;
+    database::get_entities_by_tags(
+        &conn,
+        &project_id,
+        &tag_ids,
+        mode,
+        &entity_types.unwrap_or_default(),
+    )
+    .map_err(|e| e.to_string())
+}
+
+// ============================================================================
+// Database Commands - Query
+// ============================================================================
+
+#[tauri::command]
+pub fn db_query_entities(
+    db: DbConn<'_>,
+    project_id: String,
+    entity_type: database::EntityKind,
+    query: database::EntityQuery,
+) -> Result<database::QueryPage, String> {
+    let conn = db.0.read().map_err(|e| e.to_string())?;
+    database::query_entities(&conn, &project_id, entity_type, &query).map_err(|e| e.to_string())
+}
+
+// ============================================================================
+// Database Commands - Project Settings
+// ============================================================================
+
+#[tauri::command]
+pub fn db_get_project_settings(
+    db: DbConn<'_>,
+    project_id: String,
+) -> Result<Vec<database::ProjectSetting>, String> {
+    let conn = db.0.read().map_err(|e| e.to_string())?;
+    database::get_project_settings(&conn, &project_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn db_get_project_setting(
+    db: DbConn<'_>,
+    project_id: String,
+    key: String,
+) -> Result<serde_json::Value, String> {
+    let conn = db.0.read().map_err(|e| e.to_string())?;
+    database::get_project_setting(&conn, &project_id, &key).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn db_set_project_setting(
+    db: DbConn<'_>,
+    project_id: String,
+    key: String,
+    value: serde_json::Value,
+) -> Result<(), String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    database::set_project_setting(&conn, &project_id, &key, &value).map_err(|e| e.to_string())
+}
+
+/// Reset one setting, or every setting of the project when `key` is omitted
+#[tauri::command]
+pub fn db_reset_project_setting(
+    db: DbConn<'_>,
+    project_id: String,
+    key: Option<String>,
+) -> Result<(), String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    database::reset_project_setting(&conn, &project_id, key.as_deref()).map_err(|e| e.to_string())
+}
+
+// ============================================================================
+// Database Commands - Audit Log
+// ============================================================================
+
+#[tauri::command]
+pub fn db_get_project_audit_log(
+    db: DbConn<'_>,
+    project_id: String,
+    filter: Option<database::AuditFilter>,
+) -> Result<Vec<database::AuditEntry>, String> {
+    let conn = db.0.read().map_err(|e| e.to_string())?;
+    database::get_audit_log(&conn, Some(&project_id), None, &filter.unwrap_or_default())
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn db_get_entity_audit_log(
+    db: DbConn<'_>,
+    entity_type: database::EntityKind,
+    id: String,
+    filter: Option<database::AuditFilter>,
+) -> Result<Vec<database::AuditEntry>, String> {
+    let conn = db.0.read().map_err(|e| e.to_string())?;
+    database::get_audit_log(
+        &conn,
+        None,
+        Some((entity_type, &id)),
+        &filter.unwrap_or_default(),
+    )
+    .map_err(|e| e.to_string())
+}
+
+// ============================================================================
+// Database Commands - Undo
+// ============================================================================
+
+/// Undo the project's latest step. Returns its label, or None if there is
+/// nothing to undo.
+#[tauri::command]
+pub fn db_undo(db: DbConn<'_>, project_id: String) -> Result<Option<String>, String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    database::undo(&conn, &project_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn db_redo(db: DbConn<'_>, project_id: String) -> Result<Option<String>, String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    database::redo(&conn, &project_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn db_get_undo_steps(
+    db: DbConn<'_>,
+    project_id: String,
+) -> Result<database::UndoSteps, String> {
+    let conn = db.0.read().map_err(|e| e.to_string())?;
+    database::get_undo_steps(&conn, &project_id).map_err(|e| e.to_string())
+}
+
+// ============================================================================
+// Database Commands - Trash
+// ============================================================================
+
+#[tauri::command]
+pub fn db_list_trash(
+    db: DbConn<'_>,
+    project_id: String,
+) -> Result<Vec<database::TrashItem>, String> {
+    let conn = db.0.read().map_err(|e| e.to_string())?;
+    database::list_trash(&conn, Some(&project_id)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn db_list_deleted_projects(db: DbConn<'_>) -> Result<Vec<database::TrashItem>, String> {
+    let conn = db.0.read().map_err(|e| e.to_string())?;
+    database::list_deleted_projects(&conn).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn db_restore_from_trash(
+    db: DbConn<'_>,
+    entity_type: database::EntityKind,
+    id: String,
+) -> Result<(), String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    if database::undoable(&conn, "Restore from trash", || {
+        database::restore_from_trash(&conn, entity_type, &id)
+    })
+    .map_err(|e| e.to_string())?
+    {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} {} is not in the trash",
+            entity_type.as_str(),
+            id
+        ))
+    }
+}
+
+#[tauri::command]
+pub fn db_purge_from_trash(
+    db: DbConn<'_>,
+    entity_type: database::EntityKind,
+    id: String,
+) -> Result<(), String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    if database::purge_from_trash(&conn, entity_type, &id).map_err(|e| e.to_string())? {
+        Ok(())
+    } else {
+        Err(format!("{} {} is not in the trash", entity_type.as_str(), id))
+    }
+}
+
+#[tauri::command]
+pub fn db_empty_trash(db: DbConn<'_>, project_id: String) -> Result<usize, String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    database::empty_trash(&conn, &project_id).map_err(|e| e.to_string())
+}
+
+// ============================================================================
+// Database Commands - System
+// ============================================================================
+
+/// Report broken references, fixing them when `repair` is set
+#[tauri::command]
+pub fn db_check_integrity(
+    db: DbConn<'_>,
+    repair: bool,
+) -> Result<database::IntegrityReport, String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    database::check_integrity(&conn, repair).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn db_clear_all_data(db: DbConn<'_>) -> Result<(), String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    database::clear_database(&conn).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn db_get_setting(db: DbConn<'_>, key: String) -> Result<Option<String>, String> {
+    let conn = db.0.read().map_err(|e| e.to_string())?;
+    database::get_setting(&conn, &key).map_err(|e| e.to_string())
+}
+
+/// Settings the registry declares are validated and announced like
+/// `settings_update`; other keys (such as `workspace_path`) are stored as is
+#[tauri::command]
+pub fn db_set_setting(
+    app: AppHandle,
+    db: DbConn<'_>,
+    key: String,
+    value: String,
+) -> Result<(), String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    if !crate::settings::is_declared(&key) {
+        return database::set_setting(&conn, &key, &value).map_err(|e| e.to_string());
+    }
+    let changes = crate::settings::update_raw(&conn, &key, &value).map_err(|e| e.to_string())?;
+    app.emit(settings::SETTINGS_CHANGED, &changes).unwrap_or_default();
+    Ok(())
 }
 
 // ============================================================================
@@ -456,7 +1302,7 @@ pub fn ws_set_workspace_path(
 
 #[tauri::command]
 pub fn ws_is_first_launch(db: DbConn<'_>) -> Result<bool, String> {
-    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    let conn = db.0.read().map_err(|e| e.to_string())?;
     let path = database::get_setting(&conn, "workspace_path").map_err(|e| e.to_string())?;
     Ok(path.is_none())
 }
@@ -501,7 +1347,7 @@ pub fn ws_save_project_to_folder(
     project_id: String,
 ) -> Result<String, String> {
     let ws_path = get_ws_path(&ws)?;
-    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    let conn = db.0.read().map_err(|e| e.to_string())?;
 
     workspace::sync::sync_sql_to_filesystem(&conn, &PathBuf::from(&ws_path), &project_id)?;
 
@@ -605,7 +1451,7 @@ pub fn ws_close_project(
     let ws_pathbuf = PathBuf::from(&ws_path);
 
     // 1. Sync SQL -> filesystem
-    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    let conn = db.0.read().map_err(|e| e.to_string())?;
     workspace::sync::sync_sql_to_filesystem(&conn, &ws_pathbuf, &project_id)?;
 
     // 2. Compress to .pluma backup
@@ -656,6 +1502,27 @@ pub fn ws_resolve_image(
     Ok(resolved.to_string_lossy().to_string())
 }
 
+/// Copy the images a duplicated project refers to from the original's
+/// folder. Both projects must already have been synced to disk.
+#[tauri::command]
+pub fn ws_copy_project_images(
+    ws: State<'_, WorkspaceState>,
+    source_project_id: String,
+    target_project_id: String,
+) -> Result<usize, String> {
+    let ws_path = get_ws_path(&ws)?;
+    let ws_pathbuf = PathBuf::from(&ws_path);
+
+    let source_path = workspace::project_fs::find_project_folder(&ws_pathbuf, &source_project_id)
+        .ok_or("Source project folder not found")?;
+    let target_path = workspace::project_fs::find_project_folder(&ws_pathbuf, &target_project_id)
+        .ok_or("Project folder not found")?;
+
+    let contents = workspace::project_fs::read_project_from_folder(&target_path)?;
+    let entities = serde_json::to_value(&contents).map_err(|e| e.to_string())?;
+    workspace::images::copy_referenced_images(&source_path, &target_path, &entities)
+}
+
 // --- Sync Commands ---
 
 #[tauri::command]
@@ -665,7 +1532,7 @@ pub fn ws_sync_to_disk(
     project_id: String,
 ) -> Result<(), String> {
     let ws_path = get_ws_path(&ws)?;
-    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    let conn = db.0.read().map_err(|e| e.to_string())?;
     workspace::sync::sync_sql_to_filesystem(&conn, &PathBuf::from(&ws_path), &project_id)
 }
 
@@ -1,9 +1,10 @@
-use crate::database::{self, DbConn};
+use crate::database::{self, AuditSource, DbConn};
 use crate::packages::{
     catalog, db as pkg_db, installer, list_available_packages,
     models::*,
     registry,
 };
+use crate::settings;
 use tauri::{command, AppHandle};
 
 // ============================================================================
@@ -64,88 +65,89 @@ pub async fn inject_package_content(
     let package_dir = crate::packages::get_packages_dir(&app)?.join(&package_id);
     let conn = db.0.lock().map_err(|e| e.to_string())?;
 
-    // Inject Characters
-    if let Some(characters) = content.characters {
-        for char_val in characters {
-            let mut character: crate::database::Character = serde_json::from_value(char_val)
-                .map_err(|e| format!("Invalid character data: {}", e))?;
-
-            character.project_id = project_id.clone();
-            character.origin_package_id = Some(package_id.clone());
-
-            // Resolve relative avatar path
-            if let Some(avatar) = character.avatar_url.as_mut() {
-                if !avatar.starts_with("http")
-                    && !avatar.starts_with("data:")
-                    && !avatar.starts_with("/")
-                {
-                    let abs_path = package_dir.join("assets").join(&avatar);
-                    *avatar = abs_path.to_string_lossy().into_owned();
+    // Logged as package changes in the audit log, and undone as one step
+    database::undoable(&conn, "Inject package", || {
+        Ok::<_, rusqlite::Error>(database::with_audit_source(AuditSource::Package, || {
+            // Inject Characters
+            if let Some(characters) = content.characters {
+                for char_val in characters {
+                    let mut character: crate::database::Character =
+                        serde_json::from_value(char_val)
+                            .map_err(|e| format!("Invalid character data: {}", e))?;
+
+                    character.project_id = project_id.clone();
+                    character.origin_package_id = Some(package_id.clone());
+
+                    // Resolve relative avatar path
+                    if let Some(avatar) = character.avatar_url.as_mut() {
+                        if !avatar.starts_with("http")
+                            && !avatar.starts_with("data:")
+                            && !avatar.starts_with("/")
+                        {
+                            let abs_path = package_dir.join("assets").join(&avatar);
+                            *avatar = abs_path.to_string_lossy().into_owned();
+                        }
+                    }
+
+                    crate::database::create_character(&conn, &character).map_err(|e| {
+                        format!("Failed to inject character {}: {}", character.name, e)
+                    })?;
                 }
             }
 
-            crate::database::create_character(&conn, &character)
-                .map_err(|e| format!("Failed to inject character {}: {}", character.name, e))?;
-        }
-    }
+            // Inject Creatures
+            if let Some(creatures) = content.creatures {
+                for mut creature_val in creatures {
+                    // Package ids would be shared by every project the package is
+                    // injected into, so each copy gets its own
+                    if let Some(obj) = creature_val.as_object_mut() {
+                        obj.insert(
+                            "id".to_string(),
+                            serde_json::Value::String(uuid::Uuid::new_v4().to_string()),
+                        );
+                    }
+                    let mut creature: crate::database::Creature =
+                        serde_json::from_value(creature_val)
+                            .map_err(|e| format!("Invalid creature data: {}", e))?;
+
+                    creature.project_id = project_id.clone();
+                    creature.origin_package_id = Some(package_id.clone());
 
-    // Inject Creatures (stored in project's creatures JSON column)
-    if let Some(creatures) = content.creatures {
-        if !creatures.is_empty() {
-            let project = crate::database::get_project(&conn, &project_id)
-                .map_err(|e| format!("Failed to load project for creature injection: {}", e))?
-                .ok_or_else(|| "Project not found".to_string())?;
-
-            let mut existing: Vec<serde_json::Value> = project
-                .creatures
-                .clone()
-                .and_then(|v| serde_json::from_value(v).ok())
-                .unwrap_or_default();
-
-            for mut creature_val in creatures {
-                if let Some(obj) = creature_val.as_object_mut() {
-                    if let Some(img) = obj.get("imageUrl").and_then(|v| v.as_str()) {
+                    // Resolve relative image path
+                    if let Some(img) = creature.image_url.as_mut() {
                         if !img.starts_with("http")
                             && !img.starts_with("data:")
                             && !img.starts_with("/")
                         {
-                            let abs_path = package_dir.join("assets").join(img);
-                            obj.insert(
-                                "imageUrl".to_string(),
-                                serde_json::Value::String(abs_path.to_string_lossy().into_owned()),
-                            );
+                            let abs_path = package_dir.join("assets").join(&img);
+                            *img = abs_path.to_string_lossy().into_owned();
                         }
                     }
+
+                    crate::database::create_creature(&conn, &creature).map_err(|e| {
+                        format!("Failed to inject creature {}: {}", creature.name, e)
+                    })?;
                 }
-                existing.push(creature_val);
             }
 
-            let mut updated_project = project;
-            updated_project.creatures = Some(
-                serde_json::to_value(&existing)
-                    .map_err(|e| format!("Failed to serialize creatures: {}", e))?,
-            );
-
-            crate::database::update_project(&conn, &updated_project)
-                .map_err(|e| format!("Failed to update project with creatures: {}", e))?;
-        }
-    }
-
-    // Inject Lore Items
-    if let Some(lore_items) = content.lore_items {
-        for lore_val in lore_items {
-            let mut item: crate::database::LoreItem = serde_json::from_value(lore_val)
-                .map_err(|e| format!("Invalid lore item data: {}", e))?;
+            // Inject Lore Items
+            if let Some(lore_items) = content.lore_items {
+                for lore_val in lore_items {
+                    let mut item: crate::database::LoreItem = serde_json::from_value(lore_val)
+                        .map_err(|e| format!("Invalid lore item data: {}", e))?;
 
-            item.project_id = project_id.clone();
-            item.origin_package_id = Some(package_id.clone());
+                    item.project_id = project_id.clone();
+                    item.origin_package_id = Some(package_id.clone());
 
-            crate::database::create_lore_item(&conn, &item)
-                .map_err(|e| format!("Failed to inject lore item {}: {}", item.title, e))?;
-        }
-    }
+                    crate::database::create_lore_item(&conn, &item)
+                        .map_err(|e| format!("Failed to inject lore item {}: {}", item.title, e))?;
+                }
+            }
 
-    Ok(())
+            Ok(())
+        }))
+    })
+    .map_err(|e| e.to_string())?
 }
 
 // ============================================================================
@@ -156,10 +158,9 @@ pub async fn inject_package_content(
 fn load_registries_from_conn(conn: &rusqlite::Connection) -> Vec<RegistrySource> {
     let mut sources = vec![registry::official_registry()];
 
-    if let Ok(Some(json)) = database::get_setting(conn, "package_registries") {
-        if let Ok(custom) = serde_json::from_str::<Vec<RegistrySource>>(&json) {
-            sources.extend(custom);
-        }
+    match settings::get::<Vec<RegistrySource>>(conn, "package_registries") {
+        Ok(custom) => sources.extend(custom),
+        Err(e) => log::warn!("Failed to load package registries: {}", e),
     }
 
     sources
@@ -172,8 +173,13 @@ fn save_custom_registries_to_conn(
 ) -> Result<(), String> {
     // Only save non-official registries
     let custom: Vec<&RegistrySource> = registries.iter().filter(|r| !r.is_official).collect();
-    let json = serde_json::to_string(&custom).map_err(|e| e.to_string())?;
-    database::set_setting(conn, "package_registries", &json).map_err(|e| e.to_string())
+    let mut changes = serde_json::Map::new();
+    changes.insert(
+        "package_registries".to_string(),
+        serde_json::to_value(&custom).map_err(|e| e.to_string())?,
+    );
+    settings::update(conn, &changes).map_err(|e| e.to_string())?;
+    Ok(())
 }
 
 #[command]
@@ -0,0 +1,49 @@
+use crate::database::DbConn;
+use crate::settings::{self, AppSetting};
+use serde_json::{Map, Value};
+use tauri::{command, AppHandle, Emitter};
+
+/// Event sent to every window after settings change, with the new values
+/// keyed by setting
+pub(super) const SETTINGS_CHANGED: &str = "settings-changed";
+
+#[command]
+pub fn settings_get_all(db: DbConn<'_>) -> Result<Vec<AppSetting>, String> {
+    let conn = db.0.read().map_err(|e| e.to_string())?;
+    settings::get_all(&conn).map_err(|e| e.to_string())
+}
+
+/// Apply several changes at once; none are saved if any value is invalid
+#[command]
+pub fn settings_update(
+    app: AppHandle,
+    db: DbConn<'_>,
+    changes: Map<String, Value>,
+) -> Result<(), String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    let changes = settings::update(&conn, &changes).map_err(|e| e.to_string())?;
+    app.emit(SETTINGS_CHANGED, &changes).unwrap_or_default();
+    Ok(())
+}
+
+/// Save every setting but credentials to a JSON profile file
+#[command]
+pub fn settings_export_profile(db: DbConn<'_>, path: String) -> Result<(), String> {
+    let conn = db.0.read().map_err(|e| e.to_string())?;
+    let profile = settings::export_profile(&conn).map_err(|e| e.to_string())?;
+    let json = serde_json::to_string_pretty(&profile).map_err(|e| e.to_string())?;
+    std::fs::write(&path, json).map_err(|e| format!("Failed to write profile: {}", e))
+}
+
+/// Apply a profile file written by `settings_export_profile`
+#[command]
+pub fn settings_import_profile(app: AppHandle, db: DbConn<'_>, path: String) -> Result<(), String> {
+    let content =
+        std::fs::read_to_string(&path).map_err(|e| format!("Failed to read profile: {}", e))?;
+    let profile: Value =
+        serde_json::from_str(&content).map_err(|e| format!("Invalid settings profile: {}", e))?;
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    let changes = settings::import_profile(&conn, &profile).map_err(|e| e.to_string())?;
+    app.emit(SETTINGS_CHANGED, &changes).unwrap_or_default();
+    Ok(())
+}
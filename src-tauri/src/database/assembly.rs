@@ -0,0 +1,372 @@
+//! Chapters assembled from scenes
+//!
+//! A chapter in `ContentMode::Scenes` has no text of its own: its content is
+//! the prose of its scenes, in order, separated by scene breaks, and it is
+//! recompiled whenever one of those scenes changes. Going the other way, a
+//! chapter written as one document can be split into scenes at its break
+//! markers.
+
+use super::mentions;
+use super::models::{Chapter, ContentMode, EntityKind, Scene};
+use super::operations::{create_scene, get_chapter, get_scenes_by_chapter, with_savepoint};
+use super::revisions::{self, RevisionSource};
+use super::text;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Separator written between scenes in a compiled chapter
+pub const SCENE_BREAK: &str = "<p>* * *</p>";
+
+/// Characters that make up a break marker paragraph (`***`, `* * *`, `#`...)
+const BREAK_GLYPHS: &[char] = &['*', '#', '~', '-', '•', '·', '⁂'];
+
+#[derive(Error, Debug)]
+pub enum AssemblyError {
+    #[error("Chapter {0} not found")]
+    ChapterNotFound(String),
+
+    #[error("Chapter {0} already has scenes with content")]
+    ScenesHaveContent(String),
+
+    #[error("Database error: {0}")]
+    Database(#[from] rusqlite::Error),
+}
+
+/// A chapter after being split, with its new scenes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChapterSplit {
+    pub chapter: Chapter,
+    pub scenes: Vec<Scene>,
+}
+
+/// The chapter text its scenes add up to, whatever its current mode
+pub fn compile_chapter(conn: &Connection, chapter_id: &str) -> Result<String, AssemblyError> {
+    require_chapter(conn, chapter_id)?;
+    Ok(compiled_content(conn, chapter_id)?.0)
+}
+
+/// Switch where a chapter's text comes from. Switching to `Scenes` replaces
+/// the current text with the compiled one, after snapshotting it; switching
+/// back keeps the last compiled text as the document to edit.
+pub fn set_chapter_content_mode(
+    conn: &Connection,
+    chapter_id: &str,
+    mode: ContentMode,
+) -> Result<Chapter, AssemblyError> {
+    let chapter = require_chapter(conn, chapter_id)?;
+    if chapter.content_mode == mode {
+        return Ok(chapter);
+    }
+
+    with_savepoint(conn, || {
+        if mode == ContentMode::Scenes {
+            revisions::capture_revision(
+                conn,
+                chapter_id,
+                RevisionSource::Manual,
+                Some("Before compiling from scenes"),
+            )?;
+        }
+        conn.execute(
+            "UPDATE chapters SET content_mode = ?2, updated_at = CURRENT_TIMESTAMP WHERE id = ?1",
+            params![chapter_id, mode.as_str()],
+        )?;
+        recompile(conn, chapter_id)?;
+        mentions::index_source(conn, EntityKind::Chapter, chapter_id)
+    })?;
+
+    require_chapter(conn, chapter_id)
+}
+
+/// Split a chapter into scenes at its scene-break markers and switch it to
+/// `Scenes` mode. New scenes go after any existing ones, which must not have
+/// prose of their own (outline-only scenes are fine).
+pub fn split_chapter_into_scenes(
+    conn: &Connection,
+    chapter_id: &str,
+) -> Result<ChapterSplit, AssemblyError> {
+    let chapter = require_chapter(conn, chapter_id)?;
+    let existing = get_scenes_by_chapter(conn, chapter_id)?;
+    if existing.iter().any(has_content) {
+        return Err(AssemblyError::ScenesHaveContent(chapter_id.to_string()));
+    }
+
+    let segments = if chapter.content_mode == ContentMode::Scenes {
+        Vec::new()
+    } else {
+        split_at_breaks(&chapter.content)
+    };
+
+    with_savepoint(conn, || {
+        revisions::capture_revision(
+            conn,
+            chapter_id,
+            RevisionSource::Manual,
+            Some("Before split into scenes"),
+        )?;
+        for (index, segment) in segments.into_iter().enumerate() {
+            let position = existing.len() + index;
+            let scene = Scene {
+                id: uuid::Uuid::new_v4().to_string(),
+                chapter_id: chapter_id.to_string(),
+                title: segment_title(&segment).unwrap_or_else(|| format!("Scene {}", position + 1)),
+                character_ids: vec![],
+                location_id: None,
+                timeline_position: position as i32,
+                description: None,
+                notes: None,
+                image: None,
+                image_type: None,
+                content: Some(segment),
+                word_count: 0, // Computed on save
+            };
+            create_scene(conn, &scene)?;
+        }
+        conn.execute(
+            "UPDATE chapters SET content_mode = ?2, updated_at = CURRENT_TIMESTAMP WHERE id = ?1",
+            params![chapter_id, ContentMode::Scenes.as_str()],
+        )?;
+        recompile(conn, chapter_id)?;
+        mentions::index_source(conn, EntityKind::Chapter, chapter_id)
+    })?;
+
+    Ok(ChapterSplit {
+        chapter: require_chapter(conn, chapter_id)?,
+        scenes: get_scenes_by_chapter(conn, chapter_id)?,
+    })
+}
+
+/// Rewrite a chapter's text from its scenes if it is in `Scenes` mode.
+/// Called after any change to the chapter's scenes.
+pub(crate) fn recompile(conn: &Connection, chapter_id: &str) -> rusqlite::Result<()> {
+    let mode: Option<String> = conn
+        .query_row(
+            "SELECT content_mode FROM chapters WHERE id = ?1 AND deleted_at IS NULL",
+            params![chapter_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    if mode.as_deref().map(ContentMode::from_db) != Some(ContentMode::Scenes) {
+        return Ok(());
+    }
+
+    let (content, word_count) = compiled_content(conn, chapter_id)?;
+    let changed = conn.execute(
+        r#"UPDATE chapters SET content = ?2, word_count = ?3, updated_at = CURRENT_TIMESTAMP
+           WHERE id = ?1 AND content IS NOT ?2"#,
+        params![chapter_id, content, word_count],
+    )?;
+    if changed > 0 {
+        revisions::capture_revision(conn, chapter_id, RevisionSource::Auto, None)?;
+    }
+    Ok(())
+}
+
+/// Recompile the chapter a scene belongs to, trashed or not
+pub(crate) fn recompile_scene_chapter(conn: &Connection, scene_id: &str) -> rusqlite::Result<()> {
+    let chapter_id: Option<String> = conn
+        .query_row(
+            "SELECT chapter_id FROM scenes WHERE id = ?1",
+            params![scene_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    match chapter_id {
+        Some(chapter_id) => recompile(conn, &chapter_id),
+        None => Ok(()),
+    }
+}
+
+/// Compiled text and its word count, which leaves the break markers out
+fn compiled_content(conn: &Connection, chapter_id: &str) -> rusqlite::Result<(String, i32)> {
+    let scenes: Vec<Scene> = get_scenes_by_chapter(conn, chapter_id)?
+        .into_iter()
+        .filter(has_content)
+        .collect();
+    let word_count = scenes.iter().map(|scene| scene.word_count).sum();
+    let parts: Vec<&str> = scenes
+        .iter()
+        .filter_map(|scene| scene.content.as_deref())
+        .map(str::trim)
+        .collect();
+    Ok((parts.join(SCENE_BREAK), word_count))
+}
+
+fn require_chapter(conn: &Connection, chapter_id: &str) -> Result<Chapter, AssemblyError> {
+    get_chapter(conn, chapter_id)?
+        .ok_or_else(|| AssemblyError::ChapterNotFound(chapter_id.to_string()))
+}
+
+fn has_content(scene: &Scene) -> bool {
+    scene
+        .content
+        .as_deref()
+        .is_some_and(|content| !content.trim().is_empty())
+}
+
+fn is_break_text(text: &str) -> bool {
+    let text = text.trim();
+    !text.is_empty()
+        && text
+            .chars()
+            .all(|c| c.is_whitespace() || BREAK_GLYPHS.contains(&c))
+}
+
+/// Split chapter HTML at `<hr>` elements and at paragraphs holding nothing
+/// but a break marker. Text without markup is split at marker lines.
+fn split_at_breaks(html: &str) -> Vec<String> {
+    if !html.contains('<') {
+        return split_plain_text(html);
+    }
+
+    // ASCII lowercasing keeps byte offsets, so positions carry over to `html`
+    let lower = html.to_ascii_lowercase();
+    let mut segments = Vec::new();
+    let mut start = 0;
+    let mut pos = 0;
+    while let Some(offset) = lower[pos..].find('<') {
+        let tag_start = pos + offset;
+        let Some(tag_len) = lower[tag_start..].find('>') else {
+            break;
+        };
+        let tag_end = tag_start + tag_len + 1;
+        let name: String = lower[tag_start + 1..]
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .collect();
+        let break_end = match name.as_str() {
+            "hr" => Some(tag_end),
+            "p" => lower[tag_end..]
+                .find("</p>")
+                .map(|close| tag_end + close)
+                .filter(|&close| is_break_text(&text::strip_html(&html[tag_end..close])))
+                .map(|close| close + "</p>".len()),
+            _ => None,
+        };
+        match break_end {
+            Some(end) => {
+                segments.push(&html[start..tag_start]);
+                start = end;
+                pos = end;
+            }
+            None => pos = tag_end,
+        }
+    }
+    segments.push(&html[start..]);
+
+    segments
+        .into_iter()
+        .map(str::trim)
+        .filter(|segment| {
+            !text::strip_html(segment).is_empty() || segment.to_ascii_lowercase().contains("<img")
+        })
+        .map(str::to_string)
+        .collect()
+}
+
+fn split_plain_text(content: &str) -> Vec<String> {
+    let mut segments = vec![String::new()];
+    for line in content.lines() {
+        if is_break_text(line) {
+            segments.push(String::new());
+        } else if let Some(segment) = segments.last_mut() {
+            segment.push_str(line);
+            segment.push('\n');
+        }
+    }
+    segments
+        .into_iter()
+        .map(|segment| segment.trim().to_string())
+        .filter(|segment| !segment.is_empty())
+        .collect()
+}
+
+/// Text of the heading a segment opens with, if any
+fn segment_title(segment: &str) -> Option<String> {
+    let lower = segment.to_ascii_lowercase();
+    let level = ["<h1", "<h2", "<h3", "<h4", "<h5", "<h6"]
+        .iter()
+        .position(|tag| lower.starts_with(tag))?
+        + 1;
+    let close = lower.find(&format!("</h{}>", level))?;
+    let title = text::strip_html(&segment[..close]);
+    (!title.is_empty()).then_some(title)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database;
+    use crate::database::test_support::project_db;
+    use serde_json::json;
+
+    #[test]
+    fn test_split_and_compile_chapter_scenes() {
+        let conn = project_db();
+        let chapter = serde_json::from_value(json!({
+            "id": "c1",
+            "projectId": "p1",
+            "title": "Arrival",
+            "content": "<h2>Dock</h2><p>The ship came in.</p><p>* * *</p>\
+                        <p>Night fell.</p><hr><p>Morning.</p><p>Sun.</p>",
+        }))
+        .unwrap();
+        database::create_chapter(&conn, &chapter).unwrap();
+
+        let split = split_chapter_into_scenes(&conn, "c1").unwrap();
+        assert_eq!(split.chapter.content_mode, ContentMode::Scenes);
+        let titles: Vec<&str> = split.scenes.iter().map(|s| s.title.as_str()).collect();
+        assert_eq!(titles, ["Dock", "Scene 2", "Scene 3"]);
+        assert_eq!(split.scenes[2].word_count, 2);
+        assert_eq!(
+            split.chapter.content,
+            "<h2>Dock</h2><p>The ship came in.</p><p>* * *</p><p>Night fell.</p>\
+             <p>* * *</p><p>Morning.</p><p>Sun.</p>"
+        );
+        assert!(matches!(
+            split_chapter_into_scenes(&conn, "c1"),
+            Err(AssemblyError::ScenesHaveContent(_))
+        ));
+
+        // Scene edits flow into the chapter, and chapter saves cannot
+        // overwrite the compiled text
+        let mut night = split.scenes[1].clone();
+        night.content = Some("<p>Night fell hard.</p>".into());
+        database::update_scene(&conn, &night).unwrap();
+        database::delete_scene(&conn, &split.scenes[0].id).unwrap();
+        let mut chapter = database::get_chapter(&conn, "c1").unwrap().unwrap();
+        assert_eq!(
+            chapter.content,
+            "<p>Night fell hard.</p><p>* * *</p><p>Morning.</p><p>Sun.</p>"
+        );
+        assert_eq!(chapter.word_count, 5);
+        chapter.content = "stale".into();
+        database::update_chapter(&conn, &chapter).unwrap();
+        assert_ne!(
+            database::get_chapter(&conn, "c1").unwrap().unwrap().content,
+            "stale"
+        );
+
+        let chapter = set_chapter_content_mode(&conn, "c1", ContentMode::Document).unwrap();
+        assert_eq!(chapter.content_mode, ContentMode::Document);
+        let mut morning = split.scenes[2].clone();
+        morning.content = Some("<p>Changed.</p>".into());
+        database::update_scene(&conn, &morning).unwrap();
+        assert!(database::get_chapter(&conn, "c1")
+            .unwrap()
+            .unwrap()
+            .content
+            .contains("Morning."));
+    }
+
+    #[test]
+    fn test_split_plain_text() {
+        assert_eq!(
+            split_plain_text("One.\n***\nTwo.\n  #  \n\nThree.\n"),
+            ["One.", "Two.", "Three."]
+        );
+        assert_eq!(split_at_breaks("<p>Only</p>"), ["<p>Only</p>"]);
+    }
+}
@@ -0,0 +1,472 @@
+//! Append-only audit log of entity changes
+//!
+//! Every insert, update and delete on an entity table is recorded by
+//! triggers, so changes are logged whichever code path makes them (single
+//! edits, batches, the trash, sync). An entry holds the columns that
+//! changed with their new values; long text columns are only flagged
+//! `true`, chapter revisions keep their text.
+//!
+//! Editors autosave as the user types. Every save keeps its own entry; the
+//! listing folds a run of them (same row, columns and source, each within
+//! [`COALESCE_SECS`] of the one before) into its latest entry.
+//!
+//! Triggers cannot know who asked for a change, so callers acting for
+//! something other than the user wrap their writes in [`with_audit_source`];
+//! the triggers read it back through the `audit_source()` SQL function.
+
+use super::models::EntityKind;
+use rusqlite::{params_from_iter, types::Value as SqlValue, Connection, Result};
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+
+/// Default and maximum number of entries returned per page
+const DEFAULT_LIMIT: u32 = 100;
+const MAX_LIMIT: u32 = 1000;
+
+/// Entity types whose changes are logged
+pub(crate) const AUDITED: &[EntityKind] = &[
+    EntityKind::Project,
+    EntityKind::Chapter,
+    EntityKind::Scene,
+    EntityKind::Character,
+    EntityKind::Relationship,
+    EntityKind::Location,
+    EntityKind::LoreItem,
+    EntityKind::TimelineEvent,
+    EntityKind::Creature,
+    EntityKind::Npc,
+    EntityKind::WorldRule,
+];
+
+/// Columns left out of change sets: identity, bookkeeping and
+/// `deleted_at`, which shows as the entry's action instead
+const SKIPPED_COLUMNS: &[&str] = &["id", "created_at", "updated_at", "deleted_at"];
+
+/// Columns too long to copy into every entry
+const LONG_COLUMNS: &[&str] = &["content"];
+
+/// Gap within which repeated updates of a row are listed as one
+const COALESCE_SECS: i64 = 60;
+
+/// Who asked for a change
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AuditSource {
+    /// Edits made in the app
+    User,
+    /// Changes applied by an AI assistant
+    Ai,
+    /// Content injected from a package
+    Package,
+    /// Changes read from the project folder
+    Sync,
+}
+
+impl AuditSource {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            AuditSource::User => "user",
+            AuditSource::Ai => "ai",
+            AuditSource::Package => "package",
+            AuditSource::Sync => "sync",
+        }
+    }
+
+    fn from_db(value: &str) -> Self {
+        match value {
+            "ai" => AuditSource::Ai,
+            "package" => AuditSource::Package,
+            "sync" => AuditSource::Sync,
+            _ => AuditSource::User,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AuditAction {
+    Create,
+    Update,
+    /// Moved to the trash
+    Delete,
+    /// Restored from the trash
+    Restore,
+    /// Deleted for good
+    Purge,
+}
+
+impl AuditAction {
+    fn from_db(value: &str) -> Self {
+        match value {
+            "create" => AuditAction::Create,
+            "delete" => AuditAction::Delete,
+            "restore" => AuditAction::Restore,
+            "purge" => AuditAction::Purge,
+            _ => AuditAction::Update,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEntry {
+    pub id: i64,
+    pub project_id: Option<String>,
+    pub entity_type: EntityKind,
+    pub entity_id: String,
+    pub action: AuditAction,
+    pub source: AuditSource,
+    /// Changed columns and their new values
+    pub changes: serde_json::Value,
+    pub created_at: String,
+    /// Earlier autosaves listed as part of this entry
+    pub folded: u32,
+}
+
+/// Narrows a listing of the log
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditFilter {
+    /// Only entries changing this column, e.g. `current_vital_status`.
+    /// Every change of it is listed, autosaves are not folded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<AuditSource>,
+    /// Only entries older than this one, to page back through the log
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub before: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u32>,
+}
+
+thread_local! {
+    static SOURCE: Cell<AuditSource> = const { Cell::new(AuditSource::User) };
+}
+
+/// Restores the previous source when dropped, even on panic
+struct SourceGuard(AuditSource);
+
+impl Drop for SourceGuard {
+    fn drop(&mut self) {
+        SOURCE.with(|source| source.set(self.0));
+    }
+}
+
+/// Run `f` with its changes attributed to `source`
+pub fn with_audit_source<T>(source: AuditSource, f: impl FnOnce() -> T) -> T {
+    let _guard = SourceGuard(SOURCE.with(|current| current.replace(source)));
+    f()
+}
+
+/// Source changes made on this thread are attributed to
+pub(crate) fn current_source() -> AuditSource {
+    SOURCE.with(Cell::get)
+}
+
+/// (Re)create the audit triggers of every entity table from its current
+/// columns. Migrations that add columns to, or rebuild, an entity table
+/// must call this again.
+pub(crate) fn install_audit_triggers(conn: &Connection) -> Result<()> {
+    for kind in AUDITED {
+        let table = kind.table();
+        let columns = table_columns(conn, table)?;
+        let columns: Vec<&str> = columns
+            .iter()
+            .map(String::as_str)
+            .filter(|c| !SKIPPED_COLUMNS.contains(c))
+            .collect();
+
+        let created = change_set(&columns, |c| format!("new.{} IS NOT NULL", c));
+        let updated = change_set(&columns, |c| format!("new.{c} IS NOT old.{c}"));
+
+        conn.execute_batch(&format!(
+            r#"
+DROP TRIGGER IF EXISTS audit_{table}_insert;
+DROP TRIGGER IF EXISTS audit_{table}_update;
+DROP TRIGGER IF EXISTS audit_{table}_delete;
+
+CREATE TRIGGER audit_{table}_insert AFTER INSERT ON {table} BEGIN
+    INSERT INTO audit_log (project_id, entity_type, entity_id, action, source, changes)
+    VALUES ({new_project}, '{kind}', new.id, 'create', audit_source(), {created});
+END;
+
+CREATE TRIGGER audit_{table}_update AFTER UPDATE ON {table} BEGIN
+    INSERT INTO audit_log (project_id, entity_type, entity_id, action, source, changes)
+    SELECT {new_project}, '{kind}', new.id, action, audit_source(), changes
+    FROM (SELECT CASE
+                     WHEN old.deleted_at IS NULL AND new.deleted_at IS NOT NULL THEN 'delete'
+                     WHEN old.deleted_at IS NOT NULL AND new.deleted_at IS NULL THEN 'restore'
+                     ELSE 'update'
+                 END AS action,
+                 {updated} AS changes)
+    WHERE action != 'update' OR changes != '{{}}';
+END;
+
+CREATE TRIGGER audit_{table}_delete AFTER DELETE ON {table} BEGIN
+    INSERT INTO audit_log (project_id, entity_type, entity_id, action, source, changes)
+    VALUES ({old_project}, '{kind}', old.id, 'purge', audit_source(), '{{}}');
+END;
+"#,
+            table = table,
+            kind = kind.as_str(),
+            new_project = owning_project(*kind, "new"),
+            old_project = owning_project(*kind, "old"),
+            created = created,
+            updated = updated,
+        ))?;
+    }
+    Ok(())
+}
+
+/// Columns of a table, in order
+pub(crate) fn table_columns(conn: &Connection, table: &str) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare("SELECT name FROM pragma_table_info(?1) ORDER BY cid")?;
+    let rows = stmt.query_map([table], |row| row.get(0))?;
+    rows.collect()
+}
+
+/// SQL giving the project an entity belongs to, from a trigger's `new` or
+/// `old` row
+pub(crate) fn owning_project(kind: EntityKind, row: &str) -> String {
+    match kind {
+        EntityKind::Project => format!("{}.id", row),
+        EntityKind::Scene => format!(
+            "(SELECT project_id FROM chapters WHERE id = {}.chapter_id)",
+            row
+        ),
+        EntityKind::Relationship => format!(
+            "(SELECT project_id FROM characters WHERE id = {}.character_id)",
+            row
+        ),
+        _ => format!("{}.project_id", row),
+    }
+}
+
+/// SQL building a JSON object of the new values of the columns for which
+/// `changed` holds. JSON text columns are embedded as JSON.
+fn change_set(columns: &[&str], changed: impl Fn(&str) -> String) -> String {
+    let members: Vec<String> = columns
+        .iter()
+        .map(|c| {
+            let value = if LONG_COLUMNS.contains(c) {
+                "'true'".to_string()
+            } else {
+                format!(
+                    "CASE WHEN json_valid(new.{c}) AND substr(new.{c}, 1, 1) IN ('[', '{{') \
+                     THEN json(new.{c}) ELSE json_quote(new.{c}) END"
+                )
+            };
+            format!(
+                "CASE WHEN {} THEN ',\"{}\":' || {} ELSE '' END",
+                changed(c),
+                c,
+                value
+            )
+        })
+        .collect();
+    format!("'{{' || substr({}, 2) || '}}'", members.join(" || "))
+}
+
+/// Entries of the log, newest first, for a project or, with `entity`, a
+/// single entity. Runs of autosaves are listed as their latest entry.
+pub fn get_audit_log(
+    conn: &Connection,
+    project_id: Option<&str>,
+    entity: Option<(EntityKind, &str)>,
+    filter: &AuditFilter,
+) -> Result<Vec<AuditEntry>> {
+    let mut sql = String::from(
+        r#"SELECT id, project_id, entity_type, entity_id, action, source, changes, created_at,
+                  (SELECT group_concat(key) FROM json_each(changes)) AS keys
+           FROM audit_log WHERE 1 = 1"#,
+    );
+    let mut values = Vec::new();
+    if let Some(project_id) = project_id {
+        sql.push_str(" AND project_id = ?");
+        values.push(SqlValue::Text(project_id.to_string()));
+    }
+    if let Some((kind, id)) = entity {
+        sql.push_str(" AND entity_type = ? AND entity_id = ?");
+        values.push(SqlValue::Text(kind.as_str().to_string()));
+        values.push(SqlValue::Text(id.to_string()));
+    }
+    if let Some(field) = &filter.field {
+        sql.push_str(" AND json_type(changes, ?) IS NOT NULL");
+        values.push(SqlValue::Text(format!("$.\"{}\"", field.replace('"', ""))));
+    }
+    if let Some(source) = filter.source {
+        sql.push_str(" AND source = ?");
+        values.push(SqlValue::Text(source.as_str().to_string()));
+    }
+
+    // An entry starts a run unless it repeats the previous entry of its row
+    let starts_run = if filter.field.is_some() {
+        "1".to_string()
+    } else {
+        format!(
+            r#"CASE WHEN action = 'update'
+                     AND LAG(action) OVER row_log = 'update'
+                     AND LAG(source) OVER row_log = source
+                     AND LAG(keys) OVER row_log = keys
+                     AND created_at <= datetime(LAG(created_at) OVER row_log, '+{} seconds')
+                    THEN 0 ELSE 1 END"#,
+            COALESCE_SECS
+        )
+    };
+    let mut sql = format!(
+        r#"WITH entries AS ({sql}),
+           marked AS (
+               SELECT *, {starts_run} AS starts_run FROM entries
+               WINDOW row_log AS (PARTITION BY entity_type, entity_id ORDER BY id)
+           ),
+           runs AS (
+               SELECT *, SUM(starts_run) OVER (PARTITION BY entity_type, entity_id ORDER BY id) AS run
+               FROM marked
+           ),
+           listed AS (
+               SELECT *,
+                      ROW_NUMBER() OVER (PARTITION BY entity_type, entity_id, run ORDER BY id DESC) AS newest,
+                      COUNT(*) OVER (PARTITION BY entity_type, entity_id, run) - 1 AS folded
+               FROM runs
+           )
+           SELECT id, project_id, entity_type, entity_id, action, source, changes, created_at, folded
+           FROM listed WHERE newest = 1"#,
+        sql = sql,
+        starts_run = starts_run,
+    );
+    // Paging applies to listed entries, so a run is never split between pages
+    if let Some(before) = filter.before {
+        sql.push_str(" AND id < ?");
+        values.push(SqlValue::Integer(before));
+    }
+    sql.push_str(" ORDER BY id DESC LIMIT ?");
+    let limit = filter.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    values.push(SqlValue::Integer(limit as i64));
+
+    let mut stmt = conn.prepare(&sql)?;
+    let rows = stmt.query_map(params_from_iter(values), |row| {
+        let entity_type: String = row.get(2)?;
+        let action: String = row.get(4)?;
+        let source: String = row.get(5)?;
+        let changes: String = row.get(6)?;
+        Ok(AuditEntry {
+            id: row.get(0)?,
+            project_id: row.get(1)?,
+            entity_type: EntityKind::from_db(&entity_type).unwrap_or(EntityKind::Project),
+            entity_id: row.get(3)?,
+            action: AuditAction::from_db(&action),
+            source: AuditSource::from_db(&source),
+            changes: serde_json::from_str(&changes).unwrap_or_default(),
+            created_at: row.get(7)?,
+            folded: row.get(8)?,
+        })
+    })?;
+    rows.collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database;
+    use crate::database::test_support::project_db;
+    use serde_json::json;
+
+    #[test]
+    fn test_logs_changes_with_their_source() {
+        let conn = project_db();
+        conn.execute_batch(
+            r#"INSERT INTO characters (id, project_id, name, current_vital_status, attributes)
+               VALUES ('c1', 'p1', 'Ana', 'alive', '{"str":10}');"#,
+        )
+        .unwrap();
+        with_audit_source(AuditSource::Package, || {
+            conn.execute_batch(
+                r#"UPDATE characters SET current_vital_status = 'dead' WHERE id = 'c1';
+                   UPDATE characters SET name = 'Ana' WHERE id = 'c1';"#,
+            )
+        })
+        .unwrap();
+        assert_eq!(current_source(), AuditSource::User);
+        // Autosaves of the same field share one entry with the latest value
+        conn.execute_batch(
+            r#"UPDATE characters SET notes = 'Tall' WHERE id = 'c1';
+               UPDATE characters SET notes = 'Tall, grey' WHERE id = 'c1';"#,
+        )
+        .unwrap();
+        database::delete_character(&conn, "c1").unwrap();
+
+        let log = get_audit_log(
+            &conn,
+            None,
+            Some((EntityKind::Character, "c1")),
+            &AuditFilter::default(),
+        )
+        .unwrap();
+        let actions: Vec<_> = log.iter().map(|e| (e.action, e.source)).collect();
+        // The no-op rename is not logged
+        assert_eq!(
+            actions,
+            [
+                (AuditAction::Delete, AuditSource::User),
+                (AuditAction::Update, AuditSource::User),
+                (AuditAction::Update, AuditSource::Package),
+                (AuditAction::Create, AuditSource::User),
+            ]
+        );
+        assert_eq!(log[1].changes, json!({ "notes": "Tall, grey" }));
+        assert_eq!(log[1].folded, 1);
+        assert_eq!(log[2].changes, json!({ "current_vital_status": "dead" }));
+        assert_eq!(log[3].changes["attributes"], json!({ "str": 10 }));
+
+        let flips = get_audit_log(
+            &conn,
+            Some("p1"),
+            None,
+            &AuditFilter {
+                field: Some("current_vital_status".to_string()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(flips.len(), 2);
+    }
+
+    #[test]
+    fn test_keeps_each_change_of_a_reverted_flip() {
+        let conn = project_db();
+        conn.execute_batch(
+            r#"INSERT INTO characters (id, project_id, name, current_vital_status)
+               VALUES ('c1', 'p1', 'Ana', 'alive');
+               UPDATE characters SET current_vital_status = 'dead' WHERE id = 'c1';
+               UPDATE characters SET current_vital_status = 'alive' WHERE id = 'c1';"#,
+        )
+        .unwrap();
+
+        let updates: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM audit_log WHERE entity_id = 'c1' AND action = 'update'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(updates, 2);
+
+        let flips = get_audit_log(
+            &conn,
+            None,
+            Some((EntityKind::Character, "c1")),
+            &AuditFilter {
+                field: Some("current_vital_status".to_string()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let statuses: Vec<_> = flips
+            .iter()
+            .map(|e| e.changes["current_vital_status"].clone())
+            .collect();
+        // Newest first, down to the value the character was created with
+        assert_eq!(statuses, [json!("alive"), json!("dead"), json!("alive")]);
+    }
+}
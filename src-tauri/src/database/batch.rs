@@ -0,0 +1,370 @@
+//! Atomic batches of create/update/delete operations
+//!
+//! A batch runs in a single savepoint: either every operation is applied or
+//! none is. Each operation still reports its own outcome, so the caller can
+//! tell which one failed and why.
+
+use super::custom_fields::CustomFieldError;
+use super::models::*;
+use super::operations::*;
+use super::ordering::OrderingError;
+use rusqlite::{params, Connection};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum BatchAction {
+    Create,
+    Update,
+    Delete,
+}
+
+/// One operation of a batch
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchOperation {
+    pub action: BatchAction,
+    pub entity_type: EntityKind,
+    /// Entity id; required for deletes, otherwise taken from `data`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// The entity, shaped as for the matching `db_create_*`/`db_update_*`
+    /// command. A create without an id gets a new one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+    /// Owning character, required to create or update a relationship
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub character_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum BatchStatus {
+    /// Applied and committed
+    Applied,
+    /// Applied, then undone because a later operation failed
+    RolledBack,
+    /// The operation that aborted the batch
+    Failed,
+    /// Not attempted because an earlier operation failed
+    Skipped,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchOperationResult {
+    pub index: usize,
+    pub status: BatchStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchResult {
+    /// Whether the batch was committed
+    pub committed: bool,
+    pub results: Vec<BatchOperationResult>,
+}
+
+#[derive(Error, Debug)]
+pub enum BatchError {
+    #[error("Invalid {kind} data: {source}")]
+    InvalidData {
+        kind: &'static str,
+        source: serde_json::Error,
+    },
+
+    #[error("Missing {0}")]
+    MissingField(&'static str),
+
+    #[error("{kind} {id} not found")]
+    NotFound { kind: &'static str, id: String },
+
+    #[error(transparent)]
+    Ordering(#[from] OrderingError),
+
+    #[error(transparent)]
+    CustomField(#[from] CustomFieldError),
+
+    #[error("Database error: {0}")]
+    Database(#[from] rusqlite::Error),
+}
+
+/// Apply `operations` in order inside one savepoint. Database errors outside
+/// of any operation (opening or closing the savepoint) are returned as `Err`.
+pub fn apply_batch(
+    conn: &Connection,
+    operations: &[BatchOperation],
+) -> rusqlite::Result<BatchResult> {
+    conn.execute_batch("SAVEPOINT plumai_batch")?;
+
+    let mut results = Vec::with_capacity(operations.len());
+    let mut failed = false;
+    for (index, operation) in operations.iter().enumerate() {
+        if failed {
+            results.push(BatchOperationResult {
+                index,
+                status: BatchStatus::Skipped,
+                id: None,
+                error: None,
+            });
+            continue;
+        }
+        match apply_operation(conn, operation) {
+            Ok(id) => results.push(BatchOperationResult {
+                index,
+                status: BatchStatus::Applied,
+                id: Some(id),
+                error: None,
+            }),
+            Err(e) => {
+                failed = true;
+                results.push(BatchOperationResult {
+                    index,
+                    status: BatchStatus::Failed,
+                    id: operation.id.clone(),
+                    error: Some(e.to_string()),
+                });
+            }
+        }
+    }
+
+    if failed {
+        conn.execute_batch("ROLLBACK TO plumai_batch; RELEASE plumai_batch")?;
+        for result in &mut results {
+            if result.status == BatchStatus::Applied {
+                result.status = BatchStatus::RolledBack;
+            }
+        }
+        log::warn!("Batch of {} operation(s) rolled back", operations.len());
+    } else {
+        conn.execute_batch("RELEASE plumai_batch")?;
+    }
+
+    Ok(BatchResult {
+        committed: !failed,
+        results,
+    })
+}
+
+/// Apply one operation, returning the id of the entity it touched
+fn apply_operation(conn: &Connection, operation: &BatchOperation) -> Result<String, BatchError> {
+    let kind = operation.entity_type;
+    match operation.action {
+        BatchAction::Create => {
+            let data = with_id(operation)?;
+            let id = data["id"].as_str().unwrap_or_default().to_string();
+            match kind {
+                EntityKind::Project => create_project(conn, &parse(kind, data)?)?,
+                EntityKind::Chapter => create_chapter(conn, &parse(kind, data)?)?,
+                EntityKind::Scene => create_scene(conn, &parse(kind, data)?)?,
+                EntityKind::Character => create_character(conn, &parse(kind, data)?)?,
+                EntityKind::Relationship => {
+                    create_relationship(conn, owner(operation)?, &parse(kind, data)?)?
+                }
+                EntityKind::Location => create_location(conn, &parse(kind, data)?)?,
+                EntityKind::LoreItem => create_lore_item(conn, &parse(kind, data)?)?,
+                EntityKind::TimelineEvent => create_timeline_event(conn, &parse(kind, data)?)?,
+                EntityKind::Creature => create_creature(conn, &parse(kind, data)?)?,
+                EntityKind::Npc => create_npc(conn, &parse(kind, data)?)?,
+                EntityKind::WorldRule => create_world_rule(conn, &parse(kind, data)?)?,
+            }
+            Ok(id)
+        }
+        BatchAction::Update => {
+            let data = operation
+                .data
+                .clone()
+                .ok_or(BatchError::MissingField("data"))?;
+            let id = data
+                .get("id")
+                .and_then(Value::as_str)
+                .or(operation.id.as_deref())
+                .ok_or(BatchError::MissingField("id"))?
+                .to_string();
+            ensure_exists(conn, kind, &id)?;
+            match kind {
+                EntityKind::Project => update_project(conn, &parse(kind, data)?)?,
+                EntityKind::Chapter => update_chapter(conn, &parse(kind, data)?)?,
+                EntityKind::Scene => update_scene(conn, &parse(kind, data)?)?,
+                EntityKind::Character => update_character(conn, &parse(kind, data)?)?,
+                EntityKind::Relationship => {
+                    update_relationship(conn, owner(operation)?, &parse(kind, data)?)?
+                }
+                EntityKind::Location => update_location(conn, &parse(kind, data)?)?,
+                EntityKind::LoreItem => update_lore_item(conn, &parse(kind, data)?)?,
+                EntityKind::TimelineEvent => update_timeline_event(conn, &parse(kind, data)?)?,
+                EntityKind::Creature => update_creature(conn, &parse(kind, data)?)?,
+                EntityKind::Npc => update_npc(conn, &parse(kind, data)?)?,
+                EntityKind::WorldRule => update_world_rule(conn, &parse(kind, data)?)?,
+            }
+            Ok(id)
+        }
+        BatchAction::Delete => {
+            let id = operation.id.clone().ok_or(BatchError::MissingField("id"))?;
+            ensure_exists(conn, kind, &id)?;
+            match kind {
+                EntityKind::Project => delete_project(conn, &id)?,
+                EntityKind::Chapter => delete_chapter(conn, &id)?,
+                EntityKind::Scene => delete_scene(conn, &id)?,
+                EntityKind::Character => delete_character(conn, &id)?,
+                EntityKind::Relationship => delete_relationship(conn, &id)?,
+                EntityKind::Location => delete_location(conn, &id)?,
+                EntityKind::LoreItem => delete_lore_item(conn, &id)?,
+                EntityKind::TimelineEvent => delete_timeline_event(conn, &id)?,
+                EntityKind::Creature => delete_creature(conn, &id)?,
+                EntityKind::Npc => delete_npc(conn, &id)?,
+                EntityKind::WorldRule => delete_world_rule(conn, &id)?,
+            }
+            Ok(id)
+        }
+    }
+}
+
+/// The create payload, with an id filled in from the operation or a new one
+fn with_id(operation: &BatchOperation) -> Result<Value, BatchError> {
+    let mut data = operation
+        .data
+        .clone()
+        .ok_or(BatchError::MissingField("data"))?;
+    if let Value::Object(fields) = &mut data {
+        let has_id = matches!(fields.get("id"), Some(Value::String(id)) if !id.is_empty());
+        if !has_id {
+            let id = operation
+                .id
+                .clone()
+                .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+            fields.insert("id".into(), Value::String(id));
+        }
+    }
+    Ok(data)
+}
+
+fn parse<T: DeserializeOwned>(kind: EntityKind, data: Value) -> Result<T, BatchError> {
+    serde_json::from_value(data).map_err(|source| BatchError::InvalidData {
+        kind: kind.as_str(),
+        source,
+    })
+}
+
+fn owner(operation: &BatchOperation) -> Result<&str, BatchError> {
+    operation
+        .character_id
+        .as_deref()
+        .ok_or(BatchError::MissingField("characterId"))
+}
+
+/// Updates and deletes of missing (or trashed) entities would silently
+/// match no rows; in a batch that is almost certainly a stale id
+fn ensure_exists(conn: &Connection, kind: EntityKind, id: &str) -> Result<(), BatchError> {
+    let exists: bool = conn.query_row(
+        &format!(
+            "SELECT EXISTS(SELECT 1 FROM {} WHERE id = ?1 AND deleted_at IS NULL)",
+            kind.table()
+        ),
+        params![id],
+        |row| row.get(0),
+    )?;
+    if exists {
+        Ok(())
+    } else {
+        Err(BatchError::NotFound {
+            kind: kind.as_str(),
+            id: id.to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database;
+    use crate::database::test_support::empty_db;
+    use serde_json::json;
+
+    fn op(value: Value) -> BatchOperation {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_batch_commits_or_rolls_back_as_a_whole() {
+        let conn = empty_db();
+
+        let result = apply_batch(
+            &conn,
+            &[
+                op(json!({
+                    "action": "create",
+                    "entityType": "project",
+                    "data": { "id": "p1", "title": "Saga" },
+                })),
+                op(json!({
+                    "action": "create",
+                    "entityType": "chapter",
+                    "data": { "id": "c1", "projectId": "p1", "title": "Arrival" },
+                })),
+                op(json!({
+                    "action": "create",
+                    "entityType": "scene",
+                    "data": { "chapterId": "c1", "title": "Dock" },
+                })),
+            ],
+        )
+        .unwrap();
+        assert!(result.committed);
+        let scene_id = result.results[2].id.clone().unwrap();
+        assert_eq!(
+            database::get_scenes_by_chapter(&conn, "c1").unwrap()[0].id,
+            scene_id
+        );
+
+        let result = apply_batch(
+            &conn,
+            &[
+                op(json!({
+                    "action": "create",
+                    "entityType": "chapter",
+                    "data": { "id": "c2", "projectId": "p1", "title": "Storm" },
+                })),
+                op(json!({ "action": "delete", "entityType": "scene", "id": scene_id })),
+                op(json!({ "action": "delete", "entityType": "scene", "id": "missing" })),
+                op(json!({ "action": "delete", "entityType": "chapter", "id": "c1" })),
+            ],
+        )
+        .unwrap();
+        assert!(!result.committed);
+        let statuses: Vec<BatchStatus> = result.results.iter().map(|r| r.status).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                BatchStatus::RolledBack,
+                BatchStatus::RolledBack,
+                BatchStatus::Failed,
+                BatchStatus::Skipped,
+            ]
+        );
+        assert!(result.results[2]
+            .error
+            .as_deref()
+            .unwrap()
+            .contains("not found"));
+
+        // Nothing from the failed batch stuck
+        assert_eq!(
+            database::get_chapters_by_project(&conn, "p1")
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(
+            database::get_scenes_by_chapter(&conn, "c1").unwrap().len(),
+            1
+        );
+    }
+}
@@ -0,0 +1,65 @@
+//! Connection setup shared by every SQLite connection the app opens
+
+use super::audit;
+use super::text;
+use super::undo;
+use rusqlite::functions::FunctionFlags;
+use rusqlite::{Connection, OpenFlags, Result};
+use std::path::Path;
+use std::time::Duration;
+
+/// How long a statement waits on a lock held by another connection before
+/// failing with SQLITE_BUSY
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Open a database file and configure the connection
+pub fn open_connection(path: &Path) -> Result<Connection> {
+    let conn = Connection::open(path)?;
+    // Lets readers work while a write is in progress. Persistent, so it
+    // only has to be set by the connection that writes.
+    conn.pragma_update(None, "journal_mode", "WAL")?;
+    // Safe under WAL: a crash can lose the last commits, never corrupt
+    conn.pragma_update(None, "synchronous", "NORMAL")?;
+    configure_connection(&conn)?;
+    Ok(conn)
+}
+
+/// Open a read-only connection to a database file
+pub(crate) fn open_read_connection(path: &Path) -> Result<Connection> {
+    let conn = Connection::open_with_flags(
+        path,
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )?;
+    configure_connection(&conn)?;
+    Ok(conn)
+}
+
+/// Enforce foreign keys, wait out other connections' locks and register
+/// the SQL functions the schema's triggers rely on
+pub fn configure_connection(conn: &Connection) -> Result<()> {
+    // Off by default in SQLite, and per connection
+    conn.pragma_update(None, "foreign_keys", true)?;
+    conn.busy_timeout(BUSY_TIMEOUT)?;
+
+    conn.create_scalar_function(
+        "strip_html",
+        1,
+        FunctionFlags::SQLITE_UTF8
+            | FunctionFlags::SQLITE_DETERMINISTIC
+            | FunctionFlags::SQLITE_INNOCUOUS,
+        |ctx| {
+            let html: Option<String> = ctx.get(0)?;
+            Ok(html.map(|h| text::strip_html(&h)))
+        },
+    )?;
+
+    // Who the audit log triggers attribute changes to
+    conn.create_scalar_function("audit_source", 0, FunctionFlags::SQLITE_UTF8, |_| {
+        Ok(audit::current_source().as_str())
+    })?;
+
+    // Undo action the journal triggers file changes under
+    conn.create_scalar_function("undo_action", 0, FunctionFlags::SQLITE_UTF8, |_| {
+        Ok(undo::current_action())
+    })
+}
@@ -0,0 +1,625 @@
+//! User-defined custom fields on characters, locations and lore items
+//!
+//! Definitions live in the `custom_fields` table, one per project, entity
+//! type and name. Values are stored as a JSON object in the entity's own
+//! `custom_fields` column, keyed by field id, and are validated against the
+//! definitions on every write. Null values are dropped rather than stored.
+
+use super::models::{CustomField, CustomFieldType, EntityKind};
+use super::operations::with_savepoint;
+use rusqlite::{params, params_from_iter, types::Value as SqlValue, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum CustomFieldError {
+    #[error("{0} entities do not have custom fields")]
+    NotSupported(&'static str),
+
+    #[error("Custom field {0} not found")]
+    NotFound(String),
+
+    #[error("Custom field {field} is not defined for {kind} entities")]
+    UnknownField { kind: &'static str, field: String },
+
+    #[error("Invalid custom field definition: {0}")]
+    InvalidDefinition(String),
+
+    #[error("Invalid value for custom field \"{field}\": {reason}")]
+    InvalidValue { field: String, reason: String },
+
+    #[error("Database error: {0}")]
+    Database(#[from] rusqlite::Error),
+}
+
+/// How a filter compares a field's value
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FilterOp {
+    Eq,
+    Ne,
+    Lt,
+    Lte,
+    Gt,
+    Gte,
+    /// Case-insensitive substring match
+    Contains,
+    /// The field has a value
+    Exists,
+    /// The field has no value
+    Missing,
+}
+
+/// A condition on one custom field
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CustomFieldFilter {
+    pub field_id: String,
+    pub op: FilterOp,
+    /// Operand; unused by `Exists` and `Missing`
+    #[serde(default)]
+    pub value: Option<Value>,
+}
+
+/// Whether entities of this kind can carry custom field values
+pub fn supports_custom_fields(kind: EntityKind) -> bool {
+    matches!(
+        kind,
+        EntityKind::Character | EntityKind::Location | EntityKind::LoreItem
+    )
+}
+
+/// Whether an entity-reference field may point at entities of this kind
+fn is_referenceable(kind: EntityKind) -> bool {
+    !matches!(
+        kind,
+        EntityKind::Project | EntityKind::Scene | EntityKind::Relationship
+    )
+}
+
+pub fn create_custom_field(conn: &Connection, field: &CustomField) -> Result<(), CustomFieldError> {
+    check_definition(field)?;
+    conn.execute(
+        r#"INSERT INTO custom_fields (id, project_id, entity_type, name, field_type, options, reference_type, position)
+           VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)"#,
+        params![
+            field.id,
+            field.project_id,
+            field.entity_type.as_str(),
+            field.name.trim(),
+            field.field_type.as_str(),
+            options_column(field),
+            field.reference_type.map(|kind| kind.as_str()),
+            field.position,
+        ],
+    )?;
+    Ok(())
+}
+
+/// Field definitions of a project, optionally for one entity type only
+pub fn get_custom_fields(
+    conn: &Connection,
+    project_id: &str,
+    entity_type: Option<EntityKind>,
+) -> rusqlite::Result<Vec<CustomField>> {
+    let mut stmt = conn.prepare(
+        r#"SELECT id, project_id, entity_type, name, field_type, options, reference_type, position
+           FROM custom_fields
+           WHERE project_id = ?1 AND (?2 IS NULL OR entity_type = ?2)
+           ORDER BY entity_type, position, name"#,
+    )?;
+    let rows = stmt.query_map(
+        params![project_id, entity_type.map(|kind| kind.as_str())],
+        |row| {
+            let entity_type: String = row.get(2)?;
+            let field_type: String = row.get(4)?;
+            let reference_type: Option<String> = row.get(6)?;
+            Ok(EntityKind::from_db(&entity_type)
+                .zip(CustomFieldType::from_db(&field_type))
+                .map(
+                    |(entity_type, field_type)| -> rusqlite::Result<CustomField> {
+                        Ok(CustomField {
+                            id: row.get(0)?,
+                            project_id: row.get(1)?,
+                            entity_type,
+                            name: row.get(3)?,
+                            field_type,
+                            options: row
+                                .get::<_, Option<String>>(5)?
+                                .and_then(|s| serde_json::from_str(&s).ok())
+                                .unwrap_or_default(),
+                            reference_type: reference_type.as_deref().and_then(EntityKind::from_db),
+                            position: row.get(7)?,
+                        })
+                    },
+                ))
+        },
+    )?;
+
+    let mut fields = Vec::new();
+    for row in rows {
+        if let Some(field) = row? {
+            fields.push(field?);
+        }
+    }
+    Ok(fields)
+}
+
+/// Update a field definition. Its entity type cannot change. Values that no
+/// longer validate (a dropped enum option, a new type) are cleared; returns
+/// how many entities lost their value.
+pub fn update_custom_field(
+    conn: &Connection,
+    field: &CustomField,
+) -> Result<usize, CustomFieldError> {
+    let current = get_custom_field(conn, &field.id)?;
+    if current.entity_type != field.entity_type {
+        return Err(CustomFieldError::InvalidDefinition(
+            "the entity type of a field cannot change".to_string(),
+        ));
+    }
+    check_definition(field)?;
+
+    let cleared = with_savepoint(conn, || {
+        conn.execute(
+            r#"UPDATE custom_fields SET name = ?2, field_type = ?3, options = ?4, reference_type = ?5,
+               position = ?6, updated_at = CURRENT_TIMESTAMP WHERE id = ?1"#,
+            params![
+                field.id,
+                field.name.trim(),
+                field.field_type.as_str(),
+                options_column(field),
+                field.reference_type.map(|kind| kind.as_str()),
+                field.position,
+            ],
+        )?;
+
+        let table = field.entity_type.table();
+        let path = value_path(&field.id);
+        let values: Vec<(String, String)> = {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT id, json_quote(json_extract(custom_fields, ?2)) FROM {} WHERE project_id = ?1 AND json_type(custom_fields, ?2) IS NOT NULL",
+                table
+            ))?;
+            let rows = stmt.query_map(params![current.project_id, path], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })?;
+            rows.collect::<rusqlite::Result<_>>()?
+        };
+
+        let mut cleared = 0;
+        for (entity_id, value) in values {
+            let value: Value = serde_json::from_str(&value).unwrap_or(Value::Null);
+            if check_value(conn, field, &current.project_id, &value)?.is_err() {
+                conn.execute(
+                    &format!(
+                        "UPDATE {} SET custom_fields = json_remove(custom_fields, ?2) WHERE id = ?1",
+                        table
+                    ),
+                    params![entity_id, path],
+                )?;
+                cleared += 1;
+            }
+        }
+        Ok(cleared)
+    })?;
+    Ok(cleared)
+}
+
+/// Delete a field definition along with every value stored for it
+pub fn delete_custom_field(conn: &Connection, id: &str) -> Result<(), CustomFieldError> {
+    let field = get_custom_field(conn, id)?;
+    with_savepoint(conn, || {
+        conn.execute(
+            &format!(
+                "UPDATE {} SET custom_fields = json_remove(custom_fields, ?2) WHERE project_id = ?1 AND json_type(custom_fields, ?2) IS NOT NULL",
+                field.entity_type.table()
+            ),
+            params![field.project_id, value_path(id)],
+        )?;
+        conn.execute("DELETE FROM custom_fields WHERE id = ?1", params![id])?;
+        Ok(())
+    })?;
+    Ok(())
+}
+
+/// Replace the custom field values of one entity
+pub fn set_custom_field_values(
+    conn: &Connection,
+    kind: EntityKind,
+    id: &str,
+    values: &Map<String, Value>,
+) -> Result<(), CustomFieldError> {
+    if !supports_custom_fields(kind) {
+        return Err(CustomFieldError::NotSupported(kind.as_str()));
+    }
+    let column = checked_values(conn, kind, id, "", values)?;
+    write_column(conn, kind, id, column)?;
+    Ok(())
+}
+
+/// Ids of the live entities of a project matching every filter
+pub fn filter_by_custom_fields(
+    conn: &Connection,
+    project_id: &str,
+    kind: EntityKind,
+    filters: &[CustomFieldFilter],
+) -> Result<Vec<String>, CustomFieldError> {
+    if !supports_custom_fields(kind) {
+        return Err(CustomFieldError::NotSupported(kind.as_str()));
+    }
+    let fields = get_custom_fields(conn, project_id, Some(kind))?;
+
+    let mut sql = format!(
+        "SELECT id FROM {} WHERE project_id = ? AND deleted_at IS NULL",
+        kind.table()
+    );
+    let mut values = vec![SqlValue::Text(project_id.to_string())];
+    for filter in filters {
+        if !fields.iter().any(|f| f.id == filter.field_id) {
+            return Err(CustomFieldError::UnknownField {
+                kind: kind.as_str(),
+                field: filter.field_id.clone(),
+            });
+        }
+        let operand = filter_operand(filter.op, filter.value.as_ref()).map_err(|reason| {
+            CustomFieldError::InvalidValue {
+                field: filter.field_id.clone(),
+                reason,
+            }
+        })?;
+        sql.push_str(" AND ");
+        sql.push_str(&filter_sql(filter.op, "json_extract(custom_fields, ?)"));
+        values.push(SqlValue::Text(value_path(&filter.field_id)));
+        values.extend(operand);
+    }
+    sql.push_str(" ORDER BY id");
+
+    let mut stmt = conn.prepare(&sql)?;
+    let rows = stmt.query_map(params_from_iter(values), |row| row.get(0))?;
+    Ok(rows.collect::<rusqlite::Result<_>>()?)
+}
+
+/// Validate an entity's custom field values before writing them, returning
+/// the text to store in its `custom_fields` column. The project is looked up
+/// from the stored entity when `project_id` is empty.
+pub(crate) fn checked_values(
+    conn: &Connection,
+    kind: EntityKind,
+    entity_id: &str,
+    project_id: &str,
+    values: &Map<String, Value>,
+) -> Result<Option<String>, CustomFieldError> {
+    let values: Map<String, Value> = values
+        .iter()
+        .filter(|(_, value)| !value.is_null())
+        .map(|(id, value)| (id.clone(), value.clone()))
+        .collect();
+    if values.is_empty() {
+        return Ok(None);
+    }
+
+    let project_id = if project_id.is_empty() {
+        conn.query_row(
+            &format!("SELECT project_id FROM {} WHERE id = ?1", kind.table()),
+            params![entity_id],
+            |row| row.get(0),
+        )?
+    } else {
+        project_id.to_string()
+    };
+    let fields = get_custom_fields(conn, &project_id, Some(kind))?;
+
+    for (id, value) in &values {
+        let Some(field) = fields.iter().find(|f| &f.id == id) else {
+            return Err(CustomFieldError::UnknownField {
+                kind: kind.as_str(),
+                field: id.clone(),
+            });
+        };
+        if let Err(reason) = check_value(conn, field, &project_id, value)? {
+            return Err(CustomFieldError::InvalidValue {
+                field: field.name.clone(),
+                reason,
+            });
+        }
+    }
+    Ok(Some(Value::Object(values).to_string()))
+}
+
+/// Store custom field values that were checked before, e.g. copied from
+/// another entity with their references remapped
+pub(crate) fn store_values(
+    conn: &Connection,
+    kind: EntityKind,
+    id: &str,
+    values: &Map<String, Value>,
+) -> rusqlite::Result<()> {
+    let column = (!values.is_empty()).then(|| Value::Object(values.clone()).to_string());
+    write_column(conn, kind, id, column)
+}
+
+fn write_column(
+    conn: &Connection,
+    kind: EntityKind,
+    id: &str,
+    column: Option<String>,
+) -> rusqlite::Result<()> {
+    conn.execute(
+        &format!(
+            "UPDATE {} SET custom_fields = ?2, updated_at = CURRENT_TIMESTAMP WHERE id = ?1",
+            kind.table()
+        ),
+        params![id, column],
+    )?;
+    Ok(())
+}
+
+/// Parse a `custom_fields` column
+pub(crate) fn from_column(column: Option<String>) -> Map<String, Value> {
+    column
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default()
+}
+
+fn get_custom_field(conn: &Connection, id: &str) -> Result<CustomField, CustomFieldError> {
+    let project_id: String = conn
+        .query_row(
+            "SELECT project_id FROM custom_fields WHERE id = ?1",
+            params![id],
+            |row| row.get(0),
+        )
+        .optional()?
+        .ok_or_else(|| CustomFieldError::NotFound(id.to_string()))?;
+    get_custom_fields(conn, &project_id, None)?
+        .into_iter()
+        .find(|f| f.id == id)
+        .ok_or_else(|| CustomFieldError::NotFound(id.to_string()))
+}
+
+fn check_definition(field: &CustomField) -> Result<(), CustomFieldError> {
+    if !supports_custom_fields(field.entity_type) {
+        return Err(CustomFieldError::NotSupported(field.entity_type.as_str()));
+    }
+    if field.name.trim().is_empty() {
+        return Err(CustomFieldError::InvalidDefinition(
+            "name is empty".to_string(),
+        ));
+    }
+    match field.field_type {
+        CustomFieldType::Enum if field.options.iter().all(|o| o.trim().is_empty()) => Err(
+            CustomFieldError::InvalidDefinition("enum fields need options".to_string()),
+        ),
+        CustomFieldType::EntityReference => match field.reference_type {
+            Some(kind) if is_referenceable(kind) => Ok(()),
+            Some(kind) => Err(CustomFieldError::InvalidDefinition(format!(
+                "{} entities cannot be referenced",
+                kind.as_str()
+            ))),
+            None => Err(CustomFieldError::InvalidDefinition(
+                "entity reference fields need a reference type".to_string(),
+            )),
+        },
+        _ => Ok(()),
+    }
+}
+
+/// Check one value against its field. The outer error is a database
+/// failure, the inner one the reason the value is rejected.
+fn check_value(
+    conn: &Connection,
+    field: &CustomField,
+    project_id: &str,
+    value: &Value,
+) -> rusqlite::Result<Result<(), String>> {
+    let checked = match (field.field_type, value) {
+        (CustomFieldType::Text, Value::String(_)) => Ok(()),
+        (CustomFieldType::Number, Value::Number(_)) => Ok(()),
+        (CustomFieldType::Enum, Value::String(s)) => {
+            if field.options.iter().any(|o| o == s) {
+                Ok(())
+            } else {
+                Err(format!("\"{}\" is not one of its options", s))
+            }
+        }
+        (CustomFieldType::Date, Value::String(s)) => {
+            chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                .map(|_| ())
+                .map_err(|_| format!("\"{}\" is not a YYYY-MM-DD date", s))
+        }
+        (CustomFieldType::EntityReference, Value::String(id)) => {
+            let kind = field.reference_type.unwrap_or(field.entity_type);
+            let exists = conn
+                .query_row(
+                    &format!(
+                        "SELECT 1 FROM {} WHERE id = ?1 AND project_id = ?2 AND deleted_at IS NULL",
+                        kind.table()
+                    ),
+                    params![id, project_id],
+                    |_| Ok(()),
+                )
+                .optional()?
+                .is_some();
+            if exists {
+                Ok(())
+            } else {
+                Err(format!("no {} {} in this project", kind.as_str(), id))
+            }
+        }
+        (CustomFieldType::Number, _) => Err("expected a number".to_string()),
+        _ => Err("expected a string".to_string()),
+    };
+    Ok(checked)
+}
+
+/// SQL condition applying `op` to `expr`. Every op but `Exists` and
+/// `Missing` takes its operand as one more parameter.
+pub(crate) fn filter_sql(op: FilterOp, expr: &str) -> String {
+    match op {
+        FilterOp::Eq => format!("{} = ?", expr),
+        FilterOp::Ne => format!("{} IS NOT ?", expr),
+        FilterOp::Lt => format!("{} < ?", expr),
+        FilterOp::Lte => format!("{} <= ?", expr),
+        FilterOp::Gt => format!("{} > ?", expr),
+        FilterOp::Gte => format!("{} >= ?", expr),
+        FilterOp::Contains => format!("instr(lower(CAST({} AS TEXT)), lower(?)) > 0", expr),
+        FilterOp::Exists => format!("{} IS NOT NULL", expr),
+        FilterOp::Missing => format!("{} IS NULL", expr),
+    }
+}
+
+/// The parameter a filter compares with, if its op takes one
+pub(crate) fn filter_operand(
+    op: FilterOp,
+    value: Option<&Value>,
+) -> Result<Option<SqlValue>, String> {
+    if matches!(op, FilterOp::Exists | FilterOp::Missing) {
+        return Ok(None);
+    }
+    match value {
+        Some(Value::String(s)) => Ok(Some(SqlValue::Text(s.clone()))),
+        Some(Value::Number(n)) => Ok(Some(match n.as_i64() {
+            Some(i) => SqlValue::Integer(i),
+            None => SqlValue::Real(n.as_f64().unwrap_or_default()),
+        })),
+        Some(Value::Bool(b)) => Ok(Some(SqlValue::Integer(*b as i64))),
+        _ => Err("filter needs a string, number or boolean to compare with".to_string()),
+    }
+}
+
+/// JSON path of a field's value in the `custom_fields` column
+pub(crate) fn value_path(field_id: &str) -> String {
+    format!("$.\"{}\"", field_id)
+}
+
+fn options_column(field: &CustomField) -> Option<String> {
+    match field.field_type {
+        CustomFieldType::Enum => Some(serde_json::to_string(&field.options).unwrap_or_default()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database;
+    use crate::database::test_support::project_db;
+    use serde_json::json;
+
+    fn field(id: &str, name: &str, field_type: CustomFieldType) -> CustomField {
+        CustomField {
+            id: id.to_string(),
+            project_id: "p1".to_string(),
+            entity_type: EntityKind::Character,
+            name: name.to_string(),
+            field_type,
+            options: vec![],
+            reference_type: None,
+            position: 0,
+        }
+    }
+
+    fn setup() -> Connection {
+        let conn = project_db();
+        conn.execute_batch(
+            r#"INSERT INTO locations (id, project_id, name) VALUES ('l1', 'p1', 'Harbor');
+               INSERT INTO characters (id, project_id, name) VALUES ('c1', 'p1', 'Ada'), ('c2', 'p1', 'Cy');"#,
+        )
+        .unwrap();
+
+        let mut faction = field("f1", "Faction", CustomFieldType::Enum);
+        faction.options = vec!["Guild".to_string(), "Crown".to_string()];
+        create_custom_field(&conn, &faction).unwrap();
+        create_custom_field(&conn, &field("f2", "Born", CustomFieldType::Date)).unwrap();
+        let mut home = field("f3", "Home", CustomFieldType::EntityReference);
+        home.reference_type = Some(EntityKind::Location);
+        create_custom_field(&conn, &home).unwrap();
+        conn
+    }
+
+    fn character(conn: &Connection, id: &str) -> database::Character {
+        database::get_characters_by_project(conn, "p1")
+            .unwrap()
+            .into_iter()
+            .find(|c| c.id == id)
+            .unwrap()
+    }
+
+    fn set_values(conn: &Connection, id: &str, values: Value) -> Result<(), CustomFieldError> {
+        let mut character = character(conn, id);
+        character.custom_fields = values.as_object().unwrap().clone();
+        database::update_character(conn, &character)
+    }
+
+    #[test]
+    fn test_values_are_validated_searched_and_filtered() {
+        let conn = setup();
+
+        set_values(
+            &conn,
+            "c1",
+            json!({ "f1": "Guild", "f2": "1815-12-10", "f3": "l1" }),
+        )
+        .unwrap();
+        set_values(&conn, "c2", json!({ "f1": "Crown", "f2": null })).unwrap();
+        for bad in [
+            json!({ "f1": "Pirates" }),
+            json!({ "f2": "10/12/1815" }),
+            json!({ "f3": "c2" }),
+            json!({ "nope": "x" }),
+        ] {
+            assert!(matches!(
+                set_values(&conn, "c2", bad),
+                Err(CustomFieldError::InvalidValue { .. } | CustomFieldError::UnknownField { .. })
+            ));
+        }
+
+        let c2 = character(&conn, "c2");
+        assert_eq!(Value::Object(c2.custom_fields), json!({ "f1": "Crown" }));
+
+        let hits = database::search(&conn, "guild", Some("p1"), &[], None).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].entity_id, "c1");
+
+        let filter = |field_id: &str, op, value: Option<Value>| CustomFieldFilter {
+            field_id: field_id.to_string(),
+            op,
+            value,
+        };
+        let ids = |filters: &[CustomFieldFilter]| {
+            filter_by_custom_fields(&conn, "p1", EntityKind::Character, filters).unwrap()
+        };
+        assert_eq!(
+            ids(&[filter("f1", FilterOp::Eq, Some(json!("Crown")))]),
+            ["c2"]
+        );
+        assert_eq!(
+            ids(&[filter("f2", FilterOp::Lt, Some(json!("1900-01-01")))]),
+            ["c1"]
+        );
+        assert_eq!(ids(&[filter("f3", FilterOp::Missing, None)]), ["c2"]);
+        assert_eq!(
+            ids(&[filter("f1", FilterOp::Contains, Some(json!("ui")))]),
+            ["c1"]
+        );
+    }
+
+    #[test]
+    fn test_changing_a_field_clears_values_that_no_longer_fit() {
+        let conn = setup();
+        set_values(&conn, "c1", json!({ "f1": "Guild", "f2": "1815-12-10" })).unwrap();
+        set_values(&conn, "c2", json!({ "f1": "Crown" })).unwrap();
+
+        let mut faction = get_custom_field(&conn, "f1").unwrap();
+        faction.options = vec!["Crown".to_string()];
+        assert_eq!(update_custom_field(&conn, &faction).unwrap(), 1);
+
+        delete_custom_field(&conn, "f2").unwrap();
+        let c1 = character(&conn, "c1");
+        assert!(c1.custom_fields.is_empty());
+        let c2 = character(&conn, "c2");
+        assert_eq!(Value::Object(c2.custom_fields), json!({ "f1": "Crown" }));
+        assert_eq!(get_custom_fields(&conn, "p1", None).unwrap().len(), 2);
+    }
+}
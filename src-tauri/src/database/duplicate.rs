@@ -0,0 +1,601 @@
+//! Deep copies of projects
+//!
+//! Every copied entity gets a new id and every internal reference is
+//! rewritten to point at the copy. References to entities left out of the
+//! copy (see `DuplicateOptions`) are dropped rather than left dangling.
+
+use super::custom_fields;
+use super::mentions;
+use super::models::*;
+use super::operations::*;
+use super::tags;
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum DuplicateError {
+    #[error("Project {0} not found")]
+    ProjectNotFound(String),
+
+    #[error(transparent)]
+    CustomField(#[from] custom_fields::CustomFieldError),
+
+    #[error("Database error: {0}")]
+    Database(#[from] rusqlite::Error),
+}
+
+/// What to copy. Everything is included by default; turning off
+/// `manuscript` copies just the world bible, e.g. to start a sequel.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DuplicateOptions {
+    /// Title of the copy, "<title> (copy)" when absent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// Chapters and scenes
+    #[serde(default = "included")]
+    pub manuscript: bool,
+    /// Characters and their relationships
+    #[serde(default = "included")]
+    pub characters: bool,
+    #[serde(default = "included")]
+    pub locations: bool,
+    #[serde(default = "included")]
+    pub lore: bool,
+    #[serde(default = "included")]
+    pub timeline: bool,
+    /// Creatures, NPCs and world rules
+    #[serde(default = "included")]
+    pub rpg: bool,
+}
+
+impl Default for DuplicateOptions {
+    fn default() -> Self {
+        Self {
+            title: None,
+            manuscript: true,
+            characters: true,
+            locations: true,
+            lore: true,
+            timeline: true,
+            rpg: true,
+        }
+    }
+}
+
+fn included() -> bool {
+    true
+}
+
+/// Old id to new id for everything being copied
+#[derive(Default)]
+struct IdMap(HashMap<String, String>);
+
+impl IdMap {
+    fn assign(&mut self, old: &str) {
+        self.0
+            .insert(old.to_string(), uuid::Uuid::new_v4().to_string());
+    }
+
+    fn new_id(&self, old: &str) -> Option<String> {
+        self.0.get(old).cloned()
+    }
+
+    fn option(&self, old: &Option<String>) -> Option<String> {
+        old.as_deref().and_then(|id| self.new_id(id))
+    }
+
+    fn list(&self, old: &[String]) -> Vec<String> {
+        old.iter().filter_map(|id| self.new_id(id)).collect()
+    }
+
+    /// Rekey custom field values to the copied fields, dropping references
+    /// to entities that were not copied
+    fn custom_values(
+        &self,
+        fields: &[CustomField],
+        values: &Map<String, Value>,
+    ) -> Map<String, Value> {
+        values
+            .iter()
+            .filter_map(|(field_id, value)| {
+                let field = fields.iter().find(|f| &f.id == field_id)?;
+                let value = match (field.field_type, value) {
+                    (CustomFieldType::EntityReference, Value::String(id)) => {
+                        Value::String(self.new_id(id)?)
+                    }
+                    _ => value.clone(),
+                };
+                Some((self.new_id(field_id)?, value))
+            })
+            .collect()
+    }
+
+    /// Rewrite `key` in each JSON object; objects whose reference was not
+    /// copied are dropped when `required`, otherwise lose the key
+    fn json_refs(&self, items: &[Value], key: &str, required: bool) -> Vec<Value> {
+        items
+            .iter()
+            .filter_map(|item| {
+                let mut item = item.clone();
+                let Some(fields) = item.as_object_mut() else {
+                    return Some(item);
+                };
+                let Some(old) = fields.get(key).and_then(Value::as_str) else {
+                    return Some(item);
+                };
+                match self.new_id(old) {
+                    Some(new) => {
+                        fields.insert(key.to_string(), Value::String(new));
+                    }
+                    None if required => return None,
+                    None => {
+                        fields.remove(key);
+                    }
+                }
+                Some(item)
+            })
+            .collect()
+    }
+}
+
+/// Copy a project and the parts of it selected by `options`, returning the
+/// new project. Trashed entities are not copied.
+pub fn duplicate_project(
+    conn: &Connection,
+    project_id: &str,
+    options: &DuplicateOptions,
+) -> Result<Project, DuplicateError> {
+    let source = get_project(conn, project_id)?
+        .ok_or_else(|| DuplicateError::ProjectNotFound(project_id.to_string()))?;
+
+    // Load everything first: references can only be rewritten once every
+    // new id is known
+    let chapters = if options.manuscript {
+        get_chapters_by_project(conn, project_id)?
+    } else {
+        vec![]
+    };
+    let mut scenes = Vec::new();
+    for chapter in &chapters {
+        scenes.extend(get_scenes_by_chapter(conn, &chapter.id)?);
+    }
+    let characters = if options.characters {
+        get_characters_by_project(conn, project_id)?
+    } else {
+        vec![]
+    };
+    let mut relationships = Vec::new();
+    for character in &characters {
+        for relationship in get_relationships_by_character(conn, &character.id)? {
+            relationships.push((character.id.clone(), relationship));
+        }
+    }
+    let locations = if options.locations {
+        get_locations_by_project(conn, project_id)?
+    } else {
+        vec![]
+    };
+    let lore_items = if options.lore {
+        get_lore_items_by_project(conn, project_id)?
+    } else {
+        vec![]
+    };
+    let events = if options.timeline {
+        get_timeline_events_by_project(conn, project_id)?
+    } else {
+        vec![]
+    };
+    let (creatures, npcs, rules) = if options.rpg {
+        (
+            get_creatures_by_project(conn, project_id)?,
+            get_npcs_by_project(conn, project_id)?,
+            get_world_rules_by_project(conn, project_id)?,
+        )
+    } else {
+        (vec![], vec![], vec![])
+    };
+    let aliases = mentions::get_project_aliases(conn, project_id)?;
+    let fields = custom_fields::get_custom_fields(conn, project_id, None)?;
+    let project_tags = tags::get_project_tags(conn, project_id)?;
+    let entity_tags = tags::get_project_entity_tags(conn, project_id)?;
+
+    let mut ids = IdMap::default();
+    ids.assign(&source.id);
+    chapters.iter().for_each(|e| ids.assign(&e.id));
+    scenes.iter().for_each(|e| ids.assign(&e.id));
+    characters.iter().for_each(|e| ids.assign(&e.id));
+    relationships.iter().for_each(|(_, e)| ids.assign(&e.id));
+    locations.iter().for_each(|e| ids.assign(&e.id));
+    lore_items.iter().for_each(|e| ids.assign(&e.id));
+    events.iter().for_each(|e| ids.assign(&e.id));
+    creatures.iter().for_each(|e| ids.assign(&e.id));
+    npcs.iter().for_each(|e| ids.assign(&e.id));
+    rules.iter().for_each(|e| ids.assign(&e.id));
+    fields.iter().for_each(|e| ids.assign(&e.id));
+    project_tags.iter().for_each(|e| ids.assign(&e.id));
+    let new_id = |old: &str| ids.new_id(old).unwrap_or_default();
+
+    let project = Project {
+        id: new_id(&source.id),
+        title: options
+            .title
+            .clone()
+            .unwrap_or_else(|| format!("{} (copy)", source.title)),
+        ..source
+    };
+
+    in_savepoint(conn, || {
+        create_project(conn, &project)?;
+
+        // The whole tag vocabulary, ahead of the timeline events that name
+        // their tags
+        for tag in &project_tags {
+            conn.execute(
+                "INSERT INTO tags (id, project_id, name, color) VALUES (?1, ?2, ?3, ?4)",
+                params![new_id(&tag.id), project.id, tag.name, tag.color],
+            )?;
+        }
+
+        conn.execute(
+            r#"INSERT INTO project_settings (project_id, key, value)
+               SELECT ?2, key, value FROM project_settings WHERE project_id = ?1"#,
+            params![project_id, project.id],
+        )?;
+
+        // Values are copied once every entity they may reference exists
+        for field in &fields {
+            conn.execute(
+                r#"INSERT INTO custom_fields (id, project_id, entity_type, name, field_type, options, reference_type, position)
+                   SELECT ?2, ?3, entity_type, name, field_type, options, reference_type, position
+                   FROM custom_fields WHERE id = ?1"#,
+                params![field.id, new_id(&field.id), project.id],
+            )?;
+        }
+
+        for character in &characters {
+            create_character(
+                conn,
+                &Character {
+                    id: new_id(&character.id),
+                    project_id: project.id.clone(),
+                    vital_status_history: ids.json_refs(
+                        &character.vital_status_history,
+                        "associatedEventId",
+                        false,
+                    ),
+                    relationships: vec![],
+                    custom_fields: Default::default(),
+                    ..character.clone()
+                },
+            )?;
+        }
+        for (owner, relationship) in &relationships {
+            create_relationship(
+                conn,
+                &new_id(owner),
+                &Relationship {
+                    id: new_id(&relationship.id),
+                    character_id: new_id(&relationship.character_id),
+                    history: ids.json_refs(&relationship.history, "eventId", false),
+                    ..relationship.clone()
+                },
+            )?;
+        }
+
+        for location in &locations {
+            create_location(
+                conn,
+                &Location {
+                    id: new_id(&location.id),
+                    project_id: project.id.clone(),
+                    connections: ids.json_refs(&location.connections, "targetLocationId", true),
+                    custom_fields: Default::default(),
+                    ..location.clone()
+                },
+            )?;
+        }
+
+        // Scenes are created before their chapters switch to compiling from
+        // them, so the copied text is not rebuilt one scene at a time
+        for chapter in &chapters {
+            create_chapter(
+                conn,
+                &Chapter {
+                    id: new_id(&chapter.id),
+                    project_id: project.id.clone(),
+                    content_mode: ContentMode::Document,
+                    ..chapter.clone()
+                },
+            )?;
+        }
+        for scene in &scenes {
+            create_scene(
+                conn,
+                &Scene {
+                    id: new_id(&scene.id),
+                    chapter_id: new_id(&scene.chapter_id),
+                    character_ids: ids.list(&scene.character_ids),
+                    location_id: ids.option(&scene.location_id),
+                    ..scene.clone()
+                },
+            )?;
+        }
+        for chapter in chapters
+            .iter()
+            .filter(|c| c.content_mode != ContentMode::Document)
+        {
+            conn.execute(
+                "UPDATE chapters SET content_mode = ?2 WHERE id = ?1",
+                params![new_id(&chapter.id), chapter.content_mode.as_str()],
+            )?;
+        }
+
+        for item in &lore_items {
+            create_lore_item(
+                conn,
+                &LoreItem {
+                    id: new_id(&item.id),
+                    project_id: project.id.clone(),
+                    related_entity_ids: ids.list(&item.related_entity_ids),
+                    custom_fields: Default::default(),
+                    ..item.clone()
+                },
+            )?;
+        }
+
+        for event in &events {
+            create_timeline_event(
+                conn,
+                &TimelineEvent {
+                    id: new_id(&event.id),
+                    project_id: project.id.clone(),
+                    participants: ids.list(&event.participants),
+                    location_id: ids.option(&event.location_id),
+                    scene_id: ids.option(&event.scene_id),
+                    chapter_id: ids.option(&event.chapter_id),
+                    ..event.clone()
+                },
+            )?;
+        }
+
+        for creature in &creatures {
+            create_creature(
+                conn,
+                &Creature {
+                    id: new_id(&creature.id),
+                    project_id: project.id.clone(),
+                    related_location_ids: ids.list(&creature.related_location_ids),
+                    ..creature.clone()
+                },
+            )?;
+        }
+        for npc in &npcs {
+            create_npc(
+                conn,
+                &Npc {
+                    id: new_id(&npc.id),
+                    project_id: project.id.clone(),
+                    relationships: npc
+                        .relationships
+                        .iter()
+                        .filter_map(|r| {
+                            Some(NpcRelationship {
+                                target_npc_id: ids.new_id(&r.target_npc_id)?,
+                                ..r.clone()
+                            })
+                        })
+                        .collect(),
+                    related_location_ids: ids.list(&npc.related_location_ids),
+                    linked_character_id: ids.option(&npc.linked_character_id),
+                    ..npc.clone()
+                },
+            )?;
+        }
+        for rule in &rules {
+            create_world_rule(
+                conn,
+                &WorldRule {
+                    id: new_id(&rule.id),
+                    project_id: project.id.clone(),
+                    related_rule_ids: ids.list(&rule.related_rule_ids),
+                    related_entity_ids: ids.list(&rule.related_entity_ids),
+                    ..rule.clone()
+                },
+            )?;
+        }
+
+        let custom_values = characters
+            .iter()
+            .map(|c| (EntityKind::Character, &c.id, &c.custom_fields))
+            .chain(
+                locations
+                    .iter()
+                    .map(|l| (EntityKind::Location, &l.id, &l.custom_fields)),
+            )
+            .chain(
+                lore_items
+                    .iter()
+                    .map(|l| (EntityKind::LoreItem, &l.id, &l.custom_fields)),
+            );
+        for (kind, id, values) in custom_values {
+            let values = ids.custom_values(&fields, values);
+            if !values.is_empty() {
+                custom_fields::store_values(conn, kind, &new_id(id), &values)?;
+            }
+        }
+
+        // Tags of copied entities
+        for entity_tag in &entity_tags {
+            if let (Some(tag_id), Some(entity_id)) = (
+                ids.new_id(&entity_tag.tag_id),
+                ids.new_id(&entity_tag.entity_id),
+            ) {
+                conn.execute(
+                    "INSERT OR IGNORE INTO entity_tags (tag_id, entity_type, entity_id) VALUES (?1, ?2, ?3)",
+                    params![tag_id, entity_tag.entity_type.as_str(), entity_id],
+                )?;
+            }
+        }
+
+        // Aliases of copied entities; mentions are indexed once all is in
+        for alias in &aliases {
+            if let Some(entity_id) = ids.new_id(&alias.entity_id) {
+                conn.execute(
+                    r#"INSERT INTO entity_aliases (entity_type, entity_id, project_id, alias)
+                       VALUES (?1, ?2, ?3, ?4)"#,
+                    params![
+                        alias.entity_type.as_str(),
+                        entity_id,
+                        project.id,
+                        alias.alias
+                    ],
+                )?;
+            }
+        }
+        mentions::reindex_project(conn, &project.id)?;
+        Ok::<_, DuplicateError>(())
+    })?;
+
+    log::info!("Duplicated project {} as {}", project_id, project.id);
+    Ok(project)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database;
+    use crate::database::test_support::project_db;
+    use serde_json::json;
+
+    fn create<T: serde::de::DeserializeOwned>(value: Value) -> T {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_duplicate_rewrites_references() {
+        let conn = project_db();
+        for id in ["ada", "bo"] {
+            create_character(
+                &conn,
+                &create(json!({ "id": id, "projectId": "p1", "name": id })),
+            )
+            .unwrap();
+        }
+        create_relationship(
+            &conn,
+            "ada",
+            &create(json!({ "id": "r1", "characterId": "bo", "history": [{ "eventId": "e1" }] })),
+        )
+        .unwrap();
+        create_location(
+            &conn,
+            &create(json!({
+                "id": "l1", "projectId": "p1", "name": "Port",
+                "connections": [{ "id": "x", "targetLocationId": "l1" }],
+            })),
+        )
+        .unwrap();
+        create_chapter(
+            &conn,
+            &create(
+                json!({ "id": "c1", "projectId": "p1", "title": "One", "content": "<p>Hi</p>" }),
+            ),
+        )
+        .unwrap();
+        create_scene(
+            &conn,
+            &create(json!({
+                "id": "s1", "chapterId": "c1", "title": "Dock",
+                "characterIds": ["ada"], "locationId": "l1",
+            })),
+        )
+        .unwrap();
+        database::create_custom_field(
+            &conn,
+            &create(json!({
+                "id": "f1", "projectId": "p1", "entityType": "character", "name": "Home",
+                "fieldType": "entityReference", "referenceType": "location",
+            })),
+        )
+        .unwrap();
+        database::set_custom_field_values(
+            &conn,
+            EntityKind::Character,
+            "ada",
+            json!({ "f1": "l1" }).as_object().unwrap(),
+        )
+        .unwrap();
+        database::create_tag(
+            &conn,
+            &create(json!({ "id": "t1", "projectId": "p1", "name": "Crew" })),
+        )
+        .unwrap();
+        database::tag_entity(&conn, EntityKind::Character, "bo", "t1").unwrap();
+        create_lore_item(
+            &conn,
+            &create(json!({ "id": "k1", "projectId": "p1", "title": "Tides", "relatedEntityIds": ["l1", "c1"] })),
+        )
+        .unwrap();
+        create_timeline_event(
+            &conn,
+            &create(json!({
+                "id": "e1", "projectId": "p1", "title": "Storm",
+                "participants": ["bo"], "sceneId": "s1", "chapterId": "c1",
+            })),
+        )
+        .unwrap();
+
+        let copy = duplicate_project(&conn, "p1", &DuplicateOptions::default()).unwrap();
+        assert_eq!(copy.title, "Saga (copy)");
+        let characters = get_characters_by_project(&conn, &copy.id).unwrap();
+        let ada = characters.iter().find(|c| c.name == "ada").unwrap();
+        let bo = characters.iter().find(|c| c.name == "bo").unwrap();
+        assert_ne!(ada.id, "ada");
+        let location = &get_locations_by_project(&conn, &copy.id).unwrap()[0];
+        assert_eq!(
+            location.connections[0]["targetLocationId"],
+            json!(location.id)
+        );
+        let chapter = &get_chapters_by_project(&conn, &copy.id).unwrap()[0];
+        let scene = &get_scenes_by_chapter(&conn, &chapter.id).unwrap()[0];
+        assert_eq!(scene.character_ids, vec![ada.id.clone()]);
+        assert_eq!(scene.location_id.as_ref(), Some(&location.id));
+        let crew = database::get_entity_tags(&conn, EntityKind::Character, &bo.id).unwrap();
+        assert_eq!(crew[0].project_id, copy.id);
+        assert_eq!(crew[0].name, "Crew");
+        let home = &database::get_custom_fields(&conn, &copy.id, None).unwrap()[0];
+        assert_eq!(ada.custom_fields[&home.id], json!(location.id));
+        let event = &get_timeline_events_by_project(&conn, &copy.id).unwrap()[0];
+        assert_eq!(event.participants, vec![bo.id.clone()]);
+        assert_eq!(event.scene_id.as_ref(), Some(&scene.id));
+        let relationship = &get_relationships_by_character(&conn, &ada.id).unwrap()[0];
+        assert_eq!(relationship.character_id, bo.id);
+        assert_eq!(relationship.history[0]["eventId"], json!(event.id));
+
+        // The world bible alone: manuscript references are dropped
+        let bible = duplicate_project(
+            &conn,
+            "p1",
+            &create(json!({ "title": "Saga II", "manuscript": false })),
+        )
+        .unwrap();
+        assert!(get_chapters_by_project(&conn, &bible.id)
+            .unwrap()
+            .is_empty());
+        let lore = &get_lore_items_by_project(&conn, &bible.id).unwrap()[0];
+        assert_eq!(lore.related_entity_ids.len(), 1);
+        let event = &get_timeline_events_by_project(&conn, &bible.id).unwrap()[0];
+        assert_eq!(event.scene_id, None);
+        assert_eq!(event.chapter_id, None);
+
+        // The source is untouched
+        assert_eq!(
+            get_scenes_by_chapter(&conn, "c1").unwrap()[0].character_ids,
+            ["ada"]
+        );
+    }
+}
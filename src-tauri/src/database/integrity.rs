@@ -0,0 +1,328 @@
+//! Referential integrity checks and repair
+//!
+//! Foreign keys are enforced on every connection, but databases written
+//! before that was the case may still hold orphaned rows. Soft references
+//! (optional ids and JSON id arrays) are not covered by foreign keys at all
+//! and can point at entities that were purged.
+
+use super::operations::with_savepoint;
+use rusqlite::{params, Connection, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// Rows whose owner no longer exists: (table, column, parent table).
+/// Listed parents first, so repairing an owner runs before its children.
+const OWNED_ROWS: &[(&str, &str, &str)] = &[
+    ("chapters", "project_id", "projects"),
+    ("characters", "project_id", "projects"),
+    ("locations", "project_id", "projects"),
+    ("lore_items", "project_id", "projects"),
+    ("timeline_events", "project_id", "projects"),
+    ("creatures", "project_id", "projects"),
+    ("npcs", "project_id", "projects"),
+    ("world_rules", "project_id", "projects"),
+    ("scenes", "chapter_id", "chapters"),
+    ("relationships", "character_id", "characters"),
+    ("relationships", "target_character_id", "characters"),
+    ("chapter_revisions", "chapter_id", "chapters"),
+];
+
+/// Optional single-id references: (table, column, referenced table)
+const OPTIONAL_REFERENCES: &[(&str, &str, &str)] = &[
+    ("timeline_events", "location_id", "locations"),
+    ("timeline_events", "scene_id", "scenes"),
+    ("timeline_events", "chapter_id", "chapters"),
+    ("scenes", "location_id", "locations"),
+    ("npcs", "linked_character_id", "characters"),
+];
+
+/// JSON arrays of character ids: (table, column)
+const CHARACTER_ID_LISTS: &[(&str, &str)] = &[
+    ("scenes", "character_ids"),
+    ("timeline_events", "participants"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum IssueKind {
+    /// The row's owner is gone; repair deletes the row
+    Orphan,
+    /// An optional reference points nowhere; repair clears it
+    DanglingReference,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IntegrityIssue {
+    pub kind: IssueKind,
+    pub table: String,
+    pub row_id: String,
+    pub column: String,
+    pub missing_id: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IntegrityReport {
+    /// Problems reported by SQLite's own `integrity_check`
+    pub storage_errors: Vec<String>,
+    pub issues: Vec<IntegrityIssue>,
+    /// Whether the issues were fixed
+    pub repaired: bool,
+}
+
+/// Check the database, fixing every issue found when `repair` is set
+pub fn check_integrity(conn: &Connection, repair: bool) -> Result<IntegrityReport> {
+    let storage_errors = storage_errors(conn)?;
+
+    let mut issues = Vec::new();
+    for (table, column, parent) in OWNED_ROWS {
+        issues.extend(missing_references(
+            conn,
+            table,
+            column,
+            parent,
+            IssueKind::Orphan,
+        )?);
+    }
+    for (table, column, target) in OPTIONAL_REFERENCES {
+        issues.extend(missing_references(
+            conn,
+            table,
+            column,
+            target,
+            IssueKind::DanglingReference,
+        )?);
+    }
+    let character_ids = existing_ids(conn, "characters")?;
+    let mut list_fixes = Vec::new();
+    for (table, column) in CHARACTER_ID_LISTS {
+        let (found, fixes) = missing_in_id_lists(conn, table, column, &character_ids)?;
+        issues.extend(found);
+        list_fixes.extend(fixes);
+    }
+
+    if repair && !issues.is_empty() {
+        with_savepoint(conn, || {
+            for (table, column, parent) in OWNED_ROWS {
+                conn.execute(
+                    &format!(
+                        "DELETE FROM {t} WHERE {c} NOT IN (SELECT id FROM {p})",
+                        t = table,
+                        c = column,
+                        p = parent
+                    ),
+                    [],
+                )?;
+            }
+            for (table, column, target) in OPTIONAL_REFERENCES {
+                conn.execute(
+                    &format!(
+                        "UPDATE {t} SET {c} = NULL
+                         WHERE {c} IS NOT NULL AND {c} != '' AND {c} NOT IN (SELECT id FROM {r})",
+                        t = table,
+                        c = column,
+                        r = target
+                    ),
+                    [],
+                )?;
+            }
+            for fix in &list_fixes {
+                conn.execute(
+                    &format!("UPDATE {} SET {} = ?2 WHERE id = ?1", fix.table, fix.column),
+                    params![fix.row_id, fix.value],
+                )?;
+            }
+            Ok(())
+        })?;
+        log::info!("Repaired {} integrity issue(s)", issues.len());
+    }
+
+    Ok(IntegrityReport {
+        storage_errors,
+        repaired: repair && !issues.is_empty(),
+        issues,
+    })
+}
+
+/// Cheap storage check for startup: `quick_check` skips index verification
+/// and the reference scans, which `check_integrity` runs on demand
+pub fn quick_check(conn: &Connection) -> Result<Vec<String>> {
+    pragma_errors(conn, "quick_check")
+}
+
+fn storage_errors(conn: &Connection) -> Result<Vec<String>> {
+    pragma_errors(conn, "integrity_check")
+}
+
+fn pragma_errors(conn: &Connection, pragma: &str) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(&format!("PRAGMA {}", pragma))?;
+    let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+    let mut errors = Vec::new();
+    for row in rows {
+        let message = row?;
+        if message != "ok" {
+            errors.push(message);
+        }
+    }
+    Ok(errors)
+}
+
+fn missing_references(
+    conn: &Connection,
+    table: &str,
+    column: &str,
+    target: &str,
+    kind: IssueKind,
+) -> Result<Vec<IntegrityIssue>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT id, {c} FROM {t}
+         WHERE {c} IS NOT NULL AND {c} != '' AND {c} NOT IN (SELECT id FROM {r})",
+        t = table,
+        c = column,
+        r = target
+    ))?;
+    let rows = stmt.query_map([], |row| {
+        Ok(IntegrityIssue {
+            kind,
+            table: table.to_string(),
+            row_id: row.get(0)?,
+            column: column.to_string(),
+            missing_id: row.get(1)?,
+        })
+    })?;
+    rows.collect()
+}
+
+fn existing_ids(conn: &Connection, table: &str) -> Result<HashSet<String>> {
+    let mut stmt = conn.prepare(&format!("SELECT id FROM {}", table))?;
+    let rows = stmt.query_map([], |row| row.get(0))?;
+    rows.collect()
+}
+
+/// Replacement value for a JSON id list with the missing ids removed
+struct ListFix {
+    table: &'static str,
+    column: &'static str,
+    row_id: String,
+    value: String,
+}
+
+fn missing_in_id_lists(
+    conn: &Connection,
+    table: &'static str,
+    column: &'static str,
+    existing: &HashSet<String>,
+) -> Result<(Vec<IntegrityIssue>, Vec<ListFix>)> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT id, {c} FROM {t} WHERE {c} IS NOT NULL",
+        t = table,
+        c = column
+    ))?;
+    let rows = stmt.query_map([], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+    })?;
+
+    let mut issues = Vec::new();
+    let mut fixes = Vec::new();
+    for row in rows {
+        let (row_id, json) = row?;
+        // Malformed lists are left alone; the entity mappers already treat
+        // them as empty
+        let Ok(ids) = serde_json::from_str::<Vec<String>>(&json) else {
+            continue;
+        };
+        let (kept, missing): (Vec<String>, Vec<String>) =
+            ids.into_iter().partition(|id| existing.contains(id));
+        if missing.is_empty() {
+            continue;
+        }
+        issues.extend(missing.into_iter().map(|missing_id| IntegrityIssue {
+            kind: IssueKind::DanglingReference,
+            table: table.to_string(),
+            row_id: row_id.clone(),
+            column: column.to_string(),
+            missing_id,
+        }));
+        fixes.push(ListFix {
+            table,
+            column,
+            row_id,
+            value: serde_json::to_string(&kept).unwrap_or_else(|_| "[]".to_string()),
+        });
+    }
+    Ok((issues, fixes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database;
+    use crate::database::test_support::project_db;
+    use serde_json::json;
+
+    #[test]
+    fn test_reports_and_repairs_broken_references() {
+        let conn = project_db();
+        let character = serde_json::from_value(json!({
+            "id": "ch1",
+            "projectId": "p1",
+            "name": "Ada",
+        }))
+        .unwrap();
+        database::create_character(&conn, &character).unwrap();
+        let chapter = serde_json::from_value(json!({
+            "id": "c1",
+            "projectId": "p1",
+            "title": "Arrival",
+        }))
+        .unwrap();
+        database::create_chapter(&conn, &chapter).unwrap();
+        let scene = serde_json::from_value(json!({
+            "id": "s1",
+            "chapterId": "c1",
+            "title": "Dock",
+            "characterIds": ["ch1", "gone"],
+            "locationId": "nowhere",
+        }))
+        .unwrap();
+        database::create_scene(&conn, &scene).unwrap();
+
+        // Enforced foreign keys reject new orphans...
+        assert!(conn
+            .execute(
+                "INSERT INTO chapters (id, project_id, title) VALUES ('c2', 'missing', 'Lost')",
+                [],
+            )
+            .is_err());
+        // ...but legacy databases may already have them
+        conn.execute_batch(
+            "PRAGMA foreign_keys = OFF;
+             INSERT INTO chapters (id, project_id, title) VALUES ('c2', 'missing', 'Lost');
+             PRAGMA foreign_keys = ON;",
+        )
+        .unwrap();
+
+        assert!(quick_check(&conn).unwrap().is_empty());
+        let report = check_integrity(&conn, false).unwrap();
+        assert!(report.storage_errors.is_empty());
+        assert!(!report.repaired);
+        assert_eq!(report.issues.len(), 3);
+        assert!(report
+            .issues
+            .iter()
+            .any(|i| i.kind == IssueKind::Orphan && i.row_id == "c2"));
+        assert!(report
+            .issues
+            .iter()
+            .any(|i| i.column == "character_ids" && i.missing_id == "gone"));
+
+        let report = check_integrity(&conn, true).unwrap();
+        assert!(report.repaired);
+        assert!(check_integrity(&conn, false).unwrap().issues.is_empty());
+
+        let scenes = database::get_scenes_by_chapter(&conn, "c1").unwrap();
+        assert_eq!(scenes[0].character_ids, vec!["ch1".to_string()]);
+        assert_eq!(scenes[0].location_id, None);
+    }
+}
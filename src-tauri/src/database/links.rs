@@ -0,0 +1,595 @@
+//! Wiki-style links from manuscript text to entities
+//!
+//! Chapter and scene HTML may link entities by name: `[[Ada Byron]]`, or
+//! with a kind prefix to narrow the lookup, `[[lore:The Sundering]]`,
+//! `[[character:Ada]]`, `[[location:Harbor]]`. A `|` gives the text to
+//! show instead of the name: `[[Ada Byron|the captain]]`.
+//!
+//! Names are matched case-insensitively against characters, locations and
+//! lore items, then against their aliases. Renaming an entity through
+//! `rename_entity` rewrites the links that name it.
+
+use super::assembly;
+use super::mentions::{self, EntityAlias};
+use super::models::EntityKind;
+use super::operations::with_savepoint;
+use super::revisions::{self, RevisionSource};
+use super::text;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::ops::Range;
+use thiserror::Error;
+
+/// Link prefixes and the entity kind each one selects
+const PREFIXES: &[(&str, EntityKind)] = &[
+    ("character", EntityKind::Character),
+    ("location", EntityKind::Location),
+    ("lore", EntityKind::LoreItem),
+];
+
+#[derive(Error, Debug)]
+pub enum LinkError {
+    #[error("{0} entities cannot be linked")]
+    NotLinkable(&'static str),
+
+    #[error("{kind} {id} not found")]
+    NotFound { kind: &'static str, id: String },
+
+    #[error("Invalid name {0:?}: names cannot be empty or contain [, ] or |")]
+    InvalidName(String),
+
+    #[error("Database error: {0}")]
+    Database(#[from] rusqlite::Error),
+}
+
+/// A `[[...]]` link found in HTML. Ranges are byte ranges into the HTML.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WikiLink {
+    /// The whole link, brackets included
+    pub range: Range<usize>,
+    /// The name, without prefix or label
+    pub target_range: Range<usize>,
+    /// The text to show: the label if there is one, otherwise the name
+    pub display_range: Range<usize>,
+    /// Kind selected by a prefix, if any
+    pub kind: Option<EntityKind>,
+    /// The name with HTML entities decoded
+    pub target: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LinkStatus {
+    Resolved,
+    Broken,
+    Ambiguous,
+}
+
+/// An entity a link can point to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LinkTarget {
+    pub entity_type: EntityKind,
+    pub entity_id: String,
+    pub name: String,
+}
+
+/// A link and what it points to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResolvedLink {
+    /// Character offsets of the link in the HTML
+    pub start: u32,
+    pub end: u32,
+    /// The link as written
+    pub text: String,
+    pub target: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<EntityKind>,
+    pub status: LinkStatus,
+    /// The single match when resolved, every match when ambiguous
+    pub candidates: Vec<LinkTarget>,
+}
+
+/// A broken or ambiguous link in a chapter or scene
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LinkIssue {
+    pub source_type: EntityKind,
+    pub source_id: String,
+    pub chapter_id: String,
+    pub chapter_title: String,
+    pub link: ResolvedLink,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RenameResult {
+    pub links_updated: usize,
+    pub chapter_ids: Vec<String>,
+    pub scene_ids: Vec<String>,
+}
+
+/// A linked entity with text to explain it in an exported glossary
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GlossaryTerm {
+    pub entity_type: EntityKind,
+    pub entity_id: String,
+    pub term: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub definition: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+}
+
+/// Chapter or scene text that can hold links
+struct Source {
+    kind: EntityKind,
+    id: String,
+    chapter_id: String,
+    chapter_title: String,
+    content: String,
+}
+
+/// Live linkable entities of a project, by name and by alias
+struct Resolver {
+    names: Vec<(LinkTarget, String)>,
+    aliases: Vec<(LinkTarget, String)>,
+}
+
+/// Find the wiki links in HTML. Links cannot span tags or lines.
+pub fn parse_wiki_links(html: &str) -> Vec<WikiLink> {
+    let mut links = Vec::new();
+    let mut from = 0;
+    while let Some(open) = html[from..].find("[[").map(|i| from + i) {
+        let inner_start = open + 2;
+        let Some(close) = html[inner_start..].find("]]").map(|i| inner_start + i) else {
+            break;
+        };
+        let inner = &html[inner_start..close];
+        // A stray `[[` before a real link: retry from the next bracket
+        if inner.contains(['[', ']', '<', '>', '\n']) {
+            from = open + 1;
+            continue;
+        }
+        from = close + 2;
+
+        let (name_part, label) = match inner.find('|') {
+            Some(bar) => (
+                inner_start..inner_start + bar,
+                Some(inner_start + bar + 1..close),
+            ),
+            None => (inner_start..close, None),
+        };
+        let (kind, name_part) = match html[name_part.clone()].split_once(':') {
+            Some((prefix, _)) => match PREFIXES
+                .iter()
+                .find(|(p, _)| p.eq_ignore_ascii_case(prefix.trim()))
+            {
+                Some(&(_, kind)) => (
+                    Some(kind),
+                    name_part.start + prefix.len() + 1..name_part.end,
+                ),
+                None => (None, name_part),
+            },
+            None => (None, name_part),
+        };
+        let target_range = trimmed(html, name_part);
+        if target_range.is_empty() {
+            continue;
+        }
+        let display_range = label
+            .map(|l| trimmed(html, l))
+            .filter(|l| !l.is_empty())
+            .unwrap_or_else(|| target_range.clone());
+
+        links.push(WikiLink {
+            range: open..close + 2,
+            target: text::decode_entities(&html[target_range.clone()]),
+            target_range,
+            display_range,
+            kind,
+        });
+    }
+    links
+}
+
+/// Resolve the links in a piece of HTML against a project's entities
+pub fn resolve_links(
+    conn: &Connection,
+    project_id: &str,
+    html: &str,
+) -> rusqlite::Result<Vec<ResolvedLink>> {
+    let resolver = Resolver::load(conn, project_id)?;
+    Ok(parse_wiki_links(html)
+        .iter()
+        .map(|link| resolver.describe(html, link))
+        .collect())
+}
+
+/// Broken and ambiguous links across a project's chapters and scenes, in
+/// manuscript order
+pub fn get_link_report(conn: &Connection, project_id: &str) -> rusqlite::Result<Vec<LinkIssue>> {
+    let resolver = Resolver::load(conn, project_id)?;
+    let mut issues = Vec::new();
+    for source in project_sources(conn, project_id, false)? {
+        for link in parse_wiki_links(&source.content) {
+            let resolved = resolver.describe(&source.content, &link);
+            if resolved.status != LinkStatus::Resolved {
+                issues.push(LinkIssue {
+                    source_type: source.kind,
+                    source_id: source.id.clone(),
+                    chapter_id: source.chapter_id.clone(),
+                    chapter_title: source.chapter_title.clone(),
+                    link: resolved,
+                });
+            }
+        }
+    }
+    Ok(issues)
+}
+
+/// Rename a character, location or lore item and rewrite every link that
+/// names it, in trashed chapters and scenes too. Links through an alias
+/// still resolve and are left alone.
+pub fn rename_entity(
+    conn: &Connection,
+    kind: EntityKind,
+    id: &str,
+    new_name: &str,
+) -> Result<RenameResult, LinkError> {
+    let column = name_column(kind).ok_or(LinkError::NotLinkable(kind.as_str()))?;
+    let new_name = new_name.trim();
+    if new_name.is_empty() || new_name.contains(['[', ']', '|']) {
+        return Err(LinkError::InvalidName(new_name.to_string()));
+    }
+    let project_id: String = conn
+        .query_row(
+            &format!(
+                "SELECT project_id FROM {} WHERE id = ?1 AND deleted_at IS NULL",
+                kind.table()
+            ),
+            params![id],
+            |row| row.get(0),
+        )
+        .optional()?
+        .ok_or_else(|| LinkError::NotFound {
+            kind: kind.as_str(),
+            id: id.to_string(),
+        })?;
+
+    let resolver = Resolver::load(conn, &project_id)?;
+    let replacement = text::escape_html(new_name);
+    let mut result = RenameResult::default();
+
+    with_savepoint(conn, || {
+        for source in project_sources(conn, &project_id, true)? {
+            let mut content = source.content.clone();
+            let mut rewritten = 0;
+            // Back to front, so earlier ranges stay valid
+            for link in parse_wiki_links(&source.content).iter().rev() {
+                if resolver.names_entity(link, kind, id) {
+                    content.replace_range(link.target_range.clone(), &replacement);
+                    rewritten += 1;
+                }
+            }
+            if rewritten == 0 {
+                continue;
+            }
+            result.links_updated += rewritten;
+            write_source(conn, &source, &content)?;
+            match source.kind {
+                EntityKind::Scene => result.scene_ids.push(source.id),
+                _ => result.chapter_ids.push(source.id),
+            }
+        }
+
+        conn.execute(
+            &format!(
+                "UPDATE {} SET {} = ?2, updated_at = CURRENT_TIMESTAMP WHERE id = ?1",
+                kind.table(),
+                column
+            ),
+            params![id, new_name],
+        )?;
+        mentions::index_entity(conn, kind, id)
+    })?;
+
+    log::info!(
+        "Renamed {} {} to {:?}, {} links updated",
+        kind.as_str(),
+        id,
+        new_name,
+        result.links_updated
+    );
+    Ok(result)
+}
+
+/// Every entity linked from a project's live chapters and scenes, with a
+/// short definition for an exported glossary
+pub fn get_link_glossary(
+    conn: &Connection,
+    project_id: &str,
+) -> rusqlite::Result<Vec<GlossaryTerm>> {
+    let resolver = Resolver::load(conn, project_id)?;
+    let mut linked: Vec<LinkTarget> = Vec::new();
+    for source in project_sources(conn, project_id, false)? {
+        for link in parse_wiki_links(&source.content) {
+            let (status, candidates) = resolver.resolve(&link);
+            if status == LinkStatus::Resolved && !linked.contains(&candidates[0]) {
+                linked.extend(candidates);
+            }
+        }
+    }
+
+    let aliases = mentions::get_project_aliases(conn, project_id)?;
+    let mut terms = Vec::with_capacity(linked.len());
+    for target in linked {
+        let sql = match target.entity_type {
+            EntityKind::Character => "SELECT role FROM characters WHERE id = ?1",
+            EntityKind::Location => "SELECT description FROM locations WHERE id = ?1",
+            _ => "SELECT COALESCE(NULLIF(summary, ''), content) FROM lore_items WHERE id = ?1",
+        };
+        let definition: Option<String> = conn
+            .query_row(sql, params![target.entity_id], |row| {
+                row.get::<_, Option<String>>(0)
+            })
+            .optional()?
+            .flatten()
+            .map(|d| text::strip_html(&d))
+            .filter(|d| !d.is_empty());
+        terms.push(GlossaryTerm {
+            aliases: aliases
+                .iter()
+                .filter(|a| a.entity_type == target.entity_type && a.entity_id == target.entity_id)
+                .map(|a| a.alias.clone())
+                .collect(),
+            entity_type: target.entity_type,
+            entity_id: target.entity_id,
+            term: target.name,
+            definition,
+        });
+    }
+    terms.sort_by_key(|t| t.term.to_lowercase());
+    Ok(terms)
+}
+
+impl Resolver {
+    fn load(conn: &Connection, project_id: &str) -> rusqlite::Result<Self> {
+        let mut stmt = conn.prepare(
+            r#"SELECT 'character', id, name FROM characters WHERE project_id = ?1 AND deleted_at IS NULL
+               UNION ALL SELECT 'location', id, name FROM locations WHERE project_id = ?1 AND deleted_at IS NULL
+               UNION ALL SELECT 'lore_item', id, title FROM lore_items WHERE project_id = ?1 AND deleted_at IS NULL"#,
+        )?;
+        let targets = stmt
+            .query_map(params![project_id], |row| {
+                let kind: String = row.get(0)?;
+                Ok(LinkTarget {
+                    entity_type: EntityKind::from_db(&kind).unwrap_or(EntityKind::LoreItem),
+                    entity_id: row.get(1)?,
+                    name: row.get(2)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let aliases = mentions::get_project_aliases(conn, project_id)?
+            .into_iter()
+            .filter_map(
+                |EntityAlias {
+                     entity_type,
+                     entity_id,
+                     alias,
+                 }| {
+                    let target = targets
+                        .iter()
+                        .find(|t| t.entity_type == entity_type && t.entity_id == entity_id)?;
+                    Some((target.clone(), alias.trim().to_lowercase()))
+                },
+            )
+            .collect();
+        let names = targets
+            .into_iter()
+            .map(|t| {
+                let key = t.name.trim().to_lowercase();
+                (t, key)
+            })
+            .collect();
+        Ok(Self { names, aliases })
+    }
+
+    /// Entities a link names, looking at aliases only when no name matches
+    fn resolve(&self, link: &WikiLink) -> (LinkStatus, Vec<LinkTarget>) {
+        let key = link.target.to_lowercase();
+        let matching = |entries: &[(LinkTarget, String)]| -> Vec<LinkTarget> {
+            let mut seen = HashSet::new();
+            entries
+                .iter()
+                .filter(|(t, k)| *k == key && link.kind.map_or(true, |kind| kind == t.entity_type))
+                .filter(|(t, _)| seen.insert((t.entity_type, t.entity_id.clone())))
+                .map(|(t, _)| t.clone())
+                .collect()
+        };
+        let mut found = matching(&self.names);
+        if found.is_empty() {
+            found = matching(&self.aliases);
+        }
+        let status = match found.len() {
+            0 => LinkStatus::Broken,
+            1 => LinkStatus::Resolved,
+            _ => LinkStatus::Ambiguous,
+        };
+        (status, found)
+    }
+
+    /// Whether a link resolves to the entity by its name, not an alias
+    fn names_entity(&self, link: &WikiLink, kind: EntityKind, id: &str) -> bool {
+        let (status, found) = self.resolve(link);
+        status == LinkStatus::Resolved
+            && found[0].entity_type == kind
+            && found[0].entity_id == id
+            && found[0].name.trim().to_lowercase() == link.target.to_lowercase()
+    }
+
+    fn describe(&self, html: &str, link: &WikiLink) -> ResolvedLink {
+        let (status, candidates) = self.resolve(link);
+        let start = html[..link.range.start].chars().count();
+        ResolvedLink {
+            start: start as u32,
+            end: (start + html[link.range.clone()].chars().count()) as u32,
+            text: html[link.range.clone()].to_string(),
+            target: link.target.clone(),
+            kind: link.kind,
+            status,
+            candidates,
+        }
+    }
+}
+
+fn name_column(kind: EntityKind) -> Option<&'static str> {
+    match kind {
+        EntityKind::Character | EntityKind::Location => Some("name"),
+        EntityKind::LoreItem => Some("title"),
+        _ => None,
+    }
+}
+
+/// Shrink a byte range to exclude surrounding whitespace
+fn trimmed(html: &str, range: Range<usize>) -> Range<usize> {
+    let slice = &html[range.clone()];
+    let start = range.start + (slice.len() - slice.trim_start().len());
+    start..start + slice.trim().len()
+}
+
+/// Chapters written as documents, then scenes, in manuscript order. A
+/// chapter compiled from scenes is skipped; its text lives in the scenes.
+fn project_sources(
+    conn: &Connection,
+    project_id: &str,
+    include_trashed: bool,
+) -> rusqlite::Result<Vec<Source>> {
+    let (live_chapter, live_scene) = if include_trashed {
+        ("", "")
+    } else {
+        (
+            "AND c.deleted_at IS NULL",
+            "AND c.deleted_at IS NULL AND s.deleted_at IS NULL",
+        )
+    };
+    let mut stmt = conn.prepare(&format!(
+        r#"SELECT 'chapter', c.id, c.id, c.title, c.content, c.number, c.created_at, -1
+           FROM chapters c
+           WHERE c.project_id = ?1 AND c.content_mode = 'document' {live_chapter}
+           UNION ALL
+           SELECT 'scene', s.id, c.id, c.title, s.content, c.number, c.created_at, s.timeline_position
+           FROM scenes s JOIN chapters c ON c.id = s.chapter_id
+           WHERE c.project_id = ?1 AND s.content IS NOT NULL {live_scene}
+           ORDER BY 6, 7, 8"#
+    ))?;
+    let rows = stmt.query_map(params![project_id], |row| {
+        let kind: String = row.get(0)?;
+        Ok(Source {
+            kind: EntityKind::from_db(&kind).unwrap_or(EntityKind::Chapter),
+            id: row.get(1)?,
+            chapter_id: row.get(2)?,
+            chapter_title: row.get(3)?,
+            content: row.get::<_, Option<String>>(4)?.unwrap_or_default(),
+        })
+    })?;
+    rows.collect()
+}
+
+fn write_source(conn: &Connection, source: &Source, content: &str) -> rusqlite::Result<()> {
+    let word_count = text::count_words(&text::strip_html(content));
+    if source.kind == EntityKind::Scene {
+        conn.execute(
+            "UPDATE scenes SET content = ?2, word_count = ?3, updated_at = CURRENT_TIMESTAMP WHERE id = ?1",
+            params![source.id, content, word_count],
+        )?;
+        mentions::index_source(conn, EntityKind::Scene, &source.id)?;
+        assembly::recompile(conn, &source.chapter_id)
+    } else {
+        conn.execute(
+            "UPDATE chapters SET content = ?2, word_count = ?3, updated_at = CURRENT_TIMESTAMP WHERE id = ?1",
+            params![source.id, content, word_count],
+        )?;
+        revisions::capture_revision(conn, &source.id, RevisionSource::Auto, None)?;
+        mentions::index_source(conn, EntityKind::Chapter, &source.id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database;
+    use crate::database::test_support::project_db;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_wiki_links() {
+        let html = "<p>[[Ada]] met [[ lore:The Sundering | the fall]] at [[note: x]] [[oops [[Harbor &amp; Docks]]</p>";
+        let links = parse_wiki_links(html);
+        let shown: Vec<(&str, Option<EntityKind>, &str)> = links
+            .iter()
+            .map(|l| (l.target.as_str(), l.kind, &html[l.display_range.clone()]))
+            .collect();
+        assert_eq!(
+            shown,
+            [
+                ("Ada", None, "Ada"),
+                ("The Sundering", Some(EntityKind::LoreItem), "the fall"),
+                ("note: x", None, "note: x"),
+                ("Harbor & Docks", None, "Harbor &amp; Docks"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rename_rewrites_links_and_reports_issues() {
+        let conn = project_db();
+        for (id, name) in [("ada", "Ada"), ("bo", "Bo"), ("bo2", "Bo")] {
+            let character =
+                serde_json::from_value(json!({ "id": id, "projectId": "p1", "name": name }))
+                    .unwrap();
+            database::create_character(&conn, &character).unwrap();
+        }
+        database::set_entity_aliases(&conn, EntityKind::Character, "ada", &["Captain".into()])
+            .unwrap();
+        let chapter = serde_json::from_value(json!({
+            "id": "c1", "projectId": "p1", "title": "Arrival",
+            "content": "<p>[[ada]] and [[character:Ada|her]] met [[Captain]], [[Bo]] and [[Cy]].</p>",
+        }))
+        .unwrap();
+        database::create_chapter(&conn, &chapter).unwrap();
+
+        let report = get_link_report(&conn, "p1").unwrap();
+        let statuses: Vec<(&str, LinkStatus)> = report
+            .iter()
+            .map(|i| (i.link.target.as_str(), i.link.status))
+            .collect();
+        assert_eq!(
+            statuses,
+            [("Bo", LinkStatus::Ambiguous), ("Cy", LinkStatus::Broken)]
+        );
+        assert_eq!(report[0].link.candidates.len(), 2);
+
+        let result = rename_entity(&conn, EntityKind::Character, "ada", "Ada Byron").unwrap();
+        assert_eq!(result.links_updated, 2);
+        assert_eq!(result.chapter_ids, ["c1"]);
+        let chapter = database::get_chapter(&conn, "c1").unwrap().unwrap();
+        assert_eq!(
+            chapter.content,
+            "<p>[[Ada Byron]] and [[character:Ada Byron|her]] met [[Captain]], [[Bo]] and [[Cy]].</p>"
+        );
+        let links = resolve_links(&conn, "p1", &chapter.content).unwrap();
+        assert!(links[..3]
+            .iter()
+            .all(|l| l.status == LinkStatus::Resolved && l.candidates[0].entity_id == "ada"));
+
+        assert!(matches!(
+            rename_entity(&conn, EntityKind::Character, "ada", "[[x]]"),
+            Err(LinkError::InvalidName(_))
+        ));
+    }
+}
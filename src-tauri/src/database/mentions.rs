@@ -0,0 +1,611 @@
+//! Index of entity mentions in the manuscript
+//!
+//! Chapter, scene and lore text is scanned for the names of characters and
+//! locations, the titles of lore items, and any aliases given to them.
+//! Matches are whole words and case-sensitive; where two terms of the same
+//! entity overlap the longer one wins. Positions are character offsets into
+//! the plain text of the source (see `text::strip_html`).
+//!
+//! Sources are reindexed when saved, entities when created, renamed or given
+//! new aliases. Trashed sources and entities stay indexed and are filtered
+//! out when querying, so a restore needs no reindexing.
+
+use super::models::EntityKind;
+use super::operations::with_savepoint;
+use super::text;
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Characters of context kept on each side of a mention
+const SNIPPET_CONTEXT: usize = 40;
+
+/// Live mentions with the chapter they appear in (none for lore sources)
+const LIVE_MENTIONS: &str = r#"
+    FROM mentions m
+    LEFT JOIN scenes s ON m.source_type = 'scene' AND s.id = m.source_id
+    LEFT JOIN chapters c ON c.id = CASE m.source_type WHEN 'chapter' THEN m.source_id ELSE s.chapter_id END
+    LEFT JOIN lore_items l ON m.source_type = 'lore_item' AND l.id = m.source_id
+    WHERE ((c.id IS NOT NULL AND c.deleted_at IS NULL AND (s.id IS NULL OR s.deleted_at IS NULL))
+           OR (l.id IS NOT NULL AND l.deleted_at IS NULL))
+"#;
+
+#[derive(Error, Debug)]
+pub enum MentionError {
+    #[error("{0} entities are not indexed for mentions")]
+    NotMentionable(&'static str),
+
+    #[error("{kind} {id} not found")]
+    NotFound { kind: &'static str, id: String },
+
+    #[error("Database error: {0}")]
+    Database(#[from] rusqlite::Error),
+}
+
+/// One occurrence of an entity in a source text
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Mention {
+    pub entity_type: EntityKind,
+    pub entity_id: String,
+    pub source_type: EntityKind,
+    pub source_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chapter_id: Option<String>,
+    pub start: u32,
+    pub end: u32,
+    /// The name or alias as it appears in the text
+    pub text: String,
+    /// The mention with some surrounding text
+    pub snippet: String,
+}
+
+/// An entity appearing in a chapter
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CastMember {
+    pub entity_type: EntityKind,
+    pub entity_id: String,
+    pub name: String,
+    pub mention_count: u32,
+}
+
+/// A source text mentioning an entity
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Backlink {
+    pub source_type: EntityKind,
+    pub source_id: String,
+    pub title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chapter_id: Option<String>,
+    pub mention_count: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EntityAlias {
+    pub entity_type: EntityKind,
+    pub entity_id: String,
+    pub alias: String,
+}
+
+/// An entity and the terms it is recognised by
+struct Indexed {
+    kind: EntityKind,
+    id: String,
+    terms: Vec<String>,
+}
+
+/// Entity kinds whose names are looked for
+pub fn is_mentionable(kind: EntityKind) -> bool {
+    matches!(
+        kind,
+        EntityKind::Character | EntityKind::Location | EntityKind::LoreItem
+    )
+}
+
+/// Every mention of an entity, in manuscript order, lore last
+pub fn get_entity_mentions(conn: &Connection, kind: EntityKind, id: &str) -> Result<Vec<Mention>> {
+    let mut stmt = conn.prepare(&format!(
+        r#"SELECT m.entity_type, m.entity_id, m.source_type, m.source_id, c.id,
+                  m.start_offset, m.end_offset, m.matched, m.snippet
+           {}
+             AND m.entity_type = ?1 AND m.entity_id = ?2
+           ORDER BY c.id IS NULL, c.number, c.created_at, s.timeline_position, l.title, m.start_offset"#,
+        LIVE_MENTIONS
+    ))?;
+    let rows = stmt.query_map(params![kind.as_str(), id], |row| {
+        Ok(Mention {
+            entity_type: kind_column(row, 0)?,
+            entity_id: row.get(1)?,
+            source_type: kind_column(row, 2)?,
+            source_id: row.get(3)?,
+            chapter_id: row.get(4)?,
+            start: row.get(5)?,
+            end: row.get(6)?,
+            text: row.get(7)?,
+            snippet: row.get(8)?,
+        })
+    })?;
+    rows.collect()
+}
+
+/// Characters, locations and lore mentioned in a chapter or its scenes,
+/// most mentioned first
+pub fn get_chapter_cast(conn: &Connection, chapter_id: &str) -> Result<Vec<CastMember>> {
+    let mut stmt = conn.prepare(&format!(
+        r#"WITH names (entity_type, entity_id, name) AS (
+               SELECT 'character', id, name FROM characters WHERE deleted_at IS NULL
+               UNION ALL SELECT 'location', id, name FROM locations WHERE deleted_at IS NULL
+               UNION ALL SELECT 'lore_item', id, title FROM lore_items WHERE deleted_at IS NULL
+           )
+           SELECT m.entity_type, m.entity_id, n.name, COUNT(*)
+           {}
+             AND c.id = ?1
+           GROUP BY m.entity_type, m.entity_id
+           ORDER BY COUNT(*) DESC, n.name"#,
+        LIVE_MENTIONS.replace(
+            "FROM mentions m",
+            "FROM mentions m JOIN names n ON n.entity_type = m.entity_type AND n.entity_id = m.entity_id",
+        )
+    ))?;
+    let rows = stmt.query_map(params![chapter_id], |row| {
+        Ok(CastMember {
+            entity_type: kind_column(row, 0)?,
+            entity_id: row.get(1)?,
+            name: row.get(2)?,
+            mention_count: row.get(3)?,
+        })
+    })?;
+    rows.collect()
+}
+
+/// Chapters, scenes and lore items mentioning an entity
+pub fn get_backlinks(conn: &Connection, kind: EntityKind, id: &str) -> Result<Vec<Backlink>> {
+    let mut stmt = conn.prepare(&format!(
+        r#"SELECT m.source_type, m.source_id, COALESCE(s.title, l.title, c.title, ''), c.id, COUNT(*)
+           {}
+             AND m.entity_type = ?1 AND m.entity_id = ?2
+           GROUP BY m.source_type, m.source_id
+           ORDER BY c.id IS NULL, c.number, c.created_at, s.timeline_position, l.title"#,
+        LIVE_MENTIONS
+    ))?;
+    let rows = stmt.query_map(params![kind.as_str(), id], |row| {
+        Ok(Backlink {
+            source_type: kind_column(row, 0)?,
+            source_id: row.get(1)?,
+            title: row.get(2)?,
+            chapter_id: row.get(3)?,
+            mention_count: row.get(4)?,
+        })
+    })?;
+    rows.collect()
+}
+
+/// Aliases of every entity in a project
+pub fn get_project_aliases(conn: &Connection, project_id: &str) -> Result<Vec<EntityAlias>> {
+    let mut stmt = conn.prepare(
+        "SELECT entity_type, entity_id, alias FROM entity_aliases
+         WHERE project_id = ?1 ORDER BY entity_type, entity_id, alias",
+    )?;
+    let rows = stmt.query_map(params![project_id], |row| {
+        Ok(EntityAlias {
+            entity_type: kind_column(row, 0)?,
+            entity_id: row.get(1)?,
+            alias: row.get(2)?,
+        })
+    })?;
+    rows.collect()
+}
+
+pub fn get_entity_aliases(conn: &Connection, kind: EntityKind, id: &str) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT alias FROM entity_aliases WHERE entity_type = ?1 AND entity_id = ?2 ORDER BY alias",
+    )?;
+    let rows = stmt.query_map(params![kind.as_str(), id], |row| row.get(0))?;
+    rows.collect()
+}
+
+/// Replace an entity's aliases and reindex its mentions
+pub fn set_entity_aliases(
+    conn: &Connection,
+    kind: EntityKind,
+    id: &str,
+    aliases: &[String],
+) -> Result<(), MentionError> {
+    if !is_mentionable(kind) {
+        return Err(MentionError::NotMentionable(kind.as_str()));
+    }
+    let project_id: String = conn
+        .query_row(
+            &format!("SELECT project_id FROM {} WHERE id = ?1", kind.table()),
+            params![id],
+            |row| row.get(0),
+        )
+        .optional()?
+        .ok_or_else(|| MentionError::NotFound {
+            kind: kind.as_str(),
+            id: id.to_string(),
+        })?;
+
+    with_savepoint(conn, || {
+        conn.execute(
+            "DELETE FROM entity_aliases WHERE entity_type = ?1 AND entity_id = ?2",
+            params![kind.as_str(), id],
+        )?;
+        for alias in aliases.iter().map(|a| a.trim()).filter(|a| !a.is_empty()) {
+            conn.execute(
+                r#"INSERT OR IGNORE INTO entity_aliases (entity_type, entity_id, project_id, alias)
+                   VALUES (?1, ?2, ?3, ?4)"#,
+                params![kind.as_str(), id, project_id, alias],
+            )?;
+        }
+        index_entity(conn, kind, id)
+    })?;
+    Ok(())
+}
+
+/// Rebuild every mention in a project. Returns the number of mentions found.
+pub fn reindex_project(conn: &Connection, project_id: &str) -> Result<usize> {
+    let entities = project_entities(conn, project_id)?;
+    let sources = project_sources(conn, project_id)?;
+    with_savepoint(conn, || {
+        conn.execute(
+            "DELETE FROM mentions WHERE project_id = ?1",
+            params![project_id],
+        )?;
+        let mut count = 0;
+        for (kind, id, content) in &sources {
+            let plain = text::strip_html(content);
+            for entity in &entities {
+                count += write_mentions(conn, project_id, entity, *kind, id, &plain)?;
+            }
+        }
+        Ok(count)
+    })
+}
+
+/// Reindex the mentions found in one chapter, scene or lore item. A chapter
+/// compiled from scenes has none of its own; they belong to the scenes.
+pub(crate) fn index_source(conn: &Connection, kind: EntityKind, id: &str) -> Result<()> {
+    conn.execute(
+        "DELETE FROM mentions WHERE source_type = ?1 AND source_id = ?2",
+        params![kind.as_str(), id],
+    )?;
+    let sql = match kind {
+        EntityKind::Chapter => {
+            "SELECT project_id, content FROM chapters WHERE id = ?1 AND content_mode = 'document'"
+        }
+        EntityKind::Scene => {
+            "SELECT c.project_id, s.content FROM scenes s JOIN chapters c ON c.id = s.chapter_id
+             WHERE s.id = ?1"
+        }
+        EntityKind::LoreItem => "SELECT project_id, content FROM lore_items WHERE id = ?1",
+        _ => return Ok(()),
+    };
+    let Some((project_id, content)) = conn
+        .query_row(sql, params![id], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?))
+        })
+        .optional()?
+    else {
+        return Ok(());
+    };
+
+    let plain = text::strip_html(&content.unwrap_or_default());
+    for entity in project_entities(conn, &project_id)? {
+        write_mentions(conn, &project_id, &entity, kind, id, &plain)?;
+    }
+    Ok(())
+}
+
+/// Reindex the mentions of one entity across its project
+pub(crate) fn index_entity(conn: &Connection, kind: EntityKind, id: &str) -> Result<()> {
+    conn.execute(
+        "DELETE FROM mentions WHERE entity_type = ?1 AND entity_id = ?2",
+        params![kind.as_str(), id],
+    )?;
+    let Some(project_id) = conn
+        .query_row(
+            &format!("SELECT project_id FROM {} WHERE id = ?1", kind.table()),
+            params![id],
+            |row| row.get::<_, String>(0),
+        )
+        .optional()?
+    else {
+        return Ok(());
+    };
+    let Some(entity) = project_entities(conn, &project_id)?
+        .into_iter()
+        .find(|e| e.kind == kind && e.id == id)
+    else {
+        return Ok(());
+    };
+
+    for (source_kind, source_id, content) in project_sources(conn, &project_id)? {
+        let plain = text::strip_html(&content);
+        write_mentions(conn, &project_id, &entity, source_kind, &source_id, &plain)?;
+    }
+    Ok(())
+}
+
+/// Whether saving `name` would rename the entity (a new entity counts too)
+pub(crate) fn is_renamed(
+    conn: &Connection,
+    kind: EntityKind,
+    id: &str,
+    name: &str,
+) -> Result<bool> {
+    let column = if kind == EntityKind::LoreItem {
+        "title"
+    } else {
+        "name"
+    };
+    let current: Option<String> = conn
+        .query_row(
+            &format!("SELECT {} FROM {} WHERE id = ?1", column, kind.table()),
+            params![id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(current.as_deref() != Some(name))
+}
+
+/// Drop the mentions and aliases of a purged item
+pub(crate) fn forget(conn: &Connection, kind: EntityKind, id: &str) -> Result<()> {
+    if kind == EntityKind::Chapter {
+        conn.execute(
+            r#"DELETE FROM mentions WHERE source_type = 'scene'
+               AND source_id IN (SELECT id FROM scenes WHERE chapter_id = ?1)"#,
+            params![id],
+        )?;
+    }
+    conn.execute(
+        r#"DELETE FROM mentions
+           WHERE (source_type = ?1 AND source_id = ?2) OR (entity_type = ?1 AND entity_id = ?2)"#,
+        params![kind.as_str(), id],
+    )?;
+    conn.execute(
+        "DELETE FROM entity_aliases WHERE entity_type = ?1 AND entity_id = ?2",
+        params![kind.as_str(), id],
+    )?;
+    Ok(())
+}
+
+fn kind_column(row: &rusqlite::Row, index: usize) -> Result<EntityKind> {
+    let value: String = row.get(index)?;
+    EntityKind::from_db(&value).ok_or_else(|| {
+        rusqlite::Error::FromSqlConversionFailure(
+            index,
+            rusqlite::types::Type::Text,
+            format!("unknown entity type {}", value).into(),
+        )
+    })
+}
+
+/// Every mentionable entity of a project, trashed ones included
+fn project_entities(conn: &Connection, project_id: &str) -> Result<Vec<Indexed>> {
+    let mut entities: Vec<Indexed> = {
+        let mut stmt = conn.prepare(
+            r#"SELECT 'character', id, name FROM characters WHERE project_id = ?1
+               UNION ALL SELECT 'location', id, name FROM locations WHERE project_id = ?1
+               UNION ALL SELECT 'lore_item', id, title FROM lore_items WHERE project_id = ?1"#,
+        )?;
+        let rows = stmt.query_map(params![project_id], |row| {
+            Ok(Indexed {
+                kind: kind_column(row, 0)?,
+                id: row.get(1)?,
+                terms: vec![row.get(2)?],
+            })
+        })?;
+        rows.collect::<Result<_>>()?
+    };
+
+    for alias in get_project_aliases(conn, project_id)? {
+        if let Some(entity) = entities
+            .iter_mut()
+            .find(|e| e.kind == alias.entity_type && e.id == alias.entity_id)
+        {
+            entity.terms.push(alias.alias);
+        }
+    }
+    for entity in &mut entities {
+        entity.terms = usable_terms(&entity.terms);
+    }
+    Ok(entities)
+}
+
+/// Names and aliases worth looking for: trimmed, and longer than one character
+pub(super) fn usable_terms(terms: &[String]) -> Vec<String> {
+    terms
+        .iter()
+        .map(|t| t.trim().to_string())
+        .filter(|t| t.chars().count() > 1)
+        .collect()
+}
+
+/// (kind, id, content) of every text in a project that can mention things
+fn project_sources(
+    conn: &Connection,
+    project_id: &str,
+) -> Result<Vec<(EntityKind, String, String)>> {
+    let mut stmt = conn.prepare(
+        r#"SELECT 'chapter', id, content FROM chapters
+           WHERE project_id = ?1 AND content_mode = 'document'
+           UNION ALL
+           SELECT 'scene', s.id, s.content FROM scenes s JOIN chapters c ON c.id = s.chapter_id
+           WHERE c.project_id = ?1
+           UNION ALL
+           SELECT 'lore_item', id, content FROM lore_items WHERE project_id = ?1"#,
+    )?;
+    let rows = stmt.query_map(params![project_id], |row| {
+        Ok((
+            kind_column(row, 0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, Option<String>>(2)?.unwrap_or_default(),
+        ))
+    })?;
+    rows.collect()
+}
+
+/// Record the mentions of `entity` in a source's plain text
+fn write_mentions(
+    conn: &Connection,
+    project_id: &str,
+    entity: &Indexed,
+    source_kind: EntityKind,
+    source_id: &str,
+    plain: &str,
+) -> Result<usize> {
+    // An entry's own text does not mention itself
+    if entity.kind == source_kind && entity.id == source_id {
+        return Ok(0);
+    }
+
+    let found = locate(plain, &entity.terms);
+    for located in &found {
+        conn.execute(
+            r#"INSERT INTO mentions (project_id, entity_type, entity_id, source_type, source_id,
+                                     start_offset, end_offset, matched, snippet)
+               VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)"#,
+            params![
+                project_id,
+                entity.kind.as_str(),
+                entity.id,
+                source_kind.as_str(),
+                source_id,
+                located.start,
+                located.end,
+                located.matched,
+                located.snippet,
+            ],
+        )?;
+    }
+    Ok(found.len())
+}
+
+/// A term found in plain text, with character offsets
+pub(super) struct Located {
+    pub start: usize,
+    pub end: usize,
+    pub matched: String,
+    pub snippet: String,
+}
+
+/// Whole-word occurrences of `terms` in `plain`, without overlaps
+pub(super) fn locate(plain: &str, terms: &[String]) -> Vec<Located> {
+    let mut char_offset = 0;
+    let mut byte_offset = 0;
+    find_terms(plain, terms)
+        .into_iter()
+        .map(|(start, end)| {
+            char_offset += plain[byte_offset..start].chars().count();
+            byte_offset = start;
+            let matched = &plain[start..end];
+            Located {
+                start: char_offset,
+                end: char_offset + matched.chars().count(),
+                matched: matched.to_string(),
+                snippet: snippet(plain, start, end),
+            }
+        })
+        .collect()
+}
+
+/// Byte ranges of whole-word occurrences of `terms`, without overlaps
+fn find_terms(text: &str, terms: &[String]) -> Vec<(usize, usize)> {
+    let is_word = |c: Option<char>| c.is_some_and(char::is_alphanumeric);
+
+    let mut found = Vec::new();
+    for term in terms {
+        for (start, _) in text.match_indices(term.as_str()) {
+            let end = start + term.len();
+            if !is_word(text[..start].chars().next_back()) && !is_word(text[end..].chars().next()) {
+                found.push((start, end));
+            }
+        }
+    }
+    // Leftmost first, longest first among those starting together
+    found.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)));
+
+    let mut kept: Vec<(usize, usize)> = Vec::new();
+    for (start, end) in found {
+        if kept.last().map_or(true, |&(_, last_end)| start >= last_end) {
+            kept.push((start, end));
+        }
+    }
+    kept
+}
+
+fn snippet(text: &str, start: usize, end: usize) -> String {
+    let before: Vec<char> = text[..start].chars().rev().take(SNIPPET_CONTEXT).collect();
+    let before: String = before.into_iter().rev().collect();
+    let after: String = text[end..].chars().take(SNIPPET_CONTEXT).collect();
+    format!("{}{}{}", before, &text[start..end], after)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database;
+    use crate::database::test_support::project_db;
+    use serde_json::json;
+
+    #[test]
+    fn test_find_terms_prefers_whole_and_longest_words() {
+        let terms = vec!["Ada".to_string(), "Ada Byron".to_string()];
+        let text = "Ada Byron met Adam. Ada's ship, Ada.";
+        assert_eq!(find_terms(text, &terms), [(0, 9), (20, 23), (32, 35)]);
+    }
+
+    #[test]
+    fn test_mentions_follow_edits_aliases_and_trash() {
+        let conn = project_db();
+        let ada = serde_json::from_value(json!({ "id": "ada", "projectId": "p1", "name": "Ada" }))
+            .unwrap();
+        database::create_character(&conn, &ada).unwrap();
+        let mut chapter: database::Chapter = serde_json::from_value(json!({
+            "id": "c1", "projectId": "p1", "title": "Arrival",
+            "content": "<p>Ada waited.</p><p>The Captain came.</p>",
+        }))
+        .unwrap();
+        database::create_chapter(&conn, &chapter).unwrap();
+
+        let mentions = get_entity_mentions(&conn, EntityKind::Character, "ada").unwrap();
+        assert_eq!(mentions.len(), 1);
+        assert_eq!((mentions[0].start, mentions[0].end), (0, 3));
+        assert_eq!(mentions[0].chapter_id.as_deref(), Some("c1"));
+
+        // Aliases are picked up across existing text
+        set_entity_aliases(&conn, EntityKind::Character, "ada", &["Captain".into()]).unwrap();
+        let cast = get_chapter_cast(&conn, "c1").unwrap();
+        assert_eq!(cast[0].mention_count, 2);
+
+        // Saving the chapter reindexes it; positions are in plain text
+        chapter.content = "<p>Ada, Ada and the Captain.</p>".into();
+        database::update_chapter(&conn, &chapter).unwrap();
+        let mentions = get_entity_mentions(&conn, EntityKind::Character, "ada").unwrap();
+        let spans: Vec<(u32, u32)> = mentions.iter().map(|m| (m.start, m.end)).collect();
+        assert_eq!(spans, [(0, 3), (5, 8), (17, 24)]);
+
+        // New entities find mentions in text written before them
+        let lore = serde_json::from_value(json!({
+            "id": "k1", "projectId": "p1", "title": "Captain",
+        }))
+        .unwrap();
+        database::create_lore_item(&conn, &lore).unwrap();
+        let backlinks = get_backlinks(&conn, EntityKind::LoreItem, "k1").unwrap();
+        assert_eq!(backlinks.len(), 1);
+        assert_eq!(backlinks[0].title, "Arrival");
+
+        // Trashed sources drop out and come back on restore
+        database::delete_chapter(&conn, "c1").unwrap();
+        assert!(get_chapter_cast(&conn, "c1").unwrap().is_empty());
+        database::restore_from_trash(&conn, EntityKind::Chapter, "c1").unwrap();
+        assert_eq!(get_chapter_cast(&conn, "c1").unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,323 @@
+//! Versioned schema migrations
+//!
+//! Every migration has a unique, strictly increasing version number and is
+//! applied inside its own transaction. The applied versions are recorded in
+//! the `schema_version` table, so a migration either lands completely or not
+//! at all: any error rolls the transaction back and aborts startup.
+
+use rusqlite::{params, Connection, Transaction};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum MigrationError {
+    #[error("Database error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+    #[error("Migration {version} ({name}) failed: {source}")]
+    Failed {
+        version: u32,
+        name: &'static str,
+        source: rusqlite::Error,
+    },
+    #[error("Migration {version} ({name}) introduced {count} foreign key violation(s)")]
+    ForeignKeyViolations {
+        version: u32,
+        name: &'static str,
+        count: usize,
+    },
+    #[error("Database schema version {found} is newer than the latest known version {latest}")]
+    UnsupportedVersion { found: u32, latest: u32 },
+    #[error("Migration versions must be strictly increasing (found {0} out of order)")]
+    OutOfOrder(u32),
+}
+
+/// A single step of a migration
+pub enum MigrationStep {
+    /// Plain SQL, may contain several statements
+    Sql(&'static str),
+    /// Arbitrary Rust code, for data migrations and conditional DDL
+    Rust(fn(&Transaction) -> rusqlite::Result<()>),
+}
+
+/// A numbered schema migration
+pub struct Migration {
+    pub version: u32,
+    pub name: &'static str,
+    pub steps: &'static [MigrationStep],
+}
+
+/// Apply every migration newer than the current schema version.
+/// Returns the schema version after running.
+pub fn run_migrations(conn: &Connection, migrations: &[Migration]) -> Result<u32, MigrationError> {
+    let mut previous = 0;
+    for migration in migrations {
+        if migration.version <= previous {
+            return Err(MigrationError::OutOfOrder(migration.version));
+        }
+        previous = migration.version;
+    }
+
+    conn.execute_batch(
+        r#"
+        CREATE TABLE IF NOT EXISTS schema_version (
+            version INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            applied_at TEXT DEFAULT CURRENT_TIMESTAMP
+        );
+        "#,
+    )?;
+
+    let current = current_version(conn)?;
+    let latest = previous;
+    if current > latest {
+        return Err(MigrationError::UnsupportedVersion {
+            found: current,
+            latest,
+        });
+    }
+
+    let pending: Vec<&Migration> = migrations.iter().filter(|m| m.version > current).collect();
+    if pending.is_empty() {
+        return Ok(current);
+    }
+
+    // Table rebuilds need foreign keys off, and the pragma is a no-op inside
+    // a transaction, so it is toggled around the whole run.
+    let fk_enabled: bool = conn.query_row("PRAGMA foreign_keys", [], |row| row.get(0))?;
+    if fk_enabled {
+        conn.execute_batch("PRAGMA foreign_keys = OFF")?;
+    }
+
+    let result = pending
+        .iter()
+        .try_for_each(|migration| apply_migration(conn, migration));
+
+    if fk_enabled {
+        conn.execute_batch("PRAGMA foreign_keys = ON")?;
+    }
+    result?;
+
+    Ok(latest)
+}
+
+/// Highest applied migration version, 0 for a fresh database
+pub fn current_version(conn: &Connection) -> rusqlite::Result<u32> {
+    conn.query_row(
+        "SELECT COALESCE(MAX(version), 0) FROM schema_version",
+        [],
+        |row| row.get(0),
+    )
+}
+
+fn apply_migration(conn: &Connection, migration: &Migration) -> Result<(), MigrationError> {
+    let failed = |source| MigrationError::Failed {
+        version: migration.version,
+        name: migration.name,
+        source,
+    };
+
+    let violations_before = count_foreign_key_violations(conn).map_err(failed)?;
+
+    // Dropping the transaction without committing rolls everything back
+    let tx = conn.unchecked_transaction().map_err(failed)?;
+    for step in migration.steps {
+        match step {
+            MigrationStep::Sql(sql) => tx.execute_batch(sql),
+            MigrationStep::Rust(func) => func(&tx),
+        }
+        .map_err(failed)?;
+    }
+
+    // Legacy databases may already contain orphans, so only fail on new ones
+    let violations_after = count_foreign_key_violations(&tx).map_err(failed)?;
+    if violations_after > violations_before {
+        log::error!(
+            "Migration {} ({}) rolled back: foreign key violations",
+            migration.version,
+            migration.name
+        );
+        return Err(MigrationError::ForeignKeyViolations {
+            version: migration.version,
+            name: migration.name,
+            count: violations_after - violations_before,
+        });
+    }
+
+    tx.execute(
+        "INSERT INTO schema_version (version, name) VALUES (?1, ?2)",
+        params![migration.version, migration.name],
+    )
+    .map_err(failed)?;
+    tx.commit().map_err(failed)?;
+
+    log::info!(
+        "Applied migration {} ({})",
+        migration.version,
+        migration.name
+    );
+    Ok(())
+}
+
+fn count_foreign_key_violations(conn: &Connection) -> rusqlite::Result<usize> {
+    let mut stmt = conn.prepare("PRAGMA foreign_key_check")?;
+    let mut rows = stmt.query([])?;
+    let mut count = 0;
+    while rows.next()?.is_some() {
+        count += 1;
+    }
+    Ok(count)
+}
+
+// ============================================================================
+// Helpers for migration steps
+// ============================================================================
+
+/// Check whether `table` already has `column`
+pub fn column_exists(conn: &Connection, table: &str, column: &str) -> rusqlite::Result<bool> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let names = stmt.query_map([], |row| row.get::<_, String>(1))?;
+    for name in names {
+        if name? == column {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// `ALTER TABLE ... ADD COLUMN` that tolerates the column already existing
+pub fn add_column_if_missing(
+    conn: &Connection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> rusqlite::Result<()> {
+    if !column_exists(conn, table, column)? {
+        conn.execute_batch(&format!(
+            "ALTER TABLE {} ADD COLUMN {} {}",
+            table, column, definition
+        ))?;
+    }
+    Ok(())
+}
+
+/// Rebuild `table` from a new definition, following SQLite's recommended
+/// create-copy-drop-rename procedure.
+///
+/// `create_sql` must create the replacement table as `<table>__new`;
+/// `columns` lists the columns copied across. Indexes and triggers on the
+/// old table are dropped with it and must be recreated by the caller.
+pub fn rebuild_table(
+    conn: &Connection,
+    table: &str,
+    create_sql: &str,
+    columns: &[&str],
+) -> rusqlite::Result<()> {
+    let new_table = format!("{}__new", table);
+    let column_list = columns.join(", ");
+
+    conn.execute_batch(create_sql)?;
+    conn.execute_batch(&format!(
+        "INSERT INTO {new} ({cols}) SELECT {cols} FROM {old};
+         DROP TABLE {old};
+         ALTER TABLE {new} RENAME TO {old};",
+        new = new_table,
+        old = table,
+        cols = column_list,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CREATE_ITEMS: &[MigrationStep] = &[MigrationStep::Sql(
+        "CREATE TABLE items (id TEXT PRIMARY KEY, name TEXT NOT NULL);",
+    )];
+
+    fn add_notes(tx: &Transaction) -> rusqlite::Result<()> {
+        add_column_if_missing(tx, "items", "notes", "TEXT")
+    }
+
+    const ADD_NOTES: &[MigrationStep] = &[MigrationStep::Rust(add_notes)];
+
+    const BROKEN: &[MigrationStep] = &[
+        MigrationStep::Sql("INSERT INTO items (id, name) VALUES ('a', 'first');"),
+        MigrationStep::Sql("ALTER TABLE missing_table ADD COLUMN nope TEXT;"),
+    ];
+
+    #[test]
+    fn test_applies_pending_migrations_once() {
+        let conn = Connection::open_in_memory().unwrap();
+        let migrations = [
+            Migration { version: 1, name: "create_items", steps: CREATE_ITEMS },
+            Migration { version: 2, name: "add_notes", steps: ADD_NOTES },
+        ];
+
+        assert_eq!(run_migrations(&conn, &migrations).unwrap(), 2);
+        assert!(column_exists(&conn, "items", "notes").unwrap());
+
+        // Second run is a no-op
+        assert_eq!(run_migrations(&conn, &migrations).unwrap(), 2);
+        let applied: u32 = conn
+            .query_row("SELECT COUNT(*) FROM schema_version", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(applied, 2);
+    }
+
+    #[test]
+    fn test_failed_migration_rolls_back() {
+        let conn = Connection::open_in_memory().unwrap();
+        let migrations = [
+            Migration { version: 1, name: "create_items", steps: CREATE_ITEMS },
+            Migration { version: 2, name: "broken", steps: BROKEN },
+        ];
+
+        let err = run_migrations(&conn, &migrations).unwrap_err();
+        assert!(matches!(err, MigrationError::Failed { version: 2, .. }));
+
+        assert_eq!(current_version(&conn).unwrap(), 1);
+        let rows: u32 = conn
+            .query_row("SELECT COUNT(*) FROM items", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(rows, 0);
+    }
+
+    #[test]
+    fn test_rejects_newer_database() {
+        let conn = Connection::open_in_memory().unwrap();
+        let migrations = [
+            Migration { version: 1, name: "create_items", steps: CREATE_ITEMS },
+            Migration { version: 2, name: "add_notes", steps: ADD_NOTES },
+        ];
+        run_migrations(&conn, &migrations).unwrap();
+
+        let err = run_migrations(&conn, &migrations[..1]).unwrap_err();
+        assert!(matches!(
+            err,
+            MigrationError::UnsupportedVersion { found: 2, latest: 1 }
+        ));
+    }
+
+    #[test]
+    fn test_rebuild_table_keeps_data() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE items (id TEXT PRIMARY KEY, name TEXT, legacy TEXT);
+             INSERT INTO items VALUES ('a', 'first', 'x');",
+        )
+        .unwrap();
+
+        rebuild_table(
+            &conn,
+            "items",
+            "CREATE TABLE items__new (id TEXT PRIMARY KEY, name TEXT NOT NULL)",
+            &["id", "name"],
+        )
+        .unwrap();
+
+        assert!(!column_exists(&conn, "items", "legacy").unwrap());
+        let name: String = conn
+            .query_row("SELECT name FROM items WHERE id = 'a'", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(name, "first");
+    }
+}
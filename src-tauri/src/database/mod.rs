@@ -2,20 +2,61 @@
 //!
 //! Provides CRUD operations for all domain entities.
 
+mod assembly;
+mod audit;
+mod batch;
+mod connection;
+mod custom_fields;
+mod duplicate;
+mod integrity;
+mod links;
+mod mentions;
+mod migrations;
 mod models;
 mod operations;
+mod ordering;
+mod pool;
+mod project_settings;
+mod query;
+mod revisions;
 mod schema;
+mod search;
+mod tags;
+#[cfg(test)]
+pub(crate) mod test_support;
+mod text;
+mod trash;
+mod undo;
 
+pub use assembly::*;
+pub use audit::*;
+pub use batch::*;
+pub use connection::{configure_connection, open_connection};
+pub use custom_fields::*;
+pub use duplicate::*;
+pub use integrity::*;
+pub use links::*;
+pub use mentions::*;
 pub use models::*;
 pub use operations::*;
-pub use schema::init_database;
+pub use ordering::*;
+pub use pool::*;
+pub use project_settings::*;
+pub use query::*;
+pub use revisions::*;
+pub use schema::{init_database, legacy_entities};
+pub use search::*;
+pub use tags::*;
+pub use text::strip_html;
+pub use trash::*;
+pub use undo::*;
 
-use rusqlite::Connection;
-use std::sync::Mutex;
+use std::sync::Arc;
 use tauri::State;
 
-/// Database state managed by Tauri
-pub struct DbState(pub Mutex<Connection>);
+/// Database state managed by Tauri; shared so blocking work can move to
+/// another thread
+pub struct DbState(pub Arc<DbPool>);
 
 /// Type alias for database state in commands
 pub type DbConn<'a> = State<'a, DbState>;
@@ -27,11 +27,15 @@ pub struct Project {
     pub banners: Option<serde_json::Value>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub api_keys: Option<serde_json::Value>,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Creatures embedded by older versions; only read when importing an
+    /// old project folder. They now live in their own table.
+    #[serde(default, skip_serializing)]
     pub creatures: Option<serde_json::Value>,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Legacy embedded world rules, see `creatures`
+    #[serde(default, skip_serializing)]
     pub world_rules: Option<serde_json::Value>,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Legacy embedded NPCs, see `creatures`
+    #[serde(default, skip_serializing)]
     pub npcs: Option<serde_json::Value>,
 }
 
@@ -55,6 +59,39 @@ pub struct Chapter {
     pub image: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub image_type: Option<String>,
+    /// Where the chapter text comes from; only changed through
+    /// `set_chapter_content_mode`, never by a regular update
+    #[serde(default)]
+    pub content_mode: ContentMode,
+}
+
+/// Source of a chapter's text
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum ContentMode {
+    /// The chapter is written as one document
+    #[default]
+    Document,
+    /// The chapter text is compiled from its scenes, in order
+    Scenes,
+}
+
+impl ContentMode {
+    /// Identifier stored in the database
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ContentMode::Document => "document",
+            ContentMode::Scenes => "scenes",
+        }
+    }
+
+    /// Unknown values read as `Document`, which never rewrites content
+    pub fn from_db(value: &str) -> Self {
+        match value {
+            "scenes" => ContentMode::Scenes,
+            _ => ContentMode::Document,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -77,6 +114,12 @@ pub struct Scene {
     pub image: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub image_type: Option<String>,
+    /// Prose of the scene (HTML)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    /// Words in `content`; computed on save
+    #[serde(default)]
+    pub word_count: i32,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -112,6 +155,9 @@ pub struct Character {
     pub visual_position: Option<serde_json::Value>,
     #[serde(default)]
     pub relationships: Vec<Relationship>,
+    /// Values of the project's custom fields, keyed by field id
+    #[serde(default, skip_serializing_if = "serde_json::Map::is_empty")]
+    pub custom_fields: serde_json::Map<String, serde_json::Value>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -155,6 +201,9 @@ pub struct Location {
     pub connections: Vec<serde_json::Value>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub visual_position: Option<serde_json::Value>,
+    /// Values of the project's custom fields, keyed by field id
+    #[serde(default, skip_serializing_if = "serde_json::Map::is_empty")]
+    pub custom_fields: serde_json::Map<String, serde_json::Value>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -174,6 +223,9 @@ pub struct LoreItem {
     pub summary: Option<String>,
     #[serde(default)]
     pub related_entity_ids: Vec<String>,
+    /// Values of the project's custom fields, keyed by field id
+    #[serde(default, skip_serializing_if = "serde_json::Map::is_empty")]
+    pub custom_fields: serde_json::Map<String, serde_json::Value>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -196,6 +248,7 @@ pub struct TimelineEvent {
     pub location_id: Option<String>,
     #[serde(default = "default_importance")]
     pub importance: String,
+    /// Names of the event's tags; unknown names are added to the project's tags
     #[serde(default)]
     pub tags: Vec<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -204,6 +257,312 @@ pub struct TimelineEvent {
     pub chapter_id: Option<String>,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CreatureAbility {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cooldown: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub damage: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub range: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Creature {
+    pub id: String,
+    #[serde(default)]
+    pub project_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub origin_package_id: Option<String>,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub r#type: String,
+    #[serde(default = "default_creature_size")]
+    pub size: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub physical_description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub behavior: Option<String>,
+    #[serde(default)]
+    pub habitat: Vec<String>,
+    #[serde(default = "default_importance")]
+    pub danger_level: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub challenge_rating: Option<String>,
+    #[serde(default)]
+    pub stats: serde_json::Map<String, serde_json::Value>,
+    #[serde(default)]
+    pub abilities: Vec<CreatureAbility>,
+    #[serde(default)]
+    pub weaknesses: Vec<String>,
+    #[serde(default)]
+    pub resistances: Vec<String>,
+    #[serde(default)]
+    pub immunities: Vec<String>,
+    #[serde(default)]
+    pub loot: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image_url: Option<String>,
+    #[serde(default)]
+    pub related_location_ids: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct WorldRuleExample {
+    pub id: String,
+    pub title: String,
+    #[serde(default)]
+    pub description: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct WorldRule {
+    pub id: String,
+    #[serde(default)]
+    pub project_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub origin_package_id: Option<String>,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default = "default_rule_category")]
+    pub category: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub custom_category: Option<String>,
+    #[serde(default)]
+    pub content: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+    #[serde(default = "default_rule_importance")]
+    pub importance: String,
+    #[serde(default)]
+    pub exceptions: Vec<String>,
+    #[serde(default)]
+    pub examples: Vec<WorldRuleExample>,
+    #[serde(default)]
+    pub related_rule_ids: Vec<String>,
+    #[serde(default)]
+    pub related_entity_ids: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image_url: Option<String>,
+    #[serde(default)]
+    pub is_secret: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct NpcQuest {
+    pub id: String,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default = "default_quest_status")]
+    pub status: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct NpcDialogue {
+    pub id: String,
+    #[serde(default)]
+    pub context: String,
+    #[serde(default)]
+    pub dialogue: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct NpcRelationship {
+    pub id: String,
+    pub target_npc_id: String,
+    #[serde(default)]
+    pub r#type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Npc {
+    pub id: String,
+    #[serde(default)]
+    pub project_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub origin_package_id: Option<String>,
+    #[serde(default)]
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub personality: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub faction: Option<String>,
+    #[serde(default = "default_disposition")]
+    pub disposition: String,
+    #[serde(default = "default_role")]
+    pub importance: String,
+    #[serde(default)]
+    pub stats: serde_json::Map<String, serde_json::Value>,
+    #[serde(default)]
+    pub inventory: Vec<String>,
+    #[serde(default)]
+    pub quests: Vec<NpcQuest>,
+    #[serde(default)]
+    pub dialogues: Vec<NpcDialogue>,
+    #[serde(default)]
+    pub secrets: Vec<String>,
+    #[serde(default)]
+    pub relationships: Vec<NpcRelationship>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<String>,
+    #[serde(default)]
+    pub related_location_ids: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub linked_character_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+}
+
+/// A user-defined field on one entity type of a project
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CustomField {
+    pub id: String,
+    pub project_id: String,
+    pub entity_type: EntityKind,
+    pub name: String,
+    pub field_type: CustomFieldType,
+    /// Allowed values of an `Enum` field
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub options: Vec<String>,
+    /// Entity type an `EntityReference` field points to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reference_type: Option<EntityKind>,
+    #[serde(default)]
+    pub position: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum CustomFieldType {
+    Text,
+    Number,
+    Enum,
+    /// Calendar date, `YYYY-MM-DD`
+    Date,
+    /// Id of another entity of the project
+    EntityReference,
+}
+
+impl CustomFieldType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CustomFieldType::Text => "text",
+            CustomFieldType::Number => "number",
+            CustomFieldType::Enum => "enum",
+            CustomFieldType::Date => "date",
+            CustomFieldType::EntityReference => "entity_reference",
+        }
+    }
+
+    pub fn from_db(value: &str) -> Option<Self> {
+        match value {
+            "text" => Some(CustomFieldType::Text),
+            "number" => Some(CustomFieldType::Number),
+            "enum" => Some(CustomFieldType::Enum),
+            "date" => Some(CustomFieldType::Date),
+            "entity_reference" => Some(CustomFieldType::EntityReference),
+            _ => None,
+        }
+    }
+}
+
+/// Entity types that can be addressed generically (search, trash, batches...)
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "camelCase")]
+pub enum EntityKind {
+    Project,
+    Chapter,
+    Scene,
+    Character,
+    Relationship,
+    Location,
+    LoreItem,
+    TimelineEvent,
+    Creature,
+    Npc,
+    WorldRule,
+}
+
+impl EntityKind {
+    /// Identifier stored in the database
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EntityKind::Project => "project",
+            EntityKind::Chapter => "chapter",
+            EntityKind::Scene => "scene",
+            EntityKind::Character => "character",
+            EntityKind::Relationship => "relationship",
+            EntityKind::Location => "location",
+            EntityKind::LoreItem => "lore_item",
+            EntityKind::TimelineEvent => "timeline_event",
+            EntityKind::Creature => "creature",
+            EntityKind::Npc => "npc",
+            EntityKind::WorldRule => "world_rule",
+        }
+    }
+
+    pub fn from_db(value: &str) -> Option<Self> {
+        match value {
+            "project" => Some(EntityKind::Project),
+            "chapter" => Some(EntityKind::Chapter),
+            "scene" => Some(EntityKind::Scene),
+            "character" => Some(EntityKind::Character),
+            "relationship" => Some(EntityKind::Relationship),
+            "location" => Some(EntityKind::Location),
+            "lore_item" => Some(EntityKind::LoreItem),
+            "timeline_event" => Some(EntityKind::TimelineEvent),
+            "creature" => Some(EntityKind::Creature),
+            "npc" => Some(EntityKind::Npc),
+            "world_rule" => Some(EntityKind::WorldRule),
+            _ => None,
+        }
+    }
+
+    /// Table holding this entity
+    pub fn table(&self) -> &'static str {
+        match self {
+            EntityKind::Project => "projects",
+            EntityKind::Chapter => "chapters",
+            EntityKind::Scene => "scenes",
+            EntityKind::Character => "characters",
+            EntityKind::Relationship => "relationships",
+            EntityKind::Location => "locations",
+            EntityKind::LoreItem => "lore_items",
+            EntityKind::TimelineEvent => "timeline_events",
+            EntityKind::Creature => "creatures",
+            EntityKind::Npc => "npcs",
+            EntityKind::WorldRule => "world_rules",
+        }
+    }
+}
+
 fn default_status() -> String {
     "draft".to_string()
 }
@@ -219,3 +578,23 @@ fn default_date_mode() -> String {
 fn default_importance() -> String {
     "medium".to_string()
 }
+
+fn default_creature_size() -> String {
+    "medium".to_string()
+}
+
+fn default_rule_category() -> String {
+    "custom".to_string()
+}
+
+fn default_rule_importance() -> String {
+    "minor".to_string()
+}
+
+fn default_quest_status() -> String {
+    "available".to_string()
+}
+
+fn default_disposition() -> String {
+    "neutral".to_string()
+}
@@ -1,7 +1,60 @@
 //! CRUD operations for all entities
 
+use super::assembly;
+use super::custom_fields::{self, CustomFieldError};
+use super::mentions;
 use super::models::*;
-use rusqlite::{params, Connection, Result};
+use super::ordering;
+use super::revisions::{self, RevisionSource};
+use super::tags;
+use super::text;
+use rusqlite::{params, Connection, OptionalExtension, Result};
+
+// ============================================================================
+// Helpers
+// ============================================================================
+
+/// Run `f` inside a savepoint, so it is atomic whether or not the caller
+/// already opened a transaction
+pub fn with_savepoint<T>(conn: &Connection, f: impl FnOnce() -> Result<T>) -> Result<T> {
+    in_savepoint(conn, f)
+}
+
+/// `with_savepoint` for work failing with a module's own error type
+pub fn in_savepoint<T, E>(conn: &Connection, f: impl FnOnce() -> Result<T, E>) -> Result<T, E>
+where
+    E: From<rusqlite::Error>,
+{
+    conn.execute_batch("SAVEPOINT plumai_op")?;
+    match f() {
+        Ok(value) => {
+            conn.execute_batch("RELEASE plumai_op")?;
+            Ok(value)
+        }
+        Err(e) => {
+            conn.execute_batch("ROLLBACK TO plumai_op; RELEASE plumai_op")?;
+            Err(e)
+        }
+    }
+}
+
+/// Tombstone value for `deleted_at`. Rows trashed together share the same
+/// stamp, which is how a restore finds what a parent took with it.
+pub fn deletion_stamp() -> String {
+    chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Micros, true)
+}
+
+/// Serialize a nested value for a JSON text column
+fn to_json<T: serde::Serialize>(value: &T) -> String {
+    serde_json::to_string(value).unwrap_or_default()
+}
+
+/// Parse a JSON text column, falling back to the default when absent or invalid
+fn from_json<T: serde::de::DeserializeOwned + Default>(value: Option<String>) -> T {
+    value
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default()
+}
 
 // ============================================================================
 // Projects
@@ -9,8 +62,8 @@ use rusqlite::{params, Connection, Result};
 
 pub fn create_project(conn: &Connection, project: &Project) -> Result<()> {
     conn.execute(
-        r#"INSERT INTO projects (id, title, author, description, genre, is_rpg_mode_enabled, rpg_system, active_identity_package, origin_package_id, project_type, banners, api_keys, creatures, world_rules, npcs)
-           VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)"#,
+        r#"INSERT INTO projects (id, title, author, description, genre, is_rpg_mode_enabled, rpg_system, active_identity_package, origin_package_id, project_type, banners, api_keys)
+           VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)"#,
         params![
             project.id,
             project.title,
@@ -24,97 +77,64 @@ pub fn create_project(conn: &Connection, project: &Project) -> Result<()> {
             project.project_type,
             project.banners.as_ref().map(|v| v.to_string()),
             project.api_keys.as_ref().map(|v| v.to_string()),
-            project.creatures.as_ref().map(|v| v.to_string()),
-            project.world_rules.as_ref().map(|v| v.to_string()),
-            project.npcs.as_ref().map(|v| v.to_string()),
         ],
     )?;
     Ok(())
 }
 
-pub fn get_project(conn: &Connection, id: &str) -> Result<Option<Project>> {
-    let mut stmt = conn.prepare(
-        "SELECT id, title, author, description, genre, is_rpg_mode_enabled, rpg_system, active_identity_package, origin_package_id, project_type, banners, api_keys, creatures, world_rules, npcs FROM projects WHERE id = ?1"
-    )?;
-
-    let mut rows = stmt.query(params![id])?;
+const PROJECT_COLUMNS: &str = "id, title, author, description, genre, is_rpg_mode_enabled, rpg_system, active_identity_package, origin_package_id, project_type, banners, api_keys";
 
-    if let Some(row) = rows.next()? {
-        Ok(Some(Project {
-            id: row.get(0)?,
-            title: row.get(1)?,
-            author: row.get(2)?,
-            description: row.get(3)?,
-            genre: row.get(4)?,
-            is_rpg_mode_enabled: row.get::<_, i32>(5)? != 0,
-            rpg_system: row.get(6)?,
-            active_identity_package: row.get(7)?,
-            origin_package_id: row.get(8)?,
-            project_type: row.get(9)?,
-            banners: row
-                .get::<_, Option<String>>(10)?
-                .and_then(|s| serde_json::from_str(&s).ok()),
-            api_keys: row
-                .get::<_, Option<String>>(11)?
-                .and_then(|s| serde_json::from_str(&s).ok()),
-            creatures: row
-                .get::<_, Option<String>>(12)?
-                .and_then(|s| serde_json::from_str(&s).ok()),
-            world_rules: row
-                .get::<_, Option<String>>(13)?
-                .and_then(|s| serde_json::from_str(&s).ok()),
-            npcs: row
-                .get::<_, Option<String>>(14)?
-                .and_then(|s| serde_json::from_str(&s).ok()),
-        }))
-    } else {
-        Ok(None)
-    }
+pub fn get_project(conn: &Connection, id: &str) -> Result<Option<Project>> {
+    conn.query_row(
+        &format!(
+            "SELECT {} FROM projects WHERE id = ?1 AND deleted_at IS NULL",
+            PROJECT_COLUMNS
+        ),
+        params![id],
+        row_to_project,
+    )
+    .optional()
 }
 
 pub fn get_all_projects(conn: &Connection) -> Result<Vec<Project>> {
-    let mut stmt = conn.prepare(
-        "SELECT id, title, author, description, genre, is_rpg_mode_enabled, rpg_system, active_identity_package, origin_package_id, project_type, banners, api_keys, creatures, world_rules, npcs FROM projects ORDER BY updated_at DESC"
-    )?;
-
-    let rows = stmt.query_map([], |row| {
-        Ok(Project {
-            id: row.get(0)?,
-            title: row.get(1)?,
-            author: row.get(2)?,
-            description: row.get(3)?,
-            genre: row.get(4)?,
-            is_rpg_mode_enabled: row.get::<_, i32>(5)? != 0,
-            rpg_system: row.get(6)?,
-            active_identity_package: row.get(7)?,
-            origin_package_id: row.get(8)?,
-            project_type: row.get(9)?,
-            banners: row
-                .get::<_, Option<String>>(10)?
-                .and_then(|s| serde_json::from_str(&s).ok()),
-            api_keys: row
-                .get::<_, Option<String>>(11)?
-                .and_then(|s| serde_json::from_str(&s).ok()),
-            creatures: row
-                .get::<_, Option<String>>(12)?
-                .and_then(|s| serde_json::from_str(&s).ok()),
-            world_rules: row
-                .get::<_, Option<String>>(13)?
-                .and_then(|s| serde_json::from_str(&s).ok()),
-            npcs: row
-                .get::<_, Option<String>>(14)?
-                .and_then(|s| serde_json::from_str(&s).ok()),
-        })
-    })?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM projects WHERE deleted_at IS NULL ORDER BY updated_at DESC",
+        PROJECT_COLUMNS
+    ))?;
 
+    let rows = stmt.query_map([], row_to_project)?;
     rows.collect()
 }
 
+fn row_to_project(row: &rusqlite::Row) -> Result<Project> {
+    Ok(Project {
+        id: row.get(0)?,
+        title: row.get(1)?,
+        author: row.get(2)?,
+        description: row.get(3)?,
+        genre: row.get(4)?,
+        is_rpg_mode_enabled: row.get::<_, i32>(5)? != 0,
+        rpg_system: row.get(6)?,
+        active_identity_package: row.get(7)?,
+        origin_package_id: row.get(8)?,
+        project_type: row.get(9)?,
+        banners: row
+            .get::<_, Option<String>>(10)?
+            .and_then(|s| serde_json::from_str(&s).ok()),
+        api_keys: row
+            .get::<_, Option<String>>(11)?
+            .and_then(|s| serde_json::from_str(&s).ok()),
+        creatures: None,
+        world_rules: None,
+        npcs: None,
+    })
+}
+
 pub fn update_project(conn: &Connection, project: &Project) -> Result<()> {
     conn.execute(
         r#"UPDATE projects SET title = ?2, author = ?3, description = ?4, genre = ?5,
            is_rpg_mode_enabled = ?6, rpg_system = ?7, active_identity_package = ?8, origin_package_id = ?9,
-           project_type = ?10, banners = ?11, api_keys = ?12, creatures = ?13, world_rules = ?14, npcs = ?15, updated_at = CURRENT_TIMESTAMP
+           project_type = ?10, banners = ?11, api_keys = ?12, updated_at = CURRENT_TIMESTAMP
            WHERE id = ?1"#,
         params![
             project.id,
@@ -129,17 +149,49 @@ pub fn update_project(conn: &Connection, project: &Project) -> Result<()> {
             project.project_type,
             project.banners.as_ref().map(|v| v.to_string()),
             project.api_keys.as_ref().map(|v| v.to_string()),
-            project.creatures.as_ref().map(|v| v.to_string()),
-            project.world_rules.as_ref().map(|v| v.to_string()),
-            project.npcs.as_ref().map(|v| v.to_string()),
         ],
     )?;
     Ok(())
 }
 
+/// Move a project and everything in it to the trash
 pub fn delete_project(conn: &Connection, id: &str) -> Result<()> {
-    conn.execute("DELETE FROM projects WHERE id = ?1", params![id])?;
-    Ok(())
+    let stamp = deletion_stamp();
+    with_savepoint(conn, || {
+        conn.execute(
+            "UPDATE projects SET deleted_at = ?2 WHERE id = ?1 AND deleted_at IS NULL",
+            params![id, stamp],
+        )?;
+        conn.execute(
+            r#"UPDATE scenes SET deleted_at = ?2
+               WHERE chapter_id IN (SELECT id FROM chapters WHERE project_id = ?1) AND deleted_at IS NULL"#,
+            params![id, stamp],
+        )?;
+        conn.execute(
+            r#"UPDATE relationships SET deleted_at = ?2
+               WHERE character_id IN (SELECT id FROM characters WHERE project_id = ?1) AND deleted_at IS NULL"#,
+            params![id, stamp],
+        )?;
+        for table in [
+            "chapters",
+            "characters",
+            "locations",
+            "lore_items",
+            "timeline_events",
+            "creatures",
+            "npcs",
+            "world_rules",
+        ] {
+            conn.execute(
+                &format!(
+                    "UPDATE {} SET deleted_at = ?2 WHERE project_id = ?1 AND deleted_at IS NULL",
+                    table
+                ),
+                params![id, stamp],
+            )?;
+        }
+        Ok(())
+    })
 }
 
 // ============================================================================
@@ -147,9 +199,17 @@ pub fn delete_project(conn: &Connection, id: &str) -> Result<()> {
 // ============================================================================
 
 pub fn create_chapter(conn: &Connection, chapter: &Chapter) -> Result<()> {
+    with_savepoint(conn, || {
+        insert_chapter(conn, chapter)?;
+        revisions::capture_revision(conn, &chapter.id, RevisionSource::Auto, None)?;
+        mentions::index_source(conn, EntityKind::Chapter, &chapter.id)
+    })
+}
+
+fn insert_chapter(conn: &Connection, chapter: &Chapter) -> Result<()> {
     conn.execute(
-        r#"INSERT INTO chapters (id, project_id, title, content, status, word_count, summary, number, image, image_type)
-           VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)"#,
+        r#"INSERT INTO chapters (id, project_id, title, content, status, word_count, summary, number, image, image_type, content_mode)
+           VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)"#,
         params![
             chapter.id,
             chapter.project_id,
@@ -161,6 +221,7 @@ pub fn create_chapter(conn: &Connection, chapter: &Chapter) -> Result<()> {
             chapter.number,
             chapter.image,
             chapter.image_type,
+            chapter.content_mode.as_str(),
         ],
     )?;
     Ok(())
@@ -168,32 +229,55 @@ pub fn create_chapter(conn: &Connection, chapter: &Chapter) -> Result<()> {
 
 pub fn get_chapters_by_project(conn: &Connection, project_id: &str) -> Result<Vec<Chapter>> {
     let mut stmt = conn.prepare(
-        "SELECT id, project_id, title, content, status, word_count, summary, number, image, image_type
-         FROM chapters WHERE project_id = ?1 ORDER BY number, created_at"
+        "SELECT id, project_id, title, content, status, word_count, summary, number, image, image_type, content_mode
+         FROM chapters WHERE project_id = ?1 AND deleted_at IS NULL ORDER BY number, created_at"
     )?;
 
-    let rows = stmt.query_map(params![project_id], |row| {
-        Ok(Chapter {
-            id: row.get(0)?,
-            project_id: row.get(1)?,
-            title: row.get(2)?,
-            content: row.get(3)?,
-            status: row.get(4)?,
-            word_count: row.get(5)?,
-            summary: row.get(6)?,
-            number: row.get(7)?,
-            image: row.get(8)?,
-            image_type: row.get(9)?,
-        })
-    })?;
-
+    let rows = stmt.query_map(params![project_id], row_to_chapter)?;
     rows.collect()
 }
 
+pub fn get_chapter(conn: &Connection, id: &str) -> Result<Option<Chapter>> {
+    conn.query_row(
+        "SELECT id, project_id, title, content, status, word_count, summary, number, image, image_type, content_mode
+         FROM chapters WHERE id = ?1 AND deleted_at IS NULL",
+        params![id],
+        row_to_chapter,
+    )
+    .optional()
+}
+
+fn row_to_chapter(row: &rusqlite::Row) -> Result<Chapter> {
+    Ok(Chapter {
+        id: row.get(0)?,
+        project_id: row.get(1)?,
+        title: row.get(2)?,
+        content: row.get(3)?,
+        status: row.get(4)?,
+        word_count: row.get(5)?,
+        summary: row.get(6)?,
+        number: row.get(7)?,
+        image: row.get(8)?,
+        image_type: row.get(9)?,
+        content_mode: ContentMode::from_db(&row.get::<_, String>(10)?),
+    })
+}
+
+/// Save a chapter, recording a revision when the content changed enough.
+/// The text of a chapter compiled from scenes is left alone.
 pub fn update_chapter(conn: &Connection, chapter: &Chapter) -> Result<()> {
+    with_savepoint(conn, || {
+        write_chapter(conn, chapter)?;
+        revisions::capture_revision(conn, &chapter.id, RevisionSource::Auto, None)?;
+        mentions::index_source(conn, EntityKind::Chapter, &chapter.id)
+    })
+}
+
+fn write_chapter(conn: &Connection, chapter: &Chapter) -> Result<()> {
     conn.execute(
-        r#"UPDATE chapters SET title = ?2, content = ?3, status = ?4, word_count = ?5,
-           summary = ?6, number = ?7, image = ?8, image_type = ?9, updated_at = CURRENT_TIMESTAMP
+        r#"UPDATE chapters SET title = ?2,
+           content = CASE WHEN content_mode = 'scenes' THEN content ELSE ?3 END, status = ?4,
+           word_count = CASE WHEN content_mode = 'scenes' THEN word_count ELSE ?5 END, summary = ?6, number = ?7, image = ?8, image_type = ?9, updated_at = CURRENT_TIMESTAMP
            WHERE id = ?1"#,
         params![
             chapter.id,
@@ -210,9 +294,20 @@ pub fn update_chapter(conn: &Connection, chapter: &Chapter) -> Result<()> {
     Ok(())
 }
 
+/// Move a chapter and its scenes to the trash
 pub fn delete_chapter(conn: &Connection, id: &str) -> Result<()> {
-    conn.execute("DELETE FROM chapters WHERE id = ?1", params![id])?;
-    Ok(())
+    let stamp = deletion_stamp();
+    with_savepoint(conn, || {
+        conn.execute(
+            "UPDATE chapters SET deleted_at = ?2 WHERE id = ?1 AND deleted_at IS NULL",
+            params![id, stamp],
+        )?;
+        conn.execute(
+            "UPDATE scenes SET deleted_at = ?2 WHERE chapter_id = ?1 AND deleted_at IS NULL",
+            params![id, stamp],
+        )?;
+        Ok(())
+    })
 }
 
 // ============================================================================
@@ -220,29 +315,42 @@ pub fn delete_chapter(conn: &Connection, id: &str) -> Result<()> {
 // ============================================================================
 
 pub fn create_scene(conn: &Connection, scene: &Scene) -> Result<()> {
-    conn.execute(
-        r#"INSERT INTO scenes (id, chapter_id, title, character_ids, location_id, timeline_position, description, notes, image, image_type)
-           VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)"#,
-        params![
-            scene.id,
-            scene.chapter_id,
-            scene.title,
-            serde_json::to_string(&scene.character_ids).unwrap_or_default(),
-            scene.location_id,
-            scene.timeline_position,
-            scene.description,
-            scene.notes,
-            scene.image,
-            scene.image_type,
-        ],
-    )?;
-    Ok(())
+    with_savepoint(conn, || {
+        conn.execute(
+            r#"INSERT INTO scenes (id, chapter_id, title, character_ids, location_id, timeline_position, description, notes, image, image_type, content, word_count)
+               VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)"#,
+            params![
+                scene.id,
+                scene.chapter_id,
+                scene.title,
+                serde_json::to_string(&scene.character_ids).unwrap_or_default(),
+                scene.location_id,
+                scene.timeline_position,
+                scene.description,
+                scene.notes,
+                scene.image,
+                scene.image_type,
+                scene.content,
+                scene_word_count(scene),
+            ],
+        )?;
+        mentions::index_source(conn, EntityKind::Scene, &scene.id)?;
+        assembly::recompile(conn, &scene.chapter_id)
+    })
+}
+
+/// Word count of a scene's prose; whatever the client sent is ignored
+fn scene_word_count(scene: &Scene) -> u32 {
+    scene
+        .content
+        .as_deref()
+        .map_or(0, |content| text::count_words(&text::strip_html(content)))
 }
 
 pub fn get_scenes_by_chapter(conn: &Connection, chapter_id: &str) -> Result<Vec<Scene>> {
     let mut stmt = conn.prepare(
-        "SELECT id, chapter_id, title, character_ids, location_id, timeline_position, description, notes, image, image_type
-         FROM scenes WHERE chapter_id = ?1 ORDER BY timeline_position"
+        "SELECT id, chapter_id, title, character_ids, location_id, timeline_position, description, notes, image, image_type, content, word_count
+         FROM scenes WHERE chapter_id = ?1 AND deleted_at IS NULL ORDER BY timeline_position, created_at"
     )?;
 
     let rows = stmt.query_map(params![chapter_id], |row| {
@@ -258,70 +366,128 @@ pub fn get_scenes_by_chapter(conn: &Connection, chapter_id: &str) -> Result<Vec<
             notes: row.get(7)?,
             image: row.get(8)?,
             image_type: row.get(9)?,
+            content: row.get(10)?,
+            word_count: row.get::<_, Option<i32>>(11)?.unwrap_or_default(),
         })
     })?;
 
     rows.collect()
 }
 
-pub fn update_scene(conn: &Connection, scene: &Scene) -> Result<()> {
-    conn.execute(
-        r#"UPDATE scenes SET title = ?2, character_ids = ?3, location_id = ?4, timeline_position = ?5,
-           description = ?6, notes = ?7, image = ?8, image_type = ?9, updated_at = CURRENT_TIMESTAMP
-           WHERE id = ?1"#,
-        params![
-            scene.id,
-            scene.title,
-            serde_json::to_string(&scene.character_ids).unwrap_or_default(),
-            scene.location_id,
-            scene.timeline_position,
-            scene.description,
-            scene.notes,
-            scene.image,
-            scene.image_type,
-        ],
-    )?;
+/// Save a scene. Changing `chapter_id` moves it within its project; linked
+/// timeline events follow, but positions are left as given (see
+/// `move_scene` for that). Chapters compiled from scenes are recompiled.
+pub fn update_scene(conn: &Connection, scene: &Scene) -> Result<(), ordering::OrderingError> {
+    let previous_chapter_id: Option<String> = conn
+        .query_row(
+            "SELECT chapter_id FROM scenes WHERE id = ?1",
+            params![scene.id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    if let Some(previous) = previous_chapter_id.as_deref().filter(|p| *p != scene.chapter_id) {
+        let target = get_chapter(conn, &scene.chapter_id)?.ok_or_else(|| {
+            ordering::OrderingError::NotFound {
+                kind: "Chapter",
+                id: scene.chapter_id.clone(),
+            }
+        })?;
+        let source_project: String = conn.query_row(
+            "SELECT project_id FROM chapters WHERE id = ?1",
+            params![previous],
+            |row| row.get(0),
+        )?;
+        if source_project != target.project_id {
+            return Err(ordering::OrderingError::CrossProject);
+        }
+    }
+
+    with_savepoint(conn, || {
+        conn.execute(
+            r#"UPDATE scenes SET chapter_id = ?2, title = ?3, character_ids = ?4, location_id = ?5,
+               timeline_position = ?6, description = ?7, notes = ?8, image = ?9, image_type = ?10,
+               content = ?11, word_count = ?12, updated_at = CURRENT_TIMESTAMP
+               WHERE id = ?1"#,
+            params![
+                scene.id,
+                scene.chapter_id,
+                scene.title,
+                serde_json::to_string(&scene.character_ids).unwrap_or_default(),
+                scene.location_id,
+                scene.timeline_position,
+                scene.description,
+                scene.notes,
+                scene.image,
+                scene.image_type,
+                scene.content,
+                scene_word_count(scene),
+            ],
+        )?;
+        ordering::relink_timeline_events(conn, &scene.id)?;
+        mentions::index_source(conn, EntityKind::Scene, &scene.id)?;
+        assembly::recompile(conn, &scene.chapter_id)?;
+        match previous_chapter_id {
+            Some(previous) if previous != scene.chapter_id => assembly::recompile(conn, &previous),
+            _ => Ok(()),
+        }
+    })?;
     Ok(())
 }
 
 pub fn delete_scene(conn: &Connection, id: &str) -> Result<()> {
-    conn.execute("DELETE FROM scenes WHERE id = ?1", params![id])?;
-    Ok(())
+    with_savepoint(conn, || {
+        conn.execute(
+            "UPDATE scenes SET deleted_at = ?2 WHERE id = ?1 AND deleted_at IS NULL",
+            params![id, deletion_stamp()],
+        )?;
+        assembly::recompile_scene_chapter(conn, id)
+    })
 }
 
 // ============================================================================
 // Characters
 // ============================================================================
 
-pub fn create_character(conn: &Connection, character: &Character) -> Result<()> {
-    conn.execute(
-        r#"INSERT INTO characters (id, project_id, origin_package_id, name, role, avatar_url, physical_description, personality, history, notes, attributes, attribute_history, vital_status_history, current_vital_status, visual_position)
-           VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)"#,
-        params![
-            character.id,
-            character.project_id,
-            character.origin_package_id,
-            character.name,
-            character.role,
-            character.avatar_url,
-            character.physical_description,
-            character.personality,
-            character.history,
-            character.notes,
-            character.attributes.as_ref().map(|v| v.to_string()),
-            character.attribute_history.as_ref().map(|v| v.to_string()),
-            serde_json::to_string(&character.vital_status_history).unwrap_or_default(),
-            character.current_vital_status,
-            character.visual_position.as_ref().map(|v| v.to_string()),
-        ],
+pub fn create_character(conn: &Connection, character: &Character) -> Result<(), CustomFieldError> {
+    let custom_fields = custom_fields::checked_values(
+        conn,
+        EntityKind::Character,
+        &character.id,
+        &character.project_id,
+        &character.custom_fields,
     )?;
+    with_savepoint(conn, || {
+        conn.execute(
+            r#"INSERT INTO characters (id, project_id, origin_package_id, name, role, avatar_url, physical_description, personality, history, notes, attributes, attribute_history, vital_status_history, current_vital_status, visual_position, custom_fields)
+               VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)"#,
+            params![
+                character.id,
+                character.project_id,
+                character.origin_package_id,
+                character.name,
+                character.role,
+                character.avatar_url,
+                character.physical_description,
+                character.personality,
+                character.history,
+                character.notes,
+                character.attributes.as_ref().map(|v| v.to_string()),
+                character.attribute_history.as_ref().map(|v| v.to_string()),
+                serde_json::to_string(&character.vital_status_history).unwrap_or_default(),
+                character.current_vital_status,
+                character.visual_position.as_ref().map(|v| v.to_string()),
+                custom_fields,
+            ],
+        )?;
+        mentions::index_entity(conn, EntityKind::Character, &character.id)
+    })?;
     Ok(())
 }
 
 pub fn get_characters_by_project(conn: &Connection, project_id: &str) -> Result<Vec<Character>> {
     let mut stmt = conn.prepare(
-        "SELECT id, project_id, origin_package_id, name, role, avatar_url, physical_description, personality, history, notes, attributes, attribute_history, vital_status_history, current_vital_status, visual_position
-         FROM characters WHERE project_id = ?1 ORDER BY name"
+        "SELECT id, project_id, origin_package_id, name, role, avatar_url, physical_description, personality, history, notes, attributes, attribute_history, vital_status_history, current_vital_status, visual_position, custom_fields
+         FROM characters WHERE project_id = ?1 AND deleted_at IS NULL ORDER BY name"
     )?;
 
     let rows = stmt.query_map(params![project_id], |row| {
@@ -351,41 +517,70 @@ pub fn get_characters_by_project(conn: &Connection, project_id: &str) -> Result<
                 .get::<_, Option<String>>(14)?
                 .and_then(|s| serde_json::from_str(&s).ok()),
             relationships: vec![], // Loaded separately
+            custom_fields: custom_fields::from_column(row.get(15)?),
         })
     })?;
 
     rows.collect()
 }
 
-pub fn update_character(conn: &Connection, character: &Character) -> Result<()> {
-    conn.execute(
-        r#"UPDATE characters SET name = ?2, role = ?3, avatar_url = ?4, physical_description = ?5,
-           personality = ?6, history = ?7, notes = ?8, attributes = ?9, attribute_history = ?10,
-           vital_status_history = ?11, current_vital_status = ?12, visual_position = ?13, origin_package_id = ?14, updated_at = CURRENT_TIMESTAMP
-           WHERE id = ?1"#,
-        params![
-            character.id,
-            character.name,
-            character.role,
-            character.avatar_url,
-            character.physical_description,
-            character.personality,
-            character.history,
-            character.notes,
-            character.attributes.as_ref().map(|v| v.to_string()),
-            character.attribute_history.as_ref().map(|v| v.to_string()),
-            serde_json::to_string(&character.vital_status_history).unwrap_or_default(),
-            character.current_vital_status,
-            character.visual_position.as_ref().map(|v| v.to_string()),
-            character.origin_package_id,
-        ],
+pub fn update_character(conn: &Connection, character: &Character) -> Result<(), CustomFieldError> {
+    let renamed =
+        mentions::is_renamed(conn, EntityKind::Character, &character.id, &character.name)?;
+    let custom_fields = custom_fields::checked_values(
+        conn,
+        EntityKind::Character,
+        &character.id,
+        &character.project_id,
+        &character.custom_fields,
     )?;
+    with_savepoint(conn, || {
+        conn.execute(
+            r#"UPDATE characters SET name = ?2, role = ?3, avatar_url = ?4, physical_description = ?5,
+               personality = ?6, history = ?7, notes = ?8, attributes = ?9, attribute_history = ?10,
+               vital_status_history = ?11, current_vital_status = ?12, visual_position = ?13, origin_package_id = ?14, custom_fields = ?15, updated_at = CURRENT_TIMESTAMP
+               WHERE id = ?1"#,
+            params![
+                character.id,
+                character.name,
+                character.role,
+                character.avatar_url,
+                character.physical_description,
+                character.personality,
+                character.history,
+                character.notes,
+                character.attributes.as_ref().map(|v| v.to_string()),
+                character.attribute_history.as_ref().map(|v| v.to_string()),
+                serde_json::to_string(&character.vital_status_history).unwrap_or_default(),
+                character.current_vital_status,
+                character.visual_position.as_ref().map(|v| v.to_string()),
+                character.origin_package_id,
+                custom_fields,
+            ],
+        )?;
+        if renamed {
+            mentions::index_entity(conn, EntityKind::Character, &character.id)?;
+        }
+        Ok(())
+    })?;
     Ok(())
 }
 
+/// Move a character and its relationships (both directions) to the trash
 pub fn delete_character(conn: &Connection, id: &str) -> Result<()> {
-    conn.execute("DELETE FROM characters WHERE id = ?1", params![id])?;
-    Ok(())
+    let stamp = deletion_stamp();
+    with_savepoint(conn, || {
+        conn.execute(
+            "UPDATE characters SET deleted_at = ?2 WHERE id = ?1 AND deleted_at IS NULL",
+            params![id, stamp],
+        )?;
+        conn.execute(
+            r#"UPDATE relationships SET deleted_at = ?2
+               WHERE (character_id = ?1 OR target_character_id = ?1) AND deleted_at IS NULL"#,
+            params![id, stamp],
+        )?;
+        Ok(())
+    })
 }
 
 // ============================================================================
@@ -416,7 +611,9 @@ pub fn get_relationships_by_character(
 ) -> Result<Vec<Relationship>> {
     let mut stmt = conn.prepare(
         "SELECT id, target_character_id, current_type, current_status, current_description, is_secret, history
-         FROM relationships WHERE character_id = ?1"
+         FROM relationships
+         WHERE character_id = ?1 AND deleted_at IS NULL
+           AND target_character_id NOT IN (SELECT id FROM characters WHERE deleted_at IS NOT NULL)"
     )?;
 
     let rows = stmt.query_map(params![character_id], |row| {
@@ -456,7 +653,10 @@ pub fn update_relationship(conn: &Connection, char_id: &str, rel: &Relationship)
 }
 
 pub fn delete_relationship(conn: &Connection, id: &str) -> Result<()> {
-    conn.execute("DELETE FROM relationships WHERE id = ?1", params![id])?;
+    conn.execute(
+        "UPDATE relationships SET deleted_at = ?2 WHERE id = ?1 AND deleted_at IS NULL",
+        params![id, deletion_stamp()],
+    )?;
     Ok(())
 }
 
@@ -464,32 +664,43 @@ pub fn delete_relationship(conn: &Connection, id: &str) -> Result<()> {
 // Locations
 // ============================================================================
 
-pub fn create_location(conn: &Connection, location: &Location) -> Result<()> {
-    conn.execute(
-        r#"INSERT INTO locations (id, project_id, name, image_url, type, description, significance, notes, gallery, plans, connections, visual_position)
-           VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)"#,
-        params![
-            location.id,
-            location.project_id,
-            location.name,
-            location.image_url,
-            location.r#type,
-            location.description,
-            location.significance,
-            location.notes,
-            serde_json::to_string(&location.gallery).unwrap_or_default(),
-            serde_json::to_string(&location.plans).unwrap_or_default(),
-            serde_json::to_string(&location.connections).unwrap_or_default(),
-            location.visual_position.as_ref().map(|v| v.to_string()),
-        ],
+pub fn create_location(conn: &Connection, location: &Location) -> Result<(), CustomFieldError> {
+    let custom_fields = custom_fields::checked_values(
+        conn,
+        EntityKind::Location,
+        &location.id,
+        &location.project_id,
+        &location.custom_fields,
     )?;
+    with_savepoint(conn, || {
+        conn.execute(
+            r#"INSERT INTO locations (id, project_id, name, image_url, type, description, significance, notes, gallery, plans, connections, visual_position, custom_fields)
+               VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)"#,
+            params![
+                location.id,
+                location.project_id,
+                location.name,
+                location.image_url,
+                location.r#type,
+                location.description,
+                location.significance,
+                location.notes,
+                serde_json::to_string(&location.gallery).unwrap_or_default(),
+                serde_json::to_string(&location.plans).unwrap_or_default(),
+                serde_json::to_string(&location.connections).unwrap_or_default(),
+                location.visual_position.as_ref().map(|v| v.to_string()),
+                custom_fields,
+            ],
+        )?;
+        mentions::index_entity(conn, EntityKind::Location, &location.id)
+    })?;
     Ok(())
 }
 
 pub fn get_locations_by_project(conn: &Connection, project_id: &str) -> Result<Vec<Location>> {
     let mut stmt = conn.prepare(
-        "SELECT id, project_id, name, image_url, type, description, significance, notes, gallery, plans, connections, visual_position
-         FROM locations WHERE project_id = ?1 ORDER BY name"
+        "SELECT id, project_id, name, image_url, type, description, significance, notes, gallery, plans, connections, visual_position, custom_fields
+         FROM locations WHERE project_id = ?1 AND deleted_at IS NULL ORDER BY name"
     )?;
 
     let rows = stmt.query_map(params![project_id], |row| {
@@ -517,36 +728,55 @@ pub fn get_locations_by_project(conn: &Connection, project_id: &str) -> Result<V
             visual_position: row
                 .get::<_, Option<String>>(11)?
                 .and_then(|s| serde_json::from_str(&s).ok()),
+            custom_fields: custom_fields::from_column(row.get(12)?),
         })
     })?;
 
     rows.collect()
 }
 
-pub fn update_location(conn: &Connection, location: &Location) -> Result<()> {
-    conn.execute(
-        r#"UPDATE locations SET name = ?2, image_url = ?3, type = ?4, description = ?5,
-           significance = ?6, notes = ?7, gallery = ?8, plans = ?9, connections = ?10, visual_position = ?11, updated_at = CURRENT_TIMESTAMP
-           WHERE id = ?1"#,
-        params![
-            location.id,
-            location.name,
-            location.image_url,
-            location.r#type,
-            location.description,
-            location.significance,
-            location.notes,
-            serde_json::to_string(&location.gallery).unwrap_or_default(),
-            serde_json::to_string(&location.plans).unwrap_or_default(),
-            serde_json::to_string(&location.connections).unwrap_or_default(),
-            location.visual_position.as_ref().map(|v| v.to_string()),
-        ],
+pub fn update_location(conn: &Connection, location: &Location) -> Result<(), CustomFieldError> {
+    let renamed = mentions::is_renamed(conn, EntityKind::Location, &location.id, &location.name)?;
+    let custom_fields = custom_fields::checked_values(
+        conn,
+        EntityKind::Location,
+        &location.id,
+        &location.project_id,
+        &location.custom_fields,
     )?;
+    with_savepoint(conn, || {
+        conn.execute(
+            r#"UPDATE locations SET name = ?2, image_url = ?3, type = ?4, description = ?5,
+               significance = ?6, notes = ?7, gallery = ?8, plans = ?9, connections = ?10, visual_position = ?11, custom_fields = ?12, updated_at = CURRENT_TIMESTAMP
+               WHERE id = ?1"#,
+            params![
+                location.id,
+                location.name,
+                location.image_url,
+                location.r#type,
+                location.description,
+                location.significance,
+                location.notes,
+                serde_json::to_string(&location.gallery).unwrap_or_default(),
+                serde_json::to_string(&location.plans).unwrap_or_default(),
+                serde_json::to_string(&location.connections).unwrap_or_default(),
+                location.visual_position.as_ref().map(|v| v.to_string()),
+                custom_fields,
+            ],
+        )?;
+        if renamed {
+            mentions::index_entity(conn, EntityKind::Location, &location.id)?;
+        }
+        Ok(())
+    })?;
     Ok(())
 }
 
 pub fn delete_location(conn: &Connection, id: &str) -> Result<()> {
-    conn.execute("DELETE FROM locations WHERE id = ?1", params![id])?;
+    conn.execute(
+        "UPDATE locations SET deleted_at = ?2 WHERE id = ?1 AND deleted_at IS NULL",
+        params![id, deletion_stamp()],
+    )?;
     Ok(())
 }
 
@@ -554,28 +784,40 @@ pub fn delete_location(conn: &Connection, id: &str) -> Result<()> {
 // Lore Items
 // ============================================================================
 
-pub fn create_lore_item(conn: &Connection, item: &LoreItem) -> Result<()> {
-    conn.execute(
-        r#"INSERT INTO lore_items (id, project_id, origin_package_id, title, category, content, summary, related_entity_ids)
-           VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)"#,
-        params![
-            item.id,
-            item.project_id,
-            item.origin_package_id,
-            item.title,
-            item.category,
-            item.content,
-            item.summary,
-            serde_json::to_string(&item.related_entity_ids).unwrap_or_default(),
-        ],
+pub fn create_lore_item(conn: &Connection, item: &LoreItem) -> Result<(), CustomFieldError> {
+    let custom_fields = custom_fields::checked_values(
+        conn,
+        EntityKind::LoreItem,
+        &item.id,
+        &item.project_id,
+        &item.custom_fields,
     )?;
+    with_savepoint(conn, || {
+        conn.execute(
+            r#"INSERT INTO lore_items (id, project_id, origin_package_id, title, category, content, summary, related_entity_ids, custom_fields)
+               VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)"#,
+            params![
+                item.id,
+                item.project_id,
+                item.origin_package_id,
+                item.title,
+                item.category,
+                item.content,
+                item.summary,
+                serde_json::to_string(&item.related_entity_ids).unwrap_or_default(),
+                custom_fields,
+            ],
+        )?;
+        mentions::index_source(conn, EntityKind::LoreItem, &item.id)?;
+        mentions::index_entity(conn, EntityKind::LoreItem, &item.id)
+    })?;
     Ok(())
 }
 
 pub fn get_lore_items_by_project(conn: &Connection, project_id: &str) -> Result<Vec<LoreItem>> {
     let mut stmt = conn.prepare(
-        "SELECT id, project_id, origin_package_id, title, category, content, summary, related_entity_ids
-         FROM lore_items WHERE project_id = ?1 ORDER BY category, title"
+        "SELECT id, project_id, origin_package_id, title, category, content, summary, related_entity_ids, custom_fields
+         FROM lore_items WHERE project_id = ?1 AND deleted_at IS NULL ORDER BY category, title"
     )?;
 
     let rows = stmt.query_map(params![project_id], |row| {
@@ -591,32 +833,52 @@ pub fn get_lore_items_by_project(conn: &Connection, project_id: &str) -> Result<
                 .get::<_, Option<String>>(7)?
                 .and_then(|s| serde_json::from_str(&s).ok())
                 .unwrap_or_default(),
+            custom_fields: custom_fields::from_column(row.get(8)?),
         })
     })?;
 
     rows.collect()
 }
 
-pub fn update_lore_item(conn: &Connection, item: &LoreItem) -> Result<()> {
-    conn.execute(
-        r#"UPDATE lore_items SET title = ?2, category = ?3, content = ?4, summary = ?5,
-           related_entity_ids = ?6, origin_package_id = ?7, updated_at = CURRENT_TIMESTAMP
-           WHERE id = ?1"#,
-        params![
-            item.id,
-            item.title,
-            item.category,
-            item.content,
-            item.summary,
-            serde_json::to_string(&item.related_entity_ids).unwrap_or_default(),
-            item.origin_package_id,
-        ],
+pub fn update_lore_item(conn: &Connection, item: &LoreItem) -> Result<(), CustomFieldError> {
+    let renamed = mentions::is_renamed(conn, EntityKind::LoreItem, &item.id, &item.title)?;
+    let custom_fields = custom_fields::checked_values(
+        conn,
+        EntityKind::LoreItem,
+        &item.id,
+        &item.project_id,
+        &item.custom_fields,
     )?;
+    with_savepoint(conn, || {
+        conn.execute(
+            r#"UPDATE lore_items SET title = ?2, category = ?3, content = ?4, summary = ?5,
+               related_entity_ids = ?6, origin_package_id = ?7, custom_fields = ?8, updated_at = CURRENT_TIMESTAMP
+               WHERE id = ?1"#,
+            params![
+                item.id,
+                item.title,
+                item.category,
+                item.content,
+                item.summary,
+                serde_json::to_string(&item.related_entity_ids).unwrap_or_default(),
+                item.origin_package_id,
+                custom_fields,
+            ],
+        )?;
+        mentions::index_source(conn, EntityKind::LoreItem, &item.id)?;
+        if renamed {
+            mentions::index_entity(conn, EntityKind::LoreItem, &item.id)?;
+        }
+        Ok(())
+    })?;
     Ok(())
 }
 
 pub fn delete_lore_item(conn: &Connection, id: &str) -> Result<()> {
-    conn.execute("DELETE FROM lore_items WHERE id = ?1", params![id])?;
+    conn.execute(
+        "UPDATE lore_items SET deleted_at = ?2 WHERE id = ?1 AND deleted_at IS NULL",
+        params![id, deletion_stamp()],
+    )?;
     Ok(())
 }
 
@@ -625,26 +887,33 @@ pub fn delete_lore_item(conn: &Connection, id: &str) -> Result<()> {
 // ============================================================================
 
 pub fn create_timeline_event(conn: &Connection, event: &TimelineEvent) -> Result<()> {
-    conn.execute(
-        r#"INSERT INTO timeline_events (id, project_id, title, description, date_mode, date, era, participants, location_id, importance, tags, scene_id, chapter_id)
-           VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)"#,
-        params![
-            event.id,
-            event.project_id,
-            event.title,
-            event.description,
-            event.date_mode,
-            event.date,
-            event.era,
-            serde_json::to_string(&event.participants).unwrap_or_default(),
-            event.location_id,
-            event.importance,
-            serde_json::to_string(&event.tags).unwrap_or_default(),
-            event.scene_id,
-            event.chapter_id,
-        ],
-    )?;
-    Ok(())
+    with_savepoint(conn, || {
+        conn.execute(
+            r#"INSERT INTO timeline_events (id, project_id, title, description, date_mode, date, era, participants, location_id, importance, scene_id, chapter_id)
+               VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)"#,
+            params![
+                event.id,
+                event.project_id,
+                event.title,
+                event.description,
+                event.date_mode,
+                event.date,
+                event.era,
+                serde_json::to_string(&event.participants).unwrap_or_default(),
+                event.location_id,
+                event.importance,
+                event.scene_id,
+                event.chapter_id,
+            ],
+        )?;
+        tags::set_tag_names(
+            conn,
+            &event.project_id,
+            EntityKind::TimelineEvent,
+            &event.id,
+            &event.tags,
+        )
+    })
 }
 
 pub fn get_timeline_events_by_project(
@@ -652,8 +921,8 @@ pub fn get_timeline_events_by_project(
     project_id: &str,
 ) -> Result<Vec<TimelineEvent>> {
     let mut stmt = conn.prepare(
-        "SELECT id, project_id, title, description, date_mode, date, era, participants, location_id, importance, tags, scene_id, chapter_id
-         FROM timeline_events WHERE project_id = ?1 ORDER BY date, created_at"
+        "SELECT id, project_id, title, description, date_mode, date, era, participants, location_id, importance, scene_id, chapter_id
+         FROM timeline_events WHERE project_id = ?1 AND deleted_at IS NULL ORDER BY date, created_at"
     )?;
 
     let rows = stmt.query_map(params![project_id], |row| {
@@ -671,44 +940,377 @@ pub fn get_timeline_events_by_project(
                 .unwrap_or_default(),
             location_id: row.get(8)?,
             importance: row.get(9)?,
-            tags: row
-                .get::<_, Option<String>>(10)?
-                .and_then(|s| serde_json::from_str(&s).ok())
-                .unwrap_or_default(),
-            scene_id: row.get(11)?,
-            chapter_id: row.get(12)?,
+            tags: vec![], // Loaded separately
+            scene_id: row.get(10)?,
+            chapter_id: row.get(11)?,
         })
     })?;
 
-    rows.collect()
+    let mut events = rows.collect::<Result<Vec<_>>>()?;
+    for event in &mut events {
+        event.tags = tags::tag_names(conn, EntityKind::TimelineEvent, &event.id)?;
+    }
+    Ok(events)
 }
 
 pub fn update_timeline_event(conn: &Connection, event: &TimelineEvent) -> Result<()> {
+    with_savepoint(conn, || {
+        conn.execute(
+            r#"UPDATE timeline_events SET title = ?2, description = ?3, date_mode = ?4, date = ?5,
+               era = ?6, participants = ?7, location_id = ?8, importance = ?9,
+               scene_id = ?10, chapter_id = ?11, updated_at = CURRENT_TIMESTAMP
+               WHERE id = ?1"#,
+            params![
+                event.id,
+                event.title,
+                event.description,
+                event.date_mode,
+                event.date,
+                event.era,
+                serde_json::to_string(&event.participants).unwrap_or_default(),
+                event.location_id,
+                event.importance,
+                event.scene_id,
+                event.chapter_id,
+            ],
+        )?;
+        let project_id: String = conn.query_row(
+            "SELECT project_id FROM timeline_events WHERE id = ?1",
+            params![event.id],
+            |row| row.get(0),
+        )?;
+        tags::set_tag_names(
+            conn,
+            &project_id,
+            EntityKind::TimelineEvent,
+            &event.id,
+            &event.tags,
+        )
+    })
+}
+
+pub fn delete_timeline_event(conn: &Connection, id: &str) -> Result<()> {
+    conn.execute(
+        "UPDATE timeline_events SET deleted_at = ?2 WHERE id = ?1 AND deleted_at IS NULL",
+        params![id, deletion_stamp()],
+    )?;
+    Ok(())
+}
+
+// ============================================================================
+// Creatures
+// ============================================================================
+
+pub fn create_creature(conn: &Connection, creature: &Creature) -> Result<()> {
     conn.execute(
-        r#"UPDATE timeline_events SET title = ?2, description = ?3, date_mode = ?4, date = ?5,
-           era = ?6, participants = ?7, location_id = ?8, importance = ?9, tags = ?10,
-           scene_id = ?11, chapter_id = ?12, updated_at = CURRENT_TIMESTAMP
+        r#"INSERT INTO creatures (id, project_id, origin_package_id, name, type, size, description, physical_description, behavior, habitat, danger_level, challenge_rating, stats, abilities, weaknesses, resistances, immunities, loot, image_url, related_location_ids, notes)
+           VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21)"#,
+        params![
+            creature.id,
+            creature.project_id,
+            creature.origin_package_id,
+            creature.name,
+            creature.r#type,
+            creature.size,
+            creature.description,
+            creature.physical_description,
+            creature.behavior,
+            to_json(&creature.habitat),
+            creature.danger_level,
+            creature.challenge_rating,
+            to_json(&creature.stats),
+            to_json(&creature.abilities),
+            to_json(&creature.weaknesses),
+            to_json(&creature.resistances),
+            to_json(&creature.immunities),
+            to_json(&creature.loot),
+            creature.image_url,
+            to_json(&creature.related_location_ids),
+            creature.notes,
+        ],
+    )?;
+    Ok(())
+}
+
+pub fn get_creatures_by_project(conn: &Connection, project_id: &str) -> Result<Vec<Creature>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, project_id, origin_package_id, name, type, size, description, physical_description, behavior, habitat, danger_level, challenge_rating, stats, abilities, weaknesses, resistances, immunities, loot, image_url, related_location_ids, notes
+         FROM creatures WHERE project_id = ?1 AND deleted_at IS NULL ORDER BY name"
+    )?;
+
+    let rows = stmt.query_map(params![project_id], |row| {
+        Ok(Creature {
+            id: row.get(0)?,
+            project_id: row.get(1)?,
+            origin_package_id: row.get(2)?,
+            name: row.get(3)?,
+            r#type: row.get(4)?,
+            size: row.get(5)?,
+            description: row.get(6)?,
+            physical_description: row.get(7)?,
+            behavior: row.get(8)?,
+            habitat: from_json(row.get(9)?),
+            danger_level: row.get(10)?,
+            challenge_rating: row.get(11)?,
+            stats: from_json(row.get(12)?),
+            abilities: from_json(row.get(13)?),
+            weaknesses: from_json(row.get(14)?),
+            resistances: from_json(row.get(15)?),
+            immunities: from_json(row.get(16)?),
+            loot: from_json(row.get(17)?),
+            image_url: row.get(18)?,
+            related_location_ids: from_json(row.get(19)?),
+            notes: row.get(20)?,
+        })
+    })?;
+
+    rows.collect()
+}
+
+pub fn update_creature(conn: &Connection, creature: &Creature) -> Result<()> {
+    conn.execute(
+        r#"UPDATE creatures SET name = ?2, type = ?3, size = ?4, description = ?5, physical_description = ?6,
+           behavior = ?7, habitat = ?8, danger_level = ?9, challenge_rating = ?10, stats = ?11, abilities = ?12,
+           weaknesses = ?13, resistances = ?14, immunities = ?15, loot = ?16, image_url = ?17,
+           related_location_ids = ?18, notes = ?19, origin_package_id = ?20, updated_at = CURRENT_TIMESTAMP
            WHERE id = ?1"#,
         params![
-            event.id,
-            event.title,
-            event.description,
-            event.date_mode,
-            event.date,
-            event.era,
-            serde_json::to_string(&event.participants).unwrap_or_default(),
-            event.location_id,
-            event.importance,
-            serde_json::to_string(&event.tags).unwrap_or_default(),
-            event.scene_id,
-            event.chapter_id,
+            creature.id,
+            creature.name,
+            creature.r#type,
+            creature.size,
+            creature.description,
+            creature.physical_description,
+            creature.behavior,
+            to_json(&creature.habitat),
+            creature.danger_level,
+            creature.challenge_rating,
+            to_json(&creature.stats),
+            to_json(&creature.abilities),
+            to_json(&creature.weaknesses),
+            to_json(&creature.resistances),
+            to_json(&creature.immunities),
+            to_json(&creature.loot),
+            creature.image_url,
+            to_json(&creature.related_location_ids),
+            creature.notes,
+            creature.origin_package_id,
         ],
     )?;
     Ok(())
 }
 
-pub fn delete_timeline_event(conn: &Connection, id: &str) -> Result<()> {
-    conn.execute("DELETE FROM timeline_events WHERE id = ?1", params![id])?;
+/// Move a creature to the trash
+pub fn delete_creature(conn: &Connection, id: &str) -> Result<()> {
+    conn.execute(
+        "UPDATE creatures SET deleted_at = ?2 WHERE id = ?1 AND deleted_at IS NULL",
+        params![id, deletion_stamp()],
+    )?;
+    Ok(())
+}
+
+// ============================================================================
+// NPCs
+// ============================================================================
+
+pub fn create_npc(conn: &Connection, npc: &Npc) -> Result<()> {
+    conn.execute(
+        r#"INSERT INTO npcs (id, project_id, origin_package_id, name, image_url, description, personality, role, faction, disposition, importance, stats, inventory, quests, dialogues, secrets, relationships, schedule, related_location_ids, linked_character_id, notes)
+           VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21)"#,
+        params![
+            npc.id,
+            npc.project_id,
+            npc.origin_package_id,
+            npc.name,
+            npc.image_url,
+            npc.description,
+            npc.personality,
+            npc.role,
+            npc.faction,
+            npc.disposition,
+            npc.importance,
+            to_json(&npc.stats),
+            to_json(&npc.inventory),
+            to_json(&npc.quests),
+            to_json(&npc.dialogues),
+            to_json(&npc.secrets),
+            to_json(&npc.relationships),
+            npc.schedule,
+            to_json(&npc.related_location_ids),
+            npc.linked_character_id,
+            npc.notes,
+        ],
+    )?;
+    Ok(())
+}
+
+pub fn get_npcs_by_project(conn: &Connection, project_id: &str) -> Result<Vec<Npc>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, project_id, origin_package_id, name, image_url, description, personality, role, faction, disposition, importance, stats, inventory, quests, dialogues, secrets, relationships, schedule, related_location_ids, linked_character_id, notes
+         FROM npcs WHERE project_id = ?1 AND deleted_at IS NULL ORDER BY name"
+    )?;
+
+    let rows = stmt.query_map(params![project_id], |row| {
+        Ok(Npc {
+            id: row.get(0)?,
+            project_id: row.get(1)?,
+            origin_package_id: row.get(2)?,
+            name: row.get(3)?,
+            image_url: row.get(4)?,
+            description: row.get(5)?,
+            personality: row.get(6)?,
+            role: row.get(7)?,
+            faction: row.get(8)?,
+            disposition: row.get(9)?,
+            importance: row.get(10)?,
+            stats: from_json(row.get(11)?),
+            inventory: from_json(row.get(12)?),
+            quests: from_json(row.get(13)?),
+            dialogues: from_json(row.get(14)?),
+            secrets: from_json(row.get(15)?),
+            relationships: from_json(row.get(16)?),
+            schedule: row.get(17)?,
+            related_location_ids: from_json(row.get(18)?),
+            linked_character_id: row.get(19)?,
+            notes: row.get(20)?,
+        })
+    })?;
+
+    rows.collect()
+}
+
+pub fn update_npc(conn: &Connection, npc: &Npc) -> Result<()> {
+    conn.execute(
+        r#"UPDATE npcs SET name = ?2, image_url = ?3, description = ?4, personality = ?5, role = ?6,
+           faction = ?7, disposition = ?8, importance = ?9, stats = ?10, inventory = ?11, quests = ?12,
+           dialogues = ?13, secrets = ?14, relationships = ?15, schedule = ?16, related_location_ids = ?17,
+           linked_character_id = ?18, notes = ?19, origin_package_id = ?20, updated_at = CURRENT_TIMESTAMP
+           WHERE id = ?1"#,
+        params![
+            npc.id,
+            npc.name,
+            npc.image_url,
+            npc.description,
+            npc.personality,
+            npc.role,
+            npc.faction,
+            npc.disposition,
+            npc.importance,
+            to_json(&npc.stats),
+            to_json(&npc.inventory),
+            to_json(&npc.quests),
+            to_json(&npc.dialogues),
+            to_json(&npc.secrets),
+            to_json(&npc.relationships),
+            npc.schedule,
+            to_json(&npc.related_location_ids),
+            npc.linked_character_id,
+            npc.notes,
+            npc.origin_package_id,
+        ],
+    )?;
+    Ok(())
+}
+
+/// Move an NPC to the trash
+pub fn delete_npc(conn: &Connection, id: &str) -> Result<()> {
+    conn.execute(
+        "UPDATE npcs SET deleted_at = ?2 WHERE id = ?1 AND deleted_at IS NULL",
+        params![id, deletion_stamp()],
+    )?;
+    Ok(())
+}
+
+// ============================================================================
+// World Rules
+// ============================================================================
+
+pub fn create_world_rule(conn: &Connection, rule: &WorldRule) -> Result<()> {
+    conn.execute(
+        r#"INSERT INTO world_rules (id, project_id, origin_package_id, title, category, custom_category, content, summary, importance, exceptions, examples, related_rule_ids, related_entity_ids, image_url, is_secret)
+           VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)"#,
+        params![
+            rule.id,
+            rule.project_id,
+            rule.origin_package_id,
+            rule.title,
+            rule.category,
+            rule.custom_category,
+            rule.content,
+            rule.summary,
+            rule.importance,
+            to_json(&rule.exceptions),
+            to_json(&rule.examples),
+            to_json(&rule.related_rule_ids),
+            to_json(&rule.related_entity_ids),
+            rule.image_url,
+            rule.is_secret,
+        ],
+    )?;
+    Ok(())
+}
+
+pub fn get_world_rules_by_project(conn: &Connection, project_id: &str) -> Result<Vec<WorldRule>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, project_id, origin_package_id, title, category, custom_category, content, summary, importance, exceptions, examples, related_rule_ids, related_entity_ids, image_url, is_secret
+         FROM world_rules WHERE project_id = ?1 AND deleted_at IS NULL ORDER BY category, title"
+    )?;
+
+    let rows = stmt.query_map(params![project_id], |row| {
+        Ok(WorldRule {
+            id: row.get(0)?,
+            project_id: row.get(1)?,
+            origin_package_id: row.get(2)?,
+            title: row.get(3)?,
+            category: row.get(4)?,
+            custom_category: row.get(5)?,
+            content: row.get(6)?,
+            summary: row.get(7)?,
+            importance: row.get(8)?,
+            exceptions: from_json(row.get(9)?),
+            examples: from_json(row.get(10)?),
+            related_rule_ids: from_json(row.get(11)?),
+            related_entity_ids: from_json(row.get(12)?),
+            image_url: row.get(13)?,
+            is_secret: row.get::<_, i32>(14)? != 0,
+        })
+    })?;
+
+    rows.collect()
+}
+
+pub fn update_world_rule(conn: &Connection, rule: &WorldRule) -> Result<()> {
+    conn.execute(
+        r#"UPDATE world_rules SET title = ?2, category = ?3, custom_category = ?4, content = ?5, summary = ?6,
+           importance = ?7, exceptions = ?8, examples = ?9, related_rule_ids = ?10, related_entity_ids = ?11,
+           image_url = ?12, is_secret = ?13, origin_package_id = ?14, updated_at = CURRENT_TIMESTAMP
+           WHERE id = ?1"#,
+        params![
+            rule.id,
+            rule.title,
+            rule.category,
+            rule.custom_category,
+            rule.content,
+            rule.summary,
+            rule.importance,
+            to_json(&rule.exceptions),
+            to_json(&rule.examples),
+            to_json(&rule.related_rule_ids),
+            to_json(&rule.related_entity_ids),
+            rule.image_url,
+            rule.is_secret,
+            rule.origin_package_id,
+        ],
+    )?;
+    Ok(())
+}
+
+/// Move a world rule to the trash
+pub fn delete_world_rule(conn: &Connection, id: &str) -> Result<()> {
+    conn.execute(
+        "UPDATE world_rules SET deleted_at = ?2 WHERE id = ?1 AND deleted_at IS NULL",
+        params![id, deletion_stamp()],
+    )?;
     Ok(())
 }
 
@@ -725,11 +1327,19 @@ pub fn clear_database(conn: &Connection) -> Result<()> {
         "lore_items",
         "relationships",
         "scenes",
+        "creatures",
+        "npcs",
+        "world_rules",
+        "chapter_revisions",
         "chapters",
         "locations",
         "characters",
         "projects",
         "app_settings",
+        "undo_changes",
+        "undo_actions",
+        // Last, as clearing the other tables logs their rows' removal
+        "audit_log",
     ];
 
     for table in tables {
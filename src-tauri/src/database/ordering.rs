@@ -0,0 +1,308 @@
+//! Ordering of chapters and scenes
+//!
+//! Chapters are numbered from 1 and scenes positioned from 0 within their
+//! chapter. Every operation here rewrites the whole sequence it touches, so
+//! numbering stays dense with no duplicates or gaps.
+
+use super::assembly;
+use super::models::{Chapter, Scene};
+use super::operations::{
+    get_chapter, get_chapters_by_project, get_scenes_by_chapter, with_savepoint,
+};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum OrderingError {
+    #[error("{kind} {id} not found")]
+    NotFound { kind: &'static str, id: String },
+
+    #[error("{kind} {id} does not belong to {parent}")]
+    Foreign {
+        kind: &'static str,
+        id: String,
+        parent: String,
+    },
+
+    #[error("{0} is listed more than once")]
+    Duplicate(String),
+
+    #[error("Cannot move a scene to a chapter of another project")]
+    CrossProject,
+
+    #[error("Database error: {0}")]
+    Database(#[from] rusqlite::Error),
+}
+
+/// Both chapters touched by a scene move, in their new order
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SceneMove {
+    pub source_chapter_id: String,
+    pub source_scenes: Vec<Scene>,
+    pub target_chapter_id: String,
+    pub target_scenes: Vec<Scene>,
+}
+
+/// Renumber a project's chapters in the given order. Chapters left out of
+/// `ordered_ids` keep their relative order after the listed ones.
+pub fn reorder_chapters(
+    conn: &Connection,
+    project_id: &str,
+    ordered_ids: &[String],
+) -> Result<Vec<Chapter>, OrderingError> {
+    let current: Vec<String> = get_chapters_by_project(conn, project_id)?
+        .into_iter()
+        .map(|c| c.id)
+        .collect();
+    let order = merge_order(&current, ordered_ids, "Chapter", project_id)?;
+
+    with_savepoint(conn, || {
+        for (index, id) in order.iter().enumerate() {
+            conn.execute(
+                "UPDATE chapters SET number = ?2, updated_at = CURRENT_TIMESTAMP
+                 WHERE id = ?1 AND number IS NOT ?2",
+                params![id, index as i64 + 1],
+            )?;
+        }
+        Ok(())
+    })?;
+
+    Ok(get_chapters_by_project(conn, project_id)?)
+}
+
+/// Reposition the scenes of a chapter in the given order. Scenes left out
+/// of `ordered_ids` keep their relative order after the listed ones.
+pub fn reorder_scenes(
+    conn: &Connection,
+    chapter_id: &str,
+    ordered_ids: &[String],
+) -> Result<Vec<Scene>, OrderingError> {
+    require_chapter(conn, chapter_id)?;
+    let current = scene_ids(conn, chapter_id)?;
+    let order = merge_order(&current, ordered_ids, "Scene", chapter_id)?;
+
+    with_savepoint(conn, || {
+        write_scene_positions(conn, &order)?;
+        assembly::recompile(conn, chapter_id)
+    })?;
+
+    Ok(get_scenes_by_chapter(conn, chapter_id)?)
+}
+
+/// Move a scene to `position` in another chapter of the same project (or
+/// to the end when `position` is `None`), closing the gap it leaves behind.
+/// Timeline events linked to the scene follow it to the new chapter.
+pub fn move_scene(
+    conn: &Connection,
+    scene_id: &str,
+    target_chapter_id: &str,
+    position: Option<usize>,
+) -> Result<SceneMove, OrderingError> {
+    let source_chapter_id: String = conn
+        .query_row(
+            "SELECT chapter_id FROM scenes WHERE id = ?1 AND deleted_at IS NULL",
+            params![scene_id],
+            |row| row.get(0),
+        )
+        .optional()?
+        .ok_or_else(|| OrderingError::NotFound {
+            kind: "Scene",
+            id: scene_id.to_string(),
+        })?;
+    let source = require_chapter(conn, &source_chapter_id)?;
+    let target = require_chapter(conn, target_chapter_id)?;
+    if source.project_id != target.project_id {
+        return Err(OrderingError::CrossProject);
+    }
+
+    let mut source_order = scene_ids(conn, &source_chapter_id)?;
+    source_order.retain(|id| id != scene_id);
+    let mut target_order = if source_chapter_id == target_chapter_id {
+        source_order.clone()
+    } else {
+        scene_ids(conn, target_chapter_id)?
+    };
+    let index = position
+        .unwrap_or(target_order.len())
+        .min(target_order.len());
+    target_order.insert(index, scene_id.to_string());
+
+    with_savepoint(conn, || {
+        conn.execute(
+            "UPDATE scenes SET chapter_id = ?2, updated_at = CURRENT_TIMESTAMP WHERE id = ?1",
+            params![scene_id, target_chapter_id],
+        )?;
+        relink_timeline_events(conn, scene_id)?;
+        if source_chapter_id != target_chapter_id {
+            write_scene_positions(conn, &source_order)?;
+            assembly::recompile(conn, &source_chapter_id)?;
+        }
+        write_scene_positions(conn, &target_order)?;
+        assembly::recompile(conn, target_chapter_id)
+    })?;
+
+    Ok(SceneMove {
+        source_scenes: get_scenes_by_chapter(conn, &source_chapter_id)?,
+        source_chapter_id,
+        target_scenes: get_scenes_by_chapter(conn, target_chapter_id)?,
+        target_chapter_id: target_chapter_id.to_string(),
+    })
+}
+
+/// Point timeline events linked to a scene at the scene's current chapter
+pub(crate) fn relink_timeline_events(conn: &Connection, scene_id: &str) -> rusqlite::Result<()> {
+    conn.execute(
+        r#"UPDATE timeline_events
+           SET chapter_id = (SELECT chapter_id FROM scenes WHERE id = ?1), updated_at = CURRENT_TIMESTAMP
+           WHERE scene_id = ?1
+             AND chapter_id IS NOT (SELECT chapter_id FROM scenes WHERE id = ?1)"#,
+        params![scene_id],
+    )?;
+    Ok(())
+}
+
+fn require_chapter(conn: &Connection, chapter_id: &str) -> Result<Chapter, OrderingError> {
+    get_chapter(conn, chapter_id)?.ok_or_else(|| OrderingError::NotFound {
+        kind: "Chapter",
+        id: chapter_id.to_string(),
+    })
+}
+
+fn scene_ids(conn: &Connection, chapter_id: &str) -> rusqlite::Result<Vec<String>> {
+    Ok(get_scenes_by_chapter(conn, chapter_id)?
+        .into_iter()
+        .map(|s| s.id)
+        .collect())
+}
+
+fn write_scene_positions(conn: &Connection, order: &[String]) -> rusqlite::Result<()> {
+    for (index, id) in order.iter().enumerate() {
+        conn.execute(
+            "UPDATE scenes SET timeline_position = ?2, updated_at = CURRENT_TIMESTAMP
+             WHERE id = ?1 AND timeline_position IS NOT ?2",
+            params![id, index as i64],
+        )?;
+    }
+    Ok(())
+}
+
+/// The requested order followed by any current ids it left out
+fn merge_order(
+    current: &[String],
+    requested: &[String],
+    kind: &'static str,
+    parent: &str,
+) -> Result<Vec<String>, OrderingError> {
+    let known: HashSet<&str> = current.iter().map(String::as_str).collect();
+    let mut seen = HashSet::new();
+    for id in requested {
+        if !known.contains(id.as_str()) {
+            return Err(OrderingError::Foreign {
+                kind,
+                id: id.clone(),
+                parent: parent.to_string(),
+            });
+        }
+        if !seen.insert(id.as_str()) {
+            return Err(OrderingError::Duplicate(id.clone()));
+        }
+    }
+
+    let mut order = requested.to_vec();
+    order.extend(
+        current
+            .iter()
+            .filter(|id| !seen.contains(id.as_str()))
+            .cloned(),
+    );
+    Ok(order)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database;
+    use crate::database::test_support::project_db;
+    use serde_json::json;
+
+    fn ids<T>(items: &[T], id: impl Fn(&T) -> &str) -> Vec<String> {
+        items.iter().map(|item| id(item).to_string()).collect()
+    }
+
+    #[test]
+    fn test_reorder_and_move_keep_dense_numbering() {
+        let conn = project_db();
+        for (id, number) in [("c1", 1), ("c2", 1), ("c3", 7)] {
+            let chapter = serde_json::from_value(json!({
+                "id": id, "projectId": "p1", "title": id, "number": number,
+            }))
+            .unwrap();
+            database::create_chapter(&conn, &chapter).unwrap();
+        }
+        for (id, chapter) in [("s1", "c1"), ("s2", "c1"), ("s3", "c1"), ("s4", "c2")] {
+            let scene = serde_json::from_value(json!({
+                "id": id, "chapterId": chapter, "title": id, "timelinePosition": 0,
+            }))
+            .unwrap();
+            database::create_scene(&conn, &scene).unwrap();
+        }
+        let event = serde_json::from_value(json!({
+            "id": "e1", "projectId": "p1", "title": "Storm",
+            "sceneId": "s2", "chapterId": "c1",
+        }))
+        .unwrap();
+        database::create_timeline_event(&conn, &event).unwrap();
+
+        let chapters = reorder_chapters(&conn, "p1", &["c3".into(), "c1".into()]).unwrap();
+        assert_eq!(ids(&chapters, |c| &c.id), ["c3", "c1", "c2"]);
+        assert_eq!(
+            chapters.iter().map(|c| c.number).collect::<Vec<_>>(),
+            [Some(1), Some(2), Some(3)]
+        );
+        assert!(matches!(
+            reorder_chapters(&conn, "p1", &["c1".into(), "c1".into()]),
+            Err(OrderingError::Duplicate(_))
+        ));
+
+        let scenes = reorder_scenes(&conn, "c1", &["s3".into(), "s1".into(), "s2".into()]).unwrap();
+        assert_eq!(ids(&scenes, |s| &s.id), ["s3", "s1", "s2"]);
+
+        let moved = move_scene(&conn, "s2", "c2", Some(0)).unwrap();
+        assert_eq!(ids(&moved.source_scenes, |s| &s.id), ["s3", "s1"]);
+        assert_eq!(ids(&moved.target_scenes, |s| &s.id), ["s2", "s4"]);
+        assert_eq!(
+            moved
+                .target_scenes
+                .iter()
+                .map(|s| s.timeline_position)
+                .collect::<Vec<_>>(),
+            [0, 1]
+        );
+
+        let events = database::get_timeline_events_by_project(&conn, "p1").unwrap();
+        assert_eq!(events[0].chapter_id.as_deref(), Some("c2"));
+
+        // Neither path can carry a scene into another project
+        let other = serde_json::from_value(json!({ "id": "p2", "title": "Other" })).unwrap();
+        database::create_project(&conn, &other).unwrap();
+        let foreign =
+            serde_json::from_value(json!({ "id": "x1", "projectId": "p2", "title": "x1" }))
+                .unwrap();
+        database::create_chapter(&conn, &foreign).unwrap();
+        assert!(matches!(
+            move_scene(&conn, "s4", "x1", None),
+            Err(OrderingError::CrossProject)
+        ));
+        let mut scene = database::get_scenes_by_chapter(&conn, "c2")
+            .unwrap()
+            .remove(0);
+        scene.chapter_id = "x1".to_string();
+        assert!(matches!(
+            database::update_scene(&conn, &scene),
+            Err(OrderingError::CrossProject)
+        ));
+    }
+}
@@ -0,0 +1,234 @@
+//! Connection pool: one writer, several readers
+//!
+//! The database runs in WAL mode, so readers see the last committed state
+//! while a write is in progress instead of waiting for it to finish. Writes
+//! all go through the single writer connection, taken with [`DbPool::lock`]
+//! like the `Mutex<Connection>` this replaces.
+//!
+//! Each statement on a reader sees the latest commit; reads that must agree
+//! with each other across statements belong in one transaction.
+
+use super::connection::open_read_connection;
+use rusqlite::{Connection, Result};
+use std::ops::Deref;
+use std::path::Path;
+use std::sync::{Condvar, LockResult, Mutex, MutexGuard};
+use thiserror::Error;
+
+/// Read connections opened next to the writer
+pub const READ_CONNECTIONS: usize = 4;
+
+#[derive(Error, Debug)]
+pub enum PoolError {
+    #[error("Database connection lock poisoned")]
+    Poisoned,
+}
+
+pub struct DbPool {
+    writer: Mutex<Connection>,
+    /// Idle readers
+    readers: Mutex<Vec<Connection>>,
+    returned: Condvar,
+    size: usize,
+}
+
+impl DbPool {
+    /// Pool of `writer` and `readers` read connections to the same file
+    pub fn open(writer: Connection, path: &Path, readers: usize) -> Result<Self> {
+        let readers = (0..readers)
+            .map(|_| open_read_connection(path))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self::with_readers(writer, readers))
+    }
+
+    /// Pool without readers, where reads share the writer; for in-memory
+    /// databases, which other connections cannot open
+    pub fn single(writer: Connection) -> Self {
+        Self::with_readers(writer, Vec::new())
+    }
+
+    fn with_readers(writer: Connection, readers: Vec<Connection>) -> Self {
+        DbPool {
+            writer: Mutex::new(writer),
+            size: readers.len(),
+            readers: Mutex::new(readers),
+            returned: Condvar::new(),
+        }
+    }
+
+    /// Exclusive access to the writer connection
+    pub fn lock(&self) -> LockResult<MutexGuard<'_, Connection>> {
+        self.writer.lock()
+    }
+
+    /// A read-only connection, waiting for one to be free if all are in use
+    pub fn read(&self) -> std::result::Result<ReadConnection<'_>, PoolError> {
+        if self.size == 0 {
+            let writer = self.writer.lock().map_err(|_| PoolError::Poisoned)?;
+            return Ok(ReadConnection::Writer(writer));
+        }
+        let mut idle = self.readers.lock().map_err(|_| PoolError::Poisoned)?;
+        loop {
+            if let Some(conn) = idle.pop() {
+                return Ok(ReadConnection::Reader(self, Some(conn)));
+            }
+            idle = self.returned.wait(idle).map_err(|_| PoolError::Poisoned)?;
+        }
+    }
+}
+
+/// A connection borrowed for reading; goes back to the pool when dropped
+pub enum ReadConnection<'a> {
+    Reader(&'a DbPool, Option<Connection>),
+    Writer(MutexGuard<'a, Connection>),
+}
+
+impl Deref for ReadConnection<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        match self {
+            ReadConnection::Reader(_, conn) => conn.as_ref().expect("reader already returned"),
+            ReadConnection::Writer(guard) => guard,
+        }
+    }
+}
+
+impl Drop for ReadConnection<'_> {
+    fn drop(&mut self) {
+        if let ReadConnection::Reader(pool, conn) = self {
+            if let (Some(conn), Ok(mut idle)) = (conn.take(), pool.readers.lock()) {
+                idle.push(conn);
+                pool.returned.notify_one();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database;
+
+    #[test]
+    fn test_readers_are_not_blocked_by_an_open_write() {
+        let dir = std::env::temp_dir().join(format!("plumai-pool-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("test.db");
+        let writer = database::open_connection(&path).unwrap();
+        database::init_database(&writer).unwrap();
+        writer
+            .execute("INSERT INTO projects (id, title) VALUES ('p1', 'Saga')", [])
+            .unwrap();
+        let pool = DbPool::open(writer, &path, 2).unwrap();
+
+        let conn = pool.lock().unwrap();
+        conn.execute_batch("BEGIN; UPDATE projects SET title = 'Epic' WHERE id = 'p1';")
+            .unwrap();
+        let title = |pool: &DbPool| -> String {
+            pool.read()
+                .unwrap()
+                .query_row("SELECT title FROM projects WHERE id = 'p1'", [], |row| {
+                    row.get(0)
+                })
+                .unwrap()
+        };
+        // The uncommitted write is invisible, and does not stall the read
+        assert_eq!(title(&pool), "Saga");
+        conn.execute_batch("COMMIT").unwrap();
+        drop(conn);
+        assert_eq!(title(&pool), "Epic");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Times reads of a ~300k-word project while a long write holds the
+    /// writer. Run with `cargo test --release -- --ignored --nocapture`.
+    #[test]
+    #[ignore = "benchmark"]
+    fn bench_reads_during_a_long_write() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::Barrier;
+        use std::time::Instant;
+
+        const CHAPTERS: usize = 100;
+        const WORDS_PER_CHAPTER: usize = 3000;
+        const WORDS: &[&str] = &["the", "harbour", "lay", "quiet", "under", "grey", "rain"];
+
+        let dir = std::env::temp_dir().join(format!("plumai-pool-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("bench.db");
+        let writer = database::open_connection(&path).unwrap();
+        database::init_database(&writer).unwrap();
+        writer
+            .execute("INSERT INTO projects (id, title) VALUES ('p1', 'Saga')", [])
+            .unwrap();
+        let paragraph: String = (0..100)
+            .map(|i| WORDS[i % WORDS.len()])
+            .collect::<Vec<_>>()
+            .join(" ");
+        let content = format!("<p>{}</p>", paragraph).repeat(WORDS_PER_CHAPTER / 100);
+        for i in 0..CHAPTERS {
+            writer
+                .execute(
+                    r#"INSERT INTO chapters (id, project_id, title, content, word_count, number)
+                       VALUES (?1, 'p1', ?1, ?2, ?3, ?4)"#,
+                    rusqlite::params![format!("c{}", i), content, WORDS_PER_CHAPTER, i],
+                )
+                .unwrap();
+        }
+        let pool = DbPool::open(writer, &path, READ_CONNECTIONS).unwrap();
+
+        let started = Barrier::new(2);
+        let writing = AtomicBool::new(true);
+        let (write_time, reads) = std::thread::scope(|scope| {
+            let write = scope.spawn(|| {
+                let conn = pool.lock().unwrap();
+                let start = Instant::now();
+                conn.execute_batch("BEGIN").unwrap();
+                started.wait();
+                // Several passes over every chapter, in one transaction
+                for _ in 0..5 {
+                    conn.execute(
+                        "UPDATE chapters SET content = content || '<p>More rain.</p>', word_count = word_count + 2",
+                        [],
+                    )
+                    .unwrap();
+                }
+                conn.execute_batch("COMMIT").unwrap();
+                writing.store(false, Ordering::SeqCst);
+                start.elapsed()
+            });
+
+            started.wait();
+            let mut reads = Vec::new();
+            while writing.load(Ordering::SeqCst) {
+                let start = Instant::now();
+                let chapters =
+                    database::get_chapters_by_project(&pool.read().unwrap(), "p1").unwrap();
+                assert_eq!(chapters.len(), CHAPTERS);
+                reads.push(start.elapsed());
+            }
+            (write.join().unwrap(), reads)
+        });
+
+        let mut sorted = reads.clone();
+        sorted.sort();
+        let median = sorted.get(sorted.len() / 2).copied().unwrap_or_default();
+        let slowest = sorted.last().copied().unwrap_or_default();
+        println!(
+            "write held the writer for {:?}; {} reads meanwhile, median {:?}, slowest {:?}",
+            write_time,
+            reads.len(),
+            median,
+            slowest
+        );
+        // Reads kept going while the write was open instead of queueing
+        // behind it
+        assert!(reads.len() > 1);
+        assert!(slowest < write_time / 2);
+
+        drop(pool);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,406 @@
+//! Per-project settings
+//!
+//! Unlike `app_settings`, which holds global preferences as free-form
+//! strings, project settings are declared up front with a type and a
+//! default. Only values that differ from the default are stored, as JSON,
+//! so resetting a setting simply removes its row. A `null` default means
+//! "not set": the frontend falls back to the matching global preference.
+
+use super::operations::with_savepoint;
+use rusqlite::{params, Connection};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ProjectSettingError {
+    #[error("Unknown project setting: {0}")]
+    UnknownKey(String),
+
+    #[error("Invalid value for {key}: {reason}")]
+    InvalidValue { key: String, reason: String },
+
+    #[error("Project not found: {0}")]
+    ProjectNotFound(String),
+
+    #[error("Database error: {0}")]
+    Database(#[from] rusqlite::Error),
+}
+
+/// Shape of a setting's value
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum SettingKind {
+    Bool,
+    Integer {
+        min: i64,
+        max: i64,
+    },
+    Text,
+    /// One of a fixed set of strings
+    Choice {
+        options: &'static [&'static str],
+    },
+    TextList,
+    /// Any JSON object; its shape is checked by whoever declares the setting
+    Object,
+    /// Any JSON array
+    List,
+}
+
+impl SettingKind {
+    /// Check a (non-null) value against this kind
+    pub(crate) fn check(&self, value: &Value) -> Result<(), String> {
+        match (self, value) {
+            (SettingKind::Bool, Value::Bool(_)) => Ok(()),
+            (SettingKind::Integer { min, max }, Value::Number(n)) => match n.as_i64() {
+                Some(i) if (*min..=*max).contains(&i) => Ok(()),
+                _ => Err(format!("expected an integer from {} to {}", min, max)),
+            },
+            (SettingKind::Text, Value::String(_)) => Ok(()),
+            (SettingKind::Choice { options }, Value::String(s))
+                if options.contains(&s.as_str()) =>
+            {
+                Ok(())
+            }
+            (SettingKind::Choice { options }, _) => {
+                Err(format!("expected one of {}", options.join(", ")))
+            }
+            (SettingKind::TextList, Value::Array(items)) if items.iter().all(Value::is_string) => {
+                Ok(())
+            }
+            (SettingKind::TextList, _) => Err("expected a list of strings".to_string()),
+            (SettingKind::Object, Value::Object(_)) | (SettingKind::List, Value::Array(_)) => {
+                Ok(())
+            }
+            (SettingKind::Object, _) => Err("expected an object".to_string()),
+            (SettingKind::List, _) => Err("expected a list".to_string()),
+            (SettingKind::Integer { min, max }, _) => {
+                Err(format!("expected an integer from {} to {}", min, max))
+            }
+            (SettingKind::Bool, _) => Err("expected true or false".to_string()),
+            (SettingKind::Text, _) => Err("expected a string".to_string()),
+        }
+    }
+}
+
+/// Declaration of a project setting
+pub struct ProjectSettingDef {
+    pub key: &'static str,
+    pub kind: SettingKind,
+    /// Default value, as JSON
+    pub default: &'static str,
+}
+
+impl ProjectSettingDef {
+    fn default_value(&self) -> Value {
+        serde_json::from_str(self.default).expect("setting defaults are valid JSON")
+    }
+
+    fn check(&self, value: &Value) -> Result<(), ProjectSettingError> {
+        if value.is_null() && self.default == "null" {
+            return Ok(());
+        }
+        self.kind
+            .check(value)
+            .map_err(|reason| ProjectSettingError::InvalidValue {
+                key: self.key.to_string(),
+                reason,
+            })
+    }
+}
+
+/// Every project setting
+pub const PROJECT_SETTINGS: &[ProjectSettingDef] = &[
+    ProjectSettingDef {
+        key: "targetWordCount",
+        kind: SettingKind::Integer {
+            min: 0,
+            max: 10_000_000,
+        },
+        default: "null",
+    },
+    ProjectSettingDef {
+        key: "chapterTargetWordCount",
+        kind: SettingKind::Integer {
+            min: 0,
+            max: 1_000_000,
+        },
+        default: "null",
+    },
+    ProjectSettingDef {
+        key: "aiProvider",
+        kind: SettingKind::Text,
+        default: "null",
+    },
+    ProjectSettingDef {
+        key: "aiModel",
+        kind: SettingKind::Text,
+        default: "null",
+    },
+    ProjectSettingDef {
+        key: "exportFormat",
+        kind: SettingKind::Choice {
+            options: &["pdf", "docx"],
+        },
+        default: r#""pdf""#,
+    },
+    ProjectSettingDef {
+        key: "exportIncludeTitlePage",
+        kind: SettingKind::Bool,
+        default: "true",
+    },
+    ProjectSettingDef {
+        key: "exportIncludeToc",
+        kind: SettingKind::Bool,
+        default: "true",
+    },
+    ProjectSettingDef {
+        key: "exportLinkStyle",
+        kind: SettingKind::Choice {
+            options: &["plain", "glossary"],
+        },
+        default: r#""plain""#,
+    },
+    ProjectSettingDef {
+        key: "speechLanguage",
+        kind: SettingKind::Text,
+        default: "null",
+    },
+    ProjectSettingDef {
+        key: "speechVocabulary",
+        kind: SettingKind::TextList,
+        default: "[]",
+    },
+];
+
+/// A setting's current value, as listed for the settings UI
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProjectSetting {
+    pub key: &'static str,
+    #[serde(flatten)]
+    pub kind: SettingKind,
+    pub value: Value,
+    pub default: Value,
+    pub is_default: bool,
+}
+
+fn definition(key: &str) -> Result<&'static ProjectSettingDef, ProjectSettingError> {
+    PROJECT_SETTINGS
+        .iter()
+        .find(|def| def.key == key)
+        .ok_or_else(|| ProjectSettingError::UnknownKey(key.to_string()))
+}
+
+fn check_project(conn: &Connection, project_id: &str) -> Result<(), ProjectSettingError> {
+    let exists: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM projects WHERE id = ?1)",
+        params![project_id],
+        |row| row.get(0),
+    )?;
+    if !exists {
+        return Err(ProjectSettingError::ProjectNotFound(project_id.to_string()));
+    }
+    Ok(())
+}
+
+/// Every setting of a project, with its default
+pub fn get_project_settings(
+    conn: &Connection,
+    project_id: &str,
+) -> Result<Vec<ProjectSetting>, ProjectSettingError> {
+    let stored = get_project_setting_overrides(conn, project_id)?;
+    Ok(PROJECT_SETTINGS
+        .iter()
+        .map(|def| {
+            let default = def.default_value();
+            let value = stored.get(def.key).cloned();
+            ProjectSetting {
+                key: def.key,
+                kind: def.kind,
+                is_default: value.is_none(),
+                value: value.unwrap_or_else(|| default.clone()),
+                default,
+            }
+        })
+        .collect())
+}
+
+/// Current value of one setting
+pub fn get_project_setting(
+    conn: &Connection,
+    project_id: &str,
+    key: &str,
+) -> Result<Value, ProjectSettingError> {
+    let def = definition(key)?;
+    let mut stmt =
+        conn.prepare("SELECT value FROM project_settings WHERE project_id = ?1 AND key = ?2")?;
+    let mut rows = stmt.query(params![project_id, key])?;
+    let stored = match rows.next()? {
+        Some(row) => serde_json::from_str(&row.get::<_, String>(0)?).ok(),
+        None => None,
+    };
+    Ok(stored.unwrap_or_else(|| def.default_value()))
+}
+
+/// Set one setting; setting it to its default resets it
+pub fn set_project_setting(
+    conn: &Connection,
+    project_id: &str,
+    key: &str,
+    value: &Value,
+) -> Result<(), ProjectSettingError> {
+    let def = definition(key)?;
+    def.check(value)?;
+    check_project(conn, project_id)?;
+    if *value == def.default_value() {
+        return reset_project_setting(conn, project_id, Some(key));
+    }
+    conn.execute(
+        r#"INSERT INTO project_settings (project_id, key, value) VALUES (?1, ?2, ?3)
+           ON CONFLICT (project_id, key)
+           DO UPDATE SET value = excluded.value, updated_at = CURRENT_TIMESTAMP"#,
+        params![project_id, key, value.to_string()],
+    )?;
+    Ok(())
+}
+
+/// Reset one setting to its default, or all of them when `key` is `None`
+pub fn reset_project_setting(
+    conn: &Connection,
+    project_id: &str,
+    key: Option<&str>,
+) -> Result<(), ProjectSettingError> {
+    match key {
+        Some(key) => {
+            definition(key)?;
+            conn.execute(
+                "DELETE FROM project_settings WHERE project_id = ?1 AND key = ?2",
+                params![project_id, key],
+            )?;
+        }
+        None => {
+            conn.execute(
+                "DELETE FROM project_settings WHERE project_id = ?1",
+                params![project_id],
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Settings of a project that differ from their default, keyed by name;
+/// the form stored in project folders
+pub fn get_project_setting_overrides(
+    conn: &Connection,
+    project_id: &str,
+) -> Result<Map<String, Value>, ProjectSettingError> {
+    let mut stmt =
+        conn.prepare("SELECT key, value FROM project_settings WHERE project_id = ?1 ORDER BY key")?;
+    let rows = stmt.query_map(params![project_id], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+    })?;
+    let mut overrides = Map::new();
+    for row in rows {
+        let (key, value) = row?;
+        if let Ok(value) = serde_json::from_str(&value) {
+            overrides.insert(key, value);
+        }
+    }
+    Ok(overrides)
+}
+
+/// Replace every setting of a project with `overrides`; settings left out
+/// are reset. Unknown keys, from a newer version of the app, are skipped.
+pub fn replace_project_settings(
+    conn: &Connection,
+    project_id: &str,
+    overrides: &Map<String, Value>,
+) -> Result<(), ProjectSettingError> {
+    let known: Vec<(&str, &Value)> = overrides
+        .iter()
+        .filter_map(|(key, value)| Some((definition(key).ok()?.key, value)))
+        .collect();
+    for (key, value) in &known {
+        definition(key)?.check(value)?;
+    }
+    check_project(conn, project_id)?;
+
+    with_savepoint(conn, || {
+        conn.execute(
+            "DELETE FROM project_settings WHERE project_id = ?1",
+            params![project_id],
+        )?;
+        for (key, value) in &known {
+            conn.execute(
+                "INSERT INTO project_settings (project_id, key, value) VALUES (?1, ?2, ?3)",
+                params![project_id, key, value.to_string()],
+            )?;
+        }
+        Ok(())
+    })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::test_support::project_db;
+    use serde_json::json;
+
+    #[test]
+    fn test_project_settings_validate_and_reset() {
+        let conn = project_db();
+        conn.execute(
+            "INSERT INTO projects (id, title) VALUES ('p2', 'Other')",
+            [],
+        )
+        .unwrap();
+
+        set_project_setting(&conn, "p1", "targetWordCount", &json!(90000)).unwrap();
+        set_project_setting(&conn, "p1", "speechVocabulary", &json!(["Aerith"])).unwrap();
+        // Setting a default value stores nothing
+        set_project_setting(&conn, "p1", "exportFormat", &json!("pdf")).unwrap();
+
+        assert!(matches!(
+            set_project_setting(&conn, "p1", "targetWordCount", &json!(-5)),
+            Err(ProjectSettingError::InvalidValue { .. })
+        ));
+        assert!(matches!(
+            set_project_setting(&conn, "p1", "exportFormat", &json!(null)),
+            Err(ProjectSettingError::InvalidValue { .. })
+        ));
+        assert!(matches!(
+            set_project_setting(&conn, "p1", "theme", &json!("dark")),
+            Err(ProjectSettingError::UnknownKey(_))
+        ));
+
+        assert_eq!(
+            get_project_setting(&conn, "p1", "targetWordCount").unwrap(),
+            json!(90000)
+        );
+        assert_eq!(
+            get_project_setting(&conn, "p2", "targetWordCount").unwrap(),
+            json!(null)
+        );
+        let overrides = get_project_setting_overrides(&conn, "p1").unwrap();
+        assert_eq!(
+            Value::Object(overrides.clone()),
+            json!({ "speechVocabulary": ["Aerith"], "targetWordCount": 90000 })
+        );
+
+        replace_project_settings(&conn, "p2", &overrides).unwrap();
+        reset_project_setting(&conn, "p1", Some("targetWordCount")).unwrap();
+        let settings = get_project_settings(&conn, "p1").unwrap();
+        let target = settings
+            .iter()
+            .find(|s| s.key == "targetWordCount")
+            .unwrap();
+        assert!(target.is_default);
+        assert_eq!(
+            get_project_setting(&conn, "p2", "targetWordCount").unwrap(),
+            json!(90000)
+        );
+    }
+}
@@ -0,0 +1,600 @@
+//! Filtered, sorted and paginated entity lists
+//!
+//! A lighter alternative to the `get_*_by_project` functions for large
+//! projects: callers pick the fields they need (a chapter list without its
+//! content, say), filter on any plain column, custom field or tag, and page
+//! through the results with an opaque cursor. Fields are the camelCase names
+//! of the entity's JSON form; items come back as partial JSON objects that
+//! always include `id`.
+//!
+//! Pagination is keyset-based: the cursor holds the sort value and id of the
+//! last item, so pages stay consistent while rows are added or removed.
+
+use super::custom_fields::{self, CustomFieldFilter, FilterOp};
+use super::models::EntityKind;
+use super::tags::TagMatch;
+use rusqlite::{params_from_iter, types::Value as SqlValue, Connection};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use thiserror::Error;
+
+const DEFAULT_LIMIT: u32 = 100;
+const MAX_LIMIT: u32 = 1000;
+
+#[derive(Error, Debug)]
+pub enum QueryError {
+    #[error("{0} entities cannot be queried")]
+    Unsupported(&'static str),
+
+    #[error("{kind} entities have no field {field}")]
+    UnknownField { kind: &'static str, field: String },
+
+    #[error("Field {0} cannot be filtered or sorted on")]
+    NotComparable(String),
+
+    #[error("Invalid filter on {field}: {reason}")]
+    InvalidFilter { field: String, reason: String },
+
+    #[error("Invalid cursor")]
+    InvalidCursor,
+
+    #[error("Database error: {0}")]
+    Database(#[from] rusqlite::Error),
+}
+
+/// A condition on a plain field
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldFilter {
+    pub field: String,
+    pub op: FilterOp,
+    /// Operand; unused by `Exists` and `Missing`
+    #[serde(default)]
+    pub value: Option<Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QuerySort {
+    pub field: String,
+    #[serde(default)]
+    pub descending: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EntityQuery {
+    /// Fields to return; all of them when empty
+    #[serde(default)]
+    pub fields: Vec<String>,
+    /// Conditions that must all hold
+    #[serde(default)]
+    pub filters: Vec<FieldFilter>,
+    #[serde(default)]
+    pub custom_fields: Vec<CustomFieldFilter>,
+    /// Only entities carrying these tags, combined as `tag_match` says
+    #[serde(default)]
+    pub tag_ids: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag_match: Option<TagMatch>,
+    /// Order of the results; each entity type has a natural default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sort: Option<QuerySort>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u32>,
+    /// `next_cursor` of the previous page
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<String>,
+}
+
+/// One page of results
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryPage {
+    pub items: Vec<Value>,
+    /// Cursor of the next page; none on the last one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ColumnKind {
+    Text,
+    Integer,
+    Bool,
+    /// JSON text, returned parsed; cannot be filtered or sorted on
+    Json,
+}
+
+use ColumnKind::*;
+
+const CHAPTER_COLUMNS: &[(&str, ColumnKind)] = &[
+    ("id", Text),
+    ("project_id", Text),
+    ("title", Text),
+    ("content", Text),
+    ("status", Text),
+    ("word_count", Integer),
+    ("summary", Text),
+    ("number", Integer),
+    ("image", Text),
+    ("image_type", Text),
+    ("content_mode", Text),
+    ("created_at", Text),
+    ("updated_at", Text),
+];
+
+const SCENE_COLUMNS: &[(&str, ColumnKind)] = &[
+    ("id", Text),
+    ("chapter_id", Text),
+    ("title", Text),
+    ("character_ids", Json),
+    ("location_id", Text),
+    ("timeline_position", Integer),
+    ("description", Text),
+    ("notes", Text),
+    ("image", Text),
+    ("image_type", Text),
+    ("content", Text),
+    ("word_count", Integer),
+    ("created_at", Text),
+    ("updated_at", Text),
+];
+
+const CHARACTER_COLUMNS: &[(&str, ColumnKind)] = &[
+    ("id", Text),
+    ("project_id", Text),
+    ("origin_package_id", Text),
+    ("name", Text),
+    ("role", Text),
+    ("avatar_url", Text),
+    ("physical_description", Text),
+    ("personality", Text),
+    ("history", Text),
+    ("notes", Text),
+    ("attributes", Json),
+    ("attribute_history", Json),
+    ("vital_status_history", Json),
+    ("current_vital_status", Text),
+    ("visual_position", Json),
+    ("custom_fields", Json),
+    ("created_at", Text),
+    ("updated_at", Text),
+];
+
+const LOCATION_COLUMNS: &[(&str, ColumnKind)] = &[
+    ("id", Text),
+    ("project_id", Text),
+    ("name", Text),
+    ("image_url", Text),
+    ("type", Text),
+    ("description", Text),
+    ("significance", Text),
+    ("notes", Text),
+    ("gallery", Json),
+    ("plans", Json),
+    ("connections", Json),
+    ("visual_position", Json),
+    ("custom_fields", Json),
+    ("created_at", Text),
+    ("updated_at", Text),
+];
+
+const LORE_ITEM_COLUMNS: &[(&str, ColumnKind)] = &[
+    ("id", Text),
+    ("project_id", Text),
+    ("origin_package_id", Text),
+    ("title", Text),
+    ("category", Text),
+    ("content", Text),
+    ("summary", Text),
+    ("related_entity_ids", Json),
+    ("custom_fields", Json),
+    ("created_at", Text),
+    ("updated_at", Text),
+];
+
+const TIMELINE_EVENT_COLUMNS: &[(&str, ColumnKind)] = &[
+    ("id", Text),
+    ("project_id", Text),
+    ("title", Text),
+    ("description", Text),
+    ("date_mode", Text),
+    ("date", Text),
+    ("era", Text),
+    ("participants", Json),
+    ("location_id", Text),
+    ("importance", Text),
+    ("scene_id", Text),
+    ("chapter_id", Text),
+    ("created_at", Text),
+    ("updated_at", Text),
+];
+
+const CREATURE_COLUMNS: &[(&str, ColumnKind)] = &[
+    ("id", Text),
+    ("project_id", Text),
+    ("origin_package_id", Text),
+    ("name", Text),
+    ("type", Text),
+    ("size", Text),
+    ("description", Text),
+    ("physical_description", Text),
+    ("behavior", Text),
+    ("habitat", Json),
+    ("danger_level", Text),
+    ("challenge_rating", Text),
+    ("stats", Json),
+    ("abilities", Json),
+    ("weaknesses", Json),
+    ("resistances", Json),
+    ("immunities", Json),
+    ("loot", Json),
+    ("image_url", Text),
+    ("related_location_ids", Json),
+    ("notes", Text),
+    ("created_at", Text),
+    ("updated_at", Text),
+];
+
+const NPC_COLUMNS: &[(&str, ColumnKind)] = &[
+    ("id", Text),
+    ("project_id", Text),
+    ("origin_package_id", Text),
+    ("name", Text),
+    ("image_url", Text),
+    ("description", Text),
+    ("personality", Text),
+    ("role", Text),
+    ("faction", Text),
+    ("disposition", Text),
+    ("importance", Text),
+    ("stats", Json),
+    ("inventory", Json),
+    ("quests", Json),
+    ("dialogues", Json),
+    ("secrets", Json),
+    ("relationships", Json),
+    ("schedule", Json),
+    ("related_location_ids", Json),
+    ("linked_character_id", Text),
+    ("notes", Text),
+    ("created_at", Text),
+    ("updated_at", Text),
+];
+
+const WORLD_RULE_COLUMNS: &[(&str, ColumnKind)] = &[
+    ("id", Text),
+    ("project_id", Text),
+    ("origin_package_id", Text),
+    ("title", Text),
+    ("category", Text),
+    ("custom_category", Text),
+    ("content", Text),
+    ("summary", Text),
+    ("importance", Text),
+    ("exceptions", Json),
+    ("examples", Json),
+    ("related_rule_ids", Json),
+    ("related_entity_ids", Json),
+    ("image_url", Text),
+    ("is_secret", Bool),
+    ("created_at", Text),
+    ("updated_at", Text),
+];
+
+/// Columns of an entity type and the column it is sorted on by default
+fn columns(kind: EntityKind) -> Option<(&'static [(&'static str, ColumnKind)], &'static str)> {
+    Some(match kind {
+        EntityKind::Chapter => (CHAPTER_COLUMNS, "number"),
+        EntityKind::Scene => (SCENE_COLUMNS, "timeline_position"),
+        EntityKind::Character => (CHARACTER_COLUMNS, "name"),
+        EntityKind::Location => (LOCATION_COLUMNS, "name"),
+        EntityKind::LoreItem => (LORE_ITEM_COLUMNS, "title"),
+        EntityKind::TimelineEvent => (TIMELINE_EVENT_COLUMNS, "date"),
+        EntityKind::Creature => (CREATURE_COLUMNS, "name"),
+        EntityKind::Npc => (NPC_COLUMNS, "name"),
+        EntityKind::WorldRule => (WORLD_RULE_COLUMNS, "title"),
+        _ => return None,
+    })
+}
+
+/// List the live entities of a project matching `query`
+pub fn query_entities(
+    conn: &Connection,
+    project_id: &str,
+    kind: EntityKind,
+    query: &EntityQuery,
+) -> Result<QueryPage, QueryError> {
+    let (all_columns, default_sort) =
+        columns(kind).ok_or(QueryError::Unsupported(kind.as_str()))?;
+    let column = |field: &str| {
+        all_columns
+            .iter()
+            .copied()
+            .find(|(name, _)| camel_case(name) == field)
+            .ok_or_else(|| QueryError::UnknownField {
+                kind: kind.as_str(),
+                field: field.to_string(),
+            })
+    };
+    let comparable = |field: &str| {
+        let (name, column_kind) = column(field)?;
+        if column_kind == Json {
+            return Err(QueryError::NotComparable(field.to_string()));
+        }
+        Ok((name, column_kind))
+    };
+
+    let mut selected = vec![("id", Text)];
+    if query.fields.is_empty() {
+        selected.extend(
+            all_columns
+                .iter()
+                .copied()
+                .filter(|(name, _)| *name != "id"),
+        );
+    } else {
+        for field in &query.fields {
+            let column = column(field)?;
+            if !selected.contains(&column) {
+                selected.push(column);
+            }
+        }
+    }
+
+    let (sort_column, sort_kind) = match &query.sort {
+        Some(sort) => comparable(&sort.field)?,
+        None => comparable(&camel_case(default_sort))?,
+    };
+    let descending = query.sort.as_ref().is_some_and(|s| s.descending);
+    // NULLs sort first, as SQLite orders them, but must compare as values
+    // for the cursor to work
+    let sort_expr = match sort_kind {
+        Text => format!("IFNULL(t.{}, '')", sort_column),
+        // Spelled out since SQLite reads the literal i64::MIN as a float
+        _ => format!("IFNULL(t.{}, -{} - 1)", sort_column, i64::MAX),
+    };
+
+    let mut sql = format!(
+        "SELECT {}, {} FROM {} t",
+        selected
+            .iter()
+            .map(|(name, _)| format!("t.{}", name))
+            .collect::<Vec<_>>()
+            .join(", "),
+        sort_expr,
+        kind.table()
+    );
+    let mut values = vec![SqlValue::Text(project_id.to_string())];
+    if kind == EntityKind::Scene {
+        sql.push_str(
+            " JOIN chapters c ON c.id = t.chapter_id WHERE c.project_id = ? AND c.deleted_at IS NULL",
+        );
+    } else {
+        sql.push_str(" WHERE t.project_id = ?");
+    }
+    sql.push_str(" AND t.deleted_at IS NULL");
+
+    for filter in &query.filters {
+        let (name, _) = comparable(&filter.field)?;
+        let operand =
+            custom_fields::filter_operand(filter.op, filter.value.as_ref()).map_err(|reason| {
+                QueryError::InvalidFilter {
+                    field: filter.field.clone(),
+                    reason,
+                }
+            })?;
+        sql.push_str(" AND ");
+        sql.push_str(&custom_fields::filter_sql(
+            filter.op,
+            &format!("t.{}", name),
+        ));
+        values.extend(operand);
+    }
+
+    if !query.custom_fields.is_empty() {
+        if !custom_fields::supports_custom_fields(kind) {
+            return Err(QueryError::UnknownField {
+                kind: kind.as_str(),
+                field: "customFields".to_string(),
+            });
+        }
+        for filter in &query.custom_fields {
+            let operand = custom_fields::filter_operand(filter.op, filter.value.as_ref()).map_err(
+                |reason| QueryError::InvalidFilter {
+                    field: filter.field_id.clone(),
+                    reason,
+                },
+            )?;
+            sql.push_str(" AND ");
+            sql.push_str(&custom_fields::filter_sql(
+                filter.op,
+                "json_extract(t.custom_fields, ?)",
+            ));
+            values.push(SqlValue::Text(custom_fields::value_path(&filter.field_id)));
+            values.extend(operand);
+        }
+    }
+
+    if !query.tag_ids.is_empty() {
+        let placeholders = vec!["?"; query.tag_ids.len()].join(", ");
+        let required = match query.tag_match.unwrap_or(TagMatch::All) {
+            TagMatch::All => {
+                let mut wanted = query.tag_ids.clone();
+                wanted.sort();
+                wanted.dedup();
+                wanted.len()
+            }
+            TagMatch::Any => 1,
+        };
+        sql.push_str(&format!(
+            r#" AND (SELECT COUNT(DISTINCT tag_id) FROM entity_tags
+                     WHERE entity_type = ? AND entity_id = t.id AND tag_id IN ({})) >= ?"#,
+            placeholders
+        ));
+        values.push(SqlValue::Text(kind.as_str().to_string()));
+        values.extend(query.tag_ids.iter().map(|id| SqlValue::Text(id.clone())));
+        values.push(SqlValue::Integer(required as i64));
+    }
+
+    if let Some(cursor) = &query.cursor {
+        let (after, after_id) = decode_cursor(cursor, sort_kind)?;
+        sql.push_str(&format!(
+            " AND ({}, t.id) {} (?, ?)",
+            sort_expr,
+            if descending { "<" } else { ">" }
+        ));
+        values.push(after);
+        values.push(SqlValue::Text(after_id));
+    }
+
+    let direction = if descending { "DESC" } else { "ASC" };
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    sql.push_str(&format!(
+        " ORDER BY {} {}, t.id {} LIMIT ?",
+        sort_expr, direction, direction
+    ));
+    // One more than asked tells whether there is a next page
+    values.push(SqlValue::Integer(limit as i64 + 1));
+
+    let mut stmt = conn.prepare(&sql)?;
+    let mut rows = stmt.query(params_from_iter(values))?;
+    let mut items = Vec::new();
+    let mut last = None;
+    let mut has_more = false;
+    while let Some(row) = rows.next()? {
+        if items.len() == limit as usize {
+            has_more = true;
+            break;
+        }
+        let mut item = Map::new();
+        for (i, (name, column_kind)) in selected.iter().enumerate() {
+            let value = match column_kind {
+                Text => row.get::<_, Option<String>>(i)?.map(Value::String),
+                Integer => row.get::<_, Option<i64>>(i)?.map(Value::from),
+                Bool => row.get::<_, Option<bool>>(i)?.map(Value::Bool),
+                Json => row
+                    .get::<_, Option<String>>(i)?
+                    .and_then(|s| serde_json::from_str(&s).ok()),
+            };
+            if let Some(value) = value {
+                item.insert(camel_case(name), value);
+            }
+        }
+        let id: String = row.get(0)?;
+        let sort_value: SqlValue = row.get(selected.len())?;
+        last = Some((sort_value, id));
+        items.push(Value::Object(item));
+    }
+
+    Ok(QueryPage {
+        next_cursor: last
+            .filter(|_| has_more)
+            .map(|(value, id)| encode_cursor(value, id)),
+        items,
+    })
+}
+
+fn camel_case(column: &str) -> String {
+    let mut result = String::with_capacity(column.len());
+    let mut upper = false;
+    for c in column.chars() {
+        if c == '_' {
+            upper = true;
+        } else if upper {
+            result.extend(c.to_uppercase());
+            upper = false;
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+fn encode_cursor(value: SqlValue, id: String) -> String {
+    let value = match value {
+        SqlValue::Integer(i) => Value::from(i),
+        SqlValue::Text(s) => Value::String(s),
+        _ => Value::Null,
+    };
+    Value::Array(vec![value, Value::String(id)]).to_string()
+}
+
+fn decode_cursor(cursor: &str, kind: ColumnKind) -> Result<(SqlValue, String), QueryError> {
+    let parsed: Option<(Value, String)> = serde_json::from_str(cursor).ok();
+    match (parsed, kind) {
+        (Some((Value::String(s), id)), Text) => Ok((SqlValue::Text(s), id)),
+        (Some((Value::Number(n), id)), Integer | Bool) => n
+            .as_i64()
+            .map(|i| (SqlValue::Integer(i), id))
+            .ok_or(QueryError::InvalidCursor),
+        _ => Err(QueryError::InvalidCursor),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database;
+    use crate::database::test_support::project_db;
+    use serde_json::json;
+
+    #[test]
+    fn test_query_selects_filters_and_pages() {
+        let conn = project_db();
+        conn.execute_batch(
+            r#"INSERT INTO projects (id, title) VALUES ('p2', 'Other');
+               INSERT INTO chapters (id, project_id, title, content, status, number) VALUES
+                   ('c3', 'p1', 'Three', '<p>long</p>', 'draft', 3),
+                   ('c1', 'p1', 'One', '<p>long</p>', 'final', 1),
+                   ('c2', 'p1', 'Two', '<p>long</p>', 'draft', 2),
+                   ('c4', 'p1', 'Four', '<p>long</p>', 'draft', NULL),
+                   ('x1', 'p2', 'Elsewhere', NULL, 'draft', 1);"#,
+        )
+        .unwrap();
+        database::delete_chapter(&conn, "c2").unwrap();
+
+        let query: EntityQuery = serde_json::from_value(json!({
+            "fields": ["title", "number"],
+            "filters": [{ "field": "status", "op": "eq", "value": "draft" }],
+            "limit": 1,
+        }))
+        .unwrap();
+        let first = query_entities(&conn, "p1", EntityKind::Chapter, &query).unwrap();
+        assert_eq!(first.items, [json!({ "id": "c4", "title": "Four" })]);
+
+        let next = EntityQuery {
+            cursor: first.next_cursor,
+            ..query.clone()
+        };
+        let second = query_entities(&conn, "p1", EntityKind::Chapter, &next).unwrap();
+        assert_eq!(
+            second.items,
+            [json!({ "id": "c3", "title": "Three", "number": 3 })]
+        );
+        assert_eq!(second.next_cursor, None);
+
+        let by_title: EntityQuery = serde_json::from_value(json!({
+            "fields": ["title"],
+            "sort": { "field": "title", "descending": true },
+        }))
+        .unwrap();
+        let titles: Vec<Value> = query_entities(&conn, "p1", EntityKind::Chapter, &by_title)
+            .unwrap()
+            .items
+            .iter()
+            .map(|item| item["title"].clone())
+            .collect();
+        assert_eq!(titles, [json!("Three"), json!("One"), json!("Four")]);
+
+        let bad: EntityQuery = serde_json::from_value(json!({
+            "filters": [{ "field": "content_mode", "op": "eq", "value": "scenes" }],
+        }))
+        .unwrap();
+        assert!(matches!(
+            query_entities(&conn, "p1", EntityKind::Chapter, &bad),
+            Err(QueryError::UnknownField { .. })
+        ));
+    }
+}
@@ -0,0 +1,513 @@
+//! Chapter revision history
+//!
+//! Saving a chapter records a snapshot of its content in
+//! `chapter_revisions`. Automatic snapshots are deduplicated by content hash
+//! and throttled, so a burst of autosaves collapses into one revision unless
+//! the text changed substantially. Manual snapshots and the safety snapshot
+//! taken before a restore bypass the throttle.
+
+use super::mentions;
+use super::models::{Chapter, ContentMode, EntityKind};
+use super::operations::{get_chapter, with_savepoint};
+use super::text::{self, DiffOp, DiffSegment};
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use thiserror::Error;
+
+/// Minimum time between two automatic revisions of a chapter
+const AUTO_REVISION_INTERVAL_MINUTES: i64 = 10;
+
+/// Word count change that forces an automatic revision inside the interval
+const AUTO_REVISION_WORD_DELTA: i64 = 200;
+
+/// Automatic revisions kept per chapter; older ones are pruned
+const MAX_AUTO_REVISIONS: usize = 100;
+
+#[derive(Debug, Error)]
+pub enum RevisionError {
+    #[error("Chapter {0} is compiled from scenes; switch it to document mode first")]
+    ScenesMode(String),
+
+    #[error("Database error: {0}")]
+    Database(#[from] rusqlite::Error),
+}
+
+/// What triggered a revision
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RevisionSource {
+    /// Regular chapter save
+    Auto,
+    /// Explicit snapshot requested by the user
+    Manual,
+    /// Content replaced by a restore
+    Restore,
+}
+
+impl RevisionSource {
+    fn as_str(&self) -> &'static str {
+        match self {
+            RevisionSource::Auto => "auto",
+            RevisionSource::Manual => "manual",
+            RevisionSource::Restore => "restore",
+        }
+    }
+
+    fn from_db(value: &str) -> Self {
+        match value {
+            "manual" => RevisionSource::Manual,
+            "restore" => RevisionSource::Restore,
+            _ => RevisionSource::Auto,
+        }
+    }
+}
+
+/// Revision metadata, as listed in the history
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RevisionInfo {
+    pub id: String,
+    pub chapter_id: String,
+    pub project_id: String,
+    pub content_hash: String,
+    pub word_count: u32,
+    pub source: RevisionSource,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    pub created_at: String,
+}
+
+/// A full revision, including the chapter content
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChapterRevision {
+    #[serde(flatten)]
+    pub info: RevisionInfo,
+    pub content: String,
+}
+
+/// Word-level comparison between two versions of a chapter
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RevisionDiff {
+    pub from_revision_id: String,
+    /// None when compared against the chapter's current content
+    pub to_revision_id: Option<String>,
+    pub words_added: u32,
+    pub words_removed: u32,
+    pub segments: Vec<DiffSegment>,
+}
+
+const INFO_COLUMNS: &str =
+    "id, chapter_id, project_id, content_hash, word_count, source, label, created_at";
+
+fn row_to_info(row: &rusqlite::Row) -> Result<RevisionInfo> {
+    let source: String = row.get(5)?;
+    Ok(RevisionInfo {
+        id: row.get(0)?,
+        chapter_id: row.get(1)?,
+        project_id: row.get(2)?,
+        content_hash: row.get(3)?,
+        word_count: row.get(4)?,
+        source: RevisionSource::from_db(&source),
+        label: row.get(6)?,
+        created_at: row.get(7)?,
+    })
+}
+
+pub(super) fn content_hash(content: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(content.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+pub(super) fn now_stamp() -> String {
+    chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Micros, true)
+}
+
+/// Snapshot a chapter's current content.
+///
+/// Returns the new revision, or None when nothing was recorded: the content
+/// matches the latest revision, or (for automatic snapshots) the latest one
+/// is recent and the word count barely moved.
+pub fn capture_revision(
+    conn: &Connection,
+    chapter_id: &str,
+    source: RevisionSource,
+    label: Option<&str>,
+) -> Result<Option<RevisionInfo>> {
+    let Some((project_id, content)) = conn
+        .query_row(
+            "SELECT project_id, COALESCE(content, '') FROM chapters WHERE id = ?1",
+            params![chapter_id],
+            |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)),
+        )
+        .optional()?
+    else {
+        return Ok(None);
+    };
+
+    let hash = content_hash(&content);
+    let word_count = text::count_words(&text::strip_html(&content));
+    let latest = latest_revision(conn, chapter_id)?;
+
+    if let Some(latest) = &latest {
+        if latest.content_hash == hash {
+            return Ok(None);
+        }
+        if source == RevisionSource::Auto && is_recent(&latest.created_at) {
+            let delta = (word_count as i64 - latest.word_count as i64).abs();
+            if delta < AUTO_REVISION_WORD_DELTA {
+                return Ok(None);
+            }
+        }
+    }
+    if latest.is_none() && content.trim().is_empty() {
+        return Ok(None);
+    }
+
+    let info = RevisionInfo {
+        id: uuid::Uuid::new_v4().to_string(),
+        chapter_id: chapter_id.to_string(),
+        project_id,
+        content_hash: hash,
+        word_count,
+        source,
+        label: label.map(str::to_string),
+        created_at: now_stamp(),
+    };
+    insert_revision(
+        conn,
+        &ChapterRevision {
+            info: info.clone(),
+            content,
+        },
+    )?;
+
+    if source == RevisionSource::Auto {
+        prune_auto_revisions(conn, chapter_id)?;
+    }
+    Ok(Some(info))
+}
+
+fn is_recent(created_at: &str) -> bool {
+    chrono::DateTime::parse_from_rfc3339(created_at)
+        .map(|at| {
+            chrono::Utc::now().signed_duration_since(at)
+                < chrono::Duration::minutes(AUTO_REVISION_INTERVAL_MINUTES)
+        })
+        .unwrap_or(false)
+}
+
+/// Store a revision as-is, ignoring ids that already exist. Used when
+/// importing history from a project folder.
+pub fn insert_revision(conn: &Connection, revision: &ChapterRevision) -> Result<bool> {
+    let info = &revision.info;
+    let inserted = conn.execute(
+        r#"INSERT OR IGNORE INTO chapter_revisions
+           (id, chapter_id, project_id, content, content_hash, word_count, source, label, created_at)
+           VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)"#,
+        params![
+            info.id,
+            info.chapter_id,
+            info.project_id,
+            revision.content,
+            info.content_hash,
+            info.word_count,
+            info.source.as_str(),
+            info.label,
+            info.created_at,
+        ],
+    )?;
+    Ok(inserted > 0)
+}
+
+/// Merge history read from a project folder into a project. Revisions of
+/// chapters the project doesn't have are skipped, and automatic ones beyond
+/// the retention limit are pruned again afterwards.
+pub fn import_revisions(
+    conn: &Connection,
+    project_id: &str,
+    revisions: &[ChapterRevision],
+) -> Result<()> {
+    let mut touched = HashSet::new();
+    for revision in revisions {
+        let chapter_id = &revision.info.chapter_id;
+        let known: bool = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM chapters WHERE id = ?1 AND project_id = ?2)",
+            params![chapter_id, project_id],
+            |row| row.get(0),
+        )?;
+        if revision.info.project_id == project_id && known && insert_revision(conn, revision)? {
+            touched.insert(chapter_id.as_str());
+        }
+    }
+    for chapter_id in touched {
+        prune_auto_revisions(conn, chapter_id)?;
+    }
+    Ok(())
+}
+
+fn prune_auto_revisions(conn: &Connection, chapter_id: &str) -> Result<()> {
+    conn.execute(
+        r#"DELETE FROM chapter_revisions WHERE id IN (
+               SELECT id FROM chapter_revisions
+               WHERE chapter_id = ?1 AND source = 'auto'
+               ORDER BY created_at DESC LIMIT -1 OFFSET ?2
+           )"#,
+        params![chapter_id, MAX_AUTO_REVISIONS],
+    )?;
+    Ok(())
+}
+
+fn latest_revision(conn: &Connection, chapter_id: &str) -> Result<Option<RevisionInfo>> {
+    conn.query_row(
+        &format!(
+            "SELECT {} FROM chapter_revisions WHERE chapter_id = ?1 ORDER BY created_at DESC LIMIT 1",
+            INFO_COLUMNS
+        ),
+        params![chapter_id],
+        row_to_info,
+    )
+    .optional()
+}
+
+/// Revisions of a chapter, newest first
+pub fn list_revisions(conn: &Connection, chapter_id: &str) -> Result<Vec<RevisionInfo>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM chapter_revisions WHERE chapter_id = ?1 ORDER BY created_at DESC",
+        INFO_COLUMNS
+    ))?;
+    let rows = stmt.query_map(params![chapter_id], row_to_info)?;
+    rows.collect()
+}
+
+/// Revisions of every chapter in a project
+pub fn list_project_revisions(conn: &Connection, project_id: &str) -> Result<Vec<RevisionInfo>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM chapter_revisions WHERE project_id = ?1 ORDER BY chapter_id, created_at",
+        INFO_COLUMNS
+    ))?;
+    let rows = stmt.query_map(params![project_id], row_to_info)?;
+    rows.collect()
+}
+
+pub fn get_revision(conn: &Connection, id: &str) -> Result<Option<ChapterRevision>> {
+    conn.query_row(
+        &format!(
+            "SELECT {}, content FROM chapter_revisions WHERE id = ?1",
+            INFO_COLUMNS
+        ),
+        params![id],
+        |row| {
+            Ok(ChapterRevision {
+                info: row_to_info(row)?,
+                content: row.get(8)?,
+            })
+        },
+    )
+    .optional()
+}
+
+/// Diff two revisions of the same chapter, or a revision against the
+/// chapter's current content when `to_id` is None
+pub fn diff_revisions(
+    conn: &Connection,
+    from_id: &str,
+    to_id: Option<&str>,
+) -> Result<Option<RevisionDiff>> {
+    let Some(from) = get_revision(conn, from_id)? else {
+        return Ok(None);
+    };
+    let to_content = match to_id {
+        Some(to_id) => match get_revision(conn, to_id)? {
+            Some(to) => to.content,
+            None => return Ok(None),
+        },
+        None => {
+            match conn
+                .query_row(
+                    "SELECT COALESCE(content, '') FROM chapters WHERE id = ?1",
+                    params![from.info.chapter_id],
+                    |row| row.get::<_, String>(0),
+                )
+                .optional()?
+            {
+                Some(content) => content,
+                None => return Ok(None),
+            }
+        }
+    };
+
+    let segments = text::diff_words(
+        &text::strip_html(&from.content),
+        &text::strip_html(&to_content),
+    );
+    let count = |op: DiffOp| {
+        segments
+            .iter()
+            .filter(|s| s.op == op)
+            .map(|s| s.word_count)
+            .sum()
+    };
+
+    Ok(Some(RevisionDiff {
+        from_revision_id: from.info.id,
+        to_revision_id: to_id.map(str::to_string),
+        words_added: count(DiffOp::Insert),
+        words_removed: count(DiffOp::Delete),
+        segments,
+    }))
+}
+
+/// Put a revision's content back into its chapter. The current content is
+/// snapshotted first, so a restore can itself be undone. Chapters compiled
+/// from scenes are refused, since their next recompile would overwrite it.
+pub fn restore_revision(
+    conn: &Connection,
+    id: &str,
+) -> std::result::Result<Option<Chapter>, RevisionError> {
+    let Some(revision) = get_revision(conn, id)? else {
+        return Ok(None);
+    };
+    let chapter_id = revision.info.chapter_id.clone();
+    if get_chapter(conn, &chapter_id)?.is_some_and(|c| c.content_mode == ContentMode::Scenes) {
+        return Err(RevisionError::ScenesMode(chapter_id));
+    }
+
+    with_savepoint(conn, || {
+        capture_revision(
+            conn,
+            &chapter_id,
+            RevisionSource::Restore,
+            Some("Before restore"),
+        )?;
+        conn.execute(
+            r#"UPDATE chapters SET content = ?2, word_count = ?3, updated_at = CURRENT_TIMESTAMP
+               WHERE id = ?1"#,
+            params![chapter_id, revision.content, revision.info.word_count],
+        )?;
+        mentions::index_source(conn, EntityKind::Chapter, &chapter_id)
+    })?;
+
+    Ok(get_chapter(conn, &chapter_id)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database;
+    use crate::database::test_support::project_db;
+    use serde_json::json;
+
+    fn chapter(content: &str) -> Chapter {
+        serde_json::from_value(json!({
+            "id": "c1",
+            "projectId": "p1",
+            "title": "Arrival",
+            "content": content,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_autosaves_are_deduplicated_and_throttled() {
+        let conn = project_db();
+        database::create_chapter(&conn, &chapter("<p>The gate was shut.</p>")).unwrap();
+        assert_eq!(list_revisions(&conn, "c1").unwrap().len(), 1);
+
+        // Same content, then a small edit inside the interval: no new revision
+        database::update_chapter(&conn, &chapter("<p>The gate was shut.</p>")).unwrap();
+        database::update_chapter(&conn, &chapter("<p>The gate was closed.</p>")).unwrap();
+        assert_eq!(list_revisions(&conn, "c1").unwrap().len(), 1);
+
+        // Manual snapshots always land unless the content is identical
+        let manual = capture_revision(&conn, "c1", RevisionSource::Manual, Some("Draft 1"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(manual.label.as_deref(), Some("Draft 1"));
+        assert!(capture_revision(&conn, "c1", RevisionSource::Manual, None)
+            .unwrap()
+            .is_none());
+
+        // Outside the interval, any change is recorded
+        conn.execute_batch(
+            "UPDATE chapter_revisions SET created_at = '2000-01-01T00:00:00.000000Z'",
+        )
+        .unwrap();
+        database::update_chapter(&conn, &chapter("<p>The gate was open.</p>")).unwrap();
+        assert_eq!(list_revisions(&conn, "c1").unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_diff_and_restore() {
+        let conn = project_db();
+        database::create_chapter(&conn, &chapter("<p>The old gate was shut.</p>")).unwrap();
+        let first = list_revisions(&conn, "c1").unwrap().remove(0);
+
+        database::update_chapter(&conn, &chapter("<p>The gate was wide open.</p>")).unwrap();
+        let diff = diff_revisions(&conn, &first.id, None).unwrap().unwrap();
+        assert_eq!(diff.words_removed, 2);
+        assert_eq!(diff.words_added, 2);
+        let ops: Vec<(DiffOp, &str)> = diff
+            .segments
+            .iter()
+            .map(|s| (s.op, s.text.as_str()))
+            .collect();
+        assert_eq!(
+            ops,
+            vec![
+                (DiffOp::Equal, "The"),
+                (DiffOp::Delete, "old"),
+                (DiffOp::Equal, "gate was"),
+                (DiffOp::Delete, "shut."),
+                (DiffOp::Insert, "wide open."),
+            ]
+        );
+
+        let restored = restore_revision(&conn, &first.id).unwrap().unwrap();
+        assert_eq!(restored.content, "<p>The old gate was shut.</p>");
+
+        // The replaced content was kept as a restore snapshot
+        let revisions = list_revisions(&conn, "c1").unwrap();
+        assert_eq!(revisions.len(), 2);
+        assert_eq!(revisions[0].source, RevisionSource::Restore);
+        let kept = get_revision(&conn, &revisions[0].id).unwrap().unwrap();
+        assert_eq!(kept.content, "<p>The gate was wide open.</p>");
+
+        // Once the chapter is compiled from scenes, restoring is refused
+        database::split_chapter_into_scenes(&conn, "c1").unwrap();
+        assert!(matches!(
+            restore_revision(&conn, &first.id),
+            Err(RevisionError::ScenesMode(_))
+        ));
+    }
+
+    #[test]
+    fn test_import_merges_folder_history() {
+        let conn = project_db();
+        database::create_chapter(&conn, &chapter("<p>The gate was shut.</p>")).unwrap();
+        let known = get_revision(&conn, &list_revisions(&conn, "c1").unwrap()[0].id)
+            .unwrap()
+            .unwrap();
+
+        let mut older = known.clone();
+        older.info.id = "r-old".to_string();
+        older.info.created_at = "2000-01-01T00:00:00.000000Z".to_string();
+        let mut stray = known.clone();
+        stray.info.id = "r-stray".to_string();
+        stray.info.chapter_id = "missing".to_string();
+        import_revisions(&conn, "p1", &[known, older, stray]).unwrap();
+
+        let ids: Vec<String> = list_revisions(&conn, "c1")
+            .unwrap()
+            .into_iter()
+            .map(|r| r.id)
+            .collect();
+        assert_eq!(ids.len(), 2);
+        assert!(ids.contains(&"r-old".to_string()));
+        assert!(get_revision(&conn, "r-stray").unwrap().is_none());
+    }
+}
@@ -1,13 +1,780 @@
 //! Database schema initialization
+//!
+//! The schema is defined as an ordered list of migrations. Never edit a
+//! migration that has shipped; append a new one instead. Data steps carry
+//! their own SQL rather than calling the live operations, whose column lists
+//! follow the current schema.
 
-use rusqlite::{Connection, Result};
+use super::audit;
+use super::mentions;
+use super::migrations::{
+    add_column_if_missing, rebuild_table, run_migrations, Migration, MigrationError,
+    MigrationStep,
+};
+use super::models::{Creature, Npc, WorldRule};
+use super::revisions;
+use super::text;
+use super::undo;
+use rusqlite::{params, Connection, Transaction};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
 
-/// Initialize the database with all required tables
-pub fn init_database(conn: &Connection) -> Result<()> {
-    conn.execute_batch(
-        r#"
-        -- Projects table
-        CREATE TABLE IF NOT EXISTS projects (
+/// All schema migrations, in order
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "initial_schema",
+        steps: &[MigrationStep::Sql(INITIAL_SCHEMA)],
+    },
+    Migration {
+        version: 2,
+        name: "legacy_columns",
+        steps: &[MigrationStep::Rust(add_legacy_columns)],
+    },
+    Migration {
+        version: 3,
+        name: "search_index",
+        steps: &[MigrationStep::Sql(SEARCH_INDEX)],
+    },
+    Migration {
+        version: 4,
+        name: "soft_delete",
+        steps: &[MigrationStep::Sql(SOFT_DELETE)],
+    },
+    Migration {
+        version: 5,
+        name: "chapter_revisions",
+        steps: &[
+            MigrationStep::Sql(CHAPTER_REVISIONS),
+            MigrationStep::Rust(seed_chapter_revisions),
+        ],
+    },
+    Migration {
+        version: 6,
+        name: "rpg_entities",
+        steps: &[
+            MigrationStep::Sql(RPG_ENTITIES),
+            MigrationStep::Rust(move_rpg_entities),
+        ],
+    },
+    Migration {
+        version: 7,
+        name: "scene_content",
+        steps: &[MigrationStep::Sql(SCENE_CONTENT)],
+    },
+    Migration {
+        version: 8,
+        name: "mentions",
+        steps: &[
+            MigrationStep::Sql(MENTIONS),
+            MigrationStep::Rust(index_mentions),
+        ],
+    },
+    Migration {
+        version: 9,
+        name: "custom_fields",
+        steps: &[MigrationStep::Sql(CUSTOM_FIELDS)],
+    },
+    Migration {
+        version: 10,
+        name: "tags",
+        steps: &[
+            MigrationStep::Sql(TAGS),
+            MigrationStep::Rust(move_timeline_tags),
+        ],
+    },
+    Migration {
+        version: 11,
+        name: "project_settings",
+        steps: &[MigrationStep::Sql(PROJECT_SETTINGS)],
+    },
+    Migration {
+        version: 12,
+        name: "audit_log",
+        steps: &[
+            MigrationStep::Sql(AUDIT_LOG),
+            MigrationStep::Rust(install_audit_triggers),
+        ],
+    },
+    Migration {
+        version: 13,
+        name: "undo_journal",
+        steps: &[
+            MigrationStep::Sql(UNDO_JOURNAL),
+            MigrationStep::Rust(install_undo_triggers),
+        ],
+    },
+    Migration {
+        version: 14,
+        name: "undo_tags_and_fields",
+        steps: &[MigrationStep::Rust(install_undo_triggers)],
+    },
+    Migration {
+        version: 15,
+        name: "audit_coalesce",
+        steps: &[MigrationStep::Rust(install_audit_triggers)],
+    },
+    Migration {
+        version: 16,
+        name: "audit_append_only",
+        steps: &[MigrationStep::Rust(install_audit_triggers)],
+    },
+];
+
+/// Initialize the database, applying any pending migrations
+pub fn init_database(conn: &Connection) -> Result<(), MigrationError> {
+    let version = run_migrations(conn, MIGRATIONS)?;
+    log::info!("Database schema initialized successfully (version {})", version);
+    Ok(())
+}
+
+const INITIAL_SCHEMA: &str = r#"
+    -- Projects table
+    CREATE TABLE IF NOT EXISTS projects (
+        id TEXT PRIMARY KEY,
+        title TEXT NOT NULL,
+        author TEXT,
+        description TEXT,
+        genre TEXT,
+        is_rpg_mode_enabled INTEGER DEFAULT 0,
+        rpg_system TEXT,
+        active_identity_package TEXT,
+        origin_package_id TEXT,
+        banners TEXT, -- JSON
+        api_keys TEXT, -- JSON (encrypted)
+        creatures TEXT, -- JSON
+        world_rules TEXT, -- JSON
+        npcs TEXT, -- JSON
+        created_at TEXT DEFAULT CURRENT_TIMESTAMP,
+        updated_at TEXT DEFAULT CURRENT_TIMESTAMP
+    );
+
+    -- Chapters table
+    CREATE TABLE IF NOT EXISTS chapters (
+        id TEXT PRIMARY KEY,
+        project_id TEXT NOT NULL,
+        title TEXT NOT NULL,
+        content TEXT,
+        status TEXT DEFAULT 'draft',
+        word_count INTEGER DEFAULT 0,
+        summary TEXT,
+        number INTEGER,
+        image TEXT,
+        image_type TEXT,
+        created_at TEXT DEFAULT CURRENT_TIMESTAMP,
+        updated_at TEXT DEFAULT CURRENT_TIMESTAMP,
+        FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE
+    );
+
+    -- Scenes table
+    CREATE TABLE IF NOT EXISTS scenes (
+        id TEXT PRIMARY KEY,
+        chapter_id TEXT NOT NULL,
+        title TEXT NOT NULL,
+        character_ids TEXT, -- JSON array
+        location_id TEXT,
+        timeline_position INTEGER DEFAULT 0,
+        description TEXT,
+        notes TEXT,
+        image TEXT,
+        image_type TEXT,
+        created_at TEXT DEFAULT CURRENT_TIMESTAMP,
+        updated_at TEXT DEFAULT CURRENT_TIMESTAMP,
+        FOREIGN KEY (chapter_id) REFERENCES chapters(id) ON DELETE CASCADE
+    );
+
+    -- Characters table
+    CREATE TABLE IF NOT EXISTS characters (
+        id TEXT PRIMARY KEY,
+        project_id TEXT NOT NULL,
+        origin_package_id TEXT,
+        name TEXT NOT NULL,
+        role TEXT DEFAULT 'secondary',
+        avatar_url TEXT,
+        physical_description TEXT,
+        personality TEXT,
+        history TEXT,
+        notes TEXT,
+        attributes TEXT, -- JSON
+        attribute_history TEXT, -- JSON array
+        vital_status_history TEXT, -- JSON array
+        current_vital_status TEXT,
+        visual_position TEXT, -- JSON {x, y}
+        created_at TEXT DEFAULT CURRENT_TIMESTAMP,
+        updated_at TEXT DEFAULT CURRENT_TIMESTAMP,
+        FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE
+    );
+
+    -- Relationships table
+    CREATE TABLE IF NOT EXISTS relationships (
+        id TEXT PRIMARY KEY,
+        character_id TEXT NOT NULL,
+        target_character_id TEXT NOT NULL,
+        current_type TEXT,
+        current_status TEXT,
+        current_description TEXT,
+        is_secret INTEGER DEFAULT 0,
+        history TEXT, -- JSON array
+        created_at TEXT DEFAULT CURRENT_TIMESTAMP,
+        updated_at TEXT DEFAULT CURRENT_TIMESTAMP,
+        FOREIGN KEY (character_id) REFERENCES characters(id) ON DELETE CASCADE,
+        FOREIGN KEY (target_character_id) REFERENCES characters(id) ON DELETE CASCADE
+    );
+
+    -- Locations table
+    CREATE TABLE IF NOT EXISTS locations (
+        id TEXT PRIMARY KEY,
+        project_id TEXT NOT NULL,
+        name TEXT NOT NULL,
+        image_url TEXT,
+        type TEXT,
+        description TEXT,
+        significance TEXT,
+        notes TEXT,
+        gallery TEXT, -- JSON array
+        plans TEXT, -- JSON array
+        connections TEXT, -- JSON array
+        visual_position TEXT, -- JSON {x, y}
+        created_at TEXT DEFAULT CURRENT_TIMESTAMP,
+        updated_at TEXT DEFAULT CURRENT_TIMESTAMP,
+        FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE
+    );
+
+    -- Lore items table
+    CREATE TABLE IF NOT EXISTS lore_items (
+        id TEXT PRIMARY KEY,
+        project_id TEXT NOT NULL,
+        origin_package_id TEXT,
+        title TEXT NOT NULL,
+        category TEXT,
+        content TEXT,
+        summary TEXT,
+        related_entity_ids TEXT, -- JSON array
+        created_at TEXT DEFAULT CURRENT_TIMESTAMP,
+        updated_at TEXT DEFAULT CURRENT_TIMESTAMP,
+        FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE
+    );
+
+    -- Timeline events table
+    CREATE TABLE IF NOT EXISTS timeline_events (
+        id TEXT PRIMARY KEY,
+        project_id TEXT NOT NULL,
+        title TEXT NOT NULL,
+        description TEXT,
+        date_mode TEXT DEFAULT 'absolute',
+        date TEXT,
+        era TEXT,
+        participants TEXT, -- JSON array of character IDs
+        location_id TEXT,
+        importance TEXT DEFAULT 'medium',
+        tags TEXT, -- JSON array
+        scene_id TEXT,
+        chapter_id TEXT,
+        created_at TEXT DEFAULT CURRENT_TIMESTAMP,
+        updated_at TEXT DEFAULT CURRENT_TIMESTAMP,
+        FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE
+    );
+
+    -- Create indexes for common queries
+    CREATE INDEX IF NOT EXISTS idx_chapters_project ON chapters(project_id);
+    CREATE INDEX IF NOT EXISTS idx_scenes_chapter ON scenes(chapter_id);
+    CREATE INDEX IF NOT EXISTS idx_characters_project ON characters(project_id);
+    CREATE INDEX IF NOT EXISTS idx_relationships_character ON relationships(character_id);
+    CREATE INDEX IF NOT EXISTS idx_locations_project ON locations(project_id);
+    CREATE INDEX IF NOT EXISTS idx_lore_items_project ON lore_items(project_id);
+    CREATE INDEX IF NOT EXISTS idx_timeline_events_project ON timeline_events(project_id);
+
+    -- App Settings table (Key-Value store for global preferences)
+    CREATE TABLE IF NOT EXISTS app_settings (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );
+
+    -- Installed packages table (Package Store)
+    CREATE TABLE IF NOT EXISTS installed_packages (
+        id TEXT PRIMARY KEY,
+        version TEXT NOT NULL,
+        author TEXT NOT NULL,
+        category TEXT NOT NULL,
+        registry_id TEXT NOT NULL,
+        metadata TEXT NOT NULL,
+        checksum TEXT NOT NULL,
+        size_bytes INTEGER DEFAULT 0,
+        install_path TEXT NOT NULL,
+        installed_at TEXT DEFAULT CURRENT_TIMESTAMP,
+        updated_at TEXT DEFAULT CURRENT_TIMESTAMP
+    );
+"#;
+
+/// Columns that older builds added ad hoc with `ALTER TABLE`. Databases
+/// created before versioned migrations may already have some of them.
+fn add_legacy_columns(tx: &Transaction) -> rusqlite::Result<()> {
+    let columns = [
+        ("projects", "active_identity_package", "TEXT"),
+        ("projects", "origin_package_id", "TEXT"),
+        ("projects", "creatures", "TEXT"),
+        ("projects", "world_rules", "TEXT"),
+        ("projects", "project_type", "TEXT DEFAULT 'novel'"),
+        ("characters", "origin_package_id", "TEXT"),
+        ("lore_items", "origin_package_id", "TEXT"),
+        ("projects", "npcs", "TEXT"),
+    ];
+
+    for (table, column, definition) in columns {
+        add_column_if_missing(tx, table, column, definition)?;
+    }
+    Ok(())
+}
+
+/// Full-text index over every searchable entity, kept current by triggers.
+/// Chapter and lore content is HTML, so it goes through `strip_html`, which
+/// is registered on each connection by `configure_connection`.
+const SEARCH_INDEX: &str = r#"
+CREATE VIRTUAL TABLE search_index USING fts5(
+    entity_type UNINDEXED,
+    entity_id UNINDEXED,
+    project_id UNINDEXED,
+    title,
+    body,
+    tokenize = 'unicode61 remove_diacritics 2'
+);
+
+-- Chapters
+CREATE TRIGGER search_chapters_insert AFTER INSERT ON chapters BEGIN
+    INSERT INTO search_index (entity_type, entity_id, project_id, title, body)
+    VALUES ('chapter', new.id, new.project_id, new.title,
+            COALESCE(strip_html(new.content), '') || char(10) || COALESCE(new.summary, ''));
+END;
+CREATE TRIGGER search_chapters_update AFTER UPDATE ON chapters BEGIN
+    DELETE FROM search_index WHERE entity_type = 'chapter' AND entity_id = old.id;
+    INSERT INTO search_index (entity_type, entity_id, project_id, title, body)
+    VALUES ('chapter', new.id, new.project_id, new.title,
+            COALESCE(strip_html(new.content), '') || char(10) || COALESCE(new.summary, ''));
+END;
+CREATE TRIGGER search_chapters_delete AFTER DELETE ON chapters BEGIN
+    DELETE FROM search_index WHERE entity_type = 'chapter' AND entity_id = old.id;
+END;
+
+-- Scenes (project comes from the parent chapter)
+CREATE TRIGGER search_scenes_insert AFTER INSERT ON scenes BEGIN
+    INSERT INTO search_index (entity_type, entity_id, project_id, title, body)
+    VALUES ('scene', new.id, (SELECT project_id FROM chapters WHERE id = new.chapter_id), new.title,
+            COALESCE(new.description, '') || char(10) || COALESCE(new.notes, ''));
+END;
+CREATE TRIGGER search_scenes_update AFTER UPDATE ON scenes BEGIN
+    DELETE FROM search_index WHERE entity_type = 'scene' AND entity_id = old.id;
+    INSERT INTO search_index (entity_type, entity_id, project_id, title, body)
+    VALUES ('scene', new.id, (SELECT project_id FROM chapters WHERE id = new.chapter_id), new.title,
+            COALESCE(new.description, '') || char(10) || COALESCE(new.notes, ''));
+END;
+CREATE TRIGGER search_scenes_delete AFTER DELETE ON scenes BEGIN
+    DELETE FROM search_index WHERE entity_type = 'scene' AND entity_id = old.id;
+END;
+
+-- Characters
+CREATE TRIGGER search_characters_insert AFTER INSERT ON characters BEGIN
+    INSERT INTO search_index (entity_type, entity_id, project_id, title, body)
+    VALUES ('character', new.id, new.project_id, new.name,
+            COALESCE(new.personality, '') || char(10) || COALESCE(new.history, '') || char(10) || COALESCE(new.notes, ''));
+END;
+CREATE TRIGGER search_characters_update AFTER UPDATE ON characters BEGIN
+    DELETE FROM search_index WHERE entity_type = 'character' AND entity_id = old.id;
+    INSERT INTO search_index (entity_type, entity_id, project_id, title, body)
+    VALUES ('character', new.id, new.project_id, new.name,
+            COALESCE(new.personality, '') || char(10) || COALESCE(new.history, '') || char(10) || COALESCE(new.notes, ''));
+END;
+CREATE TRIGGER search_characters_delete AFTER DELETE ON characters BEGIN
+    DELETE FROM search_index WHERE entity_type = 'character' AND entity_id = old.id;
+END;
+
+-- Locations
+CREATE TRIGGER search_locations_insert AFTER INSERT ON locations BEGIN
+    INSERT INTO search_index (entity_type, entity_id, project_id, title, body)
+    VALUES ('location', new.id, new.project_id, new.name,
+            COALESCE(new.type, '') || char(10) || COALESCE(new.description, '') || char(10) || COALESCE(new.significance, '') || char(10) || COALESCE(new.notes, ''));
+END;
+CREATE TRIGGER search_locations_update AFTER UPDATE ON locations BEGIN
+    DELETE FROM search_index WHERE entity_type = 'location' AND entity_id = old.id;
+    INSERT INTO search_index (entity_type, entity_id, project_id, title, body)
+    VALUES ('location', new.id, new.project_id, new.name,
+            COALESCE(new.type, '') || char(10) || COALESCE(new.description, '') || char(10) || COALESCE(new.significance, '') || char(10) || COALESCE(new.notes, ''));
+END;
+CREATE TRIGGER search_locations_delete AFTER DELETE ON locations BEGIN
+    DELETE FROM search_index WHERE entity_type = 'location' AND entity_id = old.id;
+END;
+
+-- Lore items
+CREATE TRIGGER search_lore_items_insert AFTER INSERT ON lore_items BEGIN
+    INSERT INTO search_index (entity_type, entity_id, project_id, title, body)
+    VALUES ('lore_item', new.id, new.project_id, new.title,
+            COALESCE(new.summary, '') || char(10) || COALESCE(strip_html(new.content), ''));
+END;
+CREATE TRIGGER search_lore_items_update AFTER UPDATE ON lore_items BEGIN
+    DELETE FROM search_index WHERE entity_type = 'lore_item' AND entity_id = old.id;
+    INSERT INTO search_index (entity_type, entity_id, project_id, title, body)
+    VALUES ('lore_item', new.id, new.project_id, new.title,
+            COALESCE(new.summary, '') || char(10) || COALESCE(strip_html(new.content), ''));
+END;
+CREATE TRIGGER search_lore_items_delete AFTER DELETE ON lore_items BEGIN
+    DELETE FROM search_index WHERE entity_type = 'lore_item' AND entity_id = old.id;
+END;
+
+-- Timeline events
+CREATE TRIGGER search_timeline_events_insert AFTER INSERT ON timeline_events BEGIN
+    INSERT INTO search_index (entity_type, entity_id, project_id, title, body)
+    VALUES ('timeline_event', new.id, new.project_id, new.title,
+            COALESCE(new.description, '') || char(10) || COALESCE(new.era, '') || char(10) || COALESCE(new.date, ''));
+END;
+CREATE TRIGGER search_timeline_events_update AFTER UPDATE ON timeline_events BEGIN
+    DELETE FROM search_index WHERE entity_type = 'timeline_event' AND entity_id = old.id;
+    INSERT INTO search_index (entity_type, entity_id, project_id, title, body)
+    VALUES ('timeline_event', new.id, new.project_id, new.title,
+            COALESCE(new.description, '') || char(10) || COALESCE(new.era, '') || char(10) || COALESCE(new.date, ''));
+END;
+CREATE TRIGGER search_timeline_events_delete AFTER DELETE ON timeline_events BEGIN
+    DELETE FROM search_index WHERE entity_type = 'timeline_event' AND entity_id = old.id;
+END;
+
+-- Index existing rows
+INSERT INTO search_index (entity_type, entity_id, project_id, title, body)
+SELECT 'chapter', id, project_id, title,
+       COALESCE(strip_html(content), '') || char(10) || COALESCE(summary, '')
+FROM chapters;
+INSERT INTO search_index (entity_type, entity_id, project_id, title, body)
+SELECT 'scene', s.id, c.project_id, s.title,
+       COALESCE(s.description, '') || char(10) || COALESCE(s.notes, '')
+FROM scenes s LEFT JOIN chapters c ON c.id = s.chapter_id;
+INSERT INTO search_index (entity_type, entity_id, project_id, title, body)
+SELECT 'character', id, project_id, name,
+       COALESCE(personality, '') || char(10) || COALESCE(history, '') || char(10) || COALESCE(notes, '')
+FROM characters;
+INSERT INTO search_index (entity_type, entity_id, project_id, title, body)
+SELECT 'location', id, project_id, name,
+       COALESCE(type, '') || char(10) || COALESCE(description, '') || char(10) || COALESCE(significance, '') || char(10) || COALESCE(notes, '')
+FROM locations;
+INSERT INTO search_index (entity_type, entity_id, project_id, title, body)
+SELECT 'lore_item', id, project_id, title,
+       COALESCE(summary, '') || char(10) || COALESCE(strip_html(content), '')
+FROM lore_items;
+INSERT INTO search_index (entity_type, entity_id, project_id, title, body)
+SELECT 'timeline_event', id, project_id, title,
+       COALESCE(description, '') || char(10) || COALESCE(era, '') || char(10) || COALESCE(date, '')
+FROM timeline_events;
+"#;
+
+/// `deleted_at` tombstones on every entity table. Trashed rows drop out of
+/// the search index; restoring them puts them back.
+const SOFT_DELETE: &str = r#"
+ALTER TABLE projects ADD COLUMN deleted_at TEXT;
+ALTER TABLE chapters ADD COLUMN deleted_at TEXT;
+ALTER TABLE scenes ADD COLUMN deleted_at TEXT;
+ALTER TABLE characters ADD COLUMN deleted_at TEXT;
+ALTER TABLE relationships ADD COLUMN deleted_at TEXT;
+ALTER TABLE locations ADD COLUMN deleted_at TEXT;
+ALTER TABLE lore_items ADD COLUMN deleted_at TEXT;
+ALTER TABLE timeline_events ADD COLUMN deleted_at TEXT;
+
+CREATE INDEX idx_relationships_target ON relationships(target_character_id);
+
+DROP TRIGGER search_chapters_update;
+CREATE TRIGGER search_chapters_update AFTER UPDATE ON chapters BEGIN
+    DELETE FROM search_index WHERE entity_type = 'chapter' AND entity_id = old.id;
+    INSERT INTO search_index (entity_type, entity_id, project_id, title, body)
+    SELECT 'chapter', new.id, new.project_id, new.title,
+           COALESCE(strip_html(new.content), '') || char(10) || COALESCE(new.summary, '')
+    WHERE new.deleted_at IS NULL;
+END;
+
+DROP TRIGGER search_scenes_update;
+CREATE TRIGGER search_scenes_update AFTER UPDATE ON scenes BEGIN
+    DELETE FROM search_index WHERE entity_type = 'scene' AND entity_id = old.id;
+    INSERT INTO search_index (entity_type, entity_id, project_id, title, body)
+    SELECT 'scene', new.id, (SELECT project_id FROM chapters WHERE id = new.chapter_id), new.title,
+           COALESCE(new.description, '') || char(10) || COALESCE(new.notes, '')
+    WHERE new.deleted_at IS NULL;
+END;
+
+DROP TRIGGER search_characters_update;
+CREATE TRIGGER search_characters_update AFTER UPDATE ON characters BEGIN
+    DELETE FROM search_index WHERE entity_type = 'character' AND entity_id = old.id;
+    INSERT INTO search_index (entity_type, entity_id, project_id, title, body)
+    SELECT 'character', new.id, new.project_id, new.name,
+           COALESCE(new.personality, '') || char(10) || COALESCE(new.history, '') || char(10) || COALESCE(new.notes, '')
+    WHERE new.deleted_at IS NULL;
+END;
+
+DROP TRIGGER search_locations_update;
+CREATE TRIGGER search_locations_update AFTER UPDATE ON locations BEGIN
+    DELETE FROM search_index WHERE entity_type = 'location' AND entity_id = old.id;
+    INSERT INTO search_index (entity_type, entity_id, project_id, title, body)
+    SELECT 'location', new.id, new.project_id, new.name,
+           COALESCE(new.type, '') || char(10) || COALESCE(new.description, '') || char(10) || COALESCE(new.significance, '') || char(10) || COALESCE(new.notes, '')
+    WHERE new.deleted_at IS NULL;
+END;
+
+DROP TRIGGER search_lore_items_update;
+CREATE TRIGGER search_lore_items_update AFTER UPDATE ON lore_items BEGIN
+    DELETE FROM search_index WHERE entity_type = 'lore_item' AND entity_id = old.id;
+    INSERT INTO search_index (entity_type, entity_id, project_id, title, body)
+    SELECT 'lore_item', new.id, new.project_id, new.title,
+           COALESCE(new.summary, '') || char(10) || COALESCE(strip_html(new.content), '')
+    WHERE new.deleted_at IS NULL;
+END;
+
+DROP TRIGGER search_timeline_events_update;
+CREATE TRIGGER search_timeline_events_update AFTER UPDATE ON timeline_events BEGIN
+    DELETE FROM search_index WHERE entity_type = 'timeline_event' AND entity_id = old.id;
+    INSERT INTO search_index (entity_type, entity_id, project_id, title, body)
+    SELECT 'timeline_event', new.id, new.project_id, new.title,
+           COALESCE(new.description, '') || char(10) || COALESCE(new.era, '') || char(10) || COALESCE(new.date, '')
+    WHERE new.deleted_at IS NULL;
+END;
+"#;
+
+const CHAPTER_REVISIONS: &str = r#"
+CREATE TABLE chapter_revisions (
+    id TEXT PRIMARY KEY,
+    chapter_id TEXT NOT NULL,
+    project_id TEXT NOT NULL,
+    content TEXT NOT NULL,
+    content_hash TEXT NOT NULL,
+    word_count INTEGER NOT NULL DEFAULT 0,
+    source TEXT NOT NULL DEFAULT 'auto', -- auto | manual | restore
+    label TEXT,
+    created_at TEXT NOT NULL,
+    FOREIGN KEY (chapter_id) REFERENCES chapters(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_chapter_revisions_chapter ON chapter_revisions(chapter_id, created_at);
+CREATE INDEX idx_chapter_revisions_project ON chapter_revisions(project_id);
+"#;
+
+/// Give existing chapters a starting point in their history
+fn seed_chapter_revisions(tx: &Transaction) -> rusqlite::Result<()> {
+    let chapters: Vec<(String, String, String)> = {
+        let mut stmt =
+            tx.prepare("SELECT id, project_id, content FROM chapters WHERE content IS NOT NULL")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+        rows.collect::<rusqlite::Result<_>>()?
+    };
+    for (chapter_id, project_id, content) in chapters {
+        if content.trim().is_empty() {
+            continue;
+        }
+        tx.execute(
+            r#"INSERT INTO chapter_revisions
+               (id, chapter_id, project_id, content, content_hash, word_count, source, created_at)
+               VALUES (?1, ?2, ?3, ?4, ?5, ?6, 'auto', ?7)"#,
+            params![
+                uuid::Uuid::new_v4().to_string(),
+                chapter_id,
+                project_id,
+                content,
+                revisions::content_hash(&content),
+                text::count_words(&text::strip_html(&content)),
+                revisions::now_stamp(),
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+/// Creatures, NPCs and world rules used to live as JSON arrays on the
+/// project row. They get their own tables, search triggers and soft delete.
+const RPG_ENTITIES: &str = r#"
+CREATE TABLE creatures (
+    id TEXT PRIMARY KEY,
+    project_id TEXT NOT NULL,
+    origin_package_id TEXT,
+    name TEXT NOT NULL,
+    type TEXT,
+    size TEXT DEFAULT 'medium',
+    description TEXT,
+    physical_description TEXT,
+    behavior TEXT,
+    habitat TEXT, -- JSON array
+    danger_level TEXT DEFAULT 'medium',
+    challenge_rating TEXT,
+    stats TEXT, -- JSON object
+    abilities TEXT, -- JSON array
+    weaknesses TEXT, -- JSON array
+    resistances TEXT, -- JSON array
+    immunities TEXT, -- JSON array
+    loot TEXT, -- JSON array
+    image_url TEXT,
+    related_location_ids TEXT, -- JSON array
+    notes TEXT,
+    created_at TEXT DEFAULT CURRENT_TIMESTAMP,
+    updated_at TEXT DEFAULT CURRENT_TIMESTAMP,
+    deleted_at TEXT,
+    FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE
+);
+
+CREATE TABLE npcs (
+    id TEXT PRIMARY KEY,
+    project_id TEXT NOT NULL,
+    origin_package_id TEXT,
+    name TEXT NOT NULL,
+    image_url TEXT,
+    description TEXT,
+    personality TEXT,
+    role TEXT,
+    faction TEXT,
+    disposition TEXT DEFAULT 'neutral',
+    importance TEXT DEFAULT 'secondary',
+    stats TEXT, -- JSON object
+    inventory TEXT, -- JSON array
+    quests TEXT, -- JSON array
+    dialogues TEXT, -- JSON array
+    secrets TEXT, -- JSON array
+    relationships TEXT, -- JSON array
+    schedule TEXT,
+    related_location_ids TEXT, -- JSON array
+    linked_character_id TEXT,
+    notes TEXT,
+    created_at TEXT DEFAULT CURRENT_TIMESTAMP,
+    updated_at TEXT DEFAULT CURRENT_TIMESTAMP,
+    deleted_at TEXT,
+    FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE
+);
+
+CREATE TABLE world_rules (
+    id TEXT PRIMARY KEY,
+    project_id TEXT NOT NULL,
+    origin_package_id TEXT,
+    title TEXT NOT NULL,
+    category TEXT DEFAULT 'custom',
+    custom_category TEXT,
+    content TEXT,
+    summary TEXT,
+    importance TEXT DEFAULT 'minor',
+    exceptions TEXT, -- JSON array
+    examples TEXT, -- JSON array
+    related_rule_ids TEXT, -- JSON array
+    related_entity_ids TEXT, -- JSON array
+    image_url TEXT,
+    is_secret INTEGER DEFAULT 0,
+    created_at TEXT DEFAULT CURRENT_TIMESTAMP,
+    updated_at TEXT DEFAULT CURRENT_TIMESTAMP,
+    deleted_at TEXT,
+    FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_creatures_project ON creatures(project_id);
+CREATE INDEX idx_npcs_project ON npcs(project_id);
+CREATE INDEX idx_world_rules_project ON world_rules(project_id);
+
+-- Creatures
+CREATE TRIGGER search_creatures_insert AFTER INSERT ON creatures BEGIN
+    INSERT INTO search_index (entity_type, entity_id, project_id, title, body)
+    SELECT 'creature', new.id, new.project_id, new.name,
+           COALESCE(new.description, '') || char(10) || COALESCE(new.physical_description, '') || char(10) ||
+           COALESCE(new.behavior, '') || char(10) || COALESCE(new.notes, '')
+    WHERE new.deleted_at IS NULL;
+END;
+CREATE TRIGGER search_creatures_update AFTER UPDATE ON creatures BEGIN
+    DELETE FROM search_index WHERE entity_type = 'creature' AND entity_id = old.id;
+    INSERT INTO search_index (entity_type, entity_id, project_id, title, body)
+    SELECT 'creature', new.id, new.project_id, new.name,
+           COALESCE(new.description, '') || char(10) || COALESCE(new.physical_description, '') || char(10) ||
+           COALESCE(new.behavior, '') || char(10) || COALESCE(new.notes, '')
+    WHERE new.deleted_at IS NULL;
+END;
+CREATE TRIGGER search_creatures_delete AFTER DELETE ON creatures BEGIN
+    DELETE FROM search_index WHERE entity_type = 'creature' AND entity_id = old.id;
+END;
+
+-- NPCs
+CREATE TRIGGER search_npcs_insert AFTER INSERT ON npcs BEGIN
+    INSERT INTO search_index (entity_type, entity_id, project_id, title, body)
+    SELECT 'npc', new.id, new.project_id, new.name,
+           COALESCE(new.role, '') || char(10) || COALESCE(new.faction, '') || char(10) ||
+           COALESCE(new.description, '') || char(10) || COALESCE(new.personality, '') || char(10) || COALESCE(new.notes, '')
+    WHERE new.deleted_at IS NULL;
+END;
+CREATE TRIGGER search_npcs_update AFTER UPDATE ON npcs BEGIN
+    DELETE FROM search_index WHERE entity_type = 'npc' AND entity_id = old.id;
+    INSERT INTO search_index (entity_type, entity_id, project_id, title, body)
+    SELECT 'npc', new.id, new.project_id, new.name,
+           COALESCE(new.role, '') || char(10) || COALESCE(new.faction, '') || char(10) ||
+           COALESCE(new.description, '') || char(10) || COALESCE(new.personality, '') || char(10) || COALESCE(new.notes, '')
+    WHERE new.deleted_at IS NULL;
+END;
+CREATE TRIGGER search_npcs_delete AFTER DELETE ON npcs BEGIN
+    DELETE FROM search_index WHERE entity_type = 'npc' AND entity_id = old.id;
+END;
+
+-- World rules
+CREATE TRIGGER search_world_rules_insert AFTER INSERT ON world_rules BEGIN
+    INSERT INTO search_index (entity_type, entity_id, project_id, title, body)
+    SELECT 'world_rule', new.id, new.project_id, new.title,
+           COALESCE(new.summary, '') || char(10) || COALESCE(strip_html(new.content), '')
+    WHERE new.deleted_at IS NULL;
+END;
+CREATE TRIGGER search_world_rules_update AFTER UPDATE ON world_rules BEGIN
+    DELETE FROM search_index WHERE entity_type = 'world_rule' AND entity_id = old.id;
+    INSERT INTO search_index (entity_type, entity_id, project_id, title, body)
+    SELECT 'world_rule', new.id, new.project_id, new.title,
+           COALESCE(new.summary, '') || char(10) || COALESCE(strip_html(new.content), '')
+    WHERE new.deleted_at IS NULL;
+END;
+CREATE TRIGGER search_world_rules_delete AFTER DELETE ON world_rules BEGIN
+    DELETE FROM search_index WHERE entity_type = 'world_rule' AND entity_id = old.id;
+END;
+"#;
+
+/// Move the embedded JSON arrays into the new tables, then drop the columns
+fn move_rpg_entities(tx: &Transaction) -> rusqlite::Result<()> {
+    let parse = |json: Option<String>| {
+        json.and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or(Value::Null)
+    };
+    let rows: Vec<(String, Value, Value, Value, Option<String>)> = {
+        let mut stmt =
+            tx.prepare("SELECT id, creatures, npcs, world_rules, deleted_at FROM projects")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get(0)?,
+                parse(row.get(1)?),
+                parse(row.get(2)?),
+                parse(row.get(3)?),
+                row.get(4)?,
+            ))
+        })?;
+        rows.collect::<rusqlite::Result<_>>()?
+    };
+
+    for (project_id, creatures, npcs, world_rules, deleted_at) in rows {
+        for creature in legacy_entities::<Creature>(&project_id, "creatures", creatures) {
+            let creature = Creature {
+                id: unused_id(tx, "creatures", creature.id)?,
+                ..creature
+            };
+            insert_creature(tx, &creature)?;
+            mark_deleted(tx, "creatures", &creature.id, deleted_at.as_deref())?;
+        }
+        for npc in legacy_entities::<Npc>(&project_id, "npcs", npcs) {
+            let npc = Npc {
+                id: unused_id(tx, "npcs", npc.id)?,
+                ..npc
+            };
+            insert_npc(tx, &npc)?;
+            mark_deleted(tx, "npcs", &npc.id, deleted_at.as_deref())?;
+        }
+        for rule in legacy_entities::<WorldRule>(&project_id, "world rules", world_rules) {
+            let rule = WorldRule {
+                id: unused_id(tx, "world_rules", rule.id)?,
+                ..rule
+            };
+            insert_world_rule(tx, &rule)?;
+            mark_deleted(tx, "world_rules", &rule.id, deleted_at.as_deref())?;
+        }
+    }
+
+    rebuild_table(
+        tx,
+        "projects",
+        r#"CREATE TABLE projects__new (
             id TEXT PRIMARY KEY,
             title TEXT NOT NULL,
             author TEXT,
@@ -17,194 +784,613 @@ pub fn init_database(conn: &Connection) -> Result<()> {
             rpg_system TEXT,
             active_identity_package TEXT,
             origin_package_id TEXT,
+            project_type TEXT DEFAULT 'novel',
             banners TEXT, -- JSON
             api_keys TEXT, -- JSON (encrypted)
-            creatures TEXT, -- JSON
-            world_rules TEXT, -- JSON
-            npcs TEXT, -- JSON
-            created_at TEXT DEFAULT CURRENT_TIMESTAMP,
-            updated_at TEXT DEFAULT CURRENT_TIMESTAMP
-        );
-
-        -- Chapters table
-        CREATE TABLE IF NOT EXISTS chapters (
-            id TEXT PRIMARY KEY,
-            project_id TEXT NOT NULL,
-            title TEXT NOT NULL,
-            content TEXT,
-            status TEXT DEFAULT 'draft',
-            word_count INTEGER DEFAULT 0,
-            summary TEXT,
-            number INTEGER,
-            image TEXT,
-            image_type TEXT,
             created_at TEXT DEFAULT CURRENT_TIMESTAMP,
             updated_at TEXT DEFAULT CURRENT_TIMESTAMP,
-            FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE
-        );
+            deleted_at TEXT
+        )"#,
+        &[
+            "id",
+            "title",
+            "author",
+            "description",
+            "genre",
+            "is_rpg_mode_enabled",
+            "rpg_system",
+            "active_identity_package",
+            "origin_package_id",
+            "project_type",
+            "banners",
+            "api_keys",
+            "created_at",
+            "updated_at",
+            "deleted_at",
+        ],
+    )
+}
 
-        -- Scenes table
-        CREATE TABLE IF NOT EXISTS scenes (
-            id TEXT PRIMARY KEY,
-            chapter_id TEXT NOT NULL,
-            title TEXT NOT NULL,
-            character_ids TEXT, -- JSON array
-            location_id TEXT,
-            timeline_position INTEGER DEFAULT 0,
-            description TEXT,
-            notes TEXT,
-            image TEXT,
-            image_type TEXT,
-            created_at TEXT DEFAULT CURRENT_TIMESTAMP,
-            updated_at TEXT DEFAULT CURRENT_TIMESTAMP,
-            FOREIGN KEY (chapter_id) REFERENCES chapters(id) ON DELETE CASCADE
-        );
+// The inserts below are frozen copies of the ones in `operations`, so
+// columns added to these tables later cannot break this migration
 
-        -- Characters table
-        CREATE TABLE IF NOT EXISTS characters (
-            id TEXT PRIMARY KEY,
-            project_id TEXT NOT NULL,
-            origin_package_id TEXT,
-            name TEXT NOT NULL,
-            role TEXT DEFAULT 'secondary',
-            avatar_url TEXT,
-            physical_description TEXT,
-            personality TEXT,
-            history TEXT,
-            notes TEXT,
-            attributes TEXT, -- JSON
-            attribute_history TEXT, -- JSON array
-            vital_status_history TEXT, -- JSON array
-            current_vital_status TEXT,
-            visual_position TEXT, -- JSON {x, y}
-            created_at TEXT DEFAULT CURRENT_TIMESTAMP,
-            updated_at TEXT DEFAULT CURRENT_TIMESTAMP,
-            FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE
-        );
+fn insert_creature(tx: &Transaction, creature: &Creature) -> rusqlite::Result<()> {
+    tx.execute(
+        r#"INSERT INTO creatures (id, project_id, origin_package_id, name, type, size, description, physical_description, behavior, habitat, danger_level, challenge_rating, stats, abilities, weaknesses, resistances, immunities, loot, image_url, related_location_ids, notes)
+           VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21)"#,
+        params![
+            creature.id,
+            creature.project_id,
+            creature.origin_package_id,
+            creature.name,
+            creature.r#type,
+            creature.size,
+            creature.description,
+            creature.physical_description,
+            creature.behavior,
+            to_json(&creature.habitat),
+            creature.danger_level,
+            creature.challenge_rating,
+            to_json(&creature.stats),
+            to_json(&creature.abilities),
+            to_json(&creature.weaknesses),
+            to_json(&creature.resistances),
+            to_json(&creature.immunities),
+            to_json(&creature.loot),
+            creature.image_url,
+            to_json(&creature.related_location_ids),
+            creature.notes,
+        ],
+    )?;
+    Ok(())
+}
 
-        -- Relationships table
-        CREATE TABLE IF NOT EXISTS relationships (
-            id TEXT PRIMARY KEY,
-            character_id TEXT NOT NULL,
-            target_character_id TEXT NOT NULL,
-            current_type TEXT,
-            current_status TEXT,
-            current_description TEXT,
-            is_secret INTEGER DEFAULT 0,
-            history TEXT, -- JSON array
-            created_at TEXT DEFAULT CURRENT_TIMESTAMP,
-            updated_at TEXT DEFAULT CURRENT_TIMESTAMP,
-            FOREIGN KEY (character_id) REFERENCES characters(id) ON DELETE CASCADE,
-            FOREIGN KEY (target_character_id) REFERENCES characters(id) ON DELETE CASCADE
-        );
+fn insert_npc(tx: &Transaction, npc: &Npc) -> rusqlite::Result<()> {
+    tx.execute(
+        r#"INSERT INTO npcs (id, project_id, origin_package_id, name, image_url, description, personality, role, faction, disposition, importance, stats, inventory, quests, dialogues, secrets, relationships, schedule, related_location_ids, linked_character_id, notes)
+           VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21)"#,
+        params![
+            npc.id,
+            npc.project_id,
+            npc.origin_package_id,
+            npc.name,
+            npc.image_url,
+            npc.description,
+            npc.personality,
+            npc.role,
+            npc.faction,
+            npc.disposition,
+            npc.importance,
+            to_json(&npc.stats),
+            to_json(&npc.inventory),
+            to_json(&npc.quests),
+            to_json(&npc.dialogues),
+            to_json(&npc.secrets),
+            to_json(&npc.relationships),
+            npc.schedule,
+            to_json(&npc.related_location_ids),
+            npc.linked_character_id,
+            npc.notes,
+        ],
+    )?;
+    Ok(())
+}
 
-        -- Locations table
-        CREATE TABLE IF NOT EXISTS locations (
-            id TEXT PRIMARY KEY,
-            project_id TEXT NOT NULL,
-            name TEXT NOT NULL,
-            image_url TEXT,
-            type TEXT,
-            description TEXT,
-            significance TEXT,
-            notes TEXT,
-            gallery TEXT, -- JSON array
-            plans TEXT, -- JSON array
-            connections TEXT, -- JSON array
-            visual_position TEXT, -- JSON {x, y}
-            created_at TEXT DEFAULT CURRENT_TIMESTAMP,
-            updated_at TEXT DEFAULT CURRENT_TIMESTAMP,
-            FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE
-        );
+fn insert_world_rule(tx: &Transaction, rule: &WorldRule) -> rusqlite::Result<()> {
+    tx.execute(
+        r#"INSERT INTO world_rules (id, project_id, origin_package_id, title, category, custom_category, content, summary, importance, exceptions, examples, related_rule_ids, related_entity_ids, image_url, is_secret)
+           VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)"#,
+        params![
+            rule.id,
+            rule.project_id,
+            rule.origin_package_id,
+            rule.title,
+            rule.category,
+            rule.custom_category,
+            rule.content,
+            rule.summary,
+            rule.importance,
+            to_json(&rule.exceptions),
+            to_json(&rule.examples),
+            to_json(&rule.related_rule_ids),
+            to_json(&rule.related_entity_ids),
+            rule.image_url,
+            rule.is_secret,
+        ],
+    )?;
+    Ok(())
+}
 
-        -- Lore items table
-        CREATE TABLE IF NOT EXISTS lore_items (
-            id TEXT PRIMARY KEY,
-            project_id TEXT NOT NULL,
-            origin_package_id TEXT,
-            title TEXT NOT NULL,
-            category TEXT,
-            content TEXT,
-            summary TEXT,
-            related_entity_ids TEXT, -- JSON array
-            created_at TEXT DEFAULT CURRENT_TIMESTAMP,
-            updated_at TEXT DEFAULT CURRENT_TIMESTAMP,
-            FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE
-        );
+fn to_json<T: serde::Serialize>(value: &T) -> String {
+    serde_json::to_string(value).unwrap_or_default()
+}
 
-        -- Timeline events table
-        CREATE TABLE IF NOT EXISTS timeline_events (
-            id TEXT PRIMARY KEY,
-            project_id TEXT NOT NULL,
-            title TEXT NOT NULL,
-            description TEXT,
-            date_mode TEXT DEFAULT 'absolute',
-            date TEXT,
-            era TEXT,
-            participants TEXT, -- JSON array of character IDs
-            location_id TEXT,
-            importance TEXT DEFAULT 'medium',
-            tags TEXT, -- JSON array
-            scene_id TEXT,
-            chapter_id TEXT,
-            created_at TEXT DEFAULT CURRENT_TIMESTAMP,
-            updated_at TEXT DEFAULT CURRENT_TIMESTAMP,
-            FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE
-        );
-
-        -- Create indexes for common queries
-        CREATE INDEX IF NOT EXISTS idx_chapters_project ON chapters(project_id);
-        CREATE INDEX IF NOT EXISTS idx_scenes_chapter ON scenes(chapter_id);
-        CREATE INDEX IF NOT EXISTS idx_characters_project ON characters(project_id);
-        CREATE INDEX IF NOT EXISTS idx_relationships_character ON relationships(character_id);
-        CREATE INDEX IF NOT EXISTS idx_locations_project ON locations(project_id);
-        CREATE INDEX IF NOT EXISTS idx_lore_items_project ON lore_items(project_id);
-        CREATE INDEX IF NOT EXISTS idx_timeline_events_project ON timeline_events(project_id);
-
-        -- App Settings table (Key-Value store for global preferences)
-        CREATE TABLE IF NOT EXISTS app_settings (
-            key TEXT PRIMARY KEY,
-            value TEXT NOT NULL
-        );
-
-        -- Installed packages table (Package Store)
-        CREATE TABLE IF NOT EXISTS installed_packages (
-            id TEXT PRIMARY KEY,
-            version TEXT NOT NULL,
-            author TEXT NOT NULL,
-            category TEXT NOT NULL,
-            registry_id TEXT NOT NULL,
-            metadata TEXT NOT NULL,
-            checksum TEXT NOT NULL,
-            size_bytes INTEGER DEFAULT 0,
-            install_path TEXT NOT NULL,
-            installed_at TEXT DEFAULT CURRENT_TIMESTAMP,
-            updated_at TEXT DEFAULT CURRENT_TIMESTAMP
-        );
-        "#,
+/// Parse a legacy embedded JSON array leniently: entries without an id get
+/// one, entries that still fail to parse are logged and dropped
+pub fn legacy_entities<T: DeserializeOwned>(project_id: &str, label: &str, json: Value) -> Vec<T> {
+    let Value::Array(items) = json else {
+        return Vec::new();
+    };
+
+    items
+        .into_iter()
+        .filter_map(|item| {
+            let Value::Object(mut fields) = item else {
+                log::warn!("Skipping malformed {} entry in project {}", label, project_id);
+                return None;
+            };
+            if !matches!(fields.get("id"), Some(Value::String(id)) if !id.is_empty()) {
+                fields.insert("id".into(), Value::String(uuid::Uuid::new_v4().to_string()));
+            }
+            fields.insert("projectId".into(), Value::String(project_id.to_string()));
+            serde_json::from_value(Value::Object(fields))
+                .map_err(|e| {
+                    log::warn!("Skipping {} entry in project {}: {}", label, project_id, e)
+                })
+                .ok()
+        })
+        .collect()
+}
+
+/// Package content was copied into projects with its ids intact, so the
+/// same id can appear in several projects
+fn unused_id(tx: &Transaction, table: &str, id: String) -> rusqlite::Result<String> {
+    let taken: bool = tx.query_row(
+        &format!("SELECT EXISTS(SELECT 1 FROM {} WHERE id = ?1)", table),
+        [&id],
+        |row| row.get(0),
     )?;
+    Ok(if taken {
+        uuid::Uuid::new_v4().to_string()
+    } else {
+        id
+    })
+}
 
-    // Migrations: add columns that may be missing on older databases.
-    // ALTER TABLE ... ADD COLUMN fails if the column already exists,
-    // so we silently ignore errors for each statement.
-    let migrations = [
-        "ALTER TABLE projects ADD COLUMN active_identity_package TEXT",
-        "ALTER TABLE projects ADD COLUMN origin_package_id TEXT",
-        "ALTER TABLE projects ADD COLUMN creatures TEXT",
-        "ALTER TABLE projects ADD COLUMN world_rules TEXT",
-        "ALTER TABLE projects ADD COLUMN project_type TEXT DEFAULT 'novel'",
-        "ALTER TABLE characters ADD COLUMN origin_package_id TEXT",
-        "ALTER TABLE lore_items ADD COLUMN origin_package_id TEXT",
-        "ALTER TABLE projects ADD COLUMN npcs TEXT",
-    ];
+/// Entities of a trashed project go to the trash with it
+fn mark_deleted(
+    tx: &Transaction,
+    table: &str,
+    id: &str,
+    deleted_at: Option<&str>,
+) -> rusqlite::Result<()> {
+    if let Some(stamp) = deleted_at {
+        tx.execute(
+            &format!("UPDATE {} SET deleted_at = ?2 WHERE id = ?1", table),
+            params![id, stamp],
+        )?;
+    }
+    Ok(())
+}
+
+/// Scenes carry their own prose, and a chapter can be compiled from it
+const SCENE_CONTENT: &str = r#"
+ALTER TABLE scenes ADD COLUMN content TEXT;
+ALTER TABLE scenes ADD COLUMN word_count INTEGER DEFAULT 0;
+ALTER TABLE chapters ADD COLUMN content_mode TEXT NOT NULL DEFAULT 'document'; -- document | scenes
+
+DROP TRIGGER search_scenes_insert;
+CREATE TRIGGER search_scenes_insert AFTER INSERT ON scenes BEGIN
+    INSERT INTO search_index (entity_type, entity_id, project_id, title, body)
+    VALUES ('scene', new.id, (SELECT project_id FROM chapters WHERE id = new.chapter_id), new.title,
+            COALESCE(new.description, '') || char(10) || COALESCE(new.notes, '') || char(10) || COALESCE(strip_html(new.content), ''));
+END;
+
+DROP TRIGGER search_scenes_update;
+CREATE TRIGGER search_scenes_update AFTER UPDATE ON scenes BEGIN
+    DELETE FROM search_index WHERE entity_type = 'scene' AND entity_id = old.id;
+    INSERT INTO search_index (entity_type, entity_id, project_id, title, body)
+    SELECT 'scene', new.id, (SELECT project_id FROM chapters WHERE id = new.chapter_id), new.title,
+           COALESCE(new.description, '') || char(10) || COALESCE(new.notes, '') || char(10) || COALESCE(strip_html(new.content), '')
+    WHERE new.deleted_at IS NULL;
+END;
+"#;
+
+/// Where characters, locations and lore are mentioned, and the aliases
+/// they are recognised by
+const MENTIONS: &str = r#"
+CREATE TABLE entity_aliases (
+    entity_type TEXT NOT NULL,
+    entity_id TEXT NOT NULL,
+    project_id TEXT NOT NULL,
+    alias TEXT NOT NULL,
+    PRIMARY KEY (entity_type, entity_id, alias),
+    FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE
+);
+
+CREATE TABLE mentions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    project_id TEXT NOT NULL,
+    entity_type TEXT NOT NULL,
+    entity_id TEXT NOT NULL,
+    source_type TEXT NOT NULL, -- chapter | scene | lore_item
+    source_id TEXT NOT NULL,
+    start_offset INTEGER NOT NULL,
+    end_offset INTEGER NOT NULL,
+    matched TEXT NOT NULL,
+    snippet TEXT NOT NULL,
+    FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_entity_aliases_project ON entity_aliases(project_id);
+CREATE INDEX idx_mentions_entity ON mentions(entity_type, entity_id);
+CREATE INDEX idx_mentions_source ON mentions(source_type, source_id);
+CREATE INDEX idx_mentions_project ON mentions(project_id);
+"#;
 
-    for sql in &migrations {
-        if let Err(e) = conn.execute(sql, []) {
-            // "duplicate column name" is expected if column already exists
-            log::debug!("Migration skipped ({}): {}", e, sql);
+/// Index the mentions in existing manuscripts. Aliases are new in this
+/// migration, so only names and titles are looked for.
+fn index_mentions(tx: &Transaction) -> rusqlite::Result<()> {
+    let entities: Vec<(String, String, String, Vec<String>)> = {
+        let mut stmt = tx.prepare(
+            r#"SELECT project_id, 'character', id, name FROM characters
+               UNION ALL SELECT project_id, 'location', id, name FROM locations
+               UNION ALL SELECT project_id, 'lore_item', id, title FROM lore_items"#,
+        )?;
+        let rows = stmt.query_map([], |row| {
+            let name: String = row.get(3)?;
+            Ok((
+                row.get(0)?,
+                row.get(1)?,
+                row.get(2)?,
+                mentions::usable_terms(&[name]),
+            ))
+        })?;
+        rows.collect::<rusqlite::Result<_>>()?
+    };
+    let sources: Vec<(String, String, String, String)> = {
+        let mut stmt = tx.prepare(
+            r#"SELECT project_id, 'chapter', id, content FROM chapters WHERE content_mode = 'document'
+               UNION ALL
+               SELECT c.project_id, 'scene', s.id, s.content FROM scenes s JOIN chapters c ON c.id = s.chapter_id
+               UNION ALL
+               SELECT project_id, 'lore_item', id, content FROM lore_items"#,
+        )?;
+        let rows = stmt.query_map([], |row| {
+            let content: Option<String> = row.get(3)?;
+            Ok((
+                row.get(0)?,
+                row.get(1)?,
+                row.get(2)?,
+                text::strip_html(&content.unwrap_or_default()),
+            ))
+        })?;
+        rows.collect::<rusqlite::Result<_>>()?
+    };
+
+    for (project_id, source_type, source_id, plain) in &sources {
+        for (_, entity_type, entity_id, terms) in entities.iter().filter(|e| &e.0 == project_id) {
+            // An entry's own text does not mention itself
+            if entity_type == source_type && entity_id == source_id {
+                continue;
+            }
+            for found in mentions::locate(plain, terms) {
+                tx.execute(
+                    r#"INSERT INTO mentions (project_id, entity_type, entity_id, source_type, source_id,
+                                             start_offset, end_offset, matched, snippet)
+                       VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)"#,
+                    params![
+                        project_id,
+                        entity_type,
+                        entity_id,
+                        source_type,
+                        source_id,
+                        found.start,
+                        found.end,
+                        found.matched,
+                        found.snippet,
+                    ],
+                )?;
+            }
         }
     }
-
-    log::info!("Database schema initialized successfully");
     Ok(())
 }
+
+/// User-defined fields for characters, locations and lore. Values live in a
+/// JSON object on each entity row, keyed by field id, and are searchable.
+const CUSTOM_FIELDS: &str = r#"
+CREATE TABLE custom_fields (
+    id TEXT PRIMARY KEY,
+    project_id TEXT NOT NULL,
+    entity_type TEXT NOT NULL, -- character | location | lore_item
+    name TEXT NOT NULL,
+    field_type TEXT NOT NULL, -- text | number | enum | date | entity_reference
+    options TEXT, -- JSON array, enum choices
+    reference_type TEXT, -- entity type an entity_reference points at
+    position INTEGER NOT NULL DEFAULT 0,
+    created_at TEXT DEFAULT CURRENT_TIMESTAMP,
+    updated_at TEXT DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (project_id, entity_type, name),
+    FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_custom_fields_project ON custom_fields(project_id, entity_type);
+
+ALTER TABLE characters ADD COLUMN custom_fields TEXT; -- JSON {field_id: value}
+ALTER TABLE locations ADD COLUMN custom_fields TEXT; -- JSON {field_id: value}
+ALTER TABLE lore_items ADD COLUMN custom_fields TEXT; -- JSON {field_id: value}
+
+DROP TRIGGER search_characters_insert;
+CREATE TRIGGER search_characters_insert AFTER INSERT ON characters BEGIN
+    INSERT INTO search_index (entity_type, entity_id, project_id, title, body)
+    VALUES ('character', new.id, new.project_id, new.name,
+            COALESCE(new.personality, '') || char(10) || COALESCE(new.history, '') || char(10) || COALESCE(new.notes, '') || char(10) || COALESCE((SELECT group_concat(value, ' ') FROM json_each(new.custom_fields)), ''));
+END;
+
+DROP TRIGGER search_characters_update;
+CREATE TRIGGER search_characters_update AFTER UPDATE ON characters BEGIN
+    DELETE FROM search_index WHERE entity_type = 'character' AND entity_id = old.id;
+    INSERT INTO search_index (entity_type, entity_id, project_id, title, body)
+    SELECT 'character', new.id, new.project_id, new.name,
+           COALESCE(new.personality, '') || char(10) || COALESCE(new.history, '') || char(10) || COALESCE(new.notes, '') || char(10) || COALESCE((SELECT group_concat(value, ' ') FROM json_each(new.custom_fields)), '')
+    WHERE new.deleted_at IS NULL;
+END;
+
+DROP TRIGGER search_locations_insert;
+CREATE TRIGGER search_locations_insert AFTER INSERT ON locations BEGIN
+    INSERT INTO search_index (entity_type, entity_id, project_id, title, body)
+    VALUES ('location', new.id, new.project_id, new.name,
+            COALESCE(new.type, '') || char(10) || COALESCE(new.description, '') || char(10) || COALESCE(new.significance, '') || char(10) || COALESCE(new.notes, '') || char(10) || COALESCE((SELECT group_concat(value, ' ') FROM json_each(new.custom_fields)), ''));
+END;
+
+DROP TRIGGER search_locations_update;
+CREATE TRIGGER search_locations_update AFTER UPDATE ON locations BEGIN
+    DELETE FROM search_index WHERE entity_type = 'location' AND entity_id = old.id;
+    INSERT INTO search_index (entity_type, entity_id, project_id, title, body)
+    SELECT 'location', new.id, new.project_id, new.name,
+           COALESCE(new.type, '') || char(10) || COALESCE(new.description, '') || char(10) || COALESCE(new.significance, '') || char(10) || COALESCE(new.notes, '') || char(10) || COALESCE((SELECT group_concat(value, ' ') FROM json_each(new.custom_fields)), '')
+    WHERE new.deleted_at IS NULL;
+END;
+
+DROP TRIGGER search_lore_items_insert;
+CREATE TRIGGER search_lore_items_insert AFTER INSERT ON lore_items BEGIN
+    INSERT INTO search_index (entity_type, entity_id, project_id, title, body)
+    VALUES ('lore_item', new.id, new.project_id, new.title,
+            COALESCE(new.summary, '') || char(10) || COALESCE(strip_html(new.content), '') || char(10) || COALESCE((SELECT group_concat(value, ' ') FROM json_each(new.custom_fields)), ''));
+END;
+
+DROP TRIGGER search_lore_items_update;
+CREATE TRIGGER search_lore_items_update AFTER UPDATE ON lore_items BEGIN
+    DELETE FROM search_index WHERE entity_type = 'lore_item' AND entity_id = old.id;
+    INSERT INTO search_index (entity_type, entity_id, project_id, title, body)
+    SELECT 'lore_item', new.id, new.project_id, new.title,
+           COALESCE(new.summary, '') || char(10) || COALESCE(strip_html(new.content), '') || char(10) || COALESCE((SELECT group_concat(value, ' ') FROM json_each(new.custom_fields)), '')
+    WHERE new.deleted_at IS NULL;
+END;
+"#;
+
+/// Project tag vocabulary and the entities carrying each tag
+const TAGS: &str = r#"
+CREATE TABLE tags (
+    id TEXT PRIMARY KEY,
+    project_id TEXT NOT NULL,
+    name TEXT NOT NULL COLLATE NOCASE,
+    color TEXT,
+    created_at TEXT DEFAULT CURRENT_TIMESTAMP,
+    updated_at TEXT DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (project_id, name),
+    FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE
+);
+
+CREATE TABLE entity_tags (
+    tag_id TEXT NOT NULL,
+    entity_type TEXT NOT NULL, -- chapter | scene | character | location | lore_item | timeline_event
+    entity_id TEXT NOT NULL,
+    PRIMARY KEY (tag_id, entity_type, entity_id),
+    FOREIGN KEY (tag_id) REFERENCES tags(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_entity_tags_entity ON entity_tags(entity_type, entity_id);
+"#;
+
+/// Move the `tags` arrays of timeline events into the tag tables, then drop
+/// the column
+fn move_timeline_tags(tx: &Transaction) -> rusqlite::Result<()> {
+    let rows: Vec<(String, String, Vec<String>)> = {
+        let mut stmt = tx.prepare(
+            "SELECT id, project_id, tags FROM timeline_events WHERE tags IS NOT NULL AND tags != '[]'",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            let tags: Option<String> = row.get(2)?;
+            Ok((
+                row.get(0)?,
+                row.get(1)?,
+                tags.and_then(|s| serde_json::from_str(&s).ok())
+                    .unwrap_or_default(),
+            ))
+        })?;
+        rows.collect::<rusqlite::Result<_>>()?
+    };
+
+    for (event_id, project_id, names) in rows {
+        for name in names.iter().map(|n| n.trim()).filter(|n| !n.is_empty()) {
+            // Tag names are case-insensitive, so an existing tag is reused
+            tx.execute(
+                "INSERT OR IGNORE INTO tags (id, project_id, name) VALUES (?1, ?2, ?3)",
+                params![uuid::Uuid::new_v4().to_string(), project_id, name],
+            )?;
+            tx.execute(
+                r#"INSERT OR IGNORE INTO entity_tags (tag_id, entity_type, entity_id)
+                   SELECT id, 'timeline_event', ?3 FROM tags WHERE project_id = ?1 AND name = ?2"#,
+                params![project_id, name, event_id],
+            )?;
+        }
+    }
+    tx.execute_batch("ALTER TABLE timeline_events DROP COLUMN tags;")
+}
+
+/// Per-project settings that differ from their declared default
+const PROJECT_SETTINGS: &str = r#"
+CREATE TABLE project_settings (
+    project_id TEXT NOT NULL,
+    key TEXT NOT NULL,
+    value TEXT NOT NULL, -- JSON
+    updated_at TEXT DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (project_id, key),
+    FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE
+);
+"#;
+
+/// Append-only log of entity changes, written by triggers
+const AUDIT_LOG: &str = r#"
+CREATE TABLE audit_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    project_id TEXT,
+    entity_type TEXT NOT NULL,
+    entity_id TEXT NOT NULL,
+    action TEXT NOT NULL, -- create | update | delete | restore | purge
+    source TEXT NOT NULL, -- user | ai | package | sync
+    changes TEXT NOT NULL, -- JSON object of changed columns
+    created_at TEXT DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_audit_log_project ON audit_log(project_id, id);
+CREATE INDEX idx_audit_log_entity ON audit_log(entity_type, entity_id, id);
+"#;
+
+fn install_audit_triggers(tx: &Transaction) -> rusqlite::Result<()> {
+    audit::install_audit_triggers(tx)
+}
+
+/// Undo/redo journal: user-level actions and the rows each one changed
+const UNDO_JOURNAL: &str = r#"
+CREATE TABLE undo_actions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    project_id TEXT,
+    label TEXT NOT NULL,
+    undone INTEGER NOT NULL DEFAULT 0,
+    created_at TEXT DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE undo_changes (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    action_id INTEGER NOT NULL,
+    project_id TEXT,
+    entity_table TEXT NOT NULL,
+    entity_id TEXT NOT NULL,
+    before TEXT, -- JSON row, NULL when the row was created
+    after TEXT, -- JSON row, NULL when the row was deleted
+    FOREIGN KEY (action_id) REFERENCES undo_actions(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_undo_actions_project ON undo_actions(project_id, id);
+CREATE INDEX idx_undo_changes_action ON undo_changes(action_id);
+CREATE INDEX idx_undo_changes_entity ON undo_changes(entity_table, entity_id);
+"#;
+
+fn install_undo_triggers(tx: &Transaction) -> rusqlite::Result<()> {
+    undo::install_undo_triggers(tx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database;
+    use crate::database::migrations::column_exists;
+
+    #[test]
+    fn test_moves_embedded_rpg_entities_into_tables() {
+        let conn = Connection::open_in_memory().unwrap();
+        database::configure_connection(&conn).unwrap();
+        run_migrations(&conn, &MIGRATIONS[..5]).unwrap();
+
+        conn.execute(
+            r#"INSERT INTO projects (id, title, creatures, npcs, world_rules) VALUES
+               ('p1', 'Saga', ?1, ?2, ?3),
+               ('p2', 'Copy', ?1, '[]', NULL)"#,
+            params![
+                r#"[{"id": "cr1", "name": "Drake", "type": "dragon", "habitat": ["caves"]}, 42]"#,
+                r#"[{"name": "Mira", "role": "innkeeper", "quests": [{"id": "q1", "name": "Lost ring"}]}]"#,
+                r#"[{"id": "r1", "title": "No magic at noon", "category": "magic", "isSecret": true}]"#,
+            ],
+        )
+        .unwrap();
+
+        init_database(&conn).unwrap();
+        assert!(!column_exists(&conn, "projects", "creatures").unwrap());
+
+        let creatures = database::get_creatures_by_project(&conn, "p1").unwrap();
+        assert_eq!(creatures.len(), 1);
+        assert_eq!(creatures[0].id, "cr1");
+        assert_eq!(creatures[0].habitat, vec!["caves".to_string()]);
+        assert_eq!(creatures[0].size, "medium");
+
+        // The copy in the second project gets a fresh id
+        let copies = database::get_creatures_by_project(&conn, "p2").unwrap();
+        assert_eq!(copies.len(), 1);
+        assert_ne!(copies[0].id, "cr1");
+
+        let npcs = database::get_npcs_by_project(&conn, "p1").unwrap();
+        assert_eq!(npcs[0].name, "Mira");
+        assert!(!npcs[0].id.is_empty());
+        assert_eq!(npcs[0].quests[0].status, "available");
+
+        let rules = database::get_world_rules_by_project(&conn, "p1").unwrap();
+        assert!(rules[0].is_secret);
+
+        let hits = database::search(&conn, "Drake", Some("p1"), &[], None).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].entity_type, database::EntityKind::Creature);
+
+        database::delete_creature(&conn, "cr1").unwrap();
+        assert!(database::get_creatures_by_project(&conn, "p1")
+            .unwrap()
+            .is_empty());
+        assert!(database::restore_from_trash(&conn, database::EntityKind::Creature, "cr1").unwrap());
+    }
+
+    #[test]
+    fn test_moves_timeline_tags_into_tag_tables() {
+        let conn = Connection::open_in_memory().unwrap();
+        database::configure_connection(&conn).unwrap();
+        run_migrations(&conn, &MIGRATIONS[..9]).unwrap();
+
+        conn.execute_batch(
+            r#"INSERT INTO projects (id, title) VALUES ('p1', 'Saga');
+               INSERT INTO timeline_events (id, project_id, title, tags) VALUES
+                   ('e1', 'p1', 'Storm', '["war", "Omen"]'),
+                   ('e2', 'p1', 'Truce', '["War"]'),
+                   ('e3', 'p1', 'Dawn', NULL);"#,
+        )
+        .unwrap();
+
+        init_database(&conn).unwrap();
+        assert!(!column_exists(&conn, "timeline_events", "tags").unwrap());
+
+        let tags = database::get_project_tags(&conn, "p1").unwrap();
+        let names: Vec<&str> = tags.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, ["Omen", "war"]);
+        let events = database::get_timeline_events_by_project(&conn, "p1").unwrap();
+        let tags_of = |id: &str| events.iter().find(|e| e.id == id).unwrap().tags.clone();
+        assert_eq!(tags_of("e1"), ["Omen", "war"]);
+        assert_eq!(tags_of("e2"), ["war"]);
+        assert!(tags_of("e3").is_empty());
+    }
+
+    #[test]
+    fn test_seeds_history_and_mentions_of_existing_chapters() {
+        let conn = Connection::open_in_memory().unwrap();
+        database::configure_connection(&conn).unwrap();
+        run_migrations(&conn, &MIGRATIONS[..4]).unwrap();
+
+        conn.execute_batch(
+            r#"INSERT INTO projects (id, title) VALUES ('p1', 'Saga');
+               INSERT INTO characters (id, project_id, name) VALUES ('ch1', 'p1', 'Ada');
+               INSERT INTO chapters (id, project_id, title, content) VALUES
+                   ('c1', 'p1', 'Arrival', '<p>Ada reached the gate.</p>'),
+                   ('c2', 'p1', 'Blank', '  ');"#,
+        )
+        .unwrap();
+
+        init_database(&conn).unwrap();
+        assert_eq!(database::list_revisions(&conn, "c1").unwrap().len(), 1);
+        assert!(database::list_revisions(&conn, "c2").unwrap().is_empty());
+
+        let mentions =
+            database::get_entity_mentions(&conn, database::EntityKind::Character, "ch1").unwrap();
+        assert_eq!(mentions.len(), 1);
+        assert_eq!((mentions[0].start, mentions[0].end), (0, 3));
+        assert_eq!(mentions[0].snippet, "Ada reached the gate.");
+    }
+}
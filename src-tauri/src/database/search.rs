@@ -0,0 +1,178 @@
+//! Full-text search over the `search_index` FTS5 table
+
+use super::models::EntityKind;
+use rusqlite::{params_from_iter, types::Value, Connection, Result};
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_LIMIT: u32 = 50;
+
+/// A ranked search result
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchHit {
+    pub entity_type: EntityKind,
+    pub entity_id: String,
+    pub project_id: Option<String>,
+    pub title: String,
+    /// Matching excerpt, with matches wrapped in `<mark>` tags
+    pub snippet: String,
+    /// BM25 score; lower is more relevant
+    pub rank: f64,
+}
+
+/// Search entities, optionally restricted to a project and entity types
+pub fn search(
+    conn: &Connection,
+    query: &str,
+    project_id: Option<&str>,
+    entity_types: &[EntityKind],
+    limit: Option<u32>,
+) -> Result<Vec<SearchHit>> {
+    let Some(match_expr) = build_match_expression(query) else {
+        return Ok(vec![]);
+    };
+
+    // Titles weigh ten times more than body text
+    let mut sql = String::from(
+        "SELECT entity_type, entity_id, project_id, title,
+                snippet(search_index, -1, '<mark>', '</mark>', '…', 16),
+                bm25(search_index, 0.0, 0.0, 0.0, 10.0, 1.0) AS score
+         FROM search_index WHERE search_index MATCH ?",
+    );
+    let mut values: Vec<Value> = vec![Value::Text(match_expr)];
+
+    if let Some(project_id) = project_id {
+        sql.push_str(" AND project_id = ?");
+        values.push(Value::Text(project_id.to_string()));
+    }
+
+    if !entity_types.is_empty() {
+        let placeholders = vec!["?"; entity_types.len()].join(", ");
+        sql.push_str(&format!(" AND entity_type IN ({})", placeholders));
+        values.extend(
+            entity_types
+                .iter()
+                .map(|kind| Value::Text(kind.as_str().to_string())),
+        );
+    }
+
+    sql.push_str(" ORDER BY score LIMIT ?");
+    values.push(Value::Integer(limit.unwrap_or(DEFAULT_LIMIT) as i64));
+
+    let mut stmt = conn.prepare(&sql)?;
+    let rows = stmt.query_map(params_from_iter(values), |row| {
+        let entity_type: String = row.get(0)?;
+        Ok(EntityKind::from_db(&entity_type).map(|kind| -> Result<SearchHit> {
+            Ok(SearchHit {
+                entity_type: kind,
+                entity_id: row.get(1)?,
+                project_id: row.get(2)?,
+                title: row.get(3)?,
+                snippet: row.get(4)?,
+                rank: row.get(5)?,
+            })
+        }))
+    })?;
+
+    let mut hits = Vec::new();
+    for row in rows {
+        if let Some(hit) = row? {
+            hits.push(hit?);
+        }
+    }
+    Ok(hits)
+}
+
+/// Turn free user input into a safe FTS5 expression: every word becomes a
+/// quoted term (so `"`, `*`, `-` or `OR` typed by the user are literal), all
+/// terms are required, and the last one matches as a prefix.
+fn build_match_expression(query: &str) -> Option<String> {
+    let terms: Vec<String> = query
+        .split_whitespace()
+        .map(|word| word.replace('"', ""))
+        .filter(|word| !word.is_empty())
+        .map(|word| format!("\"{}\"", word))
+        .collect();
+
+    if terms.is_empty() {
+        return None;
+    }
+    Some(format!("{}*", terms.join(" ")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database;
+    use crate::database::test_support::project_db;
+    use serde_json::json;
+
+    fn chapter(id: &str, title: &str, content: &str) -> database::Chapter {
+        serde_json::from_value(json!({
+            "id": id,
+            "projectId": "p1",
+            "title": title,
+            "content": content,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_search_chapter_content_without_html() {
+        let conn = project_db();
+        database::create_chapter(
+            &conn,
+            &chapter("c1", "Arrival", "<p>They reached the <b>Obsidian</b> Gate at dusk.</p>"),
+        )
+        .unwrap();
+
+        let hits = search(&conn, "obsidian gate", Some("p1"), &[], None).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].entity_type, EntityKind::Chapter);
+        assert_eq!(hits[0].entity_id, "c1");
+        assert!(hits[0].snippet.contains("<mark>Obsidian</mark>"));
+        assert!(!hits[0].snippet.contains("<b>"));
+    }
+
+    #[test]
+    fn test_search_follows_updates_and_filters() {
+        let conn = project_db();
+        let mut ch = chapter("c1", "Arrival", "<p>Nothing here</p>");
+        database::create_chapter(&conn, &ch).unwrap();
+        database::create_lore_item(
+            &conn,
+            &serde_json::from_value(json!({
+                "id": "l1",
+                "projectId": "p1",
+                "title": "Obsidian Gate",
+            }))
+            .unwrap(),
+        )
+        .unwrap();
+
+        ch.content = "<p>The gate was obsidian black.</p>".to_string();
+        database::update_chapter(&conn, &ch).unwrap();
+
+        let all = search(&conn, "obsidian", None, &[], None).unwrap();
+        assert_eq!(all.len(), 2);
+        // Title matches rank first
+        assert_eq!(all[0].entity_type, EntityKind::LoreItem);
+
+        let chapters = search(&conn, "obsidian", None, &[EntityKind::Chapter], None).unwrap();
+        assert_eq!(chapters.len(), 1);
+        assert_eq!(chapters[0].entity_id, "c1");
+
+        assert!(search(&conn, "obsidian", Some("other"), &[], None)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_match_expression_is_quoted() {
+        assert_eq!(
+            build_match_expression("gate \"OR\" -x").as_deref(),
+            Some("\"gate\" \"OR\" \"-x\"*")
+        );
+        assert_eq!(build_match_expression("   "), None);
+    }
+}
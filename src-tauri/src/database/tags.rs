@@ -0,0 +1,559 @@
+//! Project-scoped tags shared by all manuscript and world entities
+//!
+//! Each project has its own tag vocabulary; names are unique per project
+//! regardless of case. Chapters, scenes, characters, locations, lore items
+//! and timeline events can carry any number of tags. Trashed entities keep
+//! their tags and are filtered out when querying.
+
+use super::models::EntityKind;
+use super::operations::with_savepoint;
+use rusqlite::{params, params_from_iter, types::Value, Connection, OptionalExtension, Result};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Live taggable entities of a project (`?1`) with their titles
+const LIVE_ENTITIES: &str = r#"
+    SELECT 'chapter' AS entity_type, id AS entity_id, title FROM chapters
+    WHERE project_id = ?1 AND deleted_at IS NULL
+    UNION ALL
+    SELECT 'scene', s.id, s.title FROM scenes s JOIN chapters c ON c.id = s.chapter_id
+    WHERE c.project_id = ?1 AND s.deleted_at IS NULL AND c.deleted_at IS NULL
+    UNION ALL
+    SELECT 'character', id, name FROM characters WHERE project_id = ?1 AND deleted_at IS NULL
+    UNION ALL
+    SELECT 'location', id, name FROM locations WHERE project_id = ?1 AND deleted_at IS NULL
+    UNION ALL
+    SELECT 'lore_item', id, title FROM lore_items WHERE project_id = ?1 AND deleted_at IS NULL
+    UNION ALL
+    SELECT 'timeline_event', id, title FROM timeline_events WHERE project_id = ?1 AND deleted_at IS NULL
+"#;
+
+#[derive(Error, Debug)]
+pub enum TagError {
+    #[error("{0} entities cannot be tagged")]
+    NotTaggable(&'static str),
+
+    #[error("{kind} {id} not found")]
+    NotFound { kind: &'static str, id: String },
+
+    #[error("Tag name is empty")]
+    EmptyName,
+
+    #[error("A tag named \"{0}\" already exists")]
+    NameTaken(String),
+
+    #[error("Tags belong to different projects")]
+    ProjectMismatch,
+
+    #[error("Database error: {0}")]
+    Database(#[from] rusqlite::Error),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Tag {
+    pub id: String,
+    pub project_id: String,
+    pub name: String,
+    /// CSS color, e.g. `#e11d48`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+}
+
+/// A tag applied to an entity
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct EntityTag {
+    pub tag_id: String,
+    pub entity_type: EntityKind,
+    pub entity_id: String,
+}
+
+/// How several tags combine when listing entities
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TagMatch {
+    /// Entities carrying every tag
+    All,
+    /// Entities carrying at least one tag
+    Any,
+}
+
+/// An entity found by tag
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaggedEntity {
+    pub entity_type: EntityKind,
+    pub entity_id: String,
+    pub title: String,
+    /// Ids of its tags among those asked for
+    pub tag_ids: Vec<String>,
+}
+
+/// Whether entities of this kind can be tagged
+pub fn is_taggable(kind: EntityKind) -> bool {
+    matches!(
+        kind,
+        EntityKind::Chapter
+            | EntityKind::Scene
+            | EntityKind::Character
+            | EntityKind::Location
+            | EntityKind::LoreItem
+            | EntityKind::TimelineEvent
+    )
+}
+
+pub fn create_tag(conn: &Connection, tag: &Tag) -> Result<(), TagError> {
+    let name = checked_name(conn, &tag.project_id, &tag.name, None)?;
+    conn.execute(
+        "INSERT INTO tags (id, project_id, name, color) VALUES (?1, ?2, ?3, ?4)",
+        params![tag.id, tag.project_id, name, tag.color],
+    )?;
+    Ok(())
+}
+
+/// A project's tags, by name
+pub fn get_project_tags(conn: &Connection, project_id: &str) -> Result<Vec<Tag>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, project_id, name, color FROM tags WHERE project_id = ?1 ORDER BY name COLLATE NOCASE",
+    )?;
+    let rows = stmt.query_map(params![project_id], row_to_tag)?;
+    rows.collect()
+}
+
+/// Rename or recolor a tag. Renaming onto another tag's name is refused;
+/// merge the two instead.
+pub fn update_tag(conn: &Connection, tag: &Tag) -> Result<(), TagError> {
+    let current = get_tag(conn, &tag.id)?;
+    let name = checked_name(conn, &current.project_id, &tag.name, Some(&tag.id))?;
+    conn.execute(
+        "UPDATE tags SET name = ?2, color = ?3, updated_at = CURRENT_TIMESTAMP WHERE id = ?1",
+        params![tag.id, name, tag.color],
+    )?;
+    Ok(())
+}
+
+/// Delete a tag, removing it from every entity
+pub fn delete_tag(conn: &Connection, id: &str) -> Result<(), TagError> {
+    get_tag(conn, id)?;
+    with_savepoint(conn, || {
+        conn.execute("DELETE FROM entity_tags WHERE tag_id = ?1", params![id])?;
+        conn.execute("DELETE FROM tags WHERE id = ?1", params![id])?;
+        Ok(())
+    })?;
+    Ok(())
+}
+
+/// Fold `source_ids` into `target_id`: their entities get the target tag
+/// and the source tags are deleted. Returns the number of entities that
+/// gained the target tag.
+pub fn merge_tags(
+    conn: &Connection,
+    source_ids: &[String],
+    target_id: &str,
+) -> Result<usize, TagError> {
+    let target = get_tag(conn, target_id)?;
+    for id in source_ids {
+        if get_tag(conn, id)?.project_id != target.project_id {
+            return Err(TagError::ProjectMismatch);
+        }
+    }
+
+    let added = with_savepoint(conn, || {
+        let mut added = 0;
+        for id in source_ids.iter().filter(|id| *id != target_id) {
+            added += conn.execute(
+                r#"INSERT OR IGNORE INTO entity_tags (tag_id, entity_type, entity_id)
+                   SELECT ?2, entity_type, entity_id FROM entity_tags WHERE tag_id = ?1"#,
+                params![id, target_id],
+            )?;
+            conn.execute("DELETE FROM entity_tags WHERE tag_id = ?1", params![id])?;
+            conn.execute("DELETE FROM tags WHERE id = ?1", params![id])?;
+        }
+        Ok(added)
+    })?;
+    Ok(added)
+}
+
+/// Apply a tag to an entity of the same project. Returns false if the
+/// entity already had it.
+pub fn tag_entity(
+    conn: &Connection,
+    kind: EntityKind,
+    id: &str,
+    tag_id: &str,
+) -> Result<bool, TagError> {
+    let tag = get_tag(conn, tag_id)?;
+    if entity_project(conn, kind, id)? != tag.project_id {
+        return Err(TagError::ProjectMismatch);
+    }
+    let inserted = conn.execute(
+        "INSERT OR IGNORE INTO entity_tags (tag_id, entity_type, entity_id) VALUES (?1, ?2, ?3)",
+        params![tag_id, kind.as_str(), id],
+    )?;
+    Ok(inserted > 0)
+}
+
+/// Remove a tag from an entity. Returns false if the entity did not have it.
+pub fn untag_entity(conn: &Connection, kind: EntityKind, id: &str, tag_id: &str) -> Result<bool> {
+    let removed = conn.execute(
+        "DELETE FROM entity_tags WHERE tag_id = ?1 AND entity_type = ?2 AND entity_id = ?3",
+        params![tag_id, kind.as_str(), id],
+    )?;
+    Ok(removed > 0)
+}
+
+/// An entity's tags, by name
+pub fn get_entity_tags(conn: &Connection, kind: EntityKind, id: &str) -> Result<Vec<Tag>> {
+    let mut stmt = conn.prepare(
+        r#"SELECT t.id, t.project_id, t.name, t.color
+           FROM entity_tags et JOIN tags t ON t.id = et.tag_id
+           WHERE et.entity_type = ?1 AND et.entity_id = ?2
+           ORDER BY t.name COLLATE NOCASE"#,
+    )?;
+    let rows = stmt.query_map(params![kind.as_str(), id], row_to_tag)?;
+    rows.collect()
+}
+
+/// Replace the tags of an entity
+pub fn set_entity_tags(
+    conn: &Connection,
+    kind: EntityKind,
+    id: &str,
+    tag_ids: &[String],
+) -> Result<(), TagError> {
+    let project_id = entity_project(conn, kind, id)?;
+    for tag_id in tag_ids {
+        if get_tag(conn, tag_id)?.project_id != project_id {
+            return Err(TagError::ProjectMismatch);
+        }
+    }
+    with_savepoint(conn, || {
+        conn.execute(
+            "DELETE FROM entity_tags WHERE entity_type = ?1 AND entity_id = ?2",
+            params![kind.as_str(), id],
+        )?;
+        for tag_id in tag_ids {
+            conn.execute(
+                "INSERT OR IGNORE INTO entity_tags (tag_id, entity_type, entity_id) VALUES (?1, ?2, ?3)",
+                params![tag_id, kind.as_str(), id],
+            )?;
+        }
+        Ok(())
+    })?;
+    Ok(())
+}
+
+/// Every tag assignment in a project, trashed entities included
+pub fn get_project_entity_tags(conn: &Connection, project_id: &str) -> Result<Vec<EntityTag>> {
+    let mut stmt = conn.prepare(
+        r#"SELECT et.tag_id, et.entity_type, et.entity_id
+           FROM entity_tags et JOIN tags t ON t.id = et.tag_id
+           WHERE t.project_id = ?1
+           ORDER BY et.entity_type, et.entity_id, t.name COLLATE NOCASE"#,
+    )?;
+    let rows = stmt.query_map(params![project_id], |row| {
+        let entity_type: String = row.get(1)?;
+        Ok(
+            EntityKind::from_db(&entity_type).map(|kind| -> Result<EntityTag> {
+                Ok(EntityTag {
+                    tag_id: row.get(0)?,
+                    entity_type: kind,
+                    entity_id: row.get(2)?,
+                })
+            }),
+        )
+    })?;
+
+    let mut tags = Vec::new();
+    for row in rows {
+        if let Some(tag) = row? {
+            tags.push(tag?);
+        }
+    }
+    Ok(tags)
+}
+
+/// Live entities of a project carrying all (or any) of the given tags,
+/// optionally restricted to some entity types
+pub fn get_entities_by_tags(
+    conn: &Connection,
+    project_id: &str,
+    tag_ids: &[String],
+    mode: TagMatch,
+    entity_types: &[EntityKind],
+) -> Result<Vec<TaggedEntity>> {
+    if tag_ids.is_empty() {
+        return Ok(vec![]);
+    }
+
+    // `?1` is the project; the other values follow it
+    let mut values: Vec<Value> = vec![Value::Text(project_id.to_string())];
+    let mut placeholders = |items: Vec<Value>| {
+        let first = values.len() + 1;
+        values.extend(items);
+        (first..=values.len())
+            .map(|i| format!("?{}", i))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    let tags = placeholders(tag_ids.iter().map(|id| Value::Text(id.clone())).collect());
+    let mut sql = format!(
+        r#"SELECT e.entity_type, e.entity_id, e.title, group_concat(et.tag_id, char(31))
+           FROM ({}) e
+           JOIN entity_tags et ON et.entity_type = e.entity_type AND et.entity_id = e.entity_id
+           WHERE et.tag_id IN ({})"#,
+        LIVE_ENTITIES, tags
+    );
+    if !entity_types.is_empty() {
+        let kinds = placeholders(
+            entity_types
+                .iter()
+                .map(|kind| Value::Text(kind.as_str().to_string()))
+                .collect(),
+        );
+        sql.push_str(&format!(" AND e.entity_type IN ({})", kinds));
+    }
+    sql.push_str(" GROUP BY e.entity_type, e.entity_id");
+    if mode == TagMatch::All {
+        let mut wanted = tag_ids.to_vec();
+        wanted.sort();
+        wanted.dedup();
+        let count = placeholders(vec![Value::Integer(wanted.len() as i64)]);
+        sql.push_str(&format!(" HAVING COUNT(DISTINCT et.tag_id) = {}", count));
+    }
+    sql.push_str(" ORDER BY e.entity_type, e.title COLLATE NOCASE");
+
+    let mut stmt = conn.prepare(&sql)?;
+    let rows = stmt.query_map(params_from_iter(values), |row| {
+        let entity_type: String = row.get(0)?;
+        let tag_ids: String = row.get(3)?;
+        Ok(
+            EntityKind::from_db(&entity_type).map(|kind| -> Result<TaggedEntity> {
+                Ok(TaggedEntity {
+                    entity_type: kind,
+                    entity_id: row.get(1)?,
+                    title: row.get(2)?,
+                    tag_ids: tag_ids.split('\u{1f}').map(str::to_string).collect(),
+                })
+            }),
+        )
+    })?;
+
+    let mut entities = Vec::new();
+    for row in rows {
+        if let Some(entity) = row? {
+            entities.push(entity?);
+        }
+    }
+    Ok(entities)
+}
+
+/// Names of an entity's tags, for models that carry them inline
+pub(crate) fn tag_names(conn: &Connection, kind: EntityKind, id: &str) -> Result<Vec<String>> {
+    Ok(get_entity_tags(conn, kind, id)?
+        .into_iter()
+        .map(|tag| tag.name)
+        .collect())
+}
+
+/// Replace an entity's tags by name, creating the tags that do not exist yet.
+/// Tags the entity keeps are left in place, so saving unchanged tags writes
+/// nothing.
+pub(crate) fn set_tag_names(
+    conn: &Connection,
+    project_id: &str,
+    kind: EntityKind,
+    id: &str,
+    names: &[String],
+) -> Result<()> {
+    let mut tag_ids = Vec::new();
+    for name in names.iter().map(|n| n.trim()).filter(|n| !n.is_empty()) {
+        let tag_id = match find_tag(conn, project_id, name, None)? {
+            Some(tag_id) => tag_id,
+            None => {
+                let tag_id = uuid::Uuid::new_v4().to_string();
+                conn.execute(
+                    "INSERT INTO tags (id, project_id, name) VALUES (?1, ?2, ?3)",
+                    params![tag_id, project_id, name],
+                )?;
+                tag_id
+            }
+        };
+        tag_ids.push(tag_id);
+    }
+
+    conn.execute(
+        r#"DELETE FROM entity_tags WHERE entity_type = ?1 AND entity_id = ?2
+           AND tag_id NOT IN (SELECT value FROM json_each(?3))"#,
+        params![
+            kind.as_str(),
+            id,
+            serde_json::Value::from(tag_ids.as_slice()).to_string()
+        ],
+    )?;
+    for tag_id in &tag_ids {
+        conn.execute(
+            "INSERT OR IGNORE INTO entity_tags (tag_id, entity_type, entity_id) VALUES (?1, ?2, ?3)",
+            params![tag_id, kind.as_str(), id],
+        )?;
+    }
+    Ok(())
+}
+
+/// Drop the tags of a purged entity (and of the scenes of a purged chapter)
+pub(crate) fn forget(conn: &Connection, kind: EntityKind, id: &str) -> Result<()> {
+    if kind == EntityKind::Chapter {
+        conn.execute(
+            r#"DELETE FROM entity_tags WHERE entity_type = 'scene'
+               AND entity_id IN (SELECT id FROM scenes WHERE chapter_id = ?1)"#,
+            params![id],
+        )?;
+    }
+    conn.execute(
+        "DELETE FROM entity_tags WHERE entity_type = ?1 AND entity_id = ?2",
+        params![kind.as_str(), id],
+    )?;
+    Ok(())
+}
+
+fn get_tag(conn: &Connection, id: &str) -> Result<Tag, TagError> {
+    conn.query_row(
+        "SELECT id, project_id, name, color FROM tags WHERE id = ?1",
+        params![id],
+        row_to_tag,
+    )
+    .optional()?
+    .ok_or_else(|| TagError::NotFound {
+        kind: "tag",
+        id: id.to_string(),
+    })
+}
+
+fn row_to_tag(row: &rusqlite::Row) -> Result<Tag> {
+    Ok(Tag {
+        id: row.get(0)?,
+        project_id: row.get(1)?,
+        name: row.get(2)?,
+        color: row.get(3)?,
+    })
+}
+
+/// Id of the project's tag with this name, ignoring case and `except`
+fn find_tag(
+    conn: &Connection,
+    project_id: &str,
+    name: &str,
+    except: Option<&str>,
+) -> Result<Option<String>> {
+    conn.query_row(
+        "SELECT id FROM tags WHERE project_id = ?1 AND name = ?2 COLLATE NOCASE AND id IS NOT ?3",
+        params![project_id, name, except],
+        |row| row.get(0),
+    )
+    .optional()
+}
+
+fn checked_name(
+    conn: &Connection,
+    project_id: &str,
+    name: &str,
+    except: Option<&str>,
+) -> Result<String, TagError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(TagError::EmptyName);
+    }
+    if find_tag(conn, project_id, name, except)?.is_some() {
+        return Err(TagError::NameTaken(name.to_string()));
+    }
+    Ok(name.to_string())
+}
+
+fn entity_project(conn: &Connection, kind: EntityKind, id: &str) -> Result<String, TagError> {
+    if !is_taggable(kind) {
+        return Err(TagError::NotTaggable(kind.as_str()));
+    }
+    let sql = if kind == EntityKind::Scene {
+        "SELECT c.project_id FROM scenes s JOIN chapters c ON c.id = s.chapter_id WHERE s.id = ?1"
+            .to_string()
+    } else {
+        format!("SELECT project_id FROM {} WHERE id = ?1", kind.table())
+    };
+    conn.query_row(&sql, params![id], |row| row.get(0))
+        .optional()?
+        .ok_or_else(|| TagError::NotFound {
+            kind: kind.as_str(),
+            id: id.to_string(),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database;
+    use crate::database::test_support::project_db;
+
+    fn tag(id: &str, name: &str) -> Tag {
+        Tag {
+            id: id.to_string(),
+            project_id: "p1".to_string(),
+            name: name.to_string(),
+            color: None,
+        }
+    }
+
+    #[test]
+    fn test_tags_combine_rename_and_merge() {
+        let conn = project_db();
+        conn.execute_batch(
+            r#"INSERT INTO chapters (id, project_id, title) VALUES ('ch1', 'p1', 'One');
+               INSERT INTO scenes (id, chapter_id, title) VALUES ('s1', 'ch1', 'Dock');
+               INSERT INTO characters (id, project_id, name) VALUES ('c1', 'p1', 'Ada');"#,
+        )
+        .unwrap();
+
+        create_tag(&conn, &tag("t1", "Act I")).unwrap();
+        create_tag(&conn, &tag("t2", "Villain")).unwrap();
+        create_tag(&conn, &tag("t3", "villains")).unwrap();
+        assert!(matches!(
+            create_tag(&conn, &tag("t4", " act i ")),
+            Err(TagError::NameTaken(_))
+        ));
+
+        assert!(tag_entity(&conn, EntityKind::Scene, "s1", "t1").unwrap());
+        assert!(!tag_entity(&conn, EntityKind::Scene, "s1", "t1").unwrap());
+        tag_entity(&conn, EntityKind::Scene, "s1", "t2").unwrap();
+        tag_entity(&conn, EntityKind::Character, "c1", "t3").unwrap();
+        tag_entity(&conn, EntityKind::Chapter, "ch1", "t1").unwrap();
+
+        let ids = |tags: &[&str], mode| -> Vec<String> {
+            let tags: Vec<String> = tags.iter().map(|t| t.to_string()).collect();
+            get_entities_by_tags(&conn, "p1", &tags, mode, &[])
+                .unwrap()
+                .into_iter()
+                .map(|e| e.entity_id)
+                .collect()
+        };
+        assert_eq!(ids(&["t1", "t2"], TagMatch::All), ["s1"]);
+        assert_eq!(ids(&["t1", "t3"], TagMatch::Any), ["ch1", "c1", "s1"]);
+
+        // Merging keeps one tag per entity
+        tag_entity(&conn, EntityKind::Scene, "s1", "t3").unwrap();
+        assert_eq!(merge_tags(&conn, &["t3".to_string()], "t2").unwrap(), 1);
+        assert_eq!(ids(&["t2"], TagMatch::Any), ["c1", "s1"]);
+
+        let mut renamed = tag("t2", "Antagonist");
+        renamed.color = Some("#e11d48".to_string());
+        update_tag(&conn, &renamed).unwrap();
+        assert_eq!(
+            get_entity_tags(&conn, EntityKind::Character, "c1").unwrap(),
+            [renamed]
+        );
+
+        // Trashed entities drop out of the results
+        database::delete_character(&conn, "c1").unwrap();
+        assert_eq!(ids(&["t2"], TagMatch::Any), ["s1"]);
+    }
+}
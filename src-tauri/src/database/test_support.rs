@@ -0,0 +1,19 @@
+//! Fixtures shared by the database tests
+
+use rusqlite::Connection;
+
+/// A fresh in-memory database at the current schema
+pub(crate) fn empty_db() -> Connection {
+    let conn = Connection::open_in_memory().unwrap();
+    super::configure_connection(&conn).unwrap();
+    super::init_database(&conn).unwrap();
+    conn
+}
+
+/// [`empty_db`] holding one project, `p1` ("Saga")
+pub(crate) fn project_db() -> Connection {
+    let conn = empty_db();
+    conn.execute("INSERT INTO projects (id, title) VALUES ('p1', 'Saga')", [])
+        .unwrap();
+    conn
+}
@@ -0,0 +1,201 @@
+//! Plain-text helpers for HTML chapter content
+
+use serde::{Deserialize, Serialize};
+
+/// Block-level tags that separate words when stripped
+const BLOCK_TAGS: &[&str] = &[
+    "p", "br", "div", "li", "ul", "ol", "h1", "h2", "h3", "h4", "h5", "h6", "blockquote", "hr",
+    "tr", "td",
+];
+
+/// Strip HTML tags, keeping block boundaries as line breaks
+pub fn strip_html(html: &str) -> String {
+    let mut result = String::with_capacity(html.len());
+    let mut tag = String::new();
+    let mut in_tag = false;
+
+    for c in html.chars() {
+        match c {
+            '<' if !in_tag => {
+                in_tag = true;
+                tag.clear();
+            }
+            '>' if in_tag => {
+                in_tag = false;
+                let name: String = tag
+                    .trim_start_matches('/')
+                    .chars()
+                    .take_while(|c| c.is_ascii_alphanumeric())
+                    .collect::<String>()
+                    .to_ascii_lowercase();
+                if BLOCK_TAGS.contains(&name.as_str()) && !result.ends_with('\n') {
+                    result.push('\n');
+                }
+            }
+            _ if in_tag => tag.push(c),
+            _ => result.push(c),
+        }
+    }
+
+    decode_entities(&result).trim().to_string()
+}
+
+/// Decode the HTML entities an editor commonly writes
+pub fn decode_entities(text: &str) -> String {
+    text.replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Escape text for use inside HTML content
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Count words in plain text
+pub fn count_words(text: &str) -> u32 {
+    text.split_whitespace().count() as u32
+}
+
+/// Kind of change in a word diff
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DiffOp {
+    Equal,
+    Insert,
+    Delete,
+}
+
+/// A run of words sharing the same diff operation
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiffSegment {
+    pub op: DiffOp,
+    pub text: String,
+    pub word_count: u32,
+}
+
+/// Edit distance above which the diff gives up on finding the shortest
+/// script and reports a full replacement. The trace kept for backtracking
+/// grows with the square of the distance: (D + 1)² positions, about 8 MB
+/// at this cap.
+const MAX_DIFF_DISTANCE: usize = 1000;
+
+/// Word-level diff of two plain texts (Myers' algorithm)
+pub fn diff_words(old: &str, new: &str) -> Vec<DiffSegment> {
+    let a: Vec<&str> = old.split_whitespace().collect();
+    let b: Vec<&str> = new.split_whitespace().collect();
+
+    let prefix = a.iter().zip(&b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let (a_mid, b_mid) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+
+    let mut ops: Vec<(DiffOp, &str)> = a[..prefix].iter().map(|w| (DiffOp::Equal, *w)).collect();
+    match shortest_edit(a_mid, b_mid) {
+        Some(middle) => ops.extend(middle),
+        None => {
+            ops.extend(a_mid.iter().map(|w| (DiffOp::Delete, *w)));
+            ops.extend(b_mid.iter().map(|w| (DiffOp::Insert, *w)));
+        }
+    }
+    ops.extend(a[a.len() - suffix..].iter().map(|w| (DiffOp::Equal, *w)));
+
+    let mut segments: Vec<DiffSegment> = Vec::new();
+    for (op, word) in ops {
+        match segments.last_mut() {
+            Some(last) if last.op == op => {
+                last.text.push(' ');
+                last.text.push_str(word);
+                last.word_count += 1;
+            }
+            _ => segments.push(DiffSegment {
+                op,
+                text: word.to_string(),
+                word_count: 1,
+            }),
+        }
+    }
+    segments
+}
+
+/// Myers' O(ND) shortest edit script, or None past [`MAX_DIFF_DISTANCE`]
+fn shortest_edit<'a>(a: &[&'a str], b: &[&'a str]) -> Option<Vec<(DiffOp, &'a str)>> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = (n + m) as usize;
+    let offset = max as isize + 1;
+    let mut v = vec![0isize; 2 * max + 3];
+    // trace[d] holds the furthest x per diagonal k in -d..=d before round d
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+
+    for d in 0..=max.min(MAX_DIFF_DISTANCE) as isize {
+        trace.push(v[(offset - d) as usize..=(offset + d) as usize].to_vec());
+        for k in (-d..=d).step_by(2) {
+            let idx = (offset + k) as usize;
+            let mut x = if k == -d || (k != d && v[idx - 1] < v[idx + 1]) {
+                v[idx + 1]
+            } else {
+                v[idx - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[idx] = x;
+            if x >= n && y >= m {
+                return Some(backtrack(a, b, &trace));
+            }
+        }
+    }
+    None
+}
+
+fn backtrack<'a>(a: &[&'a str], b: &[&'a str], trace: &[Vec<isize>]) -> Vec<(DiffOp, &'a str)> {
+    let (mut x, mut y) = (a.len() as isize, b.len() as isize);
+    let mut ops = Vec::new();
+
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let at = |k: isize| v[(k + d) as usize];
+        let k = x - y;
+        let (prev_x, prev_y) = if d == 0 {
+            (0, 0)
+        } else {
+            let prev_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) {
+                k + 1
+            } else {
+                k - 1
+            };
+            let prev_x = at(prev_k);
+            (prev_x, prev_x - prev_k)
+        };
+
+        while x > prev_x && y > prev_y {
+            ops.push((DiffOp::Equal, a[x as usize - 1]));
+            x -= 1;
+            y -= 1;
+        }
+        if d > 0 {
+            if x == prev_x {
+                ops.push((DiffOp::Insert, b[y as usize - 1]));
+            } else {
+                ops.push((DiffOp::Delete, a[x as usize - 1]));
+            }
+        }
+        x = prev_x;
+        y = prev_y;
+    }
+
+    ops.reverse();
+    ops
+}
@@ -0,0 +1,469 @@
+//! Recoverable trash for soft-deleted entities
+//!
+//! Deleting an entity stamps `deleted_at` on it and on everything it owns
+//! (a chapter's scenes, a character's relationships, a whole project's
+//! content) with the same value. The trash lists only the items the user
+//! deleted directly; restoring one brings back whatever was trashed with it,
+//! while children trashed separately beforehand stay in the trash.
+
+use super::assembly;
+use super::mentions;
+use super::models::EntityKind;
+use super::operations::with_savepoint;
+use super::tags;
+use super::undo;
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::{Deserialize, Serialize};
+
+/// Tables owned directly by a project, in purge order
+const PROJECT_TABLES: &[&str] = &[
+    "timeline_events",
+    "lore_items",
+    "locations",
+    "characters",
+    "chapters",
+    "creatures",
+    "npcs",
+    "world_rules",
+];
+
+/// An entry in the trash
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TrashItem {
+    pub entity_type: EntityKind,
+    pub id: String,
+    pub project_id: Option<String>,
+    pub title: String,
+    pub deleted_at: String,
+}
+
+/// Queries listing directly-deleted items per entity type. `?1` is an
+/// optional project filter; rows trashed with their parent are skipped.
+const TRASH_QUERIES: &[(EntityKind, &str)] = &[
+    (
+        EntityKind::Chapter,
+        r#"SELECT c.id, c.project_id, c.title, c.deleted_at FROM chapters c
+           JOIN projects p ON p.id = c.project_id
+           WHERE c.deleted_at IS NOT NULL AND c.deleted_at IS NOT p.deleted_at
+             AND (?1 IS NULL OR c.project_id = ?1)"#,
+    ),
+    (
+        EntityKind::Scene,
+        r#"SELECT s.id, c.project_id, s.title, s.deleted_at FROM scenes s
+           JOIN chapters c ON c.id = s.chapter_id
+           WHERE s.deleted_at IS NOT NULL AND s.deleted_at IS NOT c.deleted_at
+             AND (?1 IS NULL OR c.project_id = ?1)"#,
+    ),
+    (
+        EntityKind::Character,
+        r#"SELECT c.id, c.project_id, c.name, c.deleted_at FROM characters c
+           JOIN projects p ON p.id = c.project_id
+           WHERE c.deleted_at IS NOT NULL AND c.deleted_at IS NOT p.deleted_at
+             AND (?1 IS NULL OR c.project_id = ?1)"#,
+    ),
+    (
+        EntityKind::Relationship,
+        r#"SELECT r.id, c.project_id, c.name || ' → ' || COALESCE(t.name, '?'), r.deleted_at
+           FROM relationships r
+           JOIN characters c ON c.id = r.character_id
+           LEFT JOIN characters t ON t.id = r.target_character_id
+           WHERE r.deleted_at IS NOT NULL
+             AND r.deleted_at IS NOT c.deleted_at AND r.deleted_at IS NOT t.deleted_at
+             AND (?1 IS NULL OR c.project_id = ?1)"#,
+    ),
+    (
+        EntityKind::Location,
+        r#"SELECT l.id, l.project_id, l.name, l.deleted_at FROM locations l
+           JOIN projects p ON p.id = l.project_id
+           WHERE l.deleted_at IS NOT NULL AND l.deleted_at IS NOT p.deleted_at
+             AND (?1 IS NULL OR l.project_id = ?1)"#,
+    ),
+    (
+        EntityKind::LoreItem,
+        r#"SELECT l.id, l.project_id, l.title, l.deleted_at FROM lore_items l
+           JOIN projects p ON p.id = l.project_id
+           WHERE l.deleted_at IS NOT NULL AND l.deleted_at IS NOT p.deleted_at
+             AND (?1 IS NULL OR l.project_id = ?1)"#,
+    ),
+    (
+        EntityKind::TimelineEvent,
+        r#"SELECT e.id, e.project_id, e.title, e.deleted_at FROM timeline_events e
+           JOIN projects p ON p.id = e.project_id
+           WHERE e.deleted_at IS NOT NULL AND e.deleted_at IS NOT p.deleted_at
+             AND (?1 IS NULL OR e.project_id = ?1)"#,
+    ),
+    (
+        EntityKind::Creature,
+        r#"SELECT c.id, c.project_id, c.name, c.deleted_at FROM creatures c
+           JOIN projects p ON p.id = c.project_id
+           WHERE c.deleted_at IS NOT NULL AND c.deleted_at IS NOT p.deleted_at
+             AND (?1 IS NULL OR c.project_id = ?1)"#,
+    ),
+    (
+        EntityKind::Npc,
+        r#"SELECT n.id, n.project_id, n.name, n.deleted_at FROM npcs n
+           JOIN projects p ON p.id = n.project_id
+           WHERE n.deleted_at IS NOT NULL AND n.deleted_at IS NOT p.deleted_at
+             AND (?1 IS NULL OR n.project_id = ?1)"#,
+    ),
+    (
+        EntityKind::WorldRule,
+        r#"SELECT w.id, w.project_id, w.title, w.deleted_at FROM world_rules w
+           JOIN projects p ON p.id = w.project_id
+           WHERE w.deleted_at IS NOT NULL AND w.deleted_at IS NOT p.deleted_at
+             AND (?1 IS NULL OR w.project_id = ?1)"#,
+    ),
+];
+
+/// Items deleted from a project (or from every project), newest first
+pub fn list_trash(conn: &Connection, project_id: Option<&str>) -> Result<Vec<TrashItem>> {
+    let mut items = Vec::new();
+    for (kind, sql) in TRASH_QUERIES {
+        let mut stmt = conn.prepare(sql)?;
+        let rows = stmt.query_map(params![project_id], |row| {
+            Ok(TrashItem {
+                entity_type: *kind,
+                id: row.get(0)?,
+                project_id: row.get(1)?,
+                title: row.get(2)?,
+                deleted_at: row.get(3)?,
+            })
+        })?;
+        for row in rows {
+            items.push(row?);
+        }
+    }
+    items.sort_by(|a, b| b.deleted_at.cmp(&a.deleted_at));
+    Ok(items)
+}
+
+/// Deleted projects, newest first
+pub fn list_deleted_projects(conn: &Connection) -> Result<Vec<TrashItem>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, title, deleted_at FROM projects WHERE deleted_at IS NOT NULL ORDER BY deleted_at DESC",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        let id: String = row.get(0)?;
+        Ok(TrashItem {
+            entity_type: EntityKind::Project,
+            project_id: Some(id.clone()),
+            id,
+            title: row.get(1)?,
+            deleted_at: row.get(2)?,
+        })
+    })?;
+    rows.collect()
+}
+
+/// Whether an entity exists and is in the trash
+pub fn is_in_trash(conn: &Connection, kind: EntityKind, id: &str) -> Result<bool> {
+    Ok(deleted_at(conn, kind, id)?.is_some())
+}
+
+fn deleted_at(conn: &Connection, kind: EntityKind, id: &str) -> Result<Option<String>> {
+    conn.query_row(
+        &format!("SELECT deleted_at FROM {} WHERE id = ?1", kind.table()),
+        params![id],
+        |row| row.get::<_, Option<String>>(0),
+    )
+    .optional()
+    .map(Option::flatten)
+}
+
+/// Restore an item and everything trashed along with it. A trashed parent
+/// is restored first, so the item never comes back into a deleted project
+/// or chapter. Returns false if the item is not in the trash.
+pub fn restore_from_trash(conn: &Connection, kind: EntityKind, id: &str) -> Result<bool> {
+    with_savepoint(conn, || restore_item(conn, kind, id))
+}
+
+fn restore_item(conn: &Connection, kind: EntityKind, id: &str) -> Result<bool> {
+    let Some(stamp) = deleted_at(conn, kind, id)? else {
+        return Ok(false);
+    };
+
+    for (parent_kind, parent_id) in parents(conn, kind, id)? {
+        restore_item(conn, parent_kind, &parent_id)?;
+    }
+
+    // The parent's restore may already have covered this item
+    if deleted_at(conn, kind, id)?.is_none() {
+        return Ok(true);
+    }
+
+    conn.execute(
+        &format!(
+            "UPDATE {} SET deleted_at = NULL WHERE id = ?1",
+            kind.table()
+        ),
+        params![id],
+    )?;
+
+    match kind {
+        EntityKind::Project => {
+            for table in PROJECT_TABLES {
+                conn.execute(
+                    &format!(
+                        "UPDATE {} SET deleted_at = NULL WHERE project_id = ?1 AND deleted_at = ?2",
+                        table
+                    ),
+                    params![id, stamp],
+                )?;
+            }
+            conn.execute(
+                r#"UPDATE scenes SET deleted_at = NULL
+                   WHERE chapter_id IN (SELECT id FROM chapters WHERE project_id = ?1) AND deleted_at = ?2"#,
+                params![id, stamp],
+            )?;
+            conn.execute(
+                r#"UPDATE relationships SET deleted_at = NULL
+                   WHERE character_id IN (SELECT id FROM characters WHERE project_id = ?1) AND deleted_at = ?2"#,
+                params![id, stamp],
+            )?;
+        }
+        EntityKind::Chapter => {
+            conn.execute(
+                "UPDATE scenes SET deleted_at = NULL WHERE chapter_id = ?1 AND deleted_at = ?2",
+                params![id, stamp],
+            )?;
+        }
+        EntityKind::Scene => assembly::recompile_scene_chapter(conn, id)?,
+        EntityKind::Character => {
+            conn.execute(
+                r#"UPDATE relationships SET deleted_at = NULL
+                   WHERE (character_id = ?1 OR target_character_id = ?1) AND deleted_at = ?2"#,
+                params![id, stamp],
+            )?;
+        }
+        _ => {}
+    }
+    Ok(true)
+}
+
+/// Trashed owners an item depends on
+fn parents(conn: &Connection, kind: EntityKind, id: &str) -> Result<Vec<(EntityKind, String)>> {
+    let sql = match kind {
+        EntityKind::Project => return Ok(vec![]),
+        EntityKind::Scene => {
+            "SELECT 'chapter', c.id FROM scenes s JOIN chapters c ON c.id = s.chapter_id
+             WHERE s.id = ?1 AND c.deleted_at IS NOT NULL"
+        }
+        EntityKind::Relationship => {
+            "SELECT 'character', c.id FROM relationships r JOIN characters c
+               ON c.id = r.character_id OR c.id = r.target_character_id
+             WHERE r.id = ?1 AND c.deleted_at IS NOT NULL"
+        }
+        _ => {
+            return match project_of(conn, kind, id)? {
+                Some(project_id) if is_in_trash(conn, EntityKind::Project, &project_id)? => {
+                    Ok(vec![(EntityKind::Project, project_id)])
+                }
+                _ => Ok(vec![]),
+            };
+        }
+    };
+
+    let mut stmt = conn.prepare(sql)?;
+    let rows = stmt.query_map(params![id], |row| {
+        let kind: String = row.get(0)?;
+        Ok((kind, row.get::<_, String>(1)?))
+    })?;
+    let mut result = Vec::new();
+    for row in rows {
+        let (kind, id) = row?;
+        if let Some(kind) = EntityKind::from_db(&kind) {
+            result.push((kind, id));
+        }
+    }
+    Ok(result)
+}
+
+fn project_of(conn: &Connection, kind: EntityKind, id: &str) -> Result<Option<String>> {
+    conn.query_row(
+        &format!("SELECT project_id FROM {} WHERE id = ?1", kind.table()),
+        params![id],
+        |row| row.get(0),
+    )
+    .optional()
+}
+
+/// Permanently delete a trashed item and everything it owns.
+/// Returns false if the item is not in the trash.
+pub fn purge_from_trash(conn: &Connection, kind: EntityKind, id: &str) -> Result<bool> {
+    if !is_in_trash(conn, kind, id)? {
+        return Ok(false);
+    }
+    with_savepoint(conn, || purge_item(conn, kind, id))?;
+    Ok(true)
+}
+
+fn purge_item(conn: &Connection, kind: EntityKind, id: &str) -> Result<()> {
+    mentions::forget(conn, kind, id)?;
+    tags::forget(conn, kind, id)?;
+    undo::forget(conn, kind, id)?;
+    match kind {
+        EntityKind::Project => {
+            conn.execute(
+                "DELETE FROM scenes WHERE chapter_id IN (SELECT id FROM chapters WHERE project_id = ?1)",
+                params![id],
+            )?;
+            conn.execute(
+                "DELETE FROM chapter_revisions WHERE project_id = ?1",
+                params![id],
+            )?;
+            conn.execute(
+                r#"DELETE FROM relationships
+                   WHERE character_id IN (SELECT id FROM characters WHERE project_id = ?1)
+                      OR target_character_id IN (SELECT id FROM characters WHERE project_id = ?1)"#,
+                params![id],
+            )?;
+            for table in PROJECT_TABLES {
+                conn.execute(
+                    &format!("DELETE FROM {} WHERE project_id = ?1", table),
+                    params![id],
+                )?;
+            }
+        }
+        EntityKind::Chapter => {
+            conn.execute("DELETE FROM scenes WHERE chapter_id = ?1", params![id])?;
+            conn.execute(
+                "DELETE FROM chapter_revisions WHERE chapter_id = ?1",
+                params![id],
+            )?;
+        }
+        EntityKind::Character => {
+            conn.execute(
+                "DELETE FROM relationships WHERE character_id = ?1 OR target_character_id = ?1",
+                params![id],
+            )?;
+        }
+        _ => {}
+    }
+    conn.execute(
+        &format!("DELETE FROM {} WHERE id = ?1", kind.table()),
+        params![id],
+    )?;
+    Ok(())
+}
+
+/// Permanently delete everything in a project's trash. Returns the number
+/// of trash entries removed.
+pub fn empty_trash(conn: &Connection, project_id: &str) -> Result<usize> {
+    let items = list_trash(conn, Some(project_id))?;
+    with_savepoint(conn, || {
+        for item in &items {
+            purge_item(conn, item.entity_type, &item.id)?;
+        }
+        Ok(items.len())
+    })
+}
+
+/// Purge trash entries older than `retention_days`, deleted projects
+/// included. Returns the number of entries removed.
+pub fn purge_expired_trash(conn: &Connection, retention_days: i64) -> Result<usize> {
+    let cutoff = (chrono::Utc::now() - chrono::Duration::days(retention_days))
+        .to_rfc3339_opts(chrono::SecondsFormat::Micros, true);
+
+    let mut items = list_deleted_projects(conn)?;
+    items.extend(list_trash(conn, None)?);
+    items.retain(|item| item.deleted_at < cutoff);
+
+    with_savepoint(conn, || {
+        for item in &items {
+            purge_item(conn, item.entity_type, &item.id)?;
+        }
+        Ok(items.len())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database;
+    use crate::database::test_support::project_db;
+    use serde_json::json;
+
+    fn setup() -> Connection {
+        let conn = project_db();
+        let chapter = serde_json::from_value(json!({
+            "id": "c1",
+            "projectId": "p1",
+            "title": "Arrival",
+            "content": "<p>The obsidian gate</p>",
+        }))
+        .unwrap();
+        database::create_chapter(&conn, &chapter).unwrap();
+        for id in ["s1", "s2"] {
+            let scene = serde_json::from_value(json!({
+                "id": id,
+                "chapterId": "c1",
+                "title": format!("Scene {}", id),
+            }))
+            .unwrap();
+            database::create_scene(&conn, &scene).unwrap();
+        }
+        conn
+    }
+
+    #[test]
+    fn test_delete_and_restore_chapter_with_scenes() {
+        let conn = setup();
+        database::delete_scene(&conn, "s2").unwrap();
+        database::delete_chapter(&conn, "c1").unwrap();
+
+        assert!(database::get_chapters_by_project(&conn, "p1")
+            .unwrap()
+            .is_empty());
+        assert!(database::search(&conn, "obsidian", None, &[], None)
+            .unwrap()
+            .is_empty());
+
+        // The scene cascaded with the chapter is not listed on its own
+        let trash = list_trash(&conn, Some("p1")).unwrap();
+        let ids: Vec<&str> = trash.iter().map(|item| item.id.as_str()).collect();
+        assert_eq!(ids.len(), 2);
+        assert!(ids.contains(&"c1") && ids.contains(&"s2"));
+
+        assert!(restore_from_trash(&conn, EntityKind::Chapter, "c1").unwrap());
+        let scenes = database::get_scenes_by_chapter(&conn, "c1").unwrap();
+        assert_eq!(scenes.len(), 1);
+        assert_eq!(scenes[0].id, "s1");
+        assert_eq!(
+            database::search(&conn, "obsidian", None, &[], None)
+                .unwrap()
+                .len(),
+            1
+        );
+
+        // Restoring a scene whose chapter is trashed brings the chapter back
+        database::delete_chapter(&conn, "c1").unwrap();
+        assert!(restore_from_trash(&conn, EntityKind::Scene, "s2").unwrap());
+        assert_eq!(
+            database::get_scenes_by_chapter(&conn, "c1").unwrap().len(),
+            2
+        );
+        assert!(!restore_from_trash(&conn, EntityKind::Scene, "s2").unwrap());
+    }
+
+    #[test]
+    fn test_purge_and_expiry() {
+        let conn = setup();
+        database::delete_project(&conn, "p1").unwrap();
+        assert!(database::get_project(&conn, "p1").unwrap().is_none());
+        assert_eq!(list_deleted_projects(&conn).unwrap().len(), 1);
+        assert!(list_trash(&conn, Some("p1")).unwrap().is_empty());
+
+        assert_eq!(purge_expired_trash(&conn, 30).unwrap(), 0);
+        conn.execute_batch("UPDATE projects SET deleted_at = '2000-01-01T00:00:00.000000Z'")
+            .unwrap();
+        // Children keep their own stamp, but go with the project
+        assert_eq!(purge_expired_trash(&conn, 30).unwrap(), 1);
+
+        let scenes: i64 = conn
+            .query_row("SELECT COUNT(*) FROM scenes", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(scenes, 0);
+        assert!(!purge_from_trash(&conn, EntityKind::Project, "p1").unwrap());
+    }
+}
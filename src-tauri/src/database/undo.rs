@@ -0,0 +1,732 @@
+//! Per-project undo/redo journal
+//!
+//! Commands run their writes through [`undoable`], which opens a
+//! user-level action; while it is open, triggers on the entity tables copy
+//! each touched row before and after the change into `undo_changes`.
+//! Undoing an action walks its changes backwards and puts every row back
+//! the way it was; redoing walks them forwards again.
+//!
+//! Rows are restored column by column, and only where the column still
+//! holds the value the step left there, so an undo never overwrites a
+//! later edit that was not journaled.
+//!
+//! Tags, aliases and custom field definitions are journaled too. The link
+//! tables (`entity_tags`, `entity_aliases`) have no `id`; their rows are
+//! journaled under the entity they belong to and matched on every column.
+//!
+//! Consecutive edits of the same row under the same label, within
+//! [`COALESCE_SECS`] of the first, collapse into one step: editors autosave
+//! as the user types, and each save should not be its own undo step.
+
+use super::audit::{owning_project, table_columns, AUDITED};
+use super::mentions;
+use super::models::EntityKind;
+use super::operations::with_savepoint;
+use rusqlite::{
+    params, params_from_iter, types::Value as SqlValue, Connection, OptionalExtension, Result,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::cell::Cell;
+
+/// Undo steps kept per project; older ones are dropped
+const MAX_STEPS: i64 = 100;
+
+/// Window in which repeated edits of one row share a step
+const COALESCE_SECS: i64 = 60;
+
+/// Tables keyed by `id` and owned by a project through `project_id`,
+/// journaled besides the entity tables
+const OWNED_TABLES: &[&str] = &["tags", "custom_fields"];
+
+/// Link tables: (table, SQL giving the project of a trigger's `{row}`)
+const LINK_TABLES: &[(&str, &str)] = &[
+    (
+        "entity_tags",
+        "(SELECT project_id FROM tags WHERE id = {row}.tag_id)",
+    ),
+    ("entity_aliases", "{row}.project_id"),
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UndoStep {
+    pub id: i64,
+    pub label: String,
+    pub created_at: String,
+}
+
+/// Pending steps of a project, next one first
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UndoSteps {
+    pub undo: Vec<UndoStep>,
+    pub redo: Vec<UndoStep>,
+}
+
+thread_local! {
+    static ACTION: Cell<Option<i64>> = const { Cell::new(None) };
+}
+
+/// Closes the open action when dropped, even on panic
+struct ActionGuard;
+
+impl Drop for ActionGuard {
+    fn drop(&mut self) {
+        ACTION.with(|action| action.set(None));
+    }
+}
+
+/// Action the journal triggers file changes under, if one is open
+pub(crate) fn current_action() -> Option<i64> {
+    ACTION.with(Cell::get)
+}
+
+/// Run `f` as one undoable action named `label`. Calls nested inside an
+/// open action join it.
+pub fn undoable<T, E>(
+    conn: &Connection,
+    label: &str,
+    f: impl FnOnce() -> std::result::Result<T, E>,
+) -> std::result::Result<T, E>
+where
+    E: From<rusqlite::Error>,
+{
+    if current_action().is_some() {
+        return f();
+    }
+
+    conn.execute(
+        "INSERT INTO undo_actions (label) VALUES (?1)",
+        params![label],
+    )?;
+    let action_id = conn.last_insert_rowid();
+    let result = {
+        ACTION.with(|action| action.set(Some(action_id)));
+        let _guard = ActionGuard;
+        f()
+    };
+    // Whatever `f` wrote before failing is in the database, so it is
+    // journaled either way
+    finish_action(conn, action_id)?;
+    result
+}
+
+fn finish_action(conn: &Connection, action_id: i64) -> Result<()> {
+    let project_id: Option<String> = conn
+        .query_row(
+            r#"SELECT project_id FROM undo_changes
+               WHERE action_id = ?1 AND project_id IS NOT NULL
+               ORDER BY id LIMIT 1"#,
+            params![action_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    let Some(project_id) = project_id else {
+        conn.execute("DELETE FROM undo_actions WHERE id = ?1", params![action_id])?;
+        return Ok(());
+    };
+
+    conn.execute(
+        "UPDATE undo_actions SET project_id = ?2 WHERE id = ?1",
+        params![action_id, project_id],
+    )?;
+    if coalesce(conn, action_id, &project_id)? {
+        return Ok(());
+    }
+
+    // A new action replaces whatever could have been redone
+    conn.execute(
+        "DELETE FROM undo_actions WHERE project_id = ?1 AND undone = 1",
+        params![project_id],
+    )?;
+    conn.execute(
+        r#"DELETE FROM undo_actions
+           WHERE project_id = ?1
+             AND id NOT IN (SELECT id FROM undo_actions WHERE project_id = ?1
+                            ORDER BY id DESC LIMIT ?2)"#,
+        params![project_id, MAX_STEPS],
+    )?;
+    Ok(())
+}
+
+/// Fold an action into the previous step when both edit the same row
+/// under the same label. Returns whether it did.
+fn coalesce(conn: &Connection, action_id: i64, project_id: &str) -> Result<bool> {
+    let previous: Option<i64> = conn
+        .query_row(
+            &format!(
+                r#"SELECT prev.id FROM undo_actions prev, undo_actions cur
+                   WHERE cur.id = ?1 AND prev.label = cur.label AND prev.undone = 0
+                     AND prev.created_at >= datetime('now', '-{} seconds')
+                     AND prev.id = (SELECT MAX(id) FROM undo_actions
+                                    WHERE project_id = ?2 AND id < ?1)"#,
+                COALESCE_SECS
+            ),
+            params![action_id, project_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    let Some(previous) = previous else {
+        return Ok(false);
+    };
+
+    let single_update = |id: i64| -> Result<Option<(String, String)>> {
+        let changes: Vec<(String, String, bool)> = {
+            let mut stmt = conn.prepare(
+                r#"SELECT entity_table, entity_id, before IS NOT NULL AND after IS NOT NULL
+                   FROM undo_changes WHERE action_id = ?1"#,
+            )?;
+            let rows = stmt.query_map(params![id], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })?;
+            rows.collect::<Result<_>>()?
+        };
+        Ok(match changes.as_slice() {
+            [(table, id, true)] => Some((table.clone(), id.clone())),
+            _ => None,
+        })
+    };
+    let row = single_update(action_id)?;
+    if row.is_none() || row != single_update(previous)? {
+        return Ok(false);
+    }
+
+    conn.execute(
+        r#"UPDATE undo_changes
+           SET after = (SELECT after FROM undo_changes WHERE action_id = ?2)
+           WHERE action_id = ?1"#,
+        params![previous, action_id],
+    )?;
+    conn.execute("DELETE FROM undo_actions WHERE id = ?1", params![action_id])?;
+    Ok(true)
+}
+
+/// Undo the latest step of a project. Returns its label, or None if there
+/// is nothing to undo.
+pub fn undo(conn: &Connection, project_id: &str) -> Result<Option<String>> {
+    let step: Option<(i64, String)> = conn
+        .query_row(
+            r#"SELECT id, label FROM undo_actions
+               WHERE project_id = ?1 AND undone = 0
+               ORDER BY id DESC LIMIT 1"#,
+            params![project_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?;
+    let Some((id, label)) = step else {
+        return Ok(None);
+    };
+
+    with_savepoint(conn, || {
+        for (table, entity_id, before, after) in changes(conn, id)?.into_iter().rev() {
+            apply_change(conn, &table, &entity_id, after.as_ref(), before.as_ref())?;
+        }
+        conn.execute(
+            "UPDATE undo_actions SET undone = 1 WHERE id = ?1",
+            params![id],
+        )?;
+        mentions::reindex_project(conn, project_id)?;
+        Ok(Some(label))
+    })
+}
+
+/// Redo the most recently undone step of a project. Returns its label, or
+/// None if there is nothing to redo.
+pub fn redo(conn: &Connection, project_id: &str) -> Result<Option<String>> {
+    let step: Option<(i64, String)> = conn
+        .query_row(
+            r#"SELECT id, label FROM undo_actions
+               WHERE project_id = ?1 AND undone = 1
+               ORDER BY id LIMIT 1"#,
+            params![project_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?;
+    let Some((id, label)) = step else {
+        return Ok(None);
+    };
+
+    with_savepoint(conn, || {
+        for (table, entity_id, before, after) in changes(conn, id)? {
+            apply_change(conn, &table, &entity_id, before.as_ref(), after.as_ref())?;
+        }
+        conn.execute(
+            "UPDATE undo_actions SET undone = 0 WHERE id = ?1",
+            params![id],
+        )?;
+        mentions::reindex_project(conn, project_id)?;
+        Ok(Some(label))
+    })
+}
+
+/// Labels of a project's pending undo and redo steps
+pub fn get_undo_steps(conn: &Connection, project_id: &str) -> Result<UndoSteps> {
+    let steps = |undone: bool, order: &str| -> Result<Vec<UndoStep>> {
+        let mut stmt = conn.prepare(&format!(
+            r#"SELECT id, label, created_at FROM undo_actions
+               WHERE project_id = ?1 AND undone = ?2
+               ORDER BY id {}"#,
+            order
+        ))?;
+        let rows = stmt.query_map(params![project_id, undone], |row| {
+            Ok(UndoStep {
+                id: row.get(0)?,
+                label: row.get(1)?,
+                created_at: row.get(2)?,
+            })
+        })?;
+        rows.collect()
+    };
+    Ok(UndoSteps {
+        undo: steps(false, "DESC")?,
+        redo: steps(true, "ASC")?,
+    })
+}
+
+/// Drop journaled changes of an entity that is being deleted for good,
+/// along with the rows it takes with it, so redo cannot bring them back
+pub(crate) fn forget(conn: &Connection, kind: EntityKind, id: &str) -> Result<()> {
+    match kind {
+        EntityKind::Project => {
+            conn.execute(
+                "DELETE FROM undo_actions WHERE project_id = ?1",
+                params![id],
+            )?;
+            return Ok(());
+        }
+        EntityKind::Chapter => {
+            conn.execute(
+                r#"DELETE FROM undo_changes
+                   WHERE entity_table = 'scenes'
+                     AND entity_id IN (SELECT id FROM scenes WHERE chapter_id = ?1)"#,
+                params![id],
+            )?;
+            conn.execute(
+                r#"DELETE FROM undo_changes
+                   WHERE entity_table IN ('entity_tags', 'entity_aliases')
+                     AND json_extract(COALESCE(before, after), '$.entity_type') = 'scene'
+                     AND entity_id IN (SELECT id FROM scenes WHERE chapter_id = ?1)"#,
+                params![id],
+            )?;
+        }
+        EntityKind::Character => {
+            conn.execute(
+                r#"DELETE FROM undo_changes
+                   WHERE entity_table = 'relationships'
+                     AND entity_id IN (SELECT id FROM relationships
+                                       WHERE character_id = ?1 OR target_character_id = ?1)"#,
+                params![id],
+            )?;
+        }
+        _ => {}
+    }
+    conn.execute(
+        "DELETE FROM undo_changes WHERE entity_table = ?1 AND entity_id = ?2",
+        params![kind.table(), id],
+    )?;
+    conn.execute(
+        r#"DELETE FROM undo_changes
+           WHERE entity_table IN ('entity_tags', 'entity_aliases') AND entity_id = ?2
+             AND json_extract(COALESCE(before, after), '$.entity_type') = ?1"#,
+        params![kind.as_str(), id],
+    )?;
+    conn.execute(
+        r#"DELETE FROM undo_actions
+           WHERE NOT EXISTS (SELECT 1 FROM undo_changes WHERE action_id = undo_actions.id)"#,
+        [],
+    )?;
+    Ok(())
+}
+
+type Change = (
+    String,
+    String,
+    Option<Map<String, Value>>,
+    Option<Map<String, Value>>,
+);
+
+fn changes(conn: &Connection, action_id: i64) -> Result<Vec<Change>> {
+    let parse = |json: Option<String>| {
+        json.and_then(|s| serde_json::from_str::<Map<String, Value>>(&s).ok())
+    };
+    let mut stmt = conn.prepare(
+        r#"SELECT entity_table, entity_id, before, after FROM undo_changes
+           WHERE action_id = ?1 ORDER BY id"#,
+    )?;
+    let rows = stmt.query_map(params![action_id], |row| {
+        Ok((
+            row.get(0)?,
+            row.get(1)?,
+            parse(row.get(2)?),
+            parse(row.get(3)?),
+        ))
+    })?;
+    rows.collect()
+}
+
+/// Move a row from the `from` state to the `to` state; None means the row
+/// does not exist in that state
+fn apply_change(
+    conn: &Connection,
+    table: &str,
+    id: &str,
+    from: Option<&Map<String, Value>>,
+    to: Option<&Map<String, Value>>,
+) -> Result<()> {
+    if LINK_TABLES.iter().any(|(link, _)| *link == table) {
+        return apply_link_change(conn, table, from, to);
+    }
+    if !AUDITED.iter().any(|kind| kind.table() == table) && !OWNED_TABLES.contains(&table) {
+        return Ok(());
+    }
+    // Columns dropped by a later migration are left out
+    let columns = table_columns(conn, table)?;
+    let known = |column: &String| columns.contains(column);
+
+    match (from, to) {
+        (_, None) => {
+            conn.execute(&format!("DELETE FROM {} WHERE id = ?1", table), params![id])?;
+        }
+        (None, Some(to)) => insert_row(conn, table, &columns, to)?,
+        (Some(from), Some(to)) => {
+            let Some(current) = current_row(conn, table, id, &columns)? else {
+                return Ok(());
+            };
+            let updates: Vec<(&String, &Value)> = to
+                .iter()
+                .filter(|(column, value)| {
+                    known(column)
+                        && from.get(*column) != Some(*value)
+                        && current.get(*column) == from.get(*column)
+                })
+                .collect();
+            if updates.is_empty() {
+                return Ok(());
+            }
+            let assignments: Vec<String> = updates
+                .iter()
+                .enumerate()
+                .map(|(i, (column, _))| format!("{} = ?{}", column, i + 2))
+                .collect();
+            let mut values = vec![SqlValue::Text(id.to_string())];
+            values.extend(updates.iter().map(|(_, value)| sql_value(value)));
+            conn.execute(
+                &format!(
+                    "UPDATE {} SET {} WHERE id = ?1",
+                    table,
+                    assignments.join(", ")
+                ),
+                params_from_iter(values),
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Link rows are only added or removed; their triggers journal an update
+/// as a removal followed by an addition
+fn apply_link_change(
+    conn: &Connection,
+    table: &str,
+    from: Option<&Map<String, Value>>,
+    to: Option<&Map<String, Value>>,
+) -> Result<()> {
+    let columns = table_columns(conn, table)?;
+    match (from, to) {
+        (Some(from), None) => {
+            let names: Vec<&String> = from.keys().filter(|c| columns.contains(c)).collect();
+            let conditions: Vec<String> = names
+                .iter()
+                .enumerate()
+                .map(|(i, column)| format!("{} IS ?{}", column, i + 1))
+                .collect();
+            conn.execute(
+                &format!("DELETE FROM {} WHERE {}", table, conditions.join(" AND ")),
+                params_from_iter(names.iter().map(|c| sql_value(&from[*c]))),
+            )?;
+        }
+        (None, Some(to)) => insert_row(conn, table, &columns, to)?,
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Put a journaled row back, unless a row with the same key exists again
+fn insert_row(
+    conn: &Connection,
+    table: &str,
+    columns: &[String],
+    row: &Map<String, Value>,
+) -> Result<()> {
+    let names: Vec<&String> = row.keys().filter(|c| columns.contains(c)).collect();
+    let placeholders: Vec<String> = (1..=names.len()).map(|i| format!("?{}", i)).collect();
+    let sql = format!(
+        "INSERT INTO {} ({}) VALUES ({}) ON CONFLICT DO NOTHING",
+        table,
+        names
+            .iter()
+            .map(|c| c.as_str())
+            .collect::<Vec<_>>()
+            .join(", "),
+        placeholders.join(", ")
+    );
+    conn.execute(
+        &sql,
+        params_from_iter(names.iter().map(|c| sql_value(&row[*c]))),
+    )?;
+    Ok(())
+}
+
+fn current_row(
+    conn: &Connection,
+    table: &str,
+    id: &str,
+    columns: &[String],
+) -> Result<Option<Map<String, Value>>> {
+    let json: Option<String> = conn
+        .query_row(
+            &format!(
+                "SELECT {} FROM {} WHERE id = ?1",
+                row_object(columns, table),
+                table
+            ),
+            params![id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(json.and_then(|s| serde_json::from_str(&s).ok()))
+}
+
+/// Bind a journaled JSON value back to its column
+fn sql_value(value: &Value) -> SqlValue {
+    match value {
+        Value::Null => SqlValue::Null,
+        Value::Bool(b) => SqlValue::Integer(*b as i64),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => SqlValue::Integer(i),
+            None => SqlValue::Real(n.as_f64().unwrap_or_default()),
+        },
+        Value::String(s) => SqlValue::Text(s.clone()),
+        other => SqlValue::Text(other.to_string()),
+    }
+}
+
+/// SQL building a JSON object of every column of `row`
+fn row_object(columns: &[String], row: &str) -> String {
+    let pairs: Vec<String> = columns
+        .iter()
+        .map(|c| format!("'{c}', {row}.{c}"))
+        .collect();
+    format!("json_object({})", pairs.join(", "))
+}
+
+/// (Re)create the journal triggers of every journaled table from its
+/// current columns. Like the audit triggers, they must be reinstalled by
+/// migrations that change a journaled table's columns.
+pub(crate) fn install_undo_triggers(conn: &Connection) -> Result<()> {
+    for kind in AUDITED {
+        install_table_triggers(conn, kind.table(), &|row| owning_project(*kind, row), false)?;
+    }
+    for table in OWNED_TABLES {
+        install_table_triggers(conn, table, &|row| format!("{}.project_id", row), false)?;
+    }
+    for (table, project) in LINK_TABLES {
+        install_table_triggers(conn, table, &|row| project.replace("{row}", row), true)?;
+    }
+    Ok(())
+}
+
+/// Journal triggers of one table. Link rows are journaled under their
+/// entity, and an update of one as a removal and an addition, so that both
+/// can be matched on every column.
+fn install_table_triggers(
+    conn: &Connection,
+    table: &str,
+    project: &dyn Fn(&str) -> String,
+    link: bool,
+) -> Result<()> {
+    let key = if link { "entity_id" } else { "id" };
+    let columns = table_columns(conn, table)?;
+    let journal = |row_project: &str, entity_id: &str, before: &str, after: &str| {
+        format!(
+            r#"INSERT INTO undo_changes (action_id, project_id, entity_table, entity_id, before, after)
+    VALUES (undo_action(), {}, '{}', {}, {}, {});"#,
+            row_project, table, entity_id, before, after
+        )
+    };
+    let (new_project, old_project) = (project("new"), project("old"));
+    let (new_key, old_key) = (format!("new.{}", key), format!("old.{}", key));
+    let (new_row, old_row) = (row_object(&columns, "new"), row_object(&columns, "old"));
+    let update = if link {
+        format!(
+            "{}\n    {}",
+            journal(&old_project, &old_key, &old_row, "NULL"),
+            journal(&new_project, &new_key, "NULL", &new_row)
+        )
+    } else {
+        journal(&new_project, &new_key, &old_row, &new_row)
+    };
+
+    conn.execute_batch(&format!(
+        r#"
+DROP TRIGGER IF EXISTS undo_{table}_insert;
+DROP TRIGGER IF EXISTS undo_{table}_update;
+DROP TRIGGER IF EXISTS undo_{table}_delete;
+
+CREATE TRIGGER undo_{table}_insert AFTER INSERT ON {table}
+WHEN undo_action() IS NOT NULL BEGIN
+    {insert}
+END;
+
+CREATE TRIGGER undo_{table}_update AFTER UPDATE ON {table}
+WHEN undo_action() IS NOT NULL BEGIN
+    {update}
+END;
+
+CREATE TRIGGER undo_{table}_delete AFTER DELETE ON {table}
+WHEN undo_action() IS NOT NULL BEGIN
+    {delete}
+END;
+"#,
+        table = table,
+        insert = journal(&new_project, &new_key, "NULL", &new_row),
+        update = update,
+        delete = journal(&old_project, &old_key, &old_row, "NULL"),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database;
+    use crate::database::test_support::project_db;
+
+    fn title(conn: &Connection) -> Option<String> {
+        conn.query_row("SELECT title FROM chapters WHERE id = 'c1'", [], |row| {
+            row.get(0)
+        })
+        .optional()
+        .unwrap()
+    }
+
+    #[test]
+    fn test_undo_and_redo_restore_rows() {
+        let conn = project_db();
+
+        let write = |label: &str, sql: &str| {
+            undoable(&conn, label, || conn.execute_batch(sql)).unwrap();
+        };
+        write(
+            "Create chapter",
+            "INSERT INTO chapters (id, project_id, title) VALUES ('c1', 'p1', 'One')",
+        );
+        write(
+            "Rename chapter",
+            "UPDATE chapters SET title = 'Two' WHERE id = 'c1'",
+        );
+        // Folded into the previous step
+        write(
+            "Rename chapter",
+            "UPDATE chapters SET title = 'Three' WHERE id = 'c1'",
+        );
+        // Not journaled, and kept by the undo below
+        conn.execute("UPDATE chapters SET status = 'final' WHERE id = 'c1'", [])
+            .unwrap();
+
+        let steps = get_undo_steps(&conn, "p1").unwrap();
+        let labels: Vec<&str> = steps.undo.iter().map(|s| s.label.as_str()).collect();
+        assert_eq!(labels, ["Rename chapter", "Create chapter"]);
+
+        assert_eq!(
+            undo(&conn, "p1").unwrap().as_deref(),
+            Some("Rename chapter")
+        );
+        assert_eq!(title(&conn).as_deref(), Some("One"));
+        let status: String = conn
+            .query_row("SELECT status FROM chapters WHERE id = 'c1'", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(status, "final");
+
+        undo(&conn, "p1").unwrap();
+        assert_eq!(title(&conn), None);
+        assert_eq!(undo(&conn, "p1").unwrap(), None);
+
+        redo(&conn, "p1").unwrap();
+        redo(&conn, "p1").unwrap();
+        assert_eq!(title(&conn).as_deref(), Some("Three"));
+
+        // A new action drops what is left to redo
+        undo(&conn, "p1").unwrap();
+        write(
+            "Edit chapter",
+            "UPDATE chapters SET summary = 'x' WHERE id = 'c1'",
+        );
+        assert!(get_undo_steps(&conn, "p1").unwrap().redo.is_empty());
+    }
+
+    #[test]
+    fn test_undo_restores_tags_aliases_and_fields() {
+        let conn = project_db();
+
+        let edit = |label: &str, tags: &[&str], aliases: &[&str]| {
+            let tags: Vec<String> = tags.iter().map(|t| t.to_string()).collect();
+            let aliases: Vec<String> = aliases.iter().map(|a| a.to_string()).collect();
+            undoable(&conn, label, || {
+                conn.execute(
+                    "INSERT OR IGNORE INTO characters (id, project_id, name) VALUES ('c1', 'p1', 'Ada')",
+                    [],
+                )?;
+                database::set_tag_names(&conn, "p1", EntityKind::Character, "c1", &tags)?;
+                database::set_entity_aliases(&conn, EntityKind::Character, "c1", &aliases)
+            })
+            .unwrap();
+        };
+        let state = || {
+            let tags = database::tag_names(&conn, EntityKind::Character, "c1").unwrap();
+            let aliases = database::get_entity_aliases(&conn, EntityKind::Character, "c1").unwrap();
+            (tags, aliases)
+        };
+        edit("Add character", &["hero"], &["Captain"]);
+        edit("Edit character", &["villain"], &["Mira"]);
+        undoable(&conn, "Add field", || {
+            conn.execute_batch(
+                r#"INSERT INTO custom_fields (id, project_id, entity_type, name, field_type)
+                   VALUES ('f1', 'p1', 'character', 'Rank', 'text')"#,
+            )
+        })
+        .unwrap();
+        undoable(&conn, "Delete field", || {
+            conn.execute_batch("DELETE FROM custom_fields WHERE id = 'f1'")
+        })
+        .unwrap();
+
+        undo(&conn, "p1").unwrap();
+        let fields = database::get_custom_fields(&conn, "p1", None).unwrap();
+        assert_eq!(fields.len(), 1);
+        undo(&conn, "p1").unwrap();
+        assert!(database::get_custom_fields(&conn, "p1", None)
+            .unwrap()
+            .is_empty());
+
+        undo(&conn, "p1").unwrap();
+        assert_eq!(state(), (vec!["hero".into()], vec!["Captain".into()]));
+
+        // Undoing the creation leaves no links behind
+        undo(&conn, "p1").unwrap();
+        let links: i64 = conn
+            .query_row(
+                "SELECT (SELECT COUNT(*) FROM entity_tags) + (SELECT COUNT(*) FROM entity_aliases)",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(links, 0);
+
+        redo(&conn, "p1").unwrap();
+        redo(&conn, "p1").unwrap();
+        assert_eq!(state(), (vec!["villain".into()], vec!["Mira".into()]));
+    }
+}
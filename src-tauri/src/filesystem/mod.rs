@@ -46,6 +46,9 @@ pub struct ProjectData {
     pub lore_items: Vec<serde_json::Value>,
     pub timeline_events: Vec<serde_json::Value>,
     pub relationships: Vec<serde_json::Value>,
+    /// Custom field definitions; values are stored on each entity
+    #[serde(default)]
+    pub custom_fields: Vec<serde_json::Value>,
 }
 
 /// Save a project to a .pluma file
@@ -105,6 +108,11 @@ pub fn save_project(data: &ProjectData, output_path: &Path) -> Result<(), Filesy
     let relationships_json = serde_json::to_string_pretty(&data.relationships)?;
     zip.write_all(relationships_json.as_bytes())?;
 
+    // Write custom field definitions
+    zip.start_file("custom_fields.json", options)?;
+    let custom_fields_json = serde_json::to_string_pretty(&data.custom_fields)?;
+    zip.write_all(custom_fields_json.as_bytes())?;
+
     zip.finish()?;
     Ok(())
 }
@@ -141,6 +149,13 @@ pub fn load_project(input_path: &Path) -> Result<ProjectData, FilesystemError> {
         read_json_from_zip(&mut archive, "timeline_events.json")?;
     let relationships: Vec<serde_json::Value> =
         read_json_from_zip(&mut archive, "relationships.json")?;
+    // Absent from files saved before custom fields existed
+    let custom_fields: Vec<serde_json::Value> =
+        if archive.index_for_name("custom_fields.json").is_some() {
+            read_json_from_zip(&mut archive, "custom_fields.json")?
+        } else {
+            Vec::new()
+        };
 
     Ok(ProjectData {
         project,
@@ -151,6 +166,7 @@ pub fn load_project(input_path: &Path) -> Result<ProjectData, FilesystemError> {
         lore_items,
         timeline_events,
         relationships,
+        custom_fields,
     })
 }
 
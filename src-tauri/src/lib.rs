@@ -9,11 +9,11 @@ mod database;
 mod filesystem;
 mod packages;
 mod publishing;
+mod settings;
 mod workspace;
 
 use database::DbState;
-use rusqlite::Connection;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 use tauri::Manager;
 use tauri_plugin_log::{Target, TargetKind};
 use log::LevelFilter;
@@ -63,11 +63,31 @@ pub fn run() {
             let db_path = app_dir.join("plumai.db");
             log::info!("Database path: {:?}", db_path);
 
-            let conn = Connection::open(&db_path).expect("Failed to open database");
+            let conn = database::open_connection(&db_path).expect("Failed to open database");
 
             // Initialize schema
             database::init_database(&conn).expect("Failed to initialize database");
 
+            // Report (but never repair) storage damage; the full reference
+            // check is too slow for every launch and runs on demand
+            match database::quick_check(&conn) {
+                Ok(errors) if !errors.is_empty() => {
+                    log::warn!("Database quick check found {} storage error(s)", errors.len());
+                }
+                Ok(_) => {}
+                Err(e) => log::warn!("Database quick check failed: {}", e),
+            }
+
+            // Purge trash entries past the retention period
+            match settings::get::<i64>(&conn, "trash_retention_days") {
+                Ok(retention_days) => match database::purge_expired_trash(&conn, retention_days) {
+                    Ok(0) => {}
+                    Ok(n) => log::info!("Purged {} expired trash entries", n),
+                    Err(e) => log::warn!("Trash purge failed: {}", e),
+                },
+                Err(e) => log::warn!("Skipping trash purge: {}", e),
+            }
+
             // Migrate local packages to DB
             let packages_dir = app_dir.join("packages");
             if let Err(e) = packages::migration::migrate_local_packages(&conn, &packages_dir) {
@@ -91,7 +111,9 @@ pub fn run() {
             }
 
             // Store connection in app state
-            app.manage(DbState(Mutex::new(conn)));
+            let pool = database::DbPool::open(conn, &db_path, database::READ_CONNECTIONS)
+                .expect("Failed to open database read connections");
+            app.manage(DbState(Arc::new(pool)));
             app.manage(ai::speech::SpeechState(Mutex::new(None)));
 
             // Initialize workspace state from DB setting
@@ -130,6 +152,7 @@ pub fn run() {
             commands::db_get_all_projects,
             commands::db_update_project,
             commands::db_delete_project,
+            commands::db_duplicate_project,
             // Database - Chapters
             commands::db_create_chapter,
             commands::db_get_chapters_by_project,
@@ -140,6 +163,14 @@ pub fn run() {
             commands::db_get_scenes_by_chapter,
             commands::db_update_scene,
             commands::db_delete_scene,
+            // Database - Ordering
+            commands::db_reorder_chapters,
+            commands::db_reorder_scenes,
+            commands::db_move_scene,
+            // Database - Chapter Assembly
+            commands::db_set_chapter_content_mode,
+            commands::db_compile_chapter,
+            commands::db_split_chapter_into_scenes,
             // Database - Characters
             commands::db_create_character,
             commands::db_get_characters_by_project,
@@ -165,12 +196,97 @@ pub fn run() {
             commands::db_get_timeline_events_by_project,
             commands::db_update_timeline_event,
             commands::db_delete_timeline_event,
+            // Database - Creatures
+            commands::db_create_creature,
+            commands::db_get_creatures_by_project,
+            commands::db_update_creature,
+            commands::db_delete_creature,
+            // Database - NPCs
+            commands::db_create_npc,
+            commands::db_get_npcs_by_project,
+            commands::db_update_npc,
+            commands::db_delete_npc,
+            // Database - World Rules
+            commands::db_create_world_rule,
+            commands::db_get_world_rules_by_project,
+            commands::db_update_world_rule,
+            commands::db_delete_world_rule,
+            // Database - Batch
+            commands::db_apply_batch,
+            // Database - Chapter Revisions
+            commands::db_list_chapter_revisions,
+            commands::db_get_chapter_revision,
+            commands::db_create_chapter_revision,
+            commands::db_diff_chapter_revisions,
+            commands::db_restore_chapter_revision,
+            // Database - Search
+            commands::db_search,
+            // Database - Mentions
+            commands::db_get_entity_mentions,
+            commands::db_get_chapter_cast,
+            commands::db_get_backlinks,
+            commands::db_get_entity_aliases,
+            commands::db_set_entity_aliases,
+            commands::db_reindex_mentions,
+            // Database - Links
+            commands::db_resolve_links,
+            commands::db_get_link_report,
+            commands::db_rename_entity,
+            commands::db_get_link_glossary,
+            // Database - Custom Fields
+            commands::db_create_custom_field,
+            commands::db_get_custom_fields,
+            commands::db_update_custom_field,
+            commands::db_delete_custom_field,
+            commands::db_filter_by_custom_fields,
+            // Database - Tags
+            commands::db_create_tag,
+            commands::db_get_project_tags,
+            commands::db_update_tag,
+            commands::db_delete_tag,
+            commands::db_merge_tags,
+            commands::db_tag_entity,
+            commands::db_untag_entity,
+            commands::db_get_entity_tags,
+            commands::db_get_entities_by_tags,
+            // Database - Query
+            commands::db_query_entities,
+            // Database - Project Settings
+            commands::db_get_project_settings,
+            commands::db_get_project_setting,
+            commands::db_set_project_setting,
+            commands::db_reset_project_setting,
+            // Database - Audit Log
+            commands::db_get_project_audit_log,
+            commands::db_get_entity_audit_log,
+            // Database - Undo
+            commands::db_undo,
+            commands::db_redo,
+            commands::db_get_undo_steps,
+            // Database - Trash
+            commands::db_list_trash,
+            commands::db_list_deleted_projects,
+            commands::db_restore_from_trash,
+            commands::db_purge_from_trash,
+            commands::db_empty_trash,
             // Database - System
+            commands::db_check_integrity,
             commands::db_clear_all_data,
             commands::db_get_setting,
             commands::db_set_setting,
+            // Settings
+            commands::settings_get_all,
+            commands::settings_update,
+            commands::settings_export_profile,
+            commands::settings_import_profile,
             // AI
             commands::ai_chat,
+            commands::ai_chat_stream,
+            commands::ai_chat_with_tools,
+            commands::ai_resolve_tool_call,
+            commands::ai_cancel,
+            commands::ollama_list_models,
+            commands::ollama_pull_model,
             ai::speech::start_dictation,
             ai::speech::stop_dictation,
             ai::speech::speech_get_available_models,
@@ -229,6 +345,7 @@ pub fn run() {
             commands::ws_close_project,
             commands::ws_save_image,
             commands::ws_resolve_image,
+            commands::ws_copy_project_images,
             commands::ws_sync_to_disk,
             commands::ws_sync_from_disk,
             commands::ws_migrate_existing_data,
@@ -1,5 +1,6 @@
 //! DOCX generation using docx-rs
 
+use super::links::render_links;
 use super::{DocxOptions, ExportDocument, PublishingError};
 use docx_rs::*;
 use std::fs::File;
@@ -115,6 +116,7 @@ pub fn generate_docx_bytes(
     }
 
     // Chapters
+    let rendered = render_links(document, options.link_style);
     for (i, chapter) in document.chapters.iter().enumerate() {
         let chapter_num = chapter.number.unwrap_or((i + 1) as i32);
         let chapter_title = format!("Chapter {}: {}", chapter_num, chapter.title);
@@ -133,7 +135,7 @@ pub fn generate_docx_bytes(
         docx = docx.add_paragraph(Paragraph::new());
 
         // Chapter content
-        let clean_content = strip_html(&chapter.content);
+        let clean_content = strip_html(&rendered.bodies[i]);
 
         for paragraph in clean_content.split('\n') {
             let trimmed = paragraph.trim();
@@ -153,6 +155,21 @@ pub fn generate_docx_bytes(
         }
     }
 
+    // Glossary of linked terms
+    if !rendered.glossary.is_empty() {
+        docx = docx.add_paragraph(Paragraph::new().add_run(Run::new().add_break(BreakType::Page)));
+        docx = docx.add_paragraph(
+            Paragraph::new().add_run(Run::new().add_text("Glossary").size(heading_size).bold()),
+        );
+        docx = docx.add_paragraph(Paragraph::new());
+
+        for line in rendered.glossary_lines() {
+            docx = docx.add_paragraph(
+                Paragraph::new().add_run(Run::new().add_text(&line).size(font_size)),
+            );
+        }
+    }
+
     // Generate bytes
     let mut buffer = Cursor::new(Vec::new());
     docx.build()
@@ -0,0 +1,116 @@
+//! Wiki links in exported chapter text
+
+use super::{ExportDocument, GlossaryEntry, LinkStyle};
+use crate::database::parse_wiki_links;
+
+/// Chapter bodies with their links rendered
+pub struct RenderedLinks<'a> {
+    /// HTML body of each chapter, in document order
+    pub bodies: Vec<String>,
+    /// Glossary entries referenced, numbered from 1 in order of first use
+    pub glossary: Vec<&'a GlossaryEntry>,
+}
+
+impl RenderedLinks<'_> {
+    /// Plain-text lines of the glossary, one entry per line
+    pub fn glossary_lines(&self) -> Vec<String> {
+        self.glossary
+            .iter()
+            .enumerate()
+            .map(
+                |(i, entry)| match entry.definition.as_deref().map(str::trim) {
+                    Some(definition) if !definition.is_empty() => {
+                        format!("{}. {}: {}", i + 1, entry.term, definition)
+                    }
+                    _ => format!("{}. {}", i + 1, entry.term),
+                },
+            )
+            .collect()
+    }
+}
+
+/// Replace every link with its text and, for `LinkStyle::Glossary`, a
+/// reference to the glossary entry it names. Links naming no entry are
+/// rendered as plain text.
+pub fn render_links(document: &ExportDocument, style: LinkStyle) -> RenderedLinks<'_> {
+    let mut glossary: Vec<&GlossaryEntry> = Vec::new();
+    let bodies = document
+        .chapters
+        .iter()
+        .map(|chapter| {
+            let html = chapter.body();
+            let mut rendered = String::with_capacity(html.len());
+            let mut last = 0;
+            for link in parse_wiki_links(&html) {
+                rendered.push_str(&html[last..link.range.start]);
+                rendered.push_str(&html[link.display_range.clone()]);
+                last = link.range.end;
+
+                if style != LinkStyle::Glossary {
+                    continue;
+                }
+                let Some(entry) = find_entry(&document.glossary, &link.target) else {
+                    continue;
+                };
+                let number = match glossary.iter().position(|e| std::ptr::eq(*e, entry)) {
+                    Some(index) => index + 1,
+                    None => {
+                        glossary.push(entry);
+                        glossary.len()
+                    }
+                };
+                rendered.push_str(&format!("[{}]", number));
+            }
+            rendered.push_str(&html[last..]);
+            rendered
+        })
+        .collect();
+
+    RenderedLinks { bodies, glossary }
+}
+
+fn find_entry<'a>(entries: &'a [GlossaryEntry], target: &str) -> Option<&'a GlossaryEntry> {
+    let target = target.to_lowercase();
+    entries.iter().find(|e| {
+        std::iter::once(&e.term)
+            .chain(&e.aliases)
+            .any(|name| name.trim().to_lowercase() == target)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_links_render_as_text_or_glossary_references() {
+        let document: ExportDocument = serde_json::from_value(json!({
+            "metadata": { "title": "Saga" },
+            "chapters": [
+                { "title": "One", "content": "<p>[[Ada]] saw [[lore:The Fall|the fall]], then [[Cy]].</p>" },
+                { "title": "Two", "content": "<p>[[ada|She]] left.</p>" },
+            ],
+            "glossary": [
+                { "term": "The Fall", "definition": "The end of the old world" },
+                { "term": "Ada Byron", "aliases": ["Ada"] },
+            ],
+        }))
+        .unwrap();
+
+        let plain = render_links(&document, LinkStyle::Plain);
+        assert_eq!(plain.bodies[0], "<p>Ada saw the fall, then Cy.</p>");
+        assert!(plain.glossary.is_empty());
+
+        let glossary = render_links(&document, LinkStyle::Glossary);
+        assert_eq!(
+            glossary.bodies[0],
+            "<p>Ada[1] saw the fall[2], then Cy.</p>"
+        );
+        assert_eq!(glossary.bodies[1], "<p>She[1] left.</p>");
+        assert_eq!(
+            glossary.glossary_lines(),
+            ["1. Ada Byron", "2. The Fall: The end of the old world"]
+        );
+    }
+}
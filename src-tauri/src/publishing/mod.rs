@@ -3,11 +3,13 @@
 //! Provides native document generation capabilities.
 
 mod docx;
+mod links;
 mod pdf;
 
 pub use docx::*;
 pub use pdf::*;
 
+use crate::database::SCENE_BREAK;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
@@ -30,13 +32,41 @@ pub struct DocumentMetadata {
     pub description: Option<String>,
 }
 
+/// Scene content for export
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportScene {
+    pub title: String,
+    pub content: String,
+}
+
 /// Chapter content for export
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ExportChapter {
     pub title: String,
     pub number: Option<i32>,
+    #[serde(default)]
     pub content: String,
+    /// When present, the chapter is rendered from its scenes instead of
+    /// `content`, with a scene break between each
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub scenes: Vec<ExportScene>,
+}
+
+impl ExportChapter {
+    /// HTML body to render: the scenes joined by scene breaks, or `content`
+    pub fn body(&self) -> String {
+        if self.scenes.is_empty() {
+            return self.content.clone();
+        }
+        self.scenes
+            .iter()
+            .map(|scene| scene.content.trim())
+            .filter(|content| !content.is_empty())
+            .collect::<Vec<_>>()
+            .join(SCENE_BREAK)
+    }
 }
 
 /// Full document for export
@@ -45,6 +75,33 @@ pub struct ExportChapter {
 pub struct ExportDocument {
     pub metadata: DocumentMetadata,
     pub chapters: Vec<ExportChapter>,
+    /// Terms that wiki links may refer to, for `LinkStyle::Glossary`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub glossary: Vec<GlossaryEntry>,
+}
+
+/// A term explained in the glossary at the end of an export
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GlossaryEntry {
+    pub term: String,
+    #[serde(default)]
+    pub definition: Option<String>,
+    /// Other names links may use for the term
+    #[serde(default)]
+    pub aliases: Vec<String>,
+}
+
+/// How wiki links (`[[Name]]`) in chapter text are exported
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LinkStyle {
+    /// Only the link text
+    #[default]
+    Plain,
+    /// The link text and a numbered reference into a glossary appended
+    /// after the last chapter
+    Glossary,
 }
 
 /// PDF export options
@@ -65,6 +122,8 @@ pub struct PdfOptions {
     pub include_title_page: bool,
     #[serde(default)]
     pub include_toc: bool,
+    #[serde(default)]
+    pub link_style: LinkStyle,
 }
 
 impl Default for PdfOptions {
@@ -77,6 +136,7 @@ impl Default for PdfOptions {
             line_height: default_line_height(),
             include_title_page: true,
             include_toc: true,
+            link_style: LinkStyle::default(),
         }
     }
 }
@@ -91,6 +151,8 @@ pub struct DocxOptions {
     pub include_title_page: bool,
     #[serde(default)]
     pub include_toc: bool,
+    #[serde(default)]
+    pub link_style: LinkStyle,
 }
 
 impl Default for DocxOptions {
@@ -99,6 +161,7 @@ impl Default for DocxOptions {
             font_size_pt: default_font_size(),
             include_title_page: true,
             include_toc: true,
+            link_style: LinkStyle::default(),
         }
     }
 }
@@ -1,5 +1,6 @@
 //! PDF generation using printpdf
 
+use super::links::render_links;
 use super::{ExportDocument, PdfOptions, PublishingError};
 use printpdf::*;
 use std::fs::File;
@@ -163,6 +164,7 @@ pub fn generate_pdf(
     }
 
     // Chapters
+    let rendered = render_links(document, options.link_style);
     for (i, chapter) in document.chapters.iter().enumerate() {
         let chapter_num = chapter.number.unwrap_or((i + 1) as i32);
         let chapter_title = format!("Chapter {}: {}", chapter_num, chapter.title);
@@ -172,7 +174,7 @@ pub fn generate_pdf(
         y_position -= line_height * 2.0;
 
         // Chapter content
-        let clean_content = strip_html(&chapter.content);
+        let clean_content = strip_html(&rendered.bodies[i]);
         let lines = word_wrap(&clean_content, chars_per_line);
 
         for line in lines {
@@ -210,6 +212,35 @@ pub fn generate_pdf(
         }
     }
 
+    // Glossary of linked terms, on a page of its own
+    if !rendered.glossary.is_empty() {
+        let (page, layer) = doc.add_page(
+            Mm(options.page_width_mm),
+            Mm(options.page_height_mm),
+            "Glossary",
+        );
+        current_layer = doc.get_page(page).get_layer(layer);
+        y_position = options.page_height_mm - options.margin_mm;
+
+        current_layer.use_text("Glossary", 16.0_f32, margin, Mm(y_position), &font_bold);
+        y_position -= line_height * 2.0;
+
+        let lines = word_wrap(&rendered.glossary_lines().join("\n"), chars_per_line);
+        for line in lines {
+            if y_position < options.margin_mm + line_height {
+                let (page, layer) = doc.add_page(
+                    Mm(options.page_width_mm),
+                    Mm(options.page_height_mm),
+                    "Glossary",
+                );
+                current_layer = doc.get_page(page).get_layer(layer);
+                y_position = options.page_height_mm - options.margin_mm;
+            }
+            current_layer.use_text(&line, options.font_size_pt, margin, Mm(y_position), &font);
+            y_position -= line_height;
+        }
+    }
+
     // Save the PDF
     let file = File::create(output_path)?;
     let mut writer = BufWriter::new(file);
@@ -0,0 +1,609 @@
+//! Application settings registry
+//!
+//! Every global preference stored in `app_settings` is declared here with
+//! its kind, default, optional validation and migration, so consumers read
+//! typed values instead of parsing raw strings. Values are stored as JSON;
+//! a value equal to its default is not stored at all.
+//!
+//! Settings can be exported to a JSON profile file and imported back.
+//! Credentials never leave the machine this way: see [`Export`].
+
+use crate::ai::speech::models::SpeechConfig;
+use crate::ai::{validate_profiles, ProviderProfile};
+use crate::database::{self, SettingKind};
+use crate::packages::models::RegistrySource;
+use rusqlite::{params, Connection};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use thiserror::Error;
+
+/// Version of the profile file format
+const PROFILE_VERSION: u64 = 1;
+
+#[derive(Error, Debug)]
+pub enum SettingsError {
+    #[error("Unknown setting: {0}")]
+    UnknownKey(String),
+
+    #[error("Invalid value for {key}: {reason}")]
+    InvalidValue { key: String, reason: String },
+
+    #[error("Invalid settings profile: {0}")]
+    InvalidProfile(String),
+
+    #[error("Database error: {0}")]
+    Database(#[from] rusqlite::Error),
+}
+
+/// How a setting is written to an exported profile
+#[derive(Clone, Copy)]
+pub enum Export {
+    Always,
+    /// Credentials and other machine-specific values
+    Never,
+    /// Export the object, or each object of a list, without these
+    /// (secret) fields
+    Without(&'static [&'static str]),
+}
+
+/// Checks a value beyond its kind, returning why it is invalid
+type Validator = fn(&Value) -> Result<(), String>;
+
+/// Declaration of an application setting
+pub struct SettingDef {
+    pub key: &'static str,
+    pub kind: SettingKind,
+    /// Default value, as JSON; `null` means the frontend supplies it
+    pub default: &'static str,
+    /// Checks beyond `kind`, such as the shape of an object
+    pub validate: Option<Validator>,
+    /// Upgrades a value stored by an older version of the app
+    pub migrate: Option<fn(Value) -> Value>,
+    pub export: Export,
+}
+
+impl SettingDef {
+    const fn new(key: &'static str, kind: SettingKind, default: &'static str) -> Self {
+        Self {
+            key,
+            kind,
+            default,
+            validate: None,
+            migrate: None,
+            export: Export::Always,
+        }
+    }
+
+    fn default_value(&self) -> Value {
+        serde_json::from_str(self.default).expect("setting defaults are valid JSON")
+    }
+
+    fn check(&self, value: &Value) -> Result<(), SettingsError> {
+        if value.is_null() && self.default == "null" {
+            return Ok(());
+        }
+        self.kind
+            .check(value)
+            .and_then(|_| self.validate.map_or(Ok(()), |validate| validate(value)))
+            .map_err(|reason| SettingsError::InvalidValue {
+                key: self.key.to_string(),
+                reason,
+            })
+    }
+}
+
+const fn choice(options: &'static [&'static str]) -> SettingKind {
+    SettingKind::Choice { options }
+}
+
+/// Checks that a value deserializes into `T`
+fn shape<T: DeserializeOwned>(value: &Value) -> Result<(), String> {
+    serde_json::from_value::<T>(value.clone())
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+fn validate_speech_config(value: &Value) -> Result<(), String> {
+    shape::<SpeechConfig>(value)
+}
+
+/// Round-trips the config so fields added since it was stored get their
+/// defaults
+fn migrate_speech_config(value: Value) -> Value {
+    serde_json::from_value::<SpeechConfig>(value.clone())
+        .ok()
+        .and_then(|config| serde_json::to_value(config).ok())
+        .unwrap_or(value)
+}
+
+fn validate_registries(value: &Value) -> Result<(), String> {
+    shape::<Vec<RegistrySource>>(value)
+}
+
+fn validate_provider_profiles(value: &Value) -> Result<(), String> {
+    let profiles: Vec<ProviderProfile> =
+        serde_json::from_value(value.clone()).map_err(|e| e.to_string())?;
+    validate_profiles(&profiles)
+}
+
+fn validate_model_map(value: &Value) -> Result<(), String> {
+    match value.as_object() {
+        Some(map) if map.values().all(Value::is_string) => Ok(()),
+        _ => Err("expected model names keyed by task".to_string()),
+    }
+}
+
+/// Providers built into the frontend; `activeProvider` may also name a
+/// provider profile
+const BUILT_IN_PROVIDERS: &[&str] = &[
+    "anthropic",
+    "openai",
+    "google",
+    "groq",
+    "together",
+    "huggingface",
+    "ollama",
+    "manual",
+];
+
+/// Every application setting
+pub const SETTINGS: &[SettingDef] = &[
+    SettingDef::new("activeProvider", SettingKind::Text, r#""google""#),
+    SettingDef::new("activeModel", SettingKind::Text, r#""gemini-1.5-flash""#),
+    SettingDef::new(
+        "theme",
+        choice(&[
+            "dark",
+            "dracula",
+            "light",
+            "emerald",
+            "parchment",
+            "hell",
+            "nordic",
+            "midnight",
+            "cyberpunk",
+        ]),
+        r#""dark""#,
+    ),
+    SettingDef::new("language", choice(&["es", "en"]), r#""es""#),
+    SettingDef::new(
+        "fontFamily",
+        choice(&[
+            "system",
+            "inter",
+            "roboto",
+            "nunito",
+            "merriweather",
+            "lora",
+            "montserrat",
+            "playfair",
+            "jetbrains",
+            "garamond",
+        ]),
+        r#""inter""#,
+    ),
+    SettingDef::new("fontSize", SettingKind::Integer { min: 8, max: 48 }, "16"),
+    SettingDef::new(
+        "tokenOptimizationLevel",
+        choice(&["minimal", "normal", "complete", "unlimited"]),
+        r#""normal""#,
+    ),
+    SettingDef::new("useAgenticContext", SettingKind::Bool, "true"),
+    SettingDef::new("enableLogs", SettingKind::Bool, "true"),
+    SettingDef {
+        export: Export::Never,
+        ..SettingDef::new("masterPasswordHash", SettingKind::Text, "null")
+    },
+    SettingDef::new("encryptApiKeys", SettingKind::Bool, "false"),
+    SettingDef {
+        export: Export::Never,
+        ..SettingDef::new("githubToken", SettingKind::Text, "null")
+    },
+    SettingDef::new("githubRepo", SettingKind::Text, "null"),
+    SettingDef {
+        export: Export::Never,
+        ..SettingDef::new("dropboxToken", SettingKind::Text, "null")
+    },
+    SettingDef {
+        validate: Some(validate_model_map),
+        ..SettingDef::new(
+            "groqModelMap",
+            SettingKind::Object,
+            r#"{"creative":"llama-3.3-70b-versatile","logical":"mixtral-8x7b-32768","fast":"llama-3.1-8b-instant"}"#,
+        )
+    },
+    // The default prompts are long, localized templates kept by the frontend
+    SettingDef::new("ragConfiguration", SettingKind::Object, "null"),
+    SettingDef::new("zenAmbience", SettingKind::Text, "null"),
+    SettingDef::new(
+        "dailyWordGoal",
+        SettingKind::Integer {
+            min: 0,
+            max: 1_000_000,
+        },
+        "500",
+    ),
+    SettingDef::new("showWordCountInEditor", SettingKind::Bool, "true"),
+    SettingDef::new("typewriterMode", SettingKind::Bool, "false"),
+    SettingDef::new("hemingwayMode", SettingKind::Bool, "false"),
+    SettingDef::new("pomodoroEnabled", SettingKind::Bool, "false"),
+    SettingDef::new("animationsEnabled", SettingKind::Bool, "true"),
+    SettingDef::new("ragStudioEnabled", SettingKind::Bool, "true"),
+    // `null` stands for `SpeechConfig::default()`
+    SettingDef {
+        validate: Some(validate_speech_config),
+        migrate: Some(migrate_speech_config),
+        export: Export::Without(&["whisperApiKey"]),
+        ..SettingDef::new("speech_config", SettingKind::Object, "null")
+    },
+    // Custom package registries; the official one is always added
+    SettingDef {
+        validate: Some(validate_registries),
+        ..SettingDef::new("package_registries", SettingKind::List, "[]")
+    },
+    // Network timeouts for AI requests, in seconds
+    SettingDef::new(
+        "ai_connect_timeout",
+        SettingKind::Integer { min: 1, max: 300 },
+        "10",
+    ),
+    SettingDef::new(
+        "ai_read_timeout",
+        SettingKind::Integer { min: 5, max: 3600 },
+        "120",
+    ),
+    // Days trashed items are kept before being purged at startup
+    SettingDef::new(
+        "trash_retention_days",
+        SettingKind::Integer { min: 1, max: 3650 },
+        "30",
+    ),
+    // OpenAI-compatible servers; their keys are sent with each request, but
+    // extra headers may carry credentials too
+    SettingDef {
+        validate: Some(validate_provider_profiles),
+        export: Export::Without(&["extraHeaders"]),
+        ..SettingDef::new("ai_provider_profiles", SettingKind::List, "[]")
+    },
+];
+
+/// A setting's current value
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AppSetting {
+    pub key: &'static str,
+    #[serde(flatten)]
+    pub kind: SettingKind,
+    pub value: Value,
+    pub default: Value,
+    pub is_default: bool,
+}
+
+fn definition(key: &str) -> Result<&'static SettingDef, SettingsError> {
+    SETTINGS
+        .iter()
+        .find(|def| def.key == key)
+        .ok_or_else(|| SettingsError::UnknownKey(key.to_string()))
+}
+
+/// Whether the registry declares `key`
+pub fn is_declared(key: &str) -> bool {
+    definition(key).is_ok()
+}
+
+/// Parse a value in its stored form
+fn parse_raw(def: &SettingDef, raw: &str) -> Result<Value, serde_json::Error> {
+    match serde_json::from_str(raw) {
+        Ok(value) => Ok(value),
+        // Early versions stored some strings unquoted
+        Err(_) if matches!(def.kind, SettingKind::Text | SettingKind::Choice { .. }) => {
+            Ok(Value::String(raw.to_string()))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Stored value of a setting, migrated; `None` when unset or unreadable
+fn stored_value(conn: &Connection, def: &SettingDef) -> Result<Option<Value>, SettingsError> {
+    let Some(raw) = database::get_setting(conn, def.key)? else {
+        return Ok(None);
+    };
+    let value = match parse_raw(def, &raw) {
+        Ok(value) => value,
+        Err(e) => {
+            log::warn!("Ignoring unreadable setting {}: {}", def.key, e);
+            return Ok(None);
+        }
+    };
+    let value = match def.migrate {
+        Some(migrate) => migrate(value),
+        None => value,
+    };
+    if let Err(e) = def.check(&value) {
+        log::warn!("Ignoring stored setting: {}", e);
+        return Ok(None);
+    }
+    Ok(Some(value))
+}
+
+/// Every setting with its current value
+pub fn get_all(conn: &Connection) -> Result<Vec<AppSetting>, SettingsError> {
+    SETTINGS
+        .iter()
+        .map(|def| {
+            let default = def.default_value();
+            let value = stored_value(conn, def)?;
+            Ok(AppSetting {
+                key: def.key,
+                kind: def.kind,
+                is_default: value.is_none(),
+                value: value.unwrap_or_else(|| default.clone()),
+                default,
+            })
+        })
+        .collect()
+}
+
+/// Current value of a setting as `T`
+pub fn get<T: DeserializeOwned>(conn: &Connection, key: &str) -> Result<T, SettingsError> {
+    let def = definition(key)?;
+    let value = stored_value(conn, def)?.unwrap_or_else(|| def.default_value());
+    serde_json::from_value(value).map_err(|e| SettingsError::InvalidValue {
+        key: key.to_string(),
+        reason: e.to_string(),
+    })
+}
+
+/// Apply several changes at once; nothing is written unless every value is
+/// valid. Returns the changes, as stored.
+pub fn update(
+    conn: &Connection,
+    changes: &Map<String, Value>,
+) -> Result<Map<String, Value>, SettingsError> {
+    let mut checked = Vec::with_capacity(changes.len());
+    for (key, value) in changes {
+        let def = definition(key)?;
+        def.check(value)?;
+        checked.push((def, value));
+    }
+    check_active_provider(conn, changes)?;
+
+    database::with_savepoint(conn, || {
+        for (def, value) in &checked {
+            if **value == def.default_value() {
+                conn.execute("DELETE FROM app_settings WHERE key = ?1", params![def.key])?;
+            } else {
+                database::set_setting(conn, def.key, &value.to_string())?;
+            }
+        }
+        Ok(())
+    })?;
+    Ok(changes.clone())
+}
+
+/// A changed `activeProvider` must be built in or the id of a provider
+/// profile, as the profiles will be once `changes` are applied
+fn check_active_provider(
+    conn: &Connection,
+    changes: &Map<String, Value>,
+) -> Result<(), SettingsError> {
+    let Some(provider) = changes.get("activeProvider").and_then(Value::as_str) else {
+        return Ok(());
+    };
+    if BUILT_IN_PROVIDERS.contains(&provider) {
+        return Ok(());
+    }
+    let profiles = match changes.get("ai_provider_profiles") {
+        Some(profiles) => Some(profiles.clone()),
+        None => stored_value(conn, definition("ai_provider_profiles")?)?,
+    };
+    let known = profiles
+        .as_ref()
+        .and_then(Value::as_array)
+        .is_some_and(|profiles| profiles.iter().any(|profile| profile["id"] == provider));
+    if known {
+        Ok(())
+    } else {
+        Err(SettingsError::InvalidValue {
+            key: "activeProvider".to_string(),
+            reason: format!("unknown provider: {}", provider),
+        })
+    }
+}
+
+/// Change one setting given in its stored form, as the older
+/// `db_set_setting` command sends it. Returns the change, as stored.
+pub fn update_raw(
+    conn: &Connection,
+    key: &str,
+    raw: &str,
+) -> Result<Map<String, Value>, SettingsError> {
+    let def = definition(key)?;
+    let value = parse_raw(def, raw).map_err(|e| SettingsError::InvalidValue {
+        key: key.to_string(),
+        reason: e.to_string(),
+    })?;
+    update(conn, &Map::from_iter([(key.to_string(), value)]))
+}
+
+/// Remove `fields` from an object, or from each object of a list
+fn strip_fields(value: &mut Value, fields: &[&str]) {
+    match value {
+        Value::Object(object) => fields.iter().for_each(|field| {
+            object.remove(*field);
+        }),
+        Value::Array(items) => items.iter_mut().for_each(|item| strip_fields(item, fields)),
+        _ => {}
+    }
+}
+
+/// Put back the `fields` an export stripped, from the current value. List
+/// items are matched to the current ones by `id`.
+fn restore_fields(value: &mut Value, current: &Value, fields: &[&str]) {
+    match (value, current) {
+        (Value::Object(object), Value::Object(current)) => {
+            for field in fields {
+                if let Some(kept) = current.get(*field) {
+                    object.insert(field.to_string(), kept.clone());
+                }
+            }
+        }
+        (Value::Array(items), Value::Array(current)) => {
+            for item in items {
+                let kept = current
+                    .iter()
+                    .find(|kept| kept.get("id").is_some_and(|id| Some(id) == item.get("id")));
+                if let Some(kept) = kept {
+                    restore_fields(item, kept, fields);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Every exportable setting, as a profile to save to a file
+pub fn export_profile(conn: &Connection) -> Result<Value, SettingsError> {
+    let mut settings = Map::new();
+    for def in SETTINGS {
+        let Some(mut value) = stored_value(conn, def)? else {
+            continue;
+        };
+        match def.export {
+            Export::Always => {}
+            Export::Never => continue,
+            Export::Without(fields) => strip_fields(&mut value, fields),
+        }
+        settings.insert(def.key.to_string(), value);
+    }
+
+    Ok(json!({
+        "version": PROFILE_VERSION,
+        "exportedAt": chrono::Utc::now().to_rfc3339(),
+        "settings": settings,
+    }))
+}
+
+/// Apply the settings of a profile. Settings the profile leaves out keep
+/// their value, as do the fields an export strips; unknown keys, from a
+/// newer version of the app, are skipped. Returns the changes applied.
+pub fn import_profile(
+    conn: &Connection,
+    profile: &Value,
+) -> Result<Map<String, Value>, SettingsError> {
+    let version = profile["version"].as_u64().unwrap_or(0);
+    if version == 0 || version > PROFILE_VERSION {
+        return Err(SettingsError::InvalidProfile(format!(
+            "unsupported version {}",
+            profile["version"]
+        )));
+    }
+    let Some(settings) = profile["settings"].as_object() else {
+        return Err(SettingsError::InvalidProfile(
+            "missing settings".to_string(),
+        ));
+    };
+
+    let mut changes = Map::new();
+    for (key, value) in settings {
+        let Ok(def) = definition(key) else {
+            log::warn!("Skipping unknown setting {} in profile", key);
+            continue;
+        };
+        let mut value = match def.migrate {
+            Some(migrate) => migrate(value.clone()),
+            None => value.clone(),
+        };
+        match def.export {
+            Export::Always => {}
+            Export::Never => continue,
+            Export::Without(fields) => {
+                if let Some(current) = stored_value(conn, def)? {
+                    restore_fields(&mut value, &current, fields);
+                }
+            }
+        }
+        changes.insert(key.clone(), value);
+    }
+    update(conn, &changes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::test_support::empty_db;
+
+    #[test]
+    fn test_settings_validate_and_round_trip_profiles() {
+        let conn = empty_db();
+        // Stored by an older version: unquoted, and missing newer fields
+        database::set_setting(&conn, "activeModel", "gpt-4o").unwrap();
+        database::set_setting(&conn, "fontSize", "\"huge\"").unwrap();
+        database::set_setting(
+            &conn,
+            "speech_config",
+            r#"{"engine":"whisperApi","language":"en","whisperApiKey":"sk-1"}"#,
+        )
+        .unwrap();
+
+        assert_eq!(get::<String>(&conn, "activeModel").unwrap(), "gpt-4o");
+        assert_eq!(get::<i64>(&conn, "fontSize").unwrap(), 16);
+        let speech: SpeechConfig = get(&conn, "speech_config").unwrap();
+        assert_eq!(speech.language, "en");
+
+        let bad = json!({ "theme": "light", "dailyWordGoal": -1 });
+        assert!(matches!(
+            update(&conn, bad.as_object().unwrap()),
+            Err(SettingsError::InvalidValue { .. })
+        ));
+        assert_eq!(get::<String>(&conn, "theme").unwrap(), "dark");
+        for days in [0, 100_000_000] {
+            let change = json!({ "trash_retention_days": days });
+            assert!(update(&conn, change.as_object().unwrap()).is_err());
+        }
+        assert_eq!(get::<i64>(&conn, "trash_retention_days").unwrap(), 30);
+        update_raw(&conn, "trash_retention_days", "90").unwrap();
+        assert_eq!(get::<i64>(&conn, "trash_retention_days").unwrap(), 90);
+
+        let unknown = json!({ "activeProvider": "lmstudio" });
+        assert!(update(&conn, unknown.as_object().unwrap()).is_err());
+        let changes = json!({
+            "theme": "light",
+            "githubToken": "ghp_secret",
+            "activeProvider": "lmstudio",
+            "ai_provider_profiles": [{
+                "id": "lmstudio",
+                "name": "LM Studio",
+                "baseUrl": "http://localhost:1234/v1",
+                "extraHeaders": { "x-api-key": "hdr_secret" },
+            }],
+        });
+        update(&conn, changes.as_object().unwrap()).unwrap();
+
+        let profile = export_profile(&conn).unwrap();
+        let exported = profile.to_string();
+        assert!(!exported.contains("ghp_secret"));
+        assert!(!exported.contains("sk-1"));
+        assert!(!exported.contains("hdr_secret"));
+
+        // Importing back keeps the headers of the profiles already stored
+        import_profile(&conn, &profile).unwrap();
+        let profiles: Vec<ProviderProfile> = get(&conn, "ai_provider_profiles").unwrap();
+        assert_eq!(profiles[0].extra_headers["x-api-key"], "hdr_secret");
+
+        let other = empty_db();
+        import_profile(&other, &profile).unwrap();
+        assert_eq!(get::<String>(&other, "theme").unwrap(), "light");
+        assert_eq!(get::<String>(&other, "activeModel").unwrap(), "gpt-4o");
+        assert_eq!(get::<Option<String>>(&other, "githubToken").unwrap(), None);
+        assert_eq!(get::<String>(&other, "activeProvider").unwrap(), "lmstudio");
+        let profiles: Vec<ProviderProfile> = get(&other, "ai_provider_profiles").unwrap();
+        assert!(profiles[0].extra_headers.is_empty());
+        let speech: SpeechConfig = get(&other, "speech_config").unwrap();
+        assert_eq!(
+            (speech.language.as_str(), speech.whisper_api_key),
+            ("en", None)
+        );
+    }
+}
@@ -74,6 +74,10 @@ pub fn create_project_dirs(project_path: &Path) -> Result<(), String> {
         project_path.join("lore"),
         project_path.join("timeline"),
         project_path.join("relationships"),
+        project_path.join("creatures"),
+        project_path.join("npcs"),
+        project_path.join("world-rules"),
+        project_path.join("revisions"),
     ];
 
     for dir in &dirs_to_create {
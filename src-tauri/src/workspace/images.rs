@@ -1,6 +1,8 @@
 //! Image management - save images to disk, classify image values, resolve paths
 
 use base64::Engine;
+use serde_json::Value;
+use std::collections::HashSet;
 use std::path::{Path, PathBuf};
 
 /// Classification of an image value
@@ -64,6 +66,49 @@ pub fn resolve_image_path(project_path: &Path, relative_path: &str) -> PathBuf {
     project_path.join(relative_path)
 }
 
+/// Copy the images referenced anywhere in `entities` from one project folder
+/// to another, keeping their relative paths. Images missing from the source
+/// or already in the target are skipped. Returns how many were copied.
+pub fn copy_referenced_images(
+    source_project: &Path,
+    target_project: &Path,
+    entities: &Value,
+) -> Result<usize, String> {
+    let mut paths = HashSet::new();
+    collect_image_paths(entities, &mut paths);
+
+    let mut copied = 0;
+    for relative_path in paths {
+        let source = resolve_image_path(source_project, &relative_path);
+        let target = resolve_image_path(target_project, &relative_path);
+        if !source.is_file() || target.exists() {
+            continue;
+        }
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create image dir: {}", e))?;
+        }
+        std::fs::copy(&source, &target)
+            .map_err(|e| format!("Failed to copy image {}: {}", relative_path, e))?;
+        copied += 1;
+    }
+
+    log::info!("Copied {} image(s) to {}", copied, target_project.display());
+    Ok(copied)
+}
+
+/// Relative image paths (images/...) found in any string of a JSON value
+fn collect_image_paths(value: &Value, paths: &mut HashSet<String>) {
+    match value {
+        Value::String(s) if s.starts_with("images/") && !s.split('/').any(|part| part == "..") => {
+            paths.insert(s.clone());
+        }
+        Value::Array(items) => items.iter().for_each(|item| collect_image_paths(item, paths)),
+        Value::Object(fields) => fields.values().for_each(|item| collect_image_paths(item, paths)),
+        _ => {}
+    }
+}
+
 fn decode_image_data(image_data: &str) -> Result<(Vec<u8>, String), String> {
     let engine = base64::engine::general_purpose::STANDARD;
 
@@ -1,7 +1,9 @@
 //! Project filesystem operations - read/write projects as folder structures
 
 use crate::database;
+use serde::Serialize;
 use slug::slugify;
+use std::collections::HashSet;
 use std::path::{Path, PathBuf};
 
 /// Generate a slug-shortId folder name for a project
@@ -77,16 +79,32 @@ pub fn create_project_folder(
     Ok(project_path)
 }
 
+/// A project and every entity it owns, as stored in a project folder
+#[derive(Serialize)]
+pub struct ProjectContents {
+    pub project: database::Project,
+    pub chapters: Vec<database::Chapter>,
+    pub scenes: Vec<database::Scene>,
+    pub characters: Vec<database::Character>,
+    pub locations: Vec<database::Location>,
+    pub lore_items: Vec<database::LoreItem>,
+    pub timeline_events: Vec<database::TimelineEvent>,
+    pub creatures: Vec<database::Creature>,
+    pub npcs: Vec<database::Npc>,
+    pub world_rules: Vec<database::WorldRule>,
+    pub aliases: Vec<database::EntityAlias>,
+    pub custom_fields: Vec<database::CustomField>,
+    pub tags: Vec<database::Tag>,
+    pub entity_tags: Vec<database::EntityTag>,
+    /// Project settings that differ from their default; `None` when the
+    /// folder predates them, which leaves the stored settings alone
+    pub settings: Option<serde_json::Map<String, serde_json::Value>>,
+}
+
 /// Write full project data to folder (project + all entities)
 pub fn write_project_to_folder(
     project_path: &Path,
-    project: &database::Project,
-    chapters: &[database::Chapter],
-    scenes: &[database::Scene],
-    characters: &[database::Character],
-    locations: &[database::Location],
-    lore_items: &[database::LoreItem],
-    timeline_events: &[database::TimelineEvent],
+    contents: &ProjectContents,
 ) -> Result<(), String> {
     // Update manifest timestamp
     let manifest_path = project_path.join("manifest.json");
@@ -103,57 +121,82 @@ pub fn write_project_to_folder(
     }
 
     // Write project.json
-    write_json_file(&project_path.join("project.json"), project)?;
+    write_json_file(&project_path.join("project.json"), &contents.project)?;
 
     // Write individual entity files
-    for chapter in chapters {
+    for chapter in &contents.chapters {
         let filename = format!("chapter-{}.json", chapter.id);
         write_json_file(&project_path.join("chapters").join(filename), chapter)?;
     }
 
-    for scene in scenes {
+    for scene in &contents.scenes {
         let filename = format!("scene-{}.json", scene.id);
         write_json_file(&project_path.join("scenes").join(filename), scene)?;
     }
 
-    for character in characters {
+    for character in &contents.characters {
         let filename = format!("character-{}.json", character.id);
         write_json_file(&project_path.join("characters").join(filename), character)?;
     }
 
-    for location in locations {
+    for location in &contents.locations {
         let filename = format!("location-{}.json", location.id);
         write_json_file(&project_path.join("locations").join(filename), location)?;
     }
 
-    for lore_item in lore_items {
+    for lore_item in &contents.lore_items {
         let filename = format!("lore-{}.json", lore_item.id);
         write_json_file(&project_path.join("lore").join(filename), lore_item)?;
     }
 
-    for event in timeline_events {
+    for event in &contents.timeline_events {
         let filename = format!("event-{}.json", event.id);
         write_json_file(&project_path.join("timeline").join(filename), event)?;
     }
 
+    // Folders created by older versions lack these directories
+    for dir in ["creatures", "npcs", "world-rules"] {
+        std::fs::create_dir_all(project_path.join(dir))
+            .map_err(|e| format!("Failed to create {}: {}", dir, e))?;
+    }
+
+    for creature in &contents.creatures {
+        let filename = format!("creature-{}.json", creature.id);
+        write_json_file(&project_path.join("creatures").join(filename), creature)?;
+    }
+
+    for npc in &contents.npcs {
+        let filename = format!("npc-{}.json", npc.id);
+        write_json_file(&project_path.join("npcs").join(filename), npc)?;
+    }
+
+    for rule in &contents.world_rules {
+        let filename = format!("rule-{}.json", rule.id);
+        write_json_file(&project_path.join("world-rules").join(filename), rule)?;
+    }
+
+    write_json_file(&project_path.join("aliases.json"), &contents.aliases)?;
+    write_json_file(
+        &project_path.join("custom-fields.json"),
+        &contents.custom_fields,
+    )?;
+    write_json_file(&project_path.join("tags.json"), &contents.tags)?;
+    write_json_file(
+        &project_path.join("entity-tags.json"),
+        &contents.entity_tags,
+    )?;
+    if let Some(settings) = &contents.settings {
+        write_json_file(&project_path.join("settings.json"), settings)?;
+    }
+
     log::info!("Project written to folder: {}", project_path.display());
     Ok(())
 }
 
 /// Read project data from folder
-pub fn read_project_from_folder(
-    project_path: &Path,
-) -> Result<(
-    database::Project,
-    Vec<database::Chapter>,
-    Vec<database::Scene>,
-    Vec<database::Character>,
-    Vec<database::Location>,
-    Vec<database::LoreItem>,
-    Vec<database::TimelineEvent>,
-), String> {
+pub fn read_project_from_folder(project_path: &Path) -> Result<ProjectContents, String> {
     // Read project.json
-    let project: database::Project = read_json_file(&project_path.join("project.json"))?;
+    let mut project: database::Project = read_json_file(&project_path.join("project.json"))?;
 
     // Read entity files from directories
     let chapters = read_json_dir::<database::Chapter>(&project_path.join("chapters"))?;
@@ -163,7 +206,134 @@ pub fn read_project_from_folder(
     let lore_items = read_json_dir::<database::LoreItem>(&project_path.join("lore"))?;
     let timeline_events = read_json_dir::<database::TimelineEvent>(&project_path.join("timeline"))?;
 
-    Ok((project, chapters, scenes, characters, locations, lore_items, timeline_events))
+    // Older folders kept these embedded in project.json
+    let creatures = read_entity_dir(
+        &project_path.join("creatures"),
+        &project.id,
+        "creatures",
+        project.creatures.take(),
+    )?;
+    let npcs = read_entity_dir(
+        &project_path.join("npcs"),
+        &project.id,
+        "npcs",
+        project.npcs.take(),
+    )?;
+    let world_rules = read_entity_dir(
+        &project_path.join("world-rules"),
+        &project.id,
+        "world rules",
+        project.world_rules.take(),
+    )?;
+
+    // Absent from folders written before aliases, custom fields, tags and
+    // settings existed
+    let aliases_path = project_path.join("aliases.json");
+    let aliases = if aliases_path.exists() {
+        read_json_file(&aliases_path)?
+    } else {
+        Vec::new()
+    };
+    let custom_fields_path = project_path.join("custom-fields.json");
+    let custom_fields = if custom_fields_path.exists() {
+        read_json_file(&custom_fields_path)?
+    } else {
+        Vec::new()
+    };
+    let tags_path = project_path.join("tags.json");
+    let tags = if tags_path.exists() {
+        read_json_file(&tags_path)?
+    } else {
+        Vec::new()
+    };
+    let entity_tags_path = project_path.join("entity-tags.json");
+    let entity_tags = if entity_tags_path.exists() {
+        read_json_file(&entity_tags_path)?
+    } else {
+        Vec::new()
+    };
+    let settings_path = project_path.join("settings.json");
+    let settings = if settings_path.exists() {
+        Some(read_json_file(&settings_path)?)
+    } else {
+        None
+    };
+
+    Ok(ProjectContents {
+        project,
+        chapters,
+        scenes,
+        characters,
+        locations,
+        lore_items,
+        timeline_events,
+        creatures,
+        npcs,
+        world_rules,
+        aliases,
+        custom_fields,
+        tags,
+        entity_tags,
+        settings,
+    })
+}
+
+/// Read an entity directory, falling back to the legacy embedded array when
+/// the directory does not exist yet
+fn read_entity_dir<T: for<'de> serde::Deserialize<'de>>(
+    dir: &Path,
+    project_id: &str,
+    label: &str,
+    legacy: Option<serde_json::Value>,
+) -> Result<Vec<T>, String> {
+    match legacy {
+        Some(value) if !dir.exists() => Ok(database::legacy_entities(project_id, label, value)),
+        _ => read_json_dir(dir),
+    }
+}
+
+/// Ids of the chapter revisions stored in the project folder
+pub fn list_revision_files(project_path: &Path) -> HashSet<String> {
+    let Ok(entries) = std::fs::read_dir(project_path.join("revisions")) else {
+        return HashSet::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            name.strip_prefix("revision-")
+                .and_then(|rest| rest.strip_suffix(".json"))
+                .map(str::to_string)
+        })
+        .collect()
+}
+
+/// Write a chapter revision to the project folder
+pub fn write_revision_file(
+    project_path: &Path,
+    revision: &database::ChapterRevision,
+) -> Result<(), String> {
+    let dir = project_path.join("revisions");
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    let filename = format!("revision-{}.json", revision.info.id);
+    write_json_file(&dir.join(filename), revision)
+}
+
+/// Remove a chapter revision from the project folder
+pub fn remove_revision_file(project_path: &Path, id: &str) -> Result<(), String> {
+    let path = project_path
+        .join("revisions")
+        .join(format!("revision-{}.json", id));
+    std::fs::remove_file(&path)
+        .map_err(|e| format!("Failed to remove {}: {}", path.display(), e))
+}
+
+/// Read every chapter revision stored in the project folder
+pub fn read_revisions_from_folder(
+    project_path: &Path,
+) -> Result<Vec<database::ChapterRevision>, String> {
+    read_json_dir(&project_path.join("revisions"))
 }
 
 fn write_json_file<T: serde::Serialize>(path: &Path, data: &T) -> Result<(), String> {
@@ -1,7 +1,8 @@
 //! Sync module - Dual write SQL + Filesystem
 
-use crate::database;
-use crate::workspace::project_fs;
+use crate::database::{self, AuditSource, EntityKind};
+use crate::workspace::project_fs::{self, ProjectContents};
+use std::collections::{HashMap, HashSet};
 use std::path::Path;
 
 /// Sync a project from SQL database to filesystem
@@ -9,6 +10,21 @@ pub fn sync_sql_to_filesystem(
     conn: &rusqlite::Connection,
     workspace_path: &Path,
     project_id: &str,
+) -> Result<(), String> {
+    // Read everything in one transaction, so a write committed mid-export
+    // cannot leave the folder mixing two states
+    conn.execute_batch("SAVEPOINT sync_export")
+        .map_err(|e| e.to_string())?;
+    let result = export_project_folder(conn, workspace_path, project_id);
+    conn.execute_batch("RELEASE sync_export")
+        .map_err(|e| e.to_string())?;
+    result
+}
+
+fn export_project_folder(
+    conn: &rusqlite::Connection,
+    workspace_path: &Path,
+    project_id: &str,
 ) -> Result<(), String> {
     // Load from DB
     let project = database::get_project(conn, project_id)
@@ -38,6 +54,29 @@ pub fn sync_sql_to_filesystem(
     let timeline_events = database::get_timeline_events_by_project(conn, project_id)
         .map_err(|e| format!("Failed to get timeline events: {}", e))?;
 
+    let creatures = database::get_creatures_by_project(conn, project_id)
+        .map_err(|e| format!("Failed to get creatures: {}", e))?;
+
+    let npcs = database::get_npcs_by_project(conn, project_id)
+        .map_err(|e| format!("Failed to get NPCs: {}", e))?;
+
+    let world_rules = database::get_world_rules_by_project(conn, project_id)
+        .map_err(|e| format!("Failed to get world rules: {}", e))?;
+
+    let aliases = database::get_project_aliases(conn, project_id)
+        .map_err(|e| format!("Failed to get aliases: {}", e))?;
+
+    let custom_fields = database::get_custom_fields(conn, project_id, None)
+        .map_err(|e| format!("Failed to get custom fields: {}", e))?;
+
+    let tags = database::get_project_tags(conn, project_id)
+        .map_err(|e| format!("Failed to get tags: {}", e))?;
+    let entity_tags = database::get_project_entity_tags(conn, project_id)
+        .map_err(|e| format!("Failed to get tags: {}", e))?;
+
+    let settings = database::get_project_setting_overrides(conn, project_id)
+        .map_err(|e| format!("Failed to get project settings: {}", e))?;
+
     // Find or create project folder
     let project_path = match project_fs::find_project_folder(workspace_path, project_id) {
         Some(path) => path,
@@ -47,15 +86,27 @@ pub fn sync_sql_to_filesystem(
     // Write to filesystem
     project_fs::write_project_to_folder(
         &project_path,
-        &project,
-        &chapters,
-        &all_scenes,
-        &characters,
-        &locations,
-        &lore_items,
-        &timeline_events,
+        &ProjectContents {
+            project,
+            chapters,
+            scenes: all_scenes,
+            characters,
+            locations,
+            lore_items,
+            timeline_events,
+            creatures,
+            npcs,
+            world_rules,
+            aliases,
+            custom_fields,
+            tags,
+            entity_tags,
+            settings: Some(settings),
+        },
     )?;
 
+    sync_revisions_to_folder(conn, &project_path, project_id)?;
+
     log::info!("Synced project {} to filesystem", project_id);
     Ok(())
 }
@@ -65,11 +116,42 @@ pub fn sync_filesystem_to_sql(
     conn: &rusqlite::Connection,
     project_path: &Path,
 ) -> Result<String, String> {
-    let (project, chapters, scenes, characters, locations, lore_items, timeline_events) =
-        project_fs::read_project_from_folder(project_path)?;
+    database::with_audit_source(AuditSource::Sync, || {
+        import_project_folder(conn, project_path)
+    })
+}
+
+fn import_project_folder(
+    conn: &rusqlite::Connection,
+    project_path: &Path,
+) -> Result<String, String> {
+    let ProjectContents {
+        project,
+        chapters,
+        scenes,
+        characters,
+        locations,
+        lore_items,
+        timeline_events,
+        creatures,
+        npcs,
+        world_rules,
+        aliases,
+        custom_fields,
+        tags,
+        entity_tags,
+        settings,
+    } = project_fs::read_project_from_folder(project_path)?;
 
     let project_id = project.id.clone();
 
+    if in_trash(conn, EntityKind::Project, &project_id)? {
+        return Err(format!(
+            "Project {} is in the trash; restore it before syncing",
+            project_id
+        ));
+    }
+
     // Upsert project
     match database::get_project(conn, &project_id) {
         Ok(Some(_)) => {
@@ -82,13 +164,38 @@ pub fn sync_filesystem_to_sql(
         }
     }
 
-    // Sync chapters
+    if let Some(settings) = &settings {
+        database::replace_project_settings(conn, &project_id, settings)
+            .map_err(|e| format!("Failed to update project settings: {}", e))?;
+    }
+
+    // Sync custom field definitions, which entity values are checked against
+    let known_fields = database::get_custom_fields(conn, &project_id, None)
+        .map_err(|e| format!("Failed to get custom fields: {}", e))?;
+    for field in &custom_fields {
+        if known_fields.iter().any(|f| f.id == field.id) {
+            database::update_custom_field(conn, field)
+                .map_err(|e| format!("Failed to update custom field: {}", e))?;
+        } else {
+            database::create_custom_field(conn, field)
+                .map_err(|e| format!("Failed to create custom field: {}", e))?;
+        }
+    }
+
+    // Sync chapters (entities the user trashed stay deleted until restored)
     for chapter in &chapters {
+        if in_trash(conn, EntityKind::Chapter, &chapter.id)? {
+            continue;
+        }
         match database::get_chapters_by_project(conn, &project_id) {
             Ok(existing) => {
                 if existing.iter().any(|c| c.id == chapter.id) {
                     database::update_chapter(conn, chapter)
                         .map_err(|e| format!("Failed to update chapter: {}", e))?;
+                    // Updates leave the mode alone; the scenes synced below
+                    // recompile the text of chapters built from them
+                    database::set_chapter_content_mode(conn, &chapter.id, chapter.content_mode)
+                        .map_err(|e| format!("Failed to update chapter: {}", e))?;
                 } else {
                     database::create_chapter(conn, chapter)
                         .map_err(|e| format!("Failed to create chapter: {}", e))?;
@@ -103,6 +210,9 @@ pub fn sync_filesystem_to_sql(
 
     // Sync scenes
     for scene in &scenes {
+        if in_trash(conn, EntityKind::Scene, &scene.id)? {
+            continue;
+        }
         let chapter_scenes = database::get_scenes_by_chapter(conn, &scene.chapter_id)
             .unwrap_or_default();
         if chapter_scenes.iter().any(|s| s.id == scene.id) {
@@ -114,8 +224,15 @@ pub fn sync_filesystem_to_sql(
         }
     }
 
-    // Sync characters
+    // Sync characters (custom field values are set further down)
     for character in &characters {
+        if in_trash(conn, EntityKind::Character, &character.id)? {
+            continue;
+        }
+        let character = &database::Character {
+            custom_fields: Default::default(),
+            ..character.clone()
+        };
         let existing = database::get_characters_by_project(conn, &project_id)
             .unwrap_or_default();
         if existing.iter().any(|c| c.id == character.id) {
@@ -129,6 +246,13 @@ pub fn sync_filesystem_to_sql(
 
     // Sync locations
     for location in &locations {
+        if in_trash(conn, EntityKind::Location, &location.id)? {
+            continue;
+        }
+        let location = &database::Location {
+            custom_fields: Default::default(),
+            ..location.clone()
+        };
         let existing = database::get_locations_by_project(conn, &project_id)
             .unwrap_or_default();
         if existing.iter().any(|l| l.id == location.id) {
@@ -142,6 +266,13 @@ pub fn sync_filesystem_to_sql(
 
     // Sync lore items
     for lore_item in &lore_items {
+        if in_trash(conn, EntityKind::LoreItem, &lore_item.id)? {
+            continue;
+        }
+        let lore_item = &database::LoreItem {
+            custom_fields: Default::default(),
+            ..lore_item.clone()
+        };
         let existing = database::get_lore_items_by_project(conn, &project_id)
             .unwrap_or_default();
         if existing.iter().any(|l| l.id == lore_item.id) {
@@ -155,6 +286,9 @@ pub fn sync_filesystem_to_sql(
 
     // Sync timeline events
     for event in &timeline_events {
+        if in_trash(conn, EntityKind::TimelineEvent, &event.id)? {
+            continue;
+        }
         let existing = database::get_timeline_events_by_project(conn, &project_id)
             .unwrap_or_default();
         if existing.iter().any(|e| e.id == event.id) {
@@ -166,6 +300,200 @@ pub fn sync_filesystem_to_sql(
         }
     }
 
+    // Sync creatures
+    for creature in &creatures {
+        if in_trash(conn, EntityKind::Creature, &creature.id)? {
+            continue;
+        }
+        let existing = database::get_creatures_by_project(conn, &project_id)
+            .unwrap_or_default();
+        if existing.iter().any(|c| c.id == creature.id) {
+            database::update_creature(conn, creature)
+                .map_err(|e| format!("Failed to update creature: {}", e))?;
+        } else {
+            database::create_creature(conn, creature)
+                .map_err(|e| format!("Failed to create creature: {}", e))?;
+        }
+    }
+
+    // Sync NPCs
+    for npc in &npcs {
+        if in_trash(conn, EntityKind::Npc, &npc.id)? {
+            continue;
+        }
+        let existing = database::get_npcs_by_project(conn, &project_id)
+            .unwrap_or_default();
+        if existing.iter().any(|n| n.id == npc.id) {
+            database::update_npc(conn, npc)
+                .map_err(|e| format!("Failed to update NPC: {}", e))?;
+        } else {
+            database::create_npc(conn, npc)
+                .map_err(|e| format!("Failed to create NPC: {}", e))?;
+        }
+    }
+
+    // Sync world rules
+    for rule in &world_rules {
+        if in_trash(conn, EntityKind::WorldRule, &rule.id)? {
+            continue;
+        }
+        let existing = database::get_world_rules_by_project(conn, &project_id)
+            .unwrap_or_default();
+        if existing.iter().any(|r| r.id == rule.id) {
+            database::update_world_rule(conn, rule)
+                .map_err(|e| format!("Failed to update world rule: {}", e))?;
+        } else {
+            database::create_world_rule(conn, rule)
+                .map_err(|e| format!("Failed to create world rule: {}", e))?;
+        }
+    }
+
+    // Custom field values, now that everything they may reference exists
+    let custom_values = characters
+        .iter()
+        .map(|c| (EntityKind::Character, &c.id, &c.custom_fields))
+        .chain(
+            locations
+                .iter()
+                .map(|l| (EntityKind::Location, &l.id, &l.custom_fields)),
+        )
+        .chain(
+            lore_items
+                .iter()
+                .map(|l| (EntityKind::LoreItem, &l.id, &l.custom_fields)),
+        );
+    for (kind, id, values) in custom_values {
+        if values.is_empty() || in_trash(conn, kind, id)? {
+            continue;
+        }
+        database::set_custom_field_values(conn, kind, id, values)
+            .map_err(|e| format!("Failed to set custom fields: {}", e))?;
+    }
+
+    // Sync aliases of the characters, locations and lore items in the folder
+    let named = characters
+        .iter()
+        .map(|c| (EntityKind::Character, &c.id))
+        .chain(locations.iter().map(|l| (EntityKind::Location, &l.id)))
+        .chain(lore_items.iter().map(|l| (EntityKind::LoreItem, &l.id)));
+    for (kind, id) in named {
+        if in_trash(conn, kind, id)? {
+            continue;
+        }
+        let mut wanted: Vec<String> = aliases
+            .iter()
+            .filter(|a| a.entity_type == kind && &a.entity_id == id)
+            .map(|a| a.alias.clone())
+            .collect();
+        wanted.sort();
+        let current = database::get_entity_aliases(conn, kind, id)
+            .map_err(|e| format!("Failed to get aliases: {}", e))?;
+        if current != wanted {
+            database::set_entity_aliases(conn, kind, id, &wanted)
+                .map_err(|e| format!("Failed to update aliases: {}", e))?;
+        }
+    }
+
+    // Sync tags. A folder tag matching a tag of another id by name (one
+    // created from a timeline event's tag names, say) is taken to be it.
+    let mut tag_ids = HashMap::new();
+    for tag in &tags {
+        let known = database::get_project_tags(conn, &project_id)
+            .map_err(|e| format!("Failed to get tags: {}", e))?;
+        let same_name = known
+            .iter()
+            .find(|t| t.name.eq_ignore_ascii_case(tag.name.trim()));
+        let id = if known.iter().any(|t| t.id == tag.id) {
+            database::update_tag(conn, tag)
+                .map_err(|e| format!("Failed to update tag: {}", e))?;
+            tag.id.clone()
+        } else if let Some(same) = same_name {
+            same.id.clone()
+        } else {
+            database::create_tag(conn, tag)
+                .map_err(|e| format!("Failed to create tag: {}", e))?;
+            tag.id.clone()
+        };
+        tag_ids.insert(tag.id.clone(), id);
+    }
+
+    let taggable = chapters
+        .iter()
+        .map(|c| (EntityKind::Chapter, &c.id))
+        .chain(scenes.iter().map(|s| (EntityKind::Scene, &s.id)))
+        .chain(characters.iter().map(|c| (EntityKind::Character, &c.id)))
+        .chain(locations.iter().map(|l| (EntityKind::Location, &l.id)))
+        .chain(lore_items.iter().map(|l| (EntityKind::LoreItem, &l.id)))
+        .chain(
+            timeline_events
+                .iter()
+                .map(|e| (EntityKind::TimelineEvent, &e.id)),
+        );
+    for (kind, id) in taggable {
+        if in_trash(conn, kind, id)? {
+            continue;
+        }
+        let mut wanted: Vec<String> = entity_tags
+            .iter()
+            .filter(|t| t.entity_type == kind && &t.entity_id == id)
+            .filter_map(|t| tag_ids.get(&t.tag_id).cloned())
+            .collect();
+        wanted.sort();
+        wanted.dedup();
+        let mut current: Vec<String> = database::get_entity_tags(conn, kind, id)
+            .map_err(|e| format!("Failed to get tags: {}", e))?
+            .into_iter()
+            .map(|t| t.id)
+            .collect();
+        current.sort();
+        if current != wanted {
+            database::set_entity_tags(conn, kind, id, &wanted)
+                .map_err(|e| format!("Failed to update tags: {}", e))?;
+        }
+    }
+
+    // Import chapter history. Every revision file was offered to SQL here,
+    // so the ones it did not keep were pruned or belong to purged chapters.
+    let revisions = project_fs::read_revisions_from_folder(project_path)?;
+    database::import_revisions(conn, &project_id, &revisions)
+        .map_err(|e| format!("Failed to import revisions: {}", e))?;
+    let kept: HashSet<String> = database::list_project_revisions(conn, &project_id)
+        .map_err(|e| format!("Failed to get revisions: {}", e))?
+        .into_iter()
+        .map(|r| r.id)
+        .collect();
+    for revision in &revisions {
+        if revision.info.project_id == project_id && !kept.contains(&revision.info.id) {
+            project_fs::remove_revision_file(project_path, &revision.info.id)?;
+        }
+    }
+
     log::info!("Synced filesystem to SQL for project {}", project_id);
     Ok(project_id)
 }
+
+/// Revisions never change once recorded, so only new ones are written.
+/// Files SQL doesn't know are left alone: the database may simply be missing
+/// that history, and the next import decides whether they were pruned.
+fn sync_revisions_to_folder(
+    conn: &rusqlite::Connection,
+    project_path: &Path,
+    project_id: &str,
+) -> Result<(), String> {
+    let revisions = database::list_project_revisions(conn, project_id)
+        .map_err(|e| format!("Failed to get revisions: {}", e))?;
+    let on_disk = project_fs::list_revision_files(project_path);
+
+    for info in revisions.iter().filter(|r| !on_disk.contains(&r.id)) {
+        if let Some(revision) = database::get_revision(conn, &info.id)
+            .map_err(|e| format!("Failed to get revision: {}", e))?
+        {
+            project_fs::write_revision_file(project_path, &revision)?;
+        }
+    }
+    Ok(())
+}
+
+fn in_trash(conn: &rusqlite::Connection, kind: EntityKind, id: &str) -> Result<bool, String> {
+    database::is_in_trash(conn, kind, id).map_err(|e| e.to_string())
+}
@@ -39,8 +39,6 @@ export const ProjectSettingsModal = () => {
         ...project,
         banners: typeof project.banners === 'object' ? JSON.stringify(project.banners) : project.banners,
         apiKeys: typeof project.apiKeys === 'object' ? JSON.stringify(project.apiKeys) : project.apiKeys,
-        creatures: typeof project.creatures === 'object' ? JSON.stringify(project.creatures) : project.creatures,
-        worldRules: typeof project.worldRules === 'object' ? JSON.stringify(project.worldRules) : project.worldRules,
       };
 
       await dbUpdateProject(payload as any);
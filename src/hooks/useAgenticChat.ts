@@ -3,6 +3,28 @@ import { useSettingsStore } from '@/stores/useSettingsStore';
 import { useProjectStore } from '@/stores/useProjectStore';
 import { AgenticService, ContextNeeds } from '@/lib/ai/agentic-service';
 import { generateTextAI } from '@/lib/ai/client-ai';
+import { aiChatWithTools, isTauri } from '@/lib/tauri-bridge';
+import type { AiProvider } from '@/lib/tauri-bridge';
+import { confirm } from '@/stores/useConfirmStore';
+
+/** Backend providers able to run the project tools, by settings provider */
+const TOOL_PROVIDERS: Record<string, AiProvider> = {
+  anthropic: 'claude',
+  openai: 'openai',
+  google: 'gemini',
+  ollama: 'ollama',
+};
+
+/** Context sent up front when the model fetches the rest through tools */
+const NO_EXTRA_CONTEXT: ContextNeeds = {
+  characters: [],
+  locations: [],
+  scenes: [],
+  lore: [],
+  timeline: [],
+  chaptersToRead: [],
+  reasoning: '',
+};
 
 export function useAgenticChat(persistenceKey: string = 'pluma_ai_messages') {
   const [isLoading, setIsLoading] = useState(false);
@@ -70,6 +92,34 @@ export function useAgenticChat(persistenceKey: string = 'pluma_ai_messages') {
           return;
       }
 
+      // With the desktop backend, the model looks up what it needs itself
+      const toolProvider = TOOL_PROVIDERS[activeProvider];
+      if (isTauri() && activeProject && toolProvider) {
+          const context = AgenticService.buildSelectiveContext(NO_EXTRA_CONTEXT, chapterId);
+          const prompt = AgenticService.buildFinalPrompt(mode, userInput, context, selectedText);
+
+          const response = await aiChatWithTools(
+              activeProject.id,
+              {
+                  provider: toolProvider,
+                  apiKey: apiKey || '',
+                  model: activeModel,
+                  messages: [{ role: 'user', content: prompt }],
+                  temperature: ragConfiguration?.writing?.temperature ?? 0.7,
+              },
+              {
+                  onApproval: (call) =>
+                      confirm(`The assistant wants to run ${call.name}:\n${JSON.stringify(call.arguments, null, 2)}`, {
+                          confirmText: 'Allow',
+                      }),
+              }
+          );
+
+          setMessages(prev => [...prev, { id: Math.random().toString(36).substring(7), role: 'assistant', content: response.content, timestamp: new Date().toISOString() }]);
+          return;
+      }
+
+      // Without it, a first pass picks the context to send
       // Step 1: Analyze Needs
       const inventory = AgenticService.buildContextInventory(chapterId);
       if (!inventory) throw new Error('No project loaded');
@@ -6,6 +6,8 @@
  */
 
 import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
+import type { Creature, Npc, WorldRule } from '../types/domain';
 
 // ============================================================================
 // Types
@@ -32,9 +34,6 @@ export interface DbProject {
   projectType?: string;
   banners?: Record<string, string>;
   apiKeys?: unknown;
-  creatures?: unknown;
-  worldRules?: unknown;
-  npcs?: unknown;
 }
 
 export interface DbChapter {
@@ -48,8 +47,13 @@ export interface DbChapter {
   number?: number;
   image?: string;
   imageType?: string;
+  /** Set through dbSetChapterContentMode; ignored by dbUpdateChapter */
+  contentMode?: DbContentMode;
 }
 
+/** 'scenes': the chapter text is compiled from its scenes */
+export type DbContentMode = 'document' | 'scenes';
+
 export interface DbScene {
   id: string;
   chapterId: string;
@@ -61,6 +65,9 @@ export interface DbScene {
   notes?: string;
   image?: string;
   imageType?: string;
+  content?: string;
+  /** Computed by the backend from `content` */
+  wordCount?: number;
 }
 
 export interface DbCharacter {
@@ -79,6 +86,8 @@ export interface DbCharacter {
   currentVitalStatus?: string;
   visualPosition?: { x: number; y: number };
   relationships: DbRelationship[];
+  /** Values of the project's custom fields, keyed by field id */
+  customFields?: Record<string, DbCustomFieldValue>;
 }
 
 export interface DbRelationship {
@@ -104,6 +113,8 @@ export interface DbLocation {
   plans: unknown[];
   connections: unknown[];
   visualPosition?: { x: number; y: number };
+  /** Values of the project's custom fields, keyed by field id */
+  customFields?: Record<string, DbCustomFieldValue>;
 }
 
 export interface DbLoreItem {
@@ -114,6 +125,8 @@ export interface DbLoreItem {
   content: string;
   summary?: string;
   relatedEntityIds: string[];
+  /** Values of the project's custom fields, keyed by field id */
+  customFields?: Record<string, DbCustomFieldValue>;
 }
 
 export interface DbTimelineEvent {
@@ -132,12 +145,412 @@ export interface DbTimelineEvent {
   chapterId?: string;
 }
 
+export type DbCreature = Creature & {
+  projectId: string;
+  originPackageId?: string;
+};
+
+export type DbNpc = Npc & {
+  projectId: string;
+  originPackageId?: string;
+};
+
+export type DbWorldRule = WorldRule & {
+  projectId: string;
+  originPackageId?: string;
+};
+
+export type DbEntityKind =
+  | 'project'
+  | 'chapter'
+  | 'scene'
+  | 'character'
+  | 'relationship'
+  | 'location'
+  | 'loreItem'
+  | 'timelineEvent'
+  | 'creature'
+  | 'npc'
+  | 'worldRule';
+
+export interface DbSceneMove {
+  sourceChapterId: string;
+  sourceScenes: DbScene[];
+  targetChapterId: string;
+  targetScenes: DbScene[];
+}
+
+/** What dbDuplicateProject copies; every part is included unless set to false */
+export interface DbDuplicateOptions {
+  title?: string;
+  /** Chapters and scenes; false copies only the world bible */
+  manuscript?: boolean;
+  /** Characters and their relationships */
+  characters?: boolean;
+  locations?: boolean;
+  lore?: boolean;
+  timeline?: boolean;
+  /** Creatures, NPCs and world rules */
+  rpg?: boolean;
+}
+
+export interface DbChapterSplit {
+  chapter: DbChapter;
+  scenes: DbScene[];
+}
+
+export interface DbBatchOperation {
+  action: 'create' | 'update' | 'delete';
+  entityType: DbEntityKind;
+  /** Required for deletes; creates without an id get a new one */
+  id?: string;
+  data?: unknown;
+  /** Owning character, required to create or update a relationship */
+  characterId?: string;
+}
+
+export interface DbBatchOperationResult {
+  index: number;
+  status: 'applied' | 'rolledBack' | 'failed' | 'skipped';
+  id?: string;
+  error?: string;
+}
+
+export interface DbBatchResult {
+  committed: boolean;
+  results: DbBatchOperationResult[];
+}
+
+export interface DbSearchHit {
+  entityType: DbEntityKind;
+  entityId: string;
+  projectId?: string;
+  title: string;
+  /** Matching excerpt with matches wrapped in <mark> tags */
+  snippet: string;
+  /** BM25 score; lower is more relevant */
+  rank: number;
+}
+
+export interface DbMention {
+  entityType: DbEntityKind;
+  entityId: string;
+  sourceType: DbEntityKind;
+  sourceId: string;
+  /** Chapter the source belongs to; absent for lore items */
+  chapterId?: string;
+  /** Character offsets into the plain text of the source */
+  start: number;
+  end: number;
+  /** The name or alias as written */
+  text: string;
+  snippet: string;
+}
+
+export interface DbCastMember {
+  entityType: DbEntityKind;
+  entityId: string;
+  name: string;
+  mentionCount: number;
+}
+
+export interface DbBacklink {
+  sourceType: DbEntityKind;
+  sourceId: string;
+  title: string;
+  chapterId?: string;
+  mentionCount: number;
+}
+
+export type DbLinkStatus = 'resolved' | 'broken' | 'ambiguous';
+
+export interface DbLinkTarget {
+  entityType: DbEntityKind;
+  entityId: string;
+  name: string;
+}
+
+export interface DbResolvedLink {
+  /** Character offsets of the link in the HTML */
+  start: number;
+  end: number;
+  /** The link as written, brackets included */
+  text: string;
+  target: string;
+  /** Kind selected by a prefix such as `lore:` */
+  kind?: DbEntityKind;
+  status: DbLinkStatus;
+  candidates: DbLinkTarget[];
+}
+
+export interface DbLinkIssue {
+  sourceType: DbEntityKind;
+  sourceId: string;
+  chapterId: string;
+  chapterTitle: string;
+  link: DbResolvedLink;
+}
+
+export interface DbRenameResult {
+  linksUpdated: number;
+  chapterIds: string[];
+  sceneIds: string[];
+}
+
+export interface DbGlossaryTerm extends GlossaryEntry {
+  entityType: DbEntityKind;
+  entityId: string;
+}
+
+export type DbCustomFieldType = 'text' | 'number' | 'enum' | 'date' | 'entityReference';
+
+/** Text, enum option, `YYYY-MM-DD` date or entity id; numbers for number fields */
+export type DbCustomFieldValue = string | number;
+
+export interface DbCustomField {
+  id: string;
+  projectId: string;
+  entityType: DbEntityKind;
+  name: string;
+  fieldType: DbCustomFieldType;
+  /** Allowed values of an enum field */
+  options?: string[];
+  /** Entity type an entity reference field points to */
+  referenceType?: DbEntityKind;
+  position?: number;
+}
+
+export type DbCustomFieldFilterOp =
+  | 'eq'
+  | 'ne'
+  | 'lt'
+  | 'lte'
+  | 'gt'
+  | 'gte'
+  | 'contains'
+  | 'exists'
+  | 'missing';
+
+export interface DbCustomFieldFilter {
+  fieldId: string;
+  op: DbCustomFieldFilterOp;
+  /** Unused by `exists` and `missing` */
+  value?: DbCustomFieldValue;
+}
+
+export interface DbTag {
+  id: string;
+  projectId: string;
+  name: string;
+  /** CSS color, e.g. `#e11d48` */
+  color?: string;
+}
+
+/** `all`: entities with every tag (AND); `any`: with at least one (OR) */
+export type DbTagMatch = 'all' | 'any';
+
+export interface DbTaggedEntity {
+  entityType: DbEntityKind;
+  entityId: string;
+  title: string;
+  /** Its tags among those asked for */
+  tagIds: string[];
+}
+
+export interface DbFieldFilter {
+  /** camelCase field name, e.g. `role` or `originPackageId` */
+  field: string;
+  op: DbCustomFieldFilterOp;
+  /** Unused by `exists` and `missing` */
+  value?: string | number | boolean;
+}
+
+export interface DbEntityQuery {
+  /** Fields to return (`id` always is); all of them when omitted */
+  fields?: string[];
+  filters?: DbFieldFilter[];
+  customFields?: DbCustomFieldFilter[];
+  tagIds?: string[];
+  tagMatch?: DbTagMatch;
+  sort?: { field: string; descending?: boolean };
+  /** Page size, 100 by default */
+  limit?: number;
+  /** `nextCursor` of the previous page */
+  cursor?: string;
+}
+
+export interface DbQueryPage<T = Record<string, unknown>> {
+  /** Partial entities holding the selected fields */
+  items: (Partial<T> & { id: string })[];
+  /** Absent on the last page */
+  nextCursor?: string;
+}
+
+export type DbSettingKind =
+  | { type: 'bool' }
+  | { type: 'integer'; min: number; max: number }
+  | { type: 'text' }
+  | { type: 'choice'; options: string[] }
+  | { type: 'textList' }
+  | { type: 'object' }
+  | { type: 'list' };
+
+export type DbProjectSettingKey =
+  | 'targetWordCount'
+  | 'chapterTargetWordCount'
+  | 'aiProvider'
+  | 'aiModel'
+  | 'exportFormat'
+  | 'exportIncludeTitlePage'
+  | 'exportIncludeToc'
+  | 'exportLinkStyle'
+  | 'speechLanguage'
+  | 'speechVocabulary';
+
+/** `null` values mean "not set": use the global preference */
+export type DbProjectSetting = DbSettingKind & {
+  key: DbProjectSettingKey;
+  value: unknown;
+  default: unknown;
+  isDefault: boolean;
+};
+
+/** An application setting; a `null` default is supplied by the frontend */
+export type AppSetting = DbSettingKind & {
+  key: string;
+  value: unknown;
+  default: unknown;
+  isDefault: boolean;
+};
+
+export type DbAuditSource = 'user' | 'ai' | 'package' | 'sync';
+
+export type DbAuditAction = 'create' | 'update' | 'delete' | 'restore' | 'purge';
+
+export interface DbAuditEntry {
+  id: number;
+  projectId?: string;
+  entityType: DbEntityKind;
+  entityId: string;
+  action: DbAuditAction;
+  source: DbAuditSource;
+  /** Changed columns (snake_case) and their new values; long text is `true` */
+  changes: Record<string, unknown>;
+  createdAt: string;
+  /** Earlier autosaves listed as part of this entry */
+  folded: number;
+}
+
+export interface DbAuditFilter {
+  /** Only entries changing this column, e.g. `current_vital_status`; lists every change, unfolded */
+  field?: string;
+  source?: DbAuditSource;
+  /** `id` of the oldest entry already loaded, to page back */
+  before?: number;
+  limit?: number;
+}
+
+export interface DbUndoStep {
+  id: number;
+  label: string;
+  createdAt: string;
+}
+
+/** Pending steps of a project, next one first */
+export interface DbUndoSteps {
+  undo: DbUndoStep[];
+  redo: DbUndoStep[];
+}
+
+export interface DbTrashItem {
+  entityType: DbEntityKind;
+  id: string;
+  projectId?: string;
+  title: string;
+  deletedAt: string;
+}
+
+export type DbRevisionSource = 'auto' | 'manual' | 'restore';
+
+export interface DbRevisionInfo {
+  id: string;
+  chapterId: string;
+  projectId: string;
+  contentHash: string;
+  wordCount: number;
+  source: DbRevisionSource;
+  label?: string;
+  createdAt: string;
+}
+
+export interface DbChapterRevision extends DbRevisionInfo {
+  content: string;
+}
+
+export interface DbDiffSegment {
+  op: 'equal' | 'insert' | 'delete';
+  text: string;
+  wordCount: number;
+}
+
+export interface DbRevisionDiff {
+  fromRevisionId: string;
+  /** Absent when compared against the chapter's current content */
+  toRevisionId?: string;
+  wordsAdded: number;
+  wordsRemoved: number;
+  segments: DbDiffSegment[];
+}
+
+export interface DbIntegrityIssue {
+  kind: 'orphan' | 'danglingReference';
+  table: string;
+  rowId: string;
+  column: string;
+  missingId: string;
+}
+
+export interface DbIntegrityReport {
+  storageErrors: string[];
+  issues: DbIntegrityIssue[];
+  repaired: boolean;
+}
+
 // AI Types
-export type AiProvider = 'claude' | 'openai' | 'gemini';
+/** A built-in provider, or the id of an `AiProviderProfile` */
+export type AiProvider = 'claude' | 'openai' | 'gemini' | 'ollama' | (string & {});
+
+/** OpenAI-compatible server, stored in the `ai_provider_profiles` setting */
+export interface AiProviderProfile {
+  id: string;
+  name: string;
+  /** API root, e.g. `http://localhost:8080/v1` */
+  baseUrl: string;
+  auth?: { type: 'bearer' } | { type: 'header'; name: string } | { type: 'none' };
+  defaultModel?: string;
+  extraHeaders?: Record<string, string>;
+}
 
 export interface ChatMessage {
-  role: 'user' | 'assistant';
+  role: 'user' | 'assistant' | 'tool';
   content: string;
+  /** Calls made by an assistant message */
+  toolCalls?: AiToolCall[];
+  /** On `tool` messages, the call answered */
+  toolCallId?: string;
+}
+
+export interface AiToolDefinition {
+  name: string;
+  description: string;
+  /** JSON Schema of the arguments */
+  parameters: Record<string, unknown>;
+}
+
+export interface AiToolCall {
+  id: string;
+  name: string;
+  arguments: Record<string, unknown>;
 }
 
 export interface AiChatRequest {
@@ -148,6 +561,8 @@ export interface AiChatRequest {
   maxTokens?: number;
   temperature?: number;
   systemPrompt?: string;
+  /** Tools the model may call instead of answering */
+  tools?: AiToolDefinition[];
 }
 
 export interface AiChatResponse {
@@ -157,6 +572,75 @@ export interface AiChatResponse {
     inputTokens: number;
     outputTokens: number;
   };
+  /** Calls to answer before the model goes on */
+  toolCalls?: AiToolCall[];
+}
+
+export interface AiToolChatResponse extends AiChatResponse {
+  /** Tool calls and results exchanged before the reply, for the history */
+  messages: ChatMessage[];
+}
+
+export interface AiToolApproval {
+  requestId: string;
+  call: AiToolCall;
+}
+
+export interface AiStreamDelta {
+  requestId: string;
+  delta: string;
+}
+
+/**
+ * Error from an AI command; AI commands reject with this rather than a
+ * string, so callers can react to its `kind`
+ */
+export interface AiError {
+  kind:
+    | 'requestFailed'
+    | 'apiError'
+    | 'invalidResponse'
+    | 'unknownProvider'
+    | 'config'
+    | 'rateLimited'
+    | 'unauthorized'
+    | 'contextTooLong'
+    | 'overloaded'
+    | 'cancelled'
+    | 'duplicateRequest'
+    | 'toolLimit';
+  message: string;
+  /** Seconds to wait before retrying, for `rateLimited` */
+  retryAfter?: number;
+}
+
+export interface AiStreamDone {
+  requestId: string;
+  model?: string;
+  usage?: AiChatResponse['usage'];
+  /** Set when the stream failed */
+  error?: AiError;
+}
+
+export interface OllamaModel {
+  /** Name and tag, e.g. `llama3.2:latest` */
+  name: string;
+  /** Size on disk, in bytes */
+  size: number;
+  modifiedAt: string;
+  family?: string;
+  parameterSize?: string;
+  quantizationLevel?: string;
+}
+
+export interface OllamaPullProgress {
+  model: string;
+  /** Stage, e.g. `pulling manifest` or `success` */
+  status: string;
+  digest?: string;
+  completedBytes: number;
+  totalBytes: number;
+  percent: number;
 }
 
 // Crypto Types
@@ -173,17 +657,35 @@ export interface DocumentMetadata {
   description?: string;
 }
 
+export interface ExportScene {
+  title: string;
+  content: string;
+}
+
 export interface ExportChapter {
   title: string;
   number?: number;
   content: string;
+  /** Rendered instead of `content` when present, separated by scene breaks */
+  scenes?: ExportScene[];
 }
 
 export interface ExportDocument {
   metadata: DocumentMetadata;
   chapters: ExportChapter[];
+  /** Terms wiki links may refer to, for the 'glossary' link style */
+  glossary?: GlossaryEntry[];
 }
 
+export interface GlossaryEntry {
+  term: string;
+  definition?: string;
+  aliases?: string[];
+}
+
+/** How `[[Name]]` links are exported: as their text, or with a glossary reference */
+export type LinkStyle = 'plain' | 'glossary';
+
 export interface PdfOptions {
   pageWidthMm?: number;
   pageHeightMm?: number;
@@ -192,12 +694,14 @@ export interface PdfOptions {
   lineHeight?: number;
   includeTitlePage?: boolean;
   includeToc?: boolean;
+  linkStyle?: LinkStyle;
 }
 
 export interface DocxOptions {
   fontSizePt?: number;
   includeTitlePage?: boolean;
   includeToc?: boolean;
+  linkStyle?: LinkStyle;
 }
 
 // Filesystem Types
@@ -210,6 +714,7 @@ export interface ProjectData {
   loreItems: unknown[];
   timelineEvents: unknown[];
   relationships: unknown[];
+  customFields?: unknown[];
 }
 
 // ============================================================================
@@ -281,6 +786,12 @@ export async function dbDeleteProject(id: string): Promise<void> {
   return invoke('db_delete_project', { id });
 }
 
+/** Deep-copy a project with new ids; returns the copy */
+export async function dbDuplicateProject(projectId: string, options?: DbDuplicateOptions): Promise<DbProject> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_duplicate_project', { projectId, options });
+}
+
 // ============================================================================
 // Database Commands - Chapters
 // ============================================================================
@@ -329,6 +840,54 @@ export async function dbDeleteScene(id: string): Promise<void> {
   return invoke('db_delete_scene', { id });
 }
 
+// ============================================================================
+// Database Commands - Ordering
+// ============================================================================
+
+/** Renumber chapters densely from 1; unlisted chapters keep their order after the listed ones */
+export async function dbReorderChapters(projectId: string, orderedIds: string[]): Promise<DbChapter[]> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_reorder_chapters', { projectId, orderedIds });
+}
+
+/** Reposition a chapter's scenes densely from 0 */
+export async function dbReorderScenes(chapterId: string, orderedIds: string[]): Promise<DbScene[]> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_reorder_scenes', { chapterId, orderedIds });
+}
+
+/** Move a scene to another chapter, at `position` or at the end */
+export async function dbMoveScene(
+  sceneId: string,
+  targetChapterId: string,
+  position?: number
+): Promise<DbSceneMove> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_move_scene', { sceneId, targetChapterId, position });
+}
+
+// ============================================================================
+// Database Commands - Chapter Assembly
+// ============================================================================
+
+/** Switch a chapter between its own text and text compiled from its scenes */
+export async function dbSetChapterContentMode(chapterId: string, mode: DbContentMode): Promise<DbChapter> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_set_chapter_content_mode', { chapterId, mode });
+}
+
+/** The text a chapter's scenes compile to, whatever its mode */
+export async function dbCompileChapter(chapterId: string): Promise<string> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_compile_chapter', { chapterId });
+}
+
+/** Split a chapter into scenes at its scene-break markers (<hr>, ***, #...) */
+export async function dbSplitChapterIntoScenes(chapterId: string): Promise<DbChapterSplit> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_split_chapter_into_scenes', { chapterId });
+}
+
 // ============================================================================
 // Database Commands - Characters
 // ============================================================================
@@ -457,10 +1016,433 @@ export async function dbDeleteTimelineEvent(id: string): Promise<void> {
   return invoke('db_delete_timeline_event', { id });
 }
 
+// ============================================================================
+// Database Commands - Creatures
+// ============================================================================
+
+export async function dbCreateCreature(creature: DbCreature): Promise<void> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_create_creature', { creature });
+}
+
+export async function dbGetCreaturesByProject(projectId: string): Promise<DbCreature[]> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_get_creatures_by_project', { projectId });
+}
+
+export async function dbUpdateCreature(creature: DbCreature): Promise<void> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_update_creature', { creature });
+}
+
+export async function dbDeleteCreature(id: string): Promise<void> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_delete_creature', { id });
+}
+
+// ============================================================================
+// Database Commands - NPCs
+// ============================================================================
+
+export async function dbCreateNpc(npc: DbNpc): Promise<void> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_create_npc', { npc });
+}
+
+export async function dbGetNpcsByProject(projectId: string): Promise<DbNpc[]> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_get_npcs_by_project', { projectId });
+}
+
+export async function dbUpdateNpc(npc: DbNpc): Promise<void> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_update_npc', { npc });
+}
+
+export async function dbDeleteNpc(id: string): Promise<void> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_delete_npc', { id });
+}
+
+// ============================================================================
+// Database Commands - World Rules
+// ============================================================================
+
+export async function dbCreateWorldRule(rule: DbWorldRule): Promise<void> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_create_world_rule', { rule });
+}
+
+export async function dbGetWorldRulesByProject(projectId: string): Promise<DbWorldRule[]> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_get_world_rules_by_project', { projectId });
+}
+
+export async function dbUpdateWorldRule(rule: DbWorldRule): Promise<void> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_update_world_rule', { rule });
+}
+
+export async function dbDeleteWorldRule(id: string): Promise<void> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_delete_world_rule', { id });
+}
+
+// ============================================================================
+// Database Commands - Batch
+// ============================================================================
+
+export async function dbApplyBatch(operations: DbBatchOperation[]): Promise<DbBatchResult> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_apply_batch', { operations });
+}
+
+// ============================================================================
+// Database Commands - Chapter Revisions
+// ============================================================================
+
+export async function dbListChapterRevisions(chapterId: string): Promise<DbRevisionInfo[]> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_list_chapter_revisions', { chapterId });
+}
+
+export async function dbGetChapterRevision(id: string): Promise<DbChapterRevision | null> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_get_chapter_revision', { id });
+}
+
+export async function dbCreateChapterRevision(
+  chapterId: string,
+  label?: string
+): Promise<DbRevisionInfo | null> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_create_chapter_revision', { chapterId, label });
+}
+
+export async function dbDiffChapterRevisions(
+  fromId: string,
+  toId?: string
+): Promise<DbRevisionDiff> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_diff_chapter_revisions', { fromId, toId });
+}
+
+export async function dbRestoreChapterRevision(id: string): Promise<DbChapter> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_restore_chapter_revision', { id });
+}
+
+// ============================================================================
+// Database Commands - Search
+// ============================================================================
+
+export async function dbSearch(
+  query: string,
+  projectId?: string,
+  entityTypes?: DbEntityKind[],
+  limit?: number
+): Promise<DbSearchHit[]> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_search', { query, projectId, entityTypes, limit });
+}
+
+// ============================================================================
+// Database Commands - Mentions
+// ============================================================================
+
+export async function dbGetEntityMentions(entityType: DbEntityKind, id: string): Promise<DbMention[]> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_get_entity_mentions', { entityType, id });
+}
+
+export async function dbGetChapterCast(chapterId: string): Promise<DbCastMember[]> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_get_chapter_cast', { chapterId });
+}
+
+export async function dbGetBacklinks(entityType: DbEntityKind, id: string): Promise<DbBacklink[]> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_get_backlinks', { entityType, id });
+}
+
+export async function dbGetEntityAliases(entityType: DbEntityKind, id: string): Promise<string[]> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_get_entity_aliases', { entityType, id });
+}
+
+export async function dbSetEntityAliases(
+  entityType: DbEntityKind,
+  id: string,
+  aliases: string[]
+): Promise<void> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_set_entity_aliases', { entityType, id, aliases });
+}
+
+export async function dbReindexMentions(projectId: string): Promise<number> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_reindex_mentions', { projectId });
+}
+
+// ============================================================================
+// Database Commands - Links
+// ============================================================================
+
+export async function dbResolveLinks(projectId: string, content: string): Promise<DbResolvedLink[]> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_resolve_links', { projectId, content });
+}
+
+export async function dbGetLinkReport(projectId: string): Promise<DbLinkIssue[]> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_get_link_report', { projectId });
+}
+
+export async function dbRenameEntity(
+  entityType: DbEntityKind,
+  id: string,
+  newName: string
+): Promise<DbRenameResult> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_rename_entity', { entityType, id, newName });
+}
+
+export async function dbGetLinkGlossary(projectId: string): Promise<DbGlossaryTerm[]> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_get_link_glossary', { projectId });
+}
+
+// ============================================================================
+// Database Commands - Custom Fields
+// ============================================================================
+
+export async function dbCreateCustomField(field: DbCustomField): Promise<void> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_create_custom_field', { field });
+}
+
+export async function dbGetCustomFields(
+  projectId: string,
+  entityType?: DbEntityKind
+): Promise<DbCustomField[]> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_get_custom_fields', { projectId, entityType });
+}
+
+/** Returns how many entities lost a value that no longer fits the field */
+export async function dbUpdateCustomField(field: DbCustomField): Promise<number> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_update_custom_field', { field });
+}
+
+export async function dbDeleteCustomField(id: string): Promise<void> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_delete_custom_field', { id });
+}
+
+/** Ids of the entities matching every filter */
+export async function dbFilterByCustomFields(
+  projectId: string,
+  entityType: DbEntityKind,
+  filters: DbCustomFieldFilter[]
+): Promise<string[]> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_filter_by_custom_fields', { projectId, entityType, filters });
+}
+
+// ============================================================================
+// Database Commands - Tags
+// ============================================================================
+
+export async function dbCreateTag(tag: DbTag): Promise<void> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_create_tag', { tag });
+}
+
+export async function dbGetProjectTags(projectId: string): Promise<DbTag[]> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_get_project_tags', { projectId });
+}
+
+/** Rename or recolor a tag */
+export async function dbUpdateTag(tag: DbTag): Promise<void> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_update_tag', { tag });
+}
+
+export async function dbDeleteTag(id: string): Promise<void> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_delete_tag', { id });
+}
+
+/** Fold the source tags into the target; returns how many entities gained it */
+export async function dbMergeTags(sourceIds: string[], targetId: string): Promise<number> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_merge_tags', { sourceIds, targetId });
+}
+
+export async function dbTagEntity(
+  entityType: DbEntityKind,
+  id: string,
+  tagId: string
+): Promise<boolean> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_tag_entity', { entityType, id, tagId });
+}
+
+export async function dbUntagEntity(
+  entityType: DbEntityKind,
+  id: string,
+  tagId: string
+): Promise<boolean> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_untag_entity', { entityType, id, tagId });
+}
+
+export async function dbGetEntityTags(entityType: DbEntityKind, id: string): Promise<DbTag[]> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_get_entity_tags', { entityType, id });
+}
+
+export async function dbGetEntitiesByTags(
+  projectId: string,
+  tagIds: string[],
+  mode: DbTagMatch,
+  entityTypes?: DbEntityKind[]
+): Promise<DbTaggedEntity[]> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_get_entities_by_tags', { projectId, tagIds, mode, entityTypes });
+}
+
+// ============================================================================
+// Database Commands - Query
+// ============================================================================
+
+export async function dbQueryEntities<T = Record<string, unknown>>(
+  projectId: string,
+  entityType: DbEntityKind,
+  query: DbEntityQuery
+): Promise<DbQueryPage<T>> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_query_entities', { projectId, entityType, query });
+}
+
+// ============================================================================
+// Database Commands - Project Settings
+// ============================================================================
+
+export async function dbGetProjectSettings(projectId: string): Promise<DbProjectSetting[]> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_get_project_settings', { projectId });
+}
+
+export async function dbGetProjectSetting<T = unknown>(
+  projectId: string,
+  key: DbProjectSettingKey
+): Promise<T> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_get_project_setting', { projectId, key });
+}
+
+export async function dbSetProjectSetting(
+  projectId: string,
+  key: DbProjectSettingKey,
+  value: unknown
+): Promise<void> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_set_project_setting', { projectId, key, value });
+}
+
+/** Resets every setting of the project when `key` is omitted */
+export async function dbResetProjectSetting(
+  projectId: string,
+  key?: DbProjectSettingKey
+): Promise<void> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_reset_project_setting', { projectId, key });
+}
+
+// ============================================================================
+// Database Commands - Audit Log
+// ============================================================================
+
+/** Newest first */
+export async function dbGetProjectAuditLog(
+  projectId: string,
+  filter?: DbAuditFilter
+): Promise<DbAuditEntry[]> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_get_project_audit_log', { projectId, filter });
+}
+
+export async function dbGetEntityAuditLog(
+  entityType: DbEntityKind,
+  id: string,
+  filter?: DbAuditFilter
+): Promise<DbAuditEntry[]> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_get_entity_audit_log', { entityType, id, filter });
+}
+
+// ============================================================================
+// Database Commands - Undo
+// ============================================================================
+
+/** Resolves to the undone step's label, or null if there was none */
+export async function dbUndo(projectId: string): Promise<string | null> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_undo', { projectId });
+}
+
+/** Resolves to the redone step's label, or null if there was none */
+export async function dbRedo(projectId: string): Promise<string | null> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_redo', { projectId });
+}
+
+export async function dbGetUndoSteps(projectId: string): Promise<DbUndoSteps> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_get_undo_steps', { projectId });
+}
+
+// ============================================================================
+// Database Commands - Trash
+// ============================================================================
+
+export async function dbListTrash(projectId: string): Promise<DbTrashItem[]> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_list_trash', { projectId });
+}
+
+export async function dbListDeletedProjects(): Promise<DbTrashItem[]> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_list_deleted_projects');
+}
+
+export async function dbRestoreFromTrash(entityType: DbEntityKind, id: string): Promise<void> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_restore_from_trash', { entityType, id });
+}
+
+export async function dbPurgeFromTrash(entityType: DbEntityKind, id: string): Promise<void> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_purge_from_trash', { entityType, id });
+}
+
+export async function dbEmptyTrash(projectId: string): Promise<number> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_empty_trash', { projectId });
+}
+
 // ============================================================================
 // Database Commands - System
 // ============================================================================
 
+export async function dbCheckIntegrity(repair = false): Promise<DbIntegrityReport> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('db_check_integrity', { repair });
+}
+
 export async function dbClearAllData(): Promise<void> {
   if (!isTauri()) throw new Error('Tauri not available');
   return invoke('db_clear_all_data');
@@ -479,6 +1461,35 @@ export async function dbSetSetting(key: string, value: string): Promise<void> {
   return invoke('db_set_setting', { key, value });
 }
 
+// ============================================================================
+// Settings Commands
+// ============================================================================
+
+/** Event carrying the changed settings, keyed by setting */
+export const SETTINGS_CHANGED_EVENT = 'settings-changed';
+
+export async function settingsGetAll(): Promise<AppSetting[]> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('settings_get_all');
+}
+
+/** Saves all changes or, if any value is invalid, none */
+export async function settingsUpdate(changes: Record<string, unknown>): Promise<void> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('settings_update', { changes });
+}
+
+/** Writes every setting except credentials to a JSON profile */
+export async function settingsExportProfile(path: string): Promise<void> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('settings_export_profile', { path });
+}
+
+export async function settingsImportProfile(path: string): Promise<void> {
+  if (!isTauri()) throw new Error('Tauri not available');
+  return invoke('settings_import_profile', { path });
+}
+
 // ============================================================================
 // AI Commands
 // ============================================================================
@@ -486,11 +1497,138 @@ export async function dbSetSetting(key: string, value: string): Promise<void> {
 /**
  * Send a chat request to an AI provider
  */
-export async function aiChat(request: AiChatRequest): Promise<AiChatResponse> {
+/** Send a chat request; pass a `requestId` to be able to `aiCancel` it */
+export async function aiChat(request: AiChatRequest, requestId?: string): Promise<AiChatResponse> {
+  if (!isTauri()) {
+    throw new Error('AI chat requires Tauri - not available in browser mode');
+  }
+  return invoke<AiChatResponse>('ai_chat', { request, requestId });
+}
+
+/** Abort a running AI request; false if none runs under `requestId` */
+export async function aiCancel(requestId: string): Promise<boolean> {
+  if (!isTauri()) {
+    throw new Error('AI chat requires Tauri - not available in browser mode');
+  }
+  return invoke<boolean>('ai_cancel', { requestId });
+}
+
+/** Event carrying the next piece of a streamed reply */
+export const AI_STREAM_DELTA_EVENT = 'ai-stream-delta';
+/** Event sent when a streamed reply ends, with its usage or error */
+export const AI_STREAM_DONE_EVENT = 'ai-stream-done';
+
+/**
+ * Send a chat request and receive the reply as it is generated.
+ * `onDelta` gets each piece; the promise resolves to the whole reply.
+ * `aiCancel(requestId)` stops it.
+ */
+export async function aiChatStream(
+  request: AiChatRequest,
+  onDelta: (delta: string) => void,
+  requestId: string = crypto.randomUUID()
+): Promise<AiChatResponse> {
+  if (!isTauri()) {
+    throw new Error('AI chat requires Tauri - not available in browser mode');
+  }
+  const unlisten = await listen<AiStreamDelta>(AI_STREAM_DELTA_EVENT, (event) => {
+    if (event.payload.requestId === requestId) onDelta(event.payload.delta);
+  });
+  try {
+    return await invoke<AiChatResponse>('ai_chat_stream', { requestId, request });
+  } finally {
+    unlisten();
+  }
+}
+
+/** Event asking to approve a tool call that changes the project */
+export const AI_TOOL_APPROVAL_EVENT = 'ai-tool-approval';
+
+export interface AiChatWithToolsOptions {
+  /** Most replies to ask for before giving up; 8 by default */
+  maxSteps?: number;
+  /**
+   * Decides on calls that change the project. Without it, such calls run
+   * without asking.
+   */
+  onApproval?: (call: AiToolCall) => boolean | Promise<boolean>;
+  requestId?: string;
+}
+
+/**
+ * Chat with the project's built-in tools (characters, chapters, lore,
+ * timeline); the backend runs the calls the model makes until it answers.
+ * `aiCancel(requestId)` stops it.
+ */
+export async function aiChatWithTools(
+  projectId: string,
+  request: AiChatRequest,
+  options: AiChatWithToolsOptions = {}
+): Promise<AiToolChatResponse> {
+  if (!isTauri()) {
+    throw new Error('AI chat requires Tauri - not available in browser mode');
+  }
+  const { maxSteps, onApproval, requestId = crypto.randomUUID() } = options;
+  const unlisten = await listen<AiToolApproval>(AI_TOOL_APPROVAL_EVENT, async (event) => {
+    if (event.payload.requestId !== requestId || !onApproval) return;
+    const approved = await Promise.resolve(onApproval(event.payload.call)).catch(() => false);
+    await aiResolveToolCall(requestId, event.payload.call.id, approved);
+  });
+  try {
+    return await invoke<AiToolChatResponse>('ai_chat_with_tools', {
+      requestId,
+      projectId,
+      request,
+      maxSteps,
+      approveWrites: !!onApproval,
+    });
+  } finally {
+    unlisten();
+  }
+}
+
+/** Approve or decline a tool call; false if it is not waiting */
+export async function aiResolveToolCall(
+  requestId: string,
+  callId: string,
+  approved: boolean
+): Promise<boolean> {
   if (!isTauri()) {
     throw new Error('AI chat requires Tauri - not available in browser mode');
   }
-  return invoke<AiChatResponse>('ai_chat', { request });
+  return invoke<boolean>('ai_resolve_tool_call', { requestId, callId, approved });
+}
+
+/** Event reporting each step of an Ollama model pull */
+export const OLLAMA_PULL_PROGRESS_EVENT = 'ollama-pull-progress';
+
+export async function ollamaListModels(): Promise<OllamaModel[]> {
+  if (!isTauri()) {
+    throw new Error('Ollama requires Tauri - not available in browser mode');
+  }
+  return invoke<OllamaModel[]>('ollama_list_models');
+}
+
+/**
+ * Pull a model into the local Ollama, reporting progress to `onProgress`.
+ * Pass a `requestId` to be able to `aiCancel` it.
+ */
+export async function ollamaPullModel(
+  model: string,
+  onProgress?: (progress: OllamaPullProgress) => void,
+  requestId?: string
+): Promise<void> {
+  if (!isTauri()) {
+    throw new Error('Ollama requires Tauri - not available in browser mode');
+  }
+  const unlisten = await listen<OllamaPullProgress>(OLLAMA_PULL_PROGRESS_EVENT, (event) => {
+    if (event.payload.model === model) onProgress?.(event.payload);
+  });
+  try {
+    await invoke('ollama_pull_model', { model, requestId });
+  } finally {
+    unlisten();
+  }
 }
 
 // ============================================================================
@@ -762,6 +1900,12 @@ export async function wsResolveImage(projectId: string, relativePath: string): P
   return invoke<string>('ws_resolve_image', { projectId, relativePath });
 }
 
+/** Copy the images a duplicated project refers to from the original's folder */
+export async function wsCopyProjectImages(sourceProjectId: string, targetProjectId: string): Promise<number> {
+  if (!isTauri()) throw new Error('Requires Tauri');
+  return invoke<number>('ws_copy_project_images', { sourceProjectId, targetProjectId });
+}
+
 export async function wsSyncToDisk(projectId: string): Promise<void> {
   if (!isTauri()) throw new Error('Requires Tauri');
   return invoke('ws_sync_to_disk', { projectId });
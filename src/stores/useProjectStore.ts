@@ -2,16 +2,22 @@
 import { create } from 'zustand';
 import {
   dbCreateProject, dbGetProject, dbUpdateProject,
-  dbCreateChapter, dbGetChaptersByProject, dbUpdateChapter, dbDeleteChapter,
-  dbCreateScene, dbGetScenesByChapter, dbUpdateScene, dbDeleteScene,
+  dbCreateChapter, dbGetChaptersByProject, dbUpdateChapter, dbDeleteChapter, dbReorderChapters,
+  dbSetChapterContentMode, dbSplitChapterIntoScenes,
+  dbCreateScene, dbGetScenesByChapter, dbUpdateScene, dbDeleteScene, dbReorderScenes, dbMoveScene,
   dbCreateCharacter, dbGetCharactersByProject, dbUpdateCharacter, dbDeleteCharacter,
   dbCreateRelationship, dbGetRelationshipsByCharacter, dbUpdateRelationship, dbDeleteRelationship,
   dbCreateLocation, dbGetLocationsByProject, dbUpdateLocation, dbDeleteLocation,
   dbCreateLoreItem, dbGetLoreItemsByProject, dbUpdateLoreItem, dbDeleteLoreItem,
-  dbCreateTimelineEvent, dbGetTimelineEventsByProject, dbUpdateTimelineEvent, dbDeleteTimelineEvent
+  dbCreateTimelineEvent, dbGetTimelineEventsByProject, dbUpdateTimelineEvent, dbDeleteTimelineEvent,
+  dbCreateCreature, dbGetCreaturesByProject, dbUpdateCreature, dbDeleteCreature,
+  dbCreateNpc, dbGetNpcsByProject, dbUpdateNpc, dbDeleteNpc,
+  dbCreateWorldRule, dbGetWorldRulesByProject, dbUpdateWorldRule, dbDeleteWorldRule,
+  dbDuplicateProject, wsCopyProjectImages, dbRenameEntity
 } from '@/lib/tauri-bridge';
+import type { DbDuplicateOptions } from '@/lib/tauri-bridge';
 import { useWorkspaceStore } from './useWorkspaceStore';
-import type { Project, Chapter, Character, Location, Scene, ProjectApiKeys, ApiKeyEntry, VitalStatusEntry, LoreItem, TimelineEvent, RelationshipHistoryEntry, LocationImage, LocationConnection, Creature, CreatureAbility, WorldRule, WorldRuleExample, ProjectType, Npc, NpcQuest, NpcDialogue } from '@/types/domain';
+import type { Project, Chapter, Character, Location, Scene, ProjectApiKeys, ApiKeyEntry, VitalStatusEntry, LoreItem, TimelineEvent, RelationshipHistoryEntry, LocationImage, LocationConnection, Creature, CreatureAbility, WorldRule, WorldRuleExample, ProjectType, Npc, NpcQuest, NpcDialogue, ChapterContentMode } from '@/types/domain';
 
 interface ProjectState {
   activeProject: Project | null;
@@ -26,6 +32,9 @@ interface ProjectState {
   addChapter: (chapter: Omit<Chapter, 'id'>) => Promise<void>;
   updateChapter: (id: string, updates: Partial<Chapter>) => Promise<void>;
   deleteChapter: (id: string) => Promise<void>;
+  reorderChapters: (orderedIds: string[]) => Promise<void>;
+  setChapterContentMode: (chapterId: string, mode: ChapterContentMode) => Promise<void>;
+  splitChapterIntoScenes: (chapterId: string) => Promise<void>;
   
   // Characters
   addCharacter: (character: Omit<Character, 'id' | 'vitalStatusHistory' | 'currentVitalStatus'>) => Promise<void>;
@@ -63,6 +72,11 @@ interface ProjectState {
   addScene: (scene: Omit<Scene, 'id'>) => Promise<void>;
   updateScene: (id: string, updates: Partial<Scene>) => Promise<void>;
   deleteScene: (id: string) => Promise<void>;
+  reorderScenes: (chapterId: string, orderedIds: string[]) => Promise<void>;
+  moveScene: (sceneId: string, targetChapterId: string, position?: number) => Promise<void>;
+
+  // Links
+  renameEntity: (entityType: 'character' | 'location' | 'loreItem', id: string, newName: string) => Promise<number>;
   
   // AI API Key Management
   addApiKey: (type: 'text' | 'image', provider: string, keyData: { name: string; key: string }) => Promise<void>;
@@ -70,6 +84,7 @@ interface ProjectState {
   deleteApiKey: (type: 'text' | 'image', provider: string, keyId: string) => Promise<void>;
   setDefaultApiKey: (type: 'text' | 'image', provider: string, keyId: string) => Promise<void>;
   createNewProject: (projectInfo: { title: string; author?: string; genre?: string; projectType?: ProjectType }) => Promise<void>;
+  duplicateProject: (projectId: string, options?: DbDuplicateOptions) => Promise<string>;
   
   // Worldbuilder Mode
   toggleRpgMode: (enabled: boolean) => Promise<void>;
@@ -121,6 +136,57 @@ const initialApiKeys: ProjectApiKeys = {
   }
 };
 
+type SetProjectState = (fn: (state: ProjectState) => Partial<ProjectState>) => void;
+
+// Persist one creature and replace it in the active project
+const saveCreature = async (projectId: string, creature: Creature, set: SetProjectState) => {
+  await dbUpdateCreature({ ...creature, projectId });
+  set((state) => ({
+    activeProject: state.activeProject ? {
+      ...state.activeProject,
+      creatures: (state.activeProject.creatures || []).map((c) => c.id === creature.id ? creature : c),
+    } : null
+  }));
+};
+
+const saveWorldRule = async (projectId: string, rule: WorldRule, set: SetProjectState) => {
+  await dbUpdateWorldRule({ ...rule, projectId });
+  set((state) => ({
+    activeProject: state.activeProject ? {
+      ...state.activeProject,
+      worldRules: (state.activeProject.worldRules || []).map((r) => r.id === rule.id ? rule : r),
+    } : null
+  }));
+};
+
+const saveNpc = async (projectId: string, npc: Npc, set: SetProjectState) => {
+  await dbUpdateNpc({ ...npc, projectId });
+  set((state) => ({
+    activeProject: state.activeProject ? {
+      ...state.activeProject,
+      npcs: (state.activeProject.npcs || []).map((n) => n.id === npc.id ? npc : n),
+    } : null
+  }));
+};
+
+// Chapters compiled from scenes change whenever their scenes do; pull the
+// new text of any such chapter among `chapterIds`
+const refreshCompiledChapters = async (project: Project, chapterIds: string[], set: SetProjectState) => {
+  const ids = new Set(chapterIds);
+  if (!project.chapters.some((c) => ids.has(c.id) && c.contentMode === 'scenes')) return;
+
+  const fresh = new Map((await dbGetChaptersByProject(project.id)).map((c) => [c.id, c]));
+  set((state) => ({
+    activeProject: state.activeProject ? {
+      ...state.activeProject,
+      chapters: state.activeProject.chapters.map((c) => {
+        const chapter = ids.has(c.id) ? fresh.get(c.id) : undefined;
+        return chapter ? { ...c, content: chapter.content, wordCount: chapter.wordCount } : c;
+      }),
+    } : null
+  }));
+};
+
 export const useProjectStore = create<ProjectState>((set, get) => ({
       activeProject: null,
       
@@ -182,12 +248,15 @@ export const useProjectStore = create<ProjectState>((set, get) => ({
           if (!dbProject) return;
 
           // Fetch all related data in parallel
-          const [chapters, characters, locations, loreItems, timelineEvents] = await Promise.all([
+          const [chapters, characters, locations, loreItems, timelineEvents, creatures, npcs, worldRules] = await Promise.all([
             dbGetChaptersByProject(id),
             dbGetCharactersByProject(id),
             dbGetLocationsByProject(id),
             dbGetLoreItemsByProject(id),
-            dbGetTimelineEventsByProject(id)
+            dbGetTimelineEventsByProject(id),
+            dbGetCreaturesByProject(id),
+            dbGetNpcsByProject(id),
+            dbGetWorldRulesByProject(id)
           ]);
 
           // Fetch scenes for each chapter (could be optimized with a join or bulk query, but loop for now)
@@ -205,10 +274,6 @@ export const useProjectStore = create<ProjectState>((set, get) => ({
           }));
 
           // Construct full Project object
-          // Note: Some fields like creatures/worldRules are not in SQLite yet (from schema check)
-          // We will use defaults for them or they might be stored in JSON fields if we add them later.
-          // For now, they are empty arrays in the store init, so we keep them empty.
-          
           const fullProject: Project = {
             id: dbProject.id,
             title: dbProject.title,
@@ -226,9 +291,9 @@ export const useProjectStore = create<ProjectState>((set, get) => ({
             loreItems: loreItems as any[],
             timelineEvents: timelineEvents as any[],
             scenes: [],
-            creatures: Array.isArray(dbProject.creatures) ? dbProject.creatures : [],
-            worldRules: Array.isArray(dbProject.worldRules) ? dbProject.worldRules : [],
-            npcs: Array.isArray((dbProject as any).npcs) ? (dbProject as any).npcs : [],
+            creatures,
+            worldRules,
+            npcs,
             apiKeys: (dbProject.apiKeys as any) || initialApiKeys,
             projectType: (dbProject.projectType as ProjectType) || 'novel'
           };
@@ -276,7 +341,7 @@ export const useProjectStore = create<ProjectState>((set, get) => ({
           apiKeys: initialApiKeys
         };
 
-        // Rust model uses Option<serde_json::Value> for banners/apiKeys/creatures/worldRules.
+        // Rust model uses Option<serde_json::Value> for banners/apiKeys.
         // Send objects directly — Tauri serializes them as JSON values for serde.
         const projectPayload = {
             id: newProject.id,
@@ -291,9 +356,6 @@ export const useProjectStore = create<ProjectState>((set, get) => ({
             projectType: newProject.projectType || 'novel',
             banners: newProject.banners || null,
             apiKeys: newProject.apiKeys || null,
-            creatures: newProject.creatures || null,
-            worldRules: newProject.worldRules || null,
-            npcs: newProject.npcs || null,
         };
 
         console.log('[createNewProject] payload:', JSON.stringify(projectPayload, null, 2));
@@ -320,6 +382,24 @@ export const useProjectStore = create<ProjectState>((set, get) => ({
         }
       },
 
+      duplicateProject: async (projectId, options) => {
+        const copy = await dbDuplicateProject(projectId, options);
+
+        // Give the copy its own folder, with the images it refers to
+        try {
+          const ws = useWorkspaceStore.getState();
+          if (ws.isInitialized) {
+            await ws.syncProjectToDisk(projectId);
+            await ws.syncProjectToDisk(copy.id);
+            await wsCopyProjectImages(projectId, copy.id);
+          }
+        } catch (wsErr) {
+          console.warn('Copying project images failed (non-blocking):', wsErr);
+        }
+
+        return copy.id;
+      },
+
       addChapter: async (chapter) => {
         const { activeProject } = get();
         if (!activeProject) return;
@@ -380,6 +460,60 @@ export const useProjectStore = create<ProjectState>((set, get) => ({
           } : null,
         }));
       },
+
+      reorderChapters: async (orderedIds) => {
+        const { activeProject } = get();
+        if (!activeProject) return;
+
+        const ordered = await dbReorderChapters(activeProject.id, orderedIds);
+        const numbers = new Map(ordered.map((c) => [c.id, c.number]));
+
+        set((state) => ({
+          activeProject: state.activeProject ? {
+            ...state.activeProject,
+            chapters: state.activeProject.chapters
+              .map((c) => {
+                const number = numbers.get(c.id);
+                return number == null ? c : { ...c, number, order: number };
+              })
+              .sort((a, b) => (a.number ?? 0) - (b.number ?? 0)),
+          } : null,
+        }));
+      },
+
+      setChapterContentMode: async (chapterId, mode) => {
+        const chapter = await dbSetChapterContentMode(chapterId, mode);
+
+        set((state) => ({
+          activeProject: state.activeProject ? {
+            ...state.activeProject,
+            chapters: state.activeProject.chapters.map((c) =>
+              c.id === chapterId
+                ? { ...c, contentMode: chapter.contentMode, content: chapter.content, wordCount: chapter.wordCount }
+                : c
+            ),
+          } : null,
+        }));
+      },
+
+      splitChapterIntoScenes: async (chapterId) => {
+        const split = await dbSplitChapterIntoScenes(chapterId);
+
+        set((state) => ({
+          activeProject: state.activeProject ? {
+            ...state.activeProject,
+            chapters: state.activeProject.chapters.map((c) =>
+              c.id === chapterId
+                ? { ...c, contentMode: split.chapter.contentMode, content: split.chapter.content, wordCount: split.chapter.wordCount }
+                : c
+            ),
+            scenes: [
+              ...(state.activeProject.scenes || []).filter((s) => s.chapterId !== chapterId),
+              ...(split.scenes as Scene[]),
+            ],
+          } : null,
+        }));
+      },
       
       addCharacter: async (character) => {
         const { activeProject } = get();
@@ -872,6 +1006,7 @@ export const useProjectStore = create<ProjectState>((set, get) => ({
                         scenes: [...(state.activeProject.scenes || []), newScene],
                     } : null
                 }));
+                await refreshCompiledChapters(activeProject, [newScene.chapterId], set);
             },
             
             updateScene: async (id, updates) => {
@@ -893,12 +1028,14 @@ export const useProjectStore = create<ProjectState>((set, get) => ({
                         ),
                     } : null
                 }));
+                await refreshCompiledChapters(activeProject, [current.chapterId, updated.chapterId], set);
             },
             
             deleteScene: async (id) => {
                 const { activeProject } = get();
                 if (!activeProject) return;
                 
+                const chapterId = (activeProject.scenes || []).find(s => s.id === id)?.chapterId;
                 await dbDeleteScene(id);
                 
                 set((state) => ({
@@ -907,6 +1044,91 @@ export const useProjectStore = create<ProjectState>((set, get) => ({
                         scenes: (state.activeProject.scenes || []).filter((s) => s.id !== id),
                     } : null
                 }));
+                if (chapterId) await refreshCompiledChapters(activeProject, [chapterId], set);
+            },
+
+            reorderScenes: async (chapterId, orderedIds) => {
+                const { activeProject } = get();
+                if (!activeProject) return;
+
+                const ordered = await dbReorderScenes(chapterId, orderedIds);
+
+                set((state) => ({
+                    activeProject: state.activeProject ? {
+                        ...state.activeProject,
+                        scenes: [
+                            ...(state.activeProject.scenes || []).filter((s) => s.chapterId !== chapterId),
+                            ...(ordered as Scene[]),
+                        ],
+                    } : null
+                }));
+                await refreshCompiledChapters(activeProject, [chapterId], set);
+            },
+
+            moveScene: async (sceneId, targetChapterId, position) => {
+                const { activeProject } = get();
+                if (!activeProject) return;
+
+                const moved = await dbMoveScene(sceneId, targetChapterId, position);
+                const touched = new Set([moved.sourceChapterId, moved.targetChapterId]);
+
+                set((state) => ({
+                    activeProject: state.activeProject ? {
+                        ...state.activeProject,
+                        scenes: [
+                            ...(state.activeProject.scenes || []).filter((s) => !touched.has(s.chapterId)),
+                            ...(moved.sourceChapterId !== moved.targetChapterId ? moved.sourceScenes as Scene[] : []),
+                            ...(moved.targetScenes as Scene[]),
+                        ],
+                        // Linked timeline events follow the scene
+                        timelineEvents: (state.activeProject.timelineEvents || []).map((e) =>
+                            e.sceneId === sceneId ? { ...e, chapterId: moved.targetChapterId } : e
+                        ),
+                    } : null
+                }));
+                await refreshCompiledChapters(activeProject, [...touched], set);
+            },
+
+            renameEntity: async (entityType, id, newName) => {
+                const { activeProject } = get();
+                if (!activeProject) return 0;
+
+                const result = await dbRenameEntity(entityType, id, newName);
+                const name = newName.trim();
+
+                // Pull the rewritten text, including chapters compiled from rewritten scenes
+                const sceneIds = new Set(result.sceneIds);
+                const chapterIds = new Set(result.chapterIds);
+                (activeProject.scenes || []).forEach((s) => { if (sceneIds.has(s.id)) chapterIds.add(s.chapterId); });
+                const chapters = new Map((await dbGetChaptersByProject(activeProject.id)).map((c) => [c.id, c]));
+                const scenes = new Map((await Promise.all(
+                    [...chapterIds].map((chapterId) => dbGetScenesByChapter(chapterId))
+                )).flat().map((s) => [s.id, s]));
+
+                set((state) => ({
+                    activeProject: state.activeProject ? {
+                        ...state.activeProject,
+                        chapters: state.activeProject.chapters.map((c) => {
+                            const chapter = chapterIds.has(c.id) ? chapters.get(c.id) : undefined;
+                            return chapter ? { ...c, content: chapter.content, wordCount: chapter.wordCount } : c;
+                        }),
+                        scenes: (state.activeProject.scenes || []).map((s) => {
+                            const scene = sceneIds.has(s.id) ? scenes.get(s.id) : undefined;
+                            return scene ? { ...s, content: scene.content, wordCount: scene.wordCount } : s;
+                        }),
+                        characters: entityType === 'character'
+                            ? state.activeProject.characters.map((c) => c.id === id ? { ...c, name } : c)
+                            : state.activeProject.characters,
+                        locations: entityType === 'location'
+                            ? state.activeProject.locations.map((l) => l.id === id ? { ...l, name } : l)
+                            : state.activeProject.locations,
+                        loreItems: entityType === 'loreItem'
+                            ? state.activeProject.loreItems.map((l) => l.id === id ? { ...l, title: name } : l)
+                            : state.activeProject.loreItems,
+                    } : null
+                }));
+
+                return result.linksUpdated;
             },
 
   addApiKey: async (type, provider, keyData) => {
@@ -1059,184 +1281,144 @@ export const useProjectStore = create<ProjectState>((set, get) => ({
       abilities: creature.abilities || [],
       stats: creature.stats || {},
     };
-    
-    const updatedProject = {
-      ...activeProject,
-      creatures: [...(activeProject.creatures || []), newCreature],
-    };
-    
-    await dbUpdateProject(updatedProject as any);
-    
-    set({ activeProject: updatedProject });
+
+    await dbCreateCreature({ ...newCreature, projectId: activeProject.id });
+
+    set((state) => ({
+      activeProject: state.activeProject ? {
+        ...state.activeProject,
+        creatures: [...(state.activeProject.creatures || []), newCreature],
+      } : null
+    }));
   },
 
   updateCreature: async (id, updates) => {
     const { activeProject } = get();
     if (!activeProject) return;
-    
-    const updatedProject = {
-      ...activeProject,
-      creatures: (activeProject.creatures || []).map((c) =>
-        c.id === id ? { ...c, ...updates } : c
-      ),
-    };
-    
-    await dbUpdateProject(updatedProject as any);
-    
-    set({ activeProject: updatedProject });
+
+    const current = (activeProject.creatures || []).find((c) => c.id === id);
+    if (!current) return;
+
+    await saveCreature(activeProject.id, { ...current, ...updates }, set);
   },
 
   deleteCreature: async (id) => {
     const { activeProject } = get();
     if (!activeProject) return;
-    
-    const updatedProject = {
-      ...activeProject,
-      creatures: (activeProject.creatures || []).filter((c) => c.id !== id),
-    };
-    
-    await dbUpdateProject(updatedProject as any);
-    
-    set({ activeProject: updatedProject });
+
+    await dbDeleteCreature(id);
+
+    set((state) => ({
+      activeProject: state.activeProject ? {
+        ...state.activeProject,
+        creatures: (state.activeProject.creatures || []).filter((c) => c.id !== id),
+      } : null
+    }));
   },
 
   addCreatureAbility: async (creatureId, ability) => {
     const { activeProject } = get();
     if (!activeProject) return;
-    
-    const updatedProject = {
-      ...activeProject,
-      creatures: (activeProject.creatures || []).map((c) => {
-        if (c.id !== creatureId) return c;
-        const newAbility: CreatureAbility = {
-          ...ability,
-          id: crypto.randomUUID(),
-        };
-        return {
-          ...c,
-          abilities: [...(c.abilities || []), newAbility],
-        };
-      }),
+
+    const current = (activeProject.creatures || []).find((c) => c.id === creatureId);
+    if (!current) return;
+
+    const newAbility: CreatureAbility = {
+      ...ability,
+      id: crypto.randomUUID(),
     };
-    
-    await dbUpdateProject(updatedProject as any);
-    
-    set({ activeProject: updatedProject });
+    await saveCreature(activeProject.id, {
+      ...current,
+      abilities: [...(current.abilities || []), newAbility],
+    }, set);
   },
 
   removeCreatureAbility: async (creatureId, abilityId) => {
     const { activeProject } = get();
     if (!activeProject) return;
-    
-    const updatedProject = {
-      ...activeProject,
-      creatures: (activeProject.creatures || []).map((c) => {
-        if (c.id !== creatureId) return c;
-        return {
-          ...c,
-          abilities: (c.abilities || []).filter((a) => a.id !== abilityId),
-        };
-      }),
-    };
-    
-    await dbUpdateProject(updatedProject as any);
-    
-    set({ activeProject: updatedProject });
+
+    const current = (activeProject.creatures || []).find((c) => c.id === creatureId);
+    if (!current) return;
+
+    await saveCreature(activeProject.id, {
+      ...current,
+      abilities: (current.abilities || []).filter((a) => a.id !== abilityId),
+    }, set);
   },
 
   // World Rules
   addWorldRule: async (rule) => {
     const { activeProject } = get();
     if (!activeProject) return;
-    
+
     const newRule: WorldRule = {
       ...rule,
       id: crypto.randomUUID(),
       examples: rule.examples || [],
     };
-    
-    const updatedProject = {
-      ...activeProject,
-      worldRules: [...(activeProject.worldRules || []), newRule],
-    };
-    
-    await dbUpdateProject(updatedProject as any);
-    
-    set({ activeProject: updatedProject });
+
+    await dbCreateWorldRule({ ...newRule, projectId: activeProject.id });
+
+    set((state) => ({
+      activeProject: state.activeProject ? {
+        ...state.activeProject,
+        worldRules: [...(state.activeProject.worldRules || []), newRule],
+      } : null
+    }));
   },
 
   updateWorldRule: async (id, updates) => {
     const { activeProject } = get();
     if (!activeProject) return;
-    
-    const updatedProject = {
-      ...activeProject,
-      worldRules: (activeProject.worldRules || []).map((r) =>
-        r.id === id ? { ...r, ...updates } : r
-      ),
-    };
-    
-    await dbUpdateProject(updatedProject as any);
-    
-    set({ activeProject: updatedProject });
+
+    const current = (activeProject.worldRules || []).find((r) => r.id === id);
+    if (!current) return;
+
+    await saveWorldRule(activeProject.id, { ...current, ...updates }, set);
   },
 
   deleteWorldRule: async (id) => {
     const { activeProject } = get();
     if (!activeProject) return;
-    
-    const updatedProject = {
-      ...activeProject,
-      worldRules: (activeProject.worldRules || []).filter((r) => r.id !== id),
-    };
-    
-    await dbUpdateProject(updatedProject as any);
-    
-    set({ activeProject: updatedProject });
+
+    await dbDeleteWorldRule(id);
+
+    set((state) => ({
+      activeProject: state.activeProject ? {
+        ...state.activeProject,
+        worldRules: (state.activeProject.worldRules || []).filter((r) => r.id !== id),
+      } : null
+    }));
   },
 
   addWorldRuleExample: async (ruleId, example) => {
     const { activeProject } = get();
     if (!activeProject) return;
-    
-    const updatedProject = {
-      ...activeProject,
-      worldRules: (activeProject.worldRules || []).map((r) => {
-        if (r.id !== ruleId) return r;
-        const newExample: WorldRuleExample = {
-          ...example,
-          id: crypto.randomUUID(),
-        };
-        return {
-          ...r,
-          examples: [...(r.examples || []), newExample],
-        };
-      }),
+
+    const current = (activeProject.worldRules || []).find((r) => r.id === ruleId);
+    if (!current) return;
+
+    const newExample: WorldRuleExample = {
+      ...example,
+      id: crypto.randomUUID(),
     };
-    
-    await dbUpdateProject(updatedProject as any);
-    
-    set({ activeProject: updatedProject });
+    await saveWorldRule(activeProject.id, {
+      ...current,
+      examples: [...(current.examples || []), newExample],
+    }, set);
   },
 
   removeWorldRuleExample: async (ruleId, exampleId) => {
     const { activeProject } = get();
     if (!activeProject) return;
-    
-    const updatedProject = {
-      ...activeProject,
-      worldRules: (activeProject.worldRules || []).map((r) => {
-        if (r.id !== ruleId) return r;
-        return {
-          ...r,
-          examples: (r.examples || []).filter((e) => e.id !== exampleId),
-        };
-      }),
-    };
-    
-    await dbUpdateProject(updatedProject as any);
-    
-    set({ activeProject: updatedProject });
+
+    const current = (activeProject.worldRules || []).find((r) => r.id === ruleId);
+    if (!current) return;
+
+    await saveWorldRule(activeProject.id, {
+      ...current,
+      examples: (current.examples || []).filter((e) => e.id !== exampleId),
+    }, set);
   },
 
   // NPCs
@@ -1250,94 +1432,67 @@ export const useProjectStore = create<ProjectState>((set, get) => ({
       quests: npc.quests || [],
       dialogues: npc.dialogues || [],
     };
-    const updatedProject = {
-      ...activeProject,
-      npcs: [...(activeProject.npcs || []), newNpc],
-    };
-    await dbUpdateProject(updatedProject as any);
-    set({ activeProject: updatedProject });
+    await dbCreateNpc({ ...newNpc, projectId: activeProject.id });
+    set((state) => ({
+      activeProject: state.activeProject ? {
+        ...state.activeProject,
+        npcs: [...(state.activeProject.npcs || []), newNpc],
+      } : null
+    }));
   },
 
   updateNpc: async (id, updates) => {
     const { activeProject } = get();
     if (!activeProject) return;
-    const updatedProject = {
-      ...activeProject,
-      npcs: (activeProject.npcs || []).map((n) =>
-        n.id === id ? { ...n, ...updates } : n
-      ),
-    };
-    await dbUpdateProject(updatedProject as any);
-    set({ activeProject: updatedProject });
+    const current = (activeProject.npcs || []).find((n) => n.id === id);
+    if (!current) return;
+    await saveNpc(activeProject.id, { ...current, ...updates }, set);
   },
 
   deleteNpc: async (id) => {
     const { activeProject } = get();
     if (!activeProject) return;
-    const updatedProject = {
-      ...activeProject,
-      npcs: (activeProject.npcs || []).filter((n) => n.id !== id),
-    };
-    await dbUpdateProject(updatedProject as any);
-    set({ activeProject: updatedProject });
+    await dbDeleteNpc(id);
+    set((state) => ({
+      activeProject: state.activeProject ? {
+        ...state.activeProject,
+        npcs: (state.activeProject.npcs || []).filter((n) => n.id !== id),
+      } : null
+    }));
   },
 
   addNpcQuest: async (npcId, quest) => {
     const { activeProject } = get();
     if (!activeProject) return;
-    const updatedProject = {
-      ...activeProject,
-      npcs: (activeProject.npcs || []).map((n) => {
-        if (n.id !== npcId) return n;
-        const newQuest: NpcQuest = { ...quest, id: crypto.randomUUID() };
-        return { ...n, quests: [...(n.quests || []), newQuest] };
-      }),
-    };
-    await dbUpdateProject(updatedProject as any);
-    set({ activeProject: updatedProject });
+    const current = (activeProject.npcs || []).find((n) => n.id === npcId);
+    if (!current) return;
+    const newQuest: NpcQuest = { ...quest, id: crypto.randomUUID() };
+    await saveNpc(activeProject.id, { ...current, quests: [...(current.quests || []), newQuest] }, set);
   },
 
   removeNpcQuest: async (npcId, questId) => {
     const { activeProject } = get();
     if (!activeProject) return;
-    const updatedProject = {
-      ...activeProject,
-      npcs: (activeProject.npcs || []).map((n) => {
-        if (n.id !== npcId) return n;
-        return { ...n, quests: (n.quests || []).filter((q) => q.id !== questId) };
-      }),
-    };
-    await dbUpdateProject(updatedProject as any);
-    set({ activeProject: updatedProject });
+    const current = (activeProject.npcs || []).find((n) => n.id === npcId);
+    if (!current) return;
+    await saveNpc(activeProject.id, { ...current, quests: (current.quests || []).filter((q) => q.id !== questId) }, set);
   },
 
   addNpcDialogue: async (npcId, dialogue) => {
     const { activeProject } = get();
     if (!activeProject) return;
-    const updatedProject = {
-      ...activeProject,
-      npcs: (activeProject.npcs || []).map((n) => {
-        if (n.id !== npcId) return n;
-        const newDialogue: NpcDialogue = { ...dialogue, id: crypto.randomUUID() };
-        return { ...n, dialogues: [...(n.dialogues || []), newDialogue] };
-      }),
-    };
-    await dbUpdateProject(updatedProject as any);
-    set({ activeProject: updatedProject });
+    const current = (activeProject.npcs || []).find((n) => n.id === npcId);
+    if (!current) return;
+    const newDialogue: NpcDialogue = { ...dialogue, id: crypto.randomUUID() };
+    await saveNpc(activeProject.id, { ...current, dialogues: [...(current.dialogues || []), newDialogue] }, set);
   },
 
   removeNpcDialogue: async (npcId, dialogueId) => {
     const { activeProject } = get();
     if (!activeProject) return;
-    const updatedProject = {
-      ...activeProject,
-      npcs: (activeProject.npcs || []).map((n) => {
-        if (n.id !== npcId) return n;
-        return { ...n, dialogues: (n.dialogues || []).filter((d) => d.id !== dialogueId) };
-      }),
-    };
-    await dbUpdateProject(updatedProject as any);
-    set({ activeProject: updatedProject });
+    const current = (activeProject.npcs || []).find((n) => n.id === npcId);
+    if (!current) return;
+    await saveNpc(activeProject.id, { ...current, dialogues: (current.dialogues || []).filter((d) => d.id !== dialogueId) }, set);
   },
 
   // Packages
@@ -1,5 +1,13 @@
 import { create } from 'zustand';
-import { dbGetSetting, dbSetSetting } from '@/lib/tauri-bridge';
+import { listen } from '@tauri-apps/api/event';
+import {
+  dbGetSetting,
+  dbSetSetting,
+  isTauri,
+  settingsGetAll,
+  settingsUpdate,
+  SETTINGS_CHANGED_EVENT,
+} from '@/lib/tauri-bridge';
 
 export type AIProvider = 
   | 'anthropic' 
@@ -141,7 +149,36 @@ Autor: {{USER_INPUT}}
 {{CHAR_NAME}}:`;
 
 const helperSet = (key: string, value: any) => {
-    dbSetSetting(key, JSON.stringify(value)).catch(e => console.error(`Failed to save setting ${key}:`, e));
+    const save = isTauri()
+        ? settingsUpdate({ [key]: value })
+        : dbSetSetting(key, JSON.stringify(value));
+    save.catch(e => console.error(`Failed to save setting ${key}:`, e));
+};
+
+// Settings the backend registry declares with a `null` default keep the
+// frontend default until set
+const loadSettings = async (keys: (keyof SettingsState)[]): Promise<Partial<SettingsState>> => {
+    const updates: Partial<SettingsState> = {};
+    if (isTauri()) {
+        for (const setting of await settingsGetAll()) {
+            const key = setting.key as keyof SettingsState;
+            if (keys.includes(key) && !(setting.value === null && setting.default === null)) {
+                updates[key] = setting.value as never;
+            }
+        }
+        return updates;
+    }
+    for (const key of keys) {
+        const val = await dbGetSetting(key);
+        if (val !== null) {
+            try {
+                updates[key] = JSON.parse(val);
+            } catch (e) {
+                console.warn(`Failed to parse setting ${key}:`, e);
+            }
+        }
+    }
+    return updates;
 };
 
 export const useSettingsStore = create<SettingsState>((set) => ({
@@ -206,22 +243,23 @@ export const useSettingsStore = create<SettingsState>((set) => ({
                 'typewriterMode', 'hemingwayMode', 'pomodoroEnabled', 'animationsEnabled', 'ragStudioEnabled'
             ];
 
-            const updates: Partial<SettingsState> = {};
-            for (const key of keys) {
-                const val = await dbGetSetting(key);
-                if (val !== null) {
-                    try {
-                        updates[key] = JSON.parse(val);
-                    } catch (e) {
-                        // If parse fails, assume string or ignore? Most settings are JSON safe.
-                        // Simple strings might not be JSON quoted if saved raw, but helperSet uses JSON.stringify.
-                        // However, legacy storage might have issues. Assuming JSON.parse works for now.
-                         console.warn(`Failed to parse setting ${key}:`, e);
+            const updates = await loadSettings(keys);
+            set(updates);
+
+            // Keep in step with changes made elsewhere (another window, a
+            // profile import)
+            if (isTauri()) {
+                listen<Partial<SettingsState>>(SETTINGS_CHANGED_EVENT, (event) => {
+                    const changed: Partial<SettingsState> = {};
+                    for (const [key, value] of Object.entries(event.payload)) {
+                        if (keys.includes(key as keyof SettingsState) && value !== null) {
+                            changed[key as keyof SettingsState] = value as never;
+                        }
                     }
-                }
+                    set(changed);
+                    if (changed.theme) document.documentElement.className = changed.theme;
+                });
             }
-            
-            set(updates);
 
             // Apply side effects immediately
             if (updates.theme) document.documentElement.className = updates.theme; // Tailwind dark mode uses class usually, or data-theme
@@ -173,8 +173,11 @@ export interface Chapter {
   imageType?: 'upload' | 'url' | 'ai';
   headerImage?: string;
   scenes?: string[]; // Scene IDs associated with this chapter
+  contentMode?: ChapterContentMode; // 'scenes': content is compiled from the chapter's scenes
 }
 
+export type ChapterContentMode = 'document' | 'scenes';
+
 export interface Scene {
   id: string;
   title: string;
@@ -186,6 +189,8 @@ export interface Scene {
   notes?: string;
   image?: string; // URL or base64
   imageType?: 'upload' | 'url' | 'ai';
+  content?: string; // Scene prose (HTML)
+  wordCount?: number;
 }
 
 export interface VitalStatusEntry {
@@ -212,6 +217,7 @@ export interface Character {
   vitalStatusHistory: VitalStatusEntry[];
   currentVitalStatus: string;
   visualPosition?: { x: number; y: number };
+  customFields?: Record<string, string | number>; // Custom field values by field id
 }
 
 export interface Relationship {
@@ -247,6 +253,7 @@ export interface Location {
   plans?: LocationImage[];
   connections?: LocationConnection[];
   visualPosition?: { x: number; y: number };
+  customFields?: Record<string, string | number>; // Custom field values by field id
 }
 
 export interface LocationImage {
@@ -272,6 +279,7 @@ export interface LoreItem {
   content: string; // Rich text content
   summary?: string;
   relatedEntityIds?: string[];
+  customFields?: Record<string, string | number>; // Custom field values by field id
 }
 
 export interface TimelineEvent {